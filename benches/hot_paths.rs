@@ -0,0 +1,211 @@
+//! Baseline numbers for the paths the performance work touches, run with `cargo bench`.
+//!
+//! Message handling that only exists inside a binary (the broadcast bus, kafka polls) is
+//! measured end to end: the binary is started once and each iteration is a request and its
+//! reply over the pipes, so the numbers include the JSON round trip.
+
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader, Lines, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use distributed_systems::broadcast::{OrSet, SnapshotSet, ValueSet};
+use distributed_systems::maelstrom::NodeMessage;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Entries in the log the kafka polls read from.
+const KAFKA_LOG_SIZE: u64 = 100_000;
+/// Sends written before their replies are read back, so the pipes never fill up.
+const KAFKA_SEND_CHUNK: u64 = 500;
+
+/// Same shape as the `broadcast` body the broadcast binaries send to their neighbors.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct BroadcastResponse {
+    #[serde(rename = "type")]
+    _type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    in_reply_to: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    msg_id: Option<u64>,
+    message: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ttl: Option<u32>,
+}
+
+/// A node binary answering requests from a single client.
+struct Node {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Lines<BufReader<ChildStdout>>,
+    next_msg_id: u64,
+}
+
+impl Node {
+    fn start(program: &str, node_ids: &[String]) -> Node {
+        let mut child = Command::new(program)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Cannot start node");
+        let mut node = Node {
+            stdin: child.stdin.take().unwrap(),
+            lines: BufReader::new(child.stdout.take().unwrap()).lines(),
+            child,
+            next_msg_id: 0,
+        };
+        node.request(
+            "c0",
+            json!({"type": "init", "node_id": node_ids[0], "node_ids": node_ids}),
+        );
+        node
+    }
+
+    /// Write a request without waiting for its reply, returning its msg_id.
+    fn send(&mut self, src: &str, mut body: Value) -> u64 {
+        self.next_msg_id += 1;
+        body["msg_id"] = json!(self.next_msg_id);
+        let msg = json!({"src": src, "dest": "n0", "body": body});
+        writeln!(self.stdin, "{}", msg).unwrap();
+        self.next_msg_id
+    }
+
+    /// Wait for the reply to `msg_id`, skipping whatever else the node sends meanwhile.
+    fn reply(&mut self, msg_id: u64) -> Value {
+        loop {
+            let line = self.lines.next().expect("Node stopped writing").unwrap();
+            let msg: Value = serde_json::from_str(&line).unwrap();
+            if msg["body"]["in_reply_to"] == msg_id {
+                return msg;
+            }
+        }
+    }
+
+    fn request(&mut self, src: &str, body: Value) -> Value {
+        let msg_id = self.send(src, body);
+        self.reply(msg_id)
+    }
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn node_ids(count: usize) -> Vec<String> {
+    (0..count).map(|i| format!("n{}", i)).collect()
+}
+
+fn broadcast_serde(c: &mut Criterion) {
+    let message = NodeMessage::new(
+        "n0".to_string(),
+        "n1".to_string(),
+        BroadcastResponse {
+            _type: "broadcast".into(),
+            in_reply_to: None,
+            msg_id: Some(42),
+            message: 1234,
+            ttl: Some(16),
+        },
+    );
+    let text = serde_json::to_string(&message).unwrap();
+
+    c.bench_function("broadcast_serialize", |b| {
+        b.iter(|| serde_json::to_string(black_box(&message)).unwrap())
+    });
+    c.bench_function("broadcast_parse", |b| {
+        b.iter(|| serde_json::from_str::<NodeMessage<BroadcastResponse>>(black_box(&text)).unwrap())
+    });
+}
+
+/// A peer read round trip while every neighbor has unacknowledged broadcasts, so each turn of
+/// the node's loop walks the bus in `pick_message`.
+fn broadcast_bus(c: &mut Criterion) {
+    let mut group = c.benchmark_group("broadcast_read_with_unacked_neighbors");
+    for cluster_size in [5, 25, 100] {
+        let node_ids = node_ids(cluster_size);
+        let mut node = Node::start(env!("CARGO_BIN_EXE_performant_broadcast_final"), &node_ids);
+        node.request("c0", json!({"type": "topology", "topology": {}}));
+        for value in 0..100 {
+            node.request("c1", json!({"type": "broadcast", "message": value}));
+        }
+
+        group.bench_with_input(
+            BenchmarkId::from_parameter(cluster_size),
+            &cluster_size,
+            |b, _| b.iter(|| node.request("n1", json!({"type": "read"}))),
+        );
+    }
+    group.finish();
+}
+
+fn kafka_poll(c: &mut Criterion) {
+    let mut node = Node::start(env!("CARGO_BIN_EXE_kafka"), &node_ids(1));
+    for chunk_start in (0..KAFKA_LOG_SIZE).step_by(KAFKA_SEND_CHUNK as usize) {
+        let chunk_end = (chunk_start + KAFKA_SEND_CHUNK).min(KAFKA_LOG_SIZE);
+        let last_msg_id = (chunk_start..chunk_end)
+            .map(|value| node.send("c1", json!({"type": "send", "key": "k", "msg": value})))
+            .last()
+            .unwrap();
+        node.reply(last_msg_id);
+    }
+
+    let mut group = c.benchmark_group("kafka_poll");
+    for offset in [0, KAFKA_LOG_SIZE / 2, KAFKA_LOG_SIZE - 10] {
+        group.bench_with_input(BenchmarkId::from_parameter(offset), &offset, |b, offset| {
+            b.iter(|| node.request("c1", json!({"type": "poll", "offsets": {"k": offset}})))
+        });
+    }
+    group.finish();
+}
+
+fn value_set_merge(c: &mut Criterion) {
+    // Two replicas sharing half of their values.
+    let left: HashSet<u64> = (0..10_000).collect();
+    let right: HashSet<u64> = (5_000..15_000).collect();
+    c.bench_function("hash_set_merge", |b| {
+        b.iter(|| {
+            let mut values = left.clone();
+            values.merge(black_box(&right));
+            values
+        })
+    });
+
+    let left_snapshot = SnapshotSet::new(left.clone());
+    let right_snapshot = SnapshotSet::new(right.clone());
+    c.bench_function("snapshot_set_merge_and_snapshot", |b| {
+        b.iter(|| {
+            let mut values = left_snapshot.clone();
+            values.merge(black_box(&right_snapshot));
+            values.snapshot().len()
+        })
+    });
+
+    let mut left_or = OrSet::new("n0");
+    let mut right_or = OrSet::new("n1");
+    for value in left.iter() {
+        left_or.apply_add(*value);
+    }
+    for value in right.iter() {
+        right_or.apply_add(*value);
+    }
+    c.bench_function("or_set_merge", |b| {
+        b.iter(|| {
+            let mut values = left_or.clone();
+            values.merge(black_box(&right_or));
+            values
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    broadcast_serde,
+    broadcast_bus,
+    kafka_poll,
+    value_set_merge
+);
+criterion_main!(benches);
@@ -0,0 +1,112 @@
+//! Wires node processes together through Unix sockets, a tiny local stand-in for Maelstrom.
+//!
+//! `cargo run --example socket_router -- target/debug/broadcast 2` starts two broadcast nodes
+//! and initializes them as `n0` and `n1`. Client messages read from stdin are then forwarded to
+//! the nodes, messages between nodes to each other, and everything sent to a client is printed
+//! on stdout.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{BufRead, BufReader, Write};
+use std::net::Shutdown;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::process::{Child, Command};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use distributed_systems::maelstrom::transport::SOCKET_PATH_ENV;
+use serde_json::{json, Value};
+
+/// How long the nodes get to settle once stdin is closed, before their sockets are closed too.
+const SETTLE: Duration = Duration::from_millis(500);
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = std::env::args().skip(1);
+    let program = args
+        .next()
+        .ok_or("Usage: socket_router <node binary> <node count>")?;
+    let node_count: usize = args.next().ok_or("Missing the node count")?.parse()?;
+
+    let socket_path =
+        std::env::temp_dir().join(format!("socket_router_{}.sock", std::process::id()));
+    let listener = UnixListener::bind(&socket_path)?;
+    let mut children = (0..node_count)
+        .map(|_| {
+            Command::new(&program)
+                .env(SOCKET_PATH_ENV, &socket_path)
+                .spawn()
+        })
+        .collect::<Result<Vec<Child>, _>>()?;
+
+    // `None` means stdin was closed.
+    let (tx, rx) = channel::<Option<String>>();
+
+    // Node ids are handed out in the order the nodes connect.
+    let node_ids: Vec<String> = (0..node_count).map(|index| format!("n{index}")).collect();
+    let mut nodes: HashMap<String, UnixStream> = HashMap::new();
+    for node_id in node_ids.iter() {
+        let (stream, _) = listener.accept()?;
+        spawn_reader(BufReader::new(stream.try_clone()?), tx.clone());
+        nodes.insert(node_id.clone(), stream);
+    }
+    std::fs::remove_file(&socket_path)?;
+
+    for (msg_id, node_id) in node_ids.iter().enumerate() {
+        let init = json!({
+            "src": "c0",
+            "dest": node_id,
+            "body": {"type": "init", "msg_id": msg_id, "node_id": node_id, "node_ids": node_ids},
+        });
+        route(&init.to_string(), &mut nodes)?;
+    }
+
+    thread::spawn(move || {
+        for line in std::io::stdin().lock().lines().map_while(Result::ok) {
+            if tx.send(Some(line)).is_err() {
+                return;
+            }
+        }
+        let _ = tx.send(None);
+    });
+
+    let mut deadline: Option<Instant> = None;
+    while deadline.is_none_or(|deadline| Instant::now() < deadline) {
+        match rx.recv_timeout(Duration::from_millis(10)) {
+            Ok(Some(line)) => route(&line, &mut nodes)?,
+            Ok(None) => deadline = Some(Instant::now() + SETTLE),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    for stream in nodes.values() {
+        stream.shutdown(Shutdown::Both)?;
+    }
+    for child in children.iter_mut() {
+        child.wait()?;
+    }
+    Ok(())
+}
+
+/// Forward every line a node writes to the router.
+fn spawn_reader(reader: BufReader<UnixStream>, tx: Sender<Option<String>>) {
+    thread::spawn(move || {
+        for line in reader.lines().map_while(Result::ok) {
+            if tx.send(Some(line)).is_err() {
+                return;
+            }
+        }
+    });
+}
+
+/// Deliver a message to the node it is addressed to, or print it if it is for a client.
+fn route(line: &str, nodes: &mut HashMap<String, UnixStream>) -> Result<(), Box<dyn Error>> {
+    let msg: Value = serde_json::from_str(line)?;
+    let dest = msg["dest"].as_str().unwrap_or_default();
+    match nodes.get_mut(dest) {
+        Some(stream) => stream.write_all(format!("{}\n", line).as_bytes())?,
+        None => println!("{}", line),
+    }
+    Ok(())
+}
@@ -1,114 +1,142 @@
 use std::collections::{HashMap, HashSet, VecDeque};
-use std::sync::mpsc::{channel, TryRecvError};
-use std::thread;
+use std::sync::mpsc::TryRecvError;
 
+use distributed_systems::logging::enter_message;
+use distributed_systems::maelstrom::membership::{strict_mode_from_env, Membership};
+use distributed_systems::maelstrom::outbox::Outbox;
 use distributed_systems::maelstrom::*;
 use serde::{Deserialize, Serialize};
 
+/// Whether a topology leaving this node without any known neighbor is accepted.
+const ALLOW_EMPTY_NEIGHBORHOOD: bool = true;
+
 fn main() {
-    let node_id = get_node_id().unwrap();
+    let membership = get_membership().unwrap();
     let mut state = GlobalState {
-        node_id,
+        node_id: membership.node_id().to_string(),
+        membership,
         neighborhood: vec![],
         values: HashSet::new(),
 
         to_send: VecDeque::new(),
         past_broadcast: HashSet::new(),
     };
-    let (tx, rx) = channel();
-
-    thread::spawn(move || loop {
-        let request: NodeMessage<RequestType> =
-            read_node_message().expect("Could not read request");
-        tx.send(request).unwrap();
-    });
+    let strict = strict_mode_from_env();
+    let mut outbox = Outbox::new();
+    let rx = spawn_request_reader::<RequestType>();
 
     loop {
         match rx.try_recv() {
-            Ok(node_message) => {
-                handle_message(node_message, &mut state).expect("Could not parse message");
+            Ok((node_message, _)) if strict && !state.membership.accepts(&node_message.src) => {}
+            Ok((node_message, context)) => {
+                let src = node_message.src.clone();
+                let _scope = enter_message(context);
+                let result = handle_message(node_message, &mut state, &mut outbox);
+                // Whatever was emitted before an error still goes out.
+                outbox.flush().expect("Cannot write message.");
+                if let Err(err) = result {
+                    report_handler_error(&state.node_id, &src, context.msg_id, err.as_ref());
+                }
             }
             Err(TryRecvError::Empty) => {
                 if let Some(response) = state.to_send.pop_front() {
                     write_node_message(&response).expect("Cannot write message.");
                 }
             }
-            Err(TryRecvError::Disconnected) => panic!("Internal error"),
+            Err(TryRecvError::Disconnected) => {
+                // Stdin is closed, whatever is still queued goes out before exiting.
+                for response in state.to_send.drain(..) {
+                    write_node_message(&response).expect("Cannot write message.");
+                }
+                return;
+            }
         }
     }
 }
 
+/// Handle `request`, pushing every message it emits to `outbox`, see `Outbox` for the order
+/// they are written in.
 fn handle_message(
     request: NodeMessage<RequestType>,
     state: &mut GlobalState,
+    outbox: &mut Outbox,
 ) -> Result<(), Box<dyn std::error::Error>> {
     match request.body {
         RequestType::BroadcastOk(broadcast_ok) => {
-            state
-                .past_broadcast
-                .insert((request.src, broadcast_ok.msg_id.unwrap()));
+            state.past_broadcast.insert((
+                request.src,
+                broadcast_ok
+                    .acked_value
+                    .ok_or("broadcast_ok without an acked_value")?,
+            ));
         }
         RequestType::Read(read_body) => {
-            let n = NodeMessage {
-                src: state.node_id.clone(),
-                dest: request.src,
-                body: ResponseBody::Read(ReadResponse {
+            let n = NodeMessage::new(
+                state.node_id.clone(),
+                request.src,
+                ResponseBody::Read(ReadResponse {
                     _type: "read_ok".into(),
                     messages: state.values.iter().copied().collect(),
                     in_reply_to: read_body.msg_id,
                     msg_id: None,
                 }),
-            };
-            write_node_message(&n).expect("Cannot write message.");
+            );
+            outbox.push(n)?;
         }
         RequestType::Broadcast(broadcast_request) => {
             state.values.insert(broadcast_request.message);
-            let n = NodeMessage {
-                src: state.node_id.clone(),
-                dest: request.src.clone(),
-                body: ResponseBody::Basic(BasicResponse {
+            let n = NodeMessage::new(
+                state.node_id.clone(),
+                request.src.clone(),
+                ResponseBody::Basic(BasicResponse {
                     _type: "broadcast_ok".into(),
                     in_reply_to: broadcast_request.msg_id,
-                    msg_id: Some(broadcast_request.message),
+                    msg_id: None,
+                    acked_value: Some(broadcast_request.message),
                 }),
-            };
-            write_node_message(&n).expect("Cannot write message.");
-
-            for neighborhood_node_id in state.neighborhood.iter() {
-                if state
-                    .past_broadcast
-                    .contains(&(neighborhood_node_id.clone(), broadcast_request.message))
-                {
-                    continue;
-                }
-                let node = NodeMessage {
-                    src: state.node_id.clone(),
-                    dest: neighborhood_node_id.clone(),
-                    body: ResponseBody::Broadcast(BroadcastResponse {
-                        _type: "broadcast".into(),
-                        in_reply_to: None,
-                        msg_id: None,
-                        message: broadcast_request.message,
-                    }),
-                };
-
-                write_node_message(&node).unwrap();
-            }
+            );
+            // The ack goes out before the fan-out.
+            outbox.push(n)?;
+
+            let fan_out = state
+                .neighborhood
+                .iter()
+                .filter(|neighborhood_node_id| {
+                    !state
+                        .past_broadcast
+                        .contains(&((*neighborhood_node_id).clone(), broadcast_request.message))
+                })
+                .map(|neighborhood_node_id| {
+                    NodeMessage::new(
+                        state.node_id.clone(),
+                        neighborhood_node_id.clone(),
+                        ResponseBody::Broadcast(BroadcastResponse {
+                            _type: "broadcast".into(),
+                            in_reply_to: None,
+                            msg_id: None,
+                            message: broadcast_request.message,
+                        }),
+                    )
+                });
+            outbox.extend(fan_out)?;
         }
         RequestType::Topology(mut topology) => {
-            if topology.topology.contains_key(&state.node_id) {
-                state.neighborhood = topology.topology.remove(&state.node_id).unwrap();
+            if let Some(neighborhood) = topology.topology.remove(&state.node_id) {
+                state.neighborhood = state
+                    .membership
+                    .known_neighborhood(neighborhood, ALLOW_EMPTY_NEIGHBORHOOD)?;
             }
-            let n = NodeMessage {
-                src: state.node_id.clone(),
-                dest: request.src,
-                body: ResponseBody::Basic(BasicResponse {
+            let n = NodeMessage::new(
+                state.node_id.clone(),
+                request.src,
+                ResponseBody::Basic(BasicResponse {
                     _type: "topology_ok".into(),
                     in_reply_to: topology.msg_id,
                     msg_id: None,
+                    acked_value: None,
                 }),
-            };
-            write_node_message(&n).expect("Cannot write message.");
+            );
+            outbox.push(n)?;
         }
     };
 
@@ -117,6 +145,7 @@ fn handle_message(
 
 struct GlobalState {
     node_id: String,
+    membership: Membership,
     neighborhood: Vec<String>,
     values: HashSet<u64>,
 
@@ -124,18 +153,6 @@ struct GlobalState {
     past_broadcast: HashSet<(String, u64)>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
-struct PendingBroadcast {
-    src_node: String,
-    message: u64,
-}
-
-#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
-struct BroadcastSent {
-    destination_node: String,
-    message: u64,
-}
-
 #[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(untagged)]
 enum ResponseBody {
@@ -154,7 +171,7 @@ enum RequestType {
     #[serde(rename = "topology")]
     Topology(TopologyBody),
     #[serde(rename = "broadcast_ok")]
-    BroadcastOk(ReadBody),
+    BroadcastOk(BroadcastOkBody),
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -174,6 +191,17 @@ struct ReadBody {
     msg_id: Option<u64>,
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct BroadcastOkBody {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    in_reply_to: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    msg_id: Option<u64>,
+    /// Value being acknowledged, `msg_id` stays free for actual message ids.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    acked_value: Option<u64>,
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 struct TopologyBody {
     topology: HashMap<String, Vec<String>>,
@@ -191,6 +219,9 @@ struct BasicResponse {
     in_reply_to: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     msg_id: Option<u64>,
+    /// Value a broadcast_ok acknowledges.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    acked_value: Option<u64>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
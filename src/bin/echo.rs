@@ -1,29 +1,33 @@
-use distributed_systems::maelstrom::*;
-use serde::{Deserialize, Serialize};
+use distributed_systems::prelude::*;
+use serde_json::Value;
 
-fn main() {
-    let node = EchoNode { node_id: "".to_string() };
-    run_node_event_loop(node);
+fn main() -> Result<(), NodeRuntimeError> {
+    let node = EchoNode {
+        node_id: "".to_string(),
+    };
+    run_node_event_loop(node)
 }
 
 impl MaelstromNode for EchoNode {
     type MessageBody = EchoRequest;
 
-    fn initialize(&mut self, node_id: String) {
+    fn initialize(&mut self, node_id: String, _node_ids: Vec<String>) {
         self.node_id = node_id;
     }
 
-    fn handle_message(&mut self, msg: NodeMessage<EchoRequest>) -> Result<(), Box<dyn std::error::Error>> {
-        let new_msg: NodeMessage<EchoResponse> = NodeMessage {
-            dest: msg.src,
-            src: self.node_id.to_owned(),
-            body: EchoResponse {
-                _type: "echo_ok".into(),
-                in_reply_to: msg.body.msg_id,
-                echo: msg.body.echo,
-            },
+    fn respond(
+        &mut self,
+        mut msg: NodeMessage<EchoRequest>,
+    ) -> Result<Vec<OutgoingMessage>, Box<dyn std::error::Error>> {
+        node_log!(self.node_id, "Received {}", msg.debug_line());
+        let response = EchoResponse {
+            _type: "echo_ok".into(),
+            in_reply_to: msg.body.msg_id,
+            echo: std::mem::take(&mut msg.body.echo),
         };
-        write_node_message(&new_msg)
+        Ok(vec![response
+            .into_reply(&self.node_id, &msg)
+            .into_outgoing()?])
     }
 }
 
@@ -36,7 +40,8 @@ pub struct EchoRequest {
     #[serde(rename = "type")]
     pub _type: String,
     pub msg_id: u64,
-    pub echo: String,
+    /// Any JSON payload, echoed back untouched.
+    pub echo: Value,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -44,5 +49,7 @@ pub struct EchoResponse {
     #[serde(rename = "type")]
     pub _type: String,
     pub in_reply_to: u64,
-    pub echo: String,
+    pub echo: Value,
 }
+
+impl IntoReply for EchoResponse {}
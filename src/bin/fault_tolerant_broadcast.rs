@@ -1,17 +1,23 @@
 use std::collections::{HashMap, HashSet, VecDeque};
-use std::sync::mpsc::{channel, TryRecvError};
-use std::thread;
+use std::sync::mpsc::TryRecvError;
 use std::time::{Duration, Instant};
 
+use distributed_systems::logging::enter_message;
+use distributed_systems::maelstrom::error::{ErrorReply, NodeError};
+use distributed_systems::maelstrom::membership::{strict_mode_from_env, Membership};
 use distributed_systems::maelstrom::*;
+use distributed_systems::node_log;
 use serde::{Deserialize, Serialize};
 
 const WAIT_TIME: Duration = Duration::from_millis(500);
+/// Whether a topology leaving this node without any known neighbor is accepted.
+const ALLOW_EMPTY_NEIGHBORHOOD: bool = true;
 
 fn main() {
-    let node_id = get_node_id().unwrap();
+    let membership = get_membership().unwrap();
     let mut state = GlobalState {
-        node_id,
+        node_id: membership.node_id().to_string(),
+        membership,
         neighborhood: vec![],
         values: HashSet::new(),
 
@@ -19,19 +25,20 @@ fn main() {
         to_send: VecDeque::new(),
         past_broadcast: HashSet::new(),
         resend_timer: Instant::now(),
+        suspected_down: HashSet::new(),
     };
-    let (tx, rx) = channel();
-
-    thread::spawn(move || loop {
-        let request: NodeMessage<RequestType> =
-            read_node_message().expect("Could not read request");
-        tx.send(request).unwrap();
-    });
+    let strict = strict_mode_from_env();
+    let rx = spawn_request_reader::<RequestType>();
 
     loop {
         match rx.try_recv() {
-            Ok(node_message) => {
-                handle_message(node_message, &mut state).expect("Could not parse message");
+            Ok((node_message, _)) if strict && !state.membership.accepts(&node_message.src) => {}
+            Ok((node_message, context)) => {
+                let src = node_message.src.clone();
+                let _scope = enter_message(context);
+                if let Err(err) = handle_message(node_message, &mut state) {
+                    report_handler_error(&state.node_id, &src, context.msg_id, err.as_ref());
+                }
             }
             Err(TryRecvError::Empty) => {
                 if state.sending_index >= state.to_send.len() {
@@ -42,7 +49,11 @@ fn main() {
                     let message = response.body.message;
                     if state.past_broadcast.contains(&(dest_node, message)) {
                         state.to_send.remove(state.sending_index).unwrap();
-                        eprintln!("Removed from to_send: {}", state.to_send.len());
+                        node_log!(
+                            state.node_id,
+                            "Removed from to_send: {}",
+                            state.to_send.len()
+                        );
                     } else if state.resend_timer.elapsed() > WAIT_TIME {
                         write_node_message(response).expect("Cannot write resend message.");
                         state.sending_index += 1;
@@ -50,7 +61,8 @@ fn main() {
                     }
                 }
             }
-            Err(TryRecvError::Disconnected) => panic!("Internal error"),
+            // Stdin is closed, no broadcast_ok can arrive anymore to stop the resends.
+            Err(TryRecvError::Disconnected) => return,
         }
     }
 }
@@ -60,73 +72,101 @@ fn handle_message(
     state: &mut GlobalState,
 ) -> Result<(), Box<dyn std::error::Error>> {
     match request.body {
+        RequestType::Error(error) => {
+            if error.code == NodeError::NodeNotFound && state.neighborhood.contains(&request.src) {
+                state.suspected_down.insert(request.src.clone());
+                state.to_send.retain(|message| message.dest != request.src);
+                state.sending_index = 0;
+                node_log!(
+                    state.node_id,
+                    "Neighbor {} not found, no longer sending to it: {:?}",
+                    request.src,
+                    error.text
+                );
+            } else {
+                node_log!(
+                    state.node_id,
+                    "Received error from {}: {:?}",
+                    request.src,
+                    error
+                );
+            }
+        }
         RequestType::BroadcastOk(broadcast_ok) => {
-            state
-                .past_broadcast
-                .insert((request.src, broadcast_ok.msg_id.unwrap()));
+            state.past_broadcast.insert((
+                request.src,
+                broadcast_ok
+                    .acked_value
+                    .ok_or("broadcast_ok without an acked_value")?,
+            ));
             state.resend_timer = Instant::now() - 2 * WAIT_TIME;
         }
         RequestType::Read(read_body) => {
-            let n = NodeMessage {
-                src: state.node_id.clone(),
-                dest: request.src,
-                body: ResponseBody::Read(ReadResponse {
+            let n = NodeMessage::new(
+                state.node_id.clone(),
+                request.src,
+                ResponseBody::Read(ReadResponse {
                     _type: "read_ok".into(),
                     messages: state.values.iter().copied().collect(),
                     in_reply_to: read_body.msg_id,
                     msg_id: None,
                 }),
-            };
+            );
             write_node_message(&n).expect("Cannot write message.");
         }
         RequestType::Broadcast(broadcast_request) => {
             state.values.insert(broadcast_request.message);
-            let n = NodeMessage {
-                src: state.node_id.clone(),
-                dest: request.src.clone(),
-                body: ResponseBody::Basic(BasicResponse {
+            let n = NodeMessage::new(
+                state.node_id.clone(),
+                request.src.clone(),
+                ResponseBody::Basic(BasicResponse {
                     _type: "broadcast_ok".into(),
                     in_reply_to: broadcast_request.msg_id,
-                    msg_id: Some(broadcast_request.message),
+                    msg_id: None,
+                    acked_value: Some(broadcast_request.message),
                 }),
-            };
+            );
             write_node_message(&n).expect("Cannot write message.");
 
             for neighborhood_node_id in state.neighborhood.iter() {
-                if state
-                    .past_broadcast
-                    .contains(&(neighborhood_node_id.clone(), broadcast_request.message))
+                if state.suspected_down.contains(neighborhood_node_id)
+                    || state
+                        .past_broadcast
+                        .contains(&(neighborhood_node_id.clone(), broadcast_request.message))
                 {
                     continue;
                 }
-                let node = NodeMessage {
-                    src: state.node_id.clone(),
-                    dest: neighborhood_node_id.clone(),
-                    body: BroadcastResponse {
+                let node = NodeMessage::new(
+                    state.node_id.clone(),
+                    neighborhood_node_id.clone(),
+                    BroadcastResponse {
                         _type: "broadcast".into(),
                         in_reply_to: None,
                         msg_id: None,
                         message: broadcast_request.message,
                     },
-                };
+                );
 
                 state.to_send.push_back(node.clone());
                 write_node_message(&node).unwrap();
             }
         }
         RequestType::Topology(mut topology) => {
-            if topology.topology.contains_key(&state.node_id) {
-                state.neighborhood = topology.topology.remove(&state.node_id).unwrap();
+            if let Some(neighborhood) = topology.topology.remove(&state.node_id) {
+                state.neighborhood = state
+                    .membership
+                    .known_neighborhood(neighborhood, ALLOW_EMPTY_NEIGHBORHOOD)?;
             }
-            let n = NodeMessage {
-                src: state.node_id.clone(),
-                dest: request.src,
-                body: ResponseBody::Basic(BasicResponse {
+            let n = NodeMessage::new(
+                state.node_id.clone(),
+                request.src,
+                ResponseBody::Basic(BasicResponse {
                     _type: "topology_ok".into(),
                     in_reply_to: topology.msg_id,
                     msg_id: None,
+                    acked_value: None,
                 }),
-            };
+            );
             write_node_message(&n).expect("Cannot write message.");
         }
     };
@@ -136,6 +176,7 @@ fn handle_message(
 
 struct GlobalState {
     node_id: String,
+    membership: Membership,
     neighborhood: Vec<String>,
     values: HashSet<u64>,
 
@@ -143,18 +184,8 @@ struct GlobalState {
     to_send: VecDeque<NodeMessage<BroadcastResponse>>,
     past_broadcast: HashSet<(String, u64)>,
     resend_timer: Instant,
-}
-
-#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
-struct PendingBroadcast {
-    src_node: String,
-    message: u64,
-}
-
-#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
-struct BroadcastSent {
-    destination_node: String,
-    message: u64,
+    /// Neighbors Maelstrom reported as unknown, nothing is sent to them anymore.
+    suspected_down: HashSet<String>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -175,7 +206,11 @@ enum RequestType {
     #[serde(rename = "topology")]
     Topology(TopologyBody),
     #[serde(rename = "broadcast_ok")]
-    BroadcastOk(ReadBody),
+    BroadcastOk(BroadcastOkBody),
+    /// Answers something we sent, e.g. `NodeNotFound` for a broadcast to a node Maelstrom
+    /// does not know.
+    #[serde(rename = "error")]
+    Error(ErrorReply),
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -195,6 +230,17 @@ struct ReadBody {
     msg_id: Option<u64>,
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct BroadcastOkBody {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    in_reply_to: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    msg_id: Option<u64>,
+    /// Value being acknowledged, `msg_id` stays free for actual message ids.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    acked_value: Option<u64>,
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 struct TopologyBody {
     topology: HashMap<String, Vec<String>>,
@@ -212,6 +258,9 @@ struct BasicResponse {
     in_reply_to: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     msg_id: Option<u64>,
+    /// Value a broadcast_ok acknowledges.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    acked_value: Option<u64>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
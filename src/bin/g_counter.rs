@@ -1,14 +1,50 @@
-use std::collections::VecDeque;
-use std::sync::mpsc::{channel, TryRecvError};
-use std::thread;
-
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::time::Duration;
+#[cfg(feature = "metrics")]
+use std::time::Instant;
+
+use distributed_systems::logging;
+use distributed_systems::maelstrom::backoff::Backoff;
+use distributed_systems::maelstrom::control::{SetParamBody, CONTROL_ENABLED};
+use distributed_systems::maelstrom::error::{NodeError, NodeRuntimeError};
+#[cfg(feature = "metrics")]
+use distributed_systems::maelstrom::histogram::Histogram;
+use distributed_systems::maelstrom::kv_service::{
+    KvService, RealKvService, LINEARIZABLE_READS_ENV,
+};
+use distributed_systems::maelstrom::membership::Membership;
+use distributed_systems::maelstrom::pending::Pending;
+use distributed_systems::maelstrom::readiness::Readiness;
 use distributed_systems::maelstrom::seq_kv::*;
 use distributed_systems::maelstrom::*;
+use distributed_systems::{dispatch, node_log};
 use serde::{Deserialize, Serialize};
 
 const READ_OK_WAIT_MS: u64 = 400;
 const PENDING_ADD_WAIT_MS: u64 = 200;
-const NODE_COUNT: u64 = 3;
+const FREE_CYCLE_WAIT_MS: u64 = 500;
+/// Let our CAS create the counter key when it is missing. With `false` the key must be created
+/// by someone else first, and a missing key is reported as `KeyDoesNotExist`.
+const CAS_CREATE_IF_NOT_EXISTS: bool = true;
+/// Retry delays for a CAS rejected because seq-kv is temporarily unavailable.
+const KV_BACKOFF_BASE_MS: u64 = 50;
+const KV_BACKOFF_MAX_MS: u64 = 2000;
+/// How long a read barrier waits for seq-kv before sending another read.
+const READ_BARRIER_WAIT_MS: u64 = 300;
+/// At most this many reads wait `READ_OK_WAIT_MS`, later ones are answered right away with the
+/// current, possibly stale, count so a read flood cannot grow the queue without bounds.
+const MAX_DEFERRED_READS: usize = 1024;
+
+/// When a `membership` message adds peers, read the counter again and send it to every peer,
+/// so the new nodes catch up right away instead of on the next add.
+const SYNC_ON_MEMBERSHIP_CHANGE: bool = true;
+/// seq-kv key holding the counter.
+const COUNTER_KEY: &str = "sum";
+
+const FREE_CYCLE_TIMER: TimerKey = "free_cycle";
+/// Parameters a `set_param` control message can change: `read_ok_wait_ms` replaces
+/// `READ_OK_WAIT_MS`, including for the reads already deferred.
+const PARAMS: [&str; 1] = ["read_ok_wait_ms"];
 
 /*
 1. SeqKV might hide state from the nodes. We need to sync all the nodes before a read.
@@ -19,115 +55,231 @@ generate 1000 messages + Ack. That sould not be a lot of data for a low-level sy
 
 */
 
-fn main() {
-    let node_id = get_node_id().unwrap();
-    let (tx, rx) = channel();
-    let mut handler = MaelstromHandler::new(node_id);
-    let mut free_cycle_timer = Timer::from_millis(500);
-
-    thread::spawn(move || loop {
-        let request: NodeMessage<RequestType> =
-            read_node_message().expect("Could not read request");
-        tx.send(request).unwrap();
-    });
-    loop {
-        match rx.try_recv() {
-            Ok(node_message) => {
-                handler
-                    .handle_message(node_message)
-                    .expect("Could not parse message");
-            }
-            Err(TryRecvError::Empty) => {
-                if free_cycle_timer.is_done() {
-                    handler.handle_free_cycle();
-                    free_cycle_timer.reset();
-                }
-            }
-            Err(TryRecvError::Disconnected) => panic!("Internal error"),
-        }
-    }
+fn main() -> Result<(), NodeRuntimeError> {
+    run_node_event_loop(MaelstromHandler::new())
 }
 
 struct MaelstromHandler {
     node_id: String,
     count: u64,
     cas_id_counter: u64,
-    pending_add: PendingAdd,
+    /// Sum of the deltas acknowledged to clients but not yet stored in seq-kv.
+    pending_delta: u64,
+    /// CAS requests in flight, with the delta each one carries.
+    pending_cas: Pending<u64>,
+    /// CAS requests that timed out, with the delta each one carried. seq-kv may still have
+    /// applied them, see `handle_cas_ok`.
+    expired_cas: HashMap<u64, u64>,
+    kv_backoff: Backoff,
     pending_read_ok: VecDeque<PendingReadOk>,
-    other_nodes: Vec<String>,
+    /// How long a read is deferred, `READ_OK_WAIT_MS` unless changed by a `set_param`.
+    read_ok_wait_ms: u64,
+    /// Longest `pending_read_ok` has been.
+    pending_read_ok_peak: usize,
+    /// Client reads waiting on a seq-kv read, by the msg_id of that read.
+    pending_kv_reads: Pending<(String, Option<u64>)>,
+    /// Answer reads only after a fresh seq-kv read, so they reflect every add confirmed before
+    /// the read began, instead of waiting `READ_OK_WAIT_MS` for the peers to sync. See
+    /// `LINEARIZABLE_READS_ENV`.
+    linearizable_reads: bool,
+    membership: Membership,
+    /// Send the count to every peer once the next seq-kv read is answered.
+    sync_peers_on_read: bool,
+    /// Where the counter is stored, seq-kv or lin-kv (see `KV_SERVICE_ENV`) unless a test swaps it
+    /// for another service.
+    kv: Box<dyn KvService>,
+    /// Client adds and reads held until the initial seq-kv read, see `WAIT_FOR_SYNC_ENV`.
+    readiness: Readiness<NodeMessage<RequestType>>,
+    /// msg_id of the initial seq-kv read, resent every free cycle until answered.
+    initial_sync: Option<u64>,
+    /// Deltas each node stored in seq-kv, ours as acknowledged by our CAS and the peers' as
+    /// they last reported along their read_ok. Only used to answer breakdown reads.
+    contributions: HashMap<String, u64>,
+    #[cfg(feature = "metrics")]
+    add_latency: Histogram,
+    #[cfg(feature = "metrics")]
+    read_latency: Histogram,
 }
 
 #[derive(Debug, Clone)]
-struct PendingAdd {
+struct PendingReadOk {
     timer: Timer,
-    msg_id: Option<u64>,
-    value: u64,
+    #[cfg(feature = "metrics")]
+    received: Instant,
+    message_data: (String, Option<u64>),
 }
 
-impl PendingAdd {
-    pub fn new(value: u64) -> PendingAdd {
-        PendingAdd {
-            timer: Timer::from_millis(PENDING_ADD_WAIT_MS),
-            msg_id: None,
-            value,
+impl MaelstromNode for MaelstromHandler {
+    type MessageBody = RequestType;
+
+    fn initialize(&mut self, node_id: String, node_ids: Vec<String>) {
+        self.membership = Membership::new(node_id.clone(), node_ids)
+            .expect("Node is not part of the counter cluster.");
+        self.node_id = node_id;
+    }
+
+    fn handle_message(
+        &mut self,
+        request: NodeMessage<RequestType>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let request = if matches!(request.body, RequestType::Add(_) | RequestType::Read(_)) {
+            match self.readiness.hold(request) {
+                Some(request) => request,
+                None => return Ok(()),
+            }
+        } else {
+            request
+        };
+        dispatch!(request, self, RequestType {
+            Add(src) => handle_add,
+            Read(src) => handle_read,
+            SeqKVError => handle_seq_kv_error,
+            CasOk => handle_cas_ok,
+            ReadOk(src) => handle_read_ok_message,
+            PendingSummary(src) => handle_pending_summary,
+            Membership(src) => handle_membership,
+            SetParam(src) => handle_set_param,
+        })
+    }
+
+    fn register_timers(&mut self, timers: &mut TimerWheel) {
+        timers.register(FREE_CYCLE_TIMER, FREE_CYCLE_WAIT_MS);
+    }
+
+    fn handle_disconnected_queue(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        // The initial sync can no longer be answered, the held requests get what we have.
+        for request in self.readiness.mark_ready() {
+            self.handle_message(request)?;
+        }
+        // Whatever did not make it out during the shutdown grace period is answered now,
+        // rather than leaving the clients hanging.
+        for pending_read_ok in std::mem::take(&mut self.pending_read_ok) {
+            let (source, msg_id) = pending_read_ok.message_data;
+            self.send_read_ok(&source, msg_id);
+        }
+
+        #[cfg(feature = "metrics")]
+        node_log!(
+            self.node_id,
+            "add latency ({} ops): {}",
+            self.add_latency.count(),
+            self.add_latency
+        );
+        #[cfg(feature = "metrics")]
+        node_log!(
+            self.node_id,
+            "read latency ({} ops): {}",
+            self.read_latency.count(),
+            self.read_latency
+        );
+        logging::dump_recent_logs();
+        Ok(())
+    }
+
+    fn on_shutdown(&mut self) {
+        node_log!(self.node_id, "Shutting down, final count: {}", self.count);
+    }
+
+    fn describe_pending_work(&self) -> Option<String> {
+        serde_json::to_string(&self.pending_summary()).ok()
+    }
+
+    fn handle_empty_queue(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.readiness.is_ready() && self.initial_sync.is_none() {
+            self.send_initial_sync()?;
         }
+        if self.kv_backoff.take_ready() {
+            self.retry_pending_cas()?;
+        }
+        Ok(())
     }
-}
 
-#[derive(Debug, Clone)]
-struct PendingReadOk {
-    timer: Timer,
-    message_data: (String, Option<u64>),
+    /// Only the delayed read_ok can still be flushed at shutdown, the seq-kv RPCs need
+    /// replies that can no longer arrive once stdin is closed.
+    fn has_pending_work(&self) -> bool {
+        !self.pending_read_ok.is_empty()
+    }
+
+    fn handle_timeout(&mut self, timer_key: TimerKey) -> Result<(), Box<dyn std::error::Error>> {
+        if timer_key == FREE_CYCLE_TIMER {
+            self.handle_free_cycle()?;
+        }
+        Ok(())
+    }
 }
 
 impl MaelstromHandler {
-    fn new(node_id: String) -> Self {
-        let system_nodes = (0..NODE_COUNT)
-            .map(|v| format!("n{v}"))
-            .filter(|v| v != &node_id)
-            .collect();
+    fn new() -> Self {
         MaelstromHandler {
-            node_id: node_id.clone(),
+            node_id: String::new(),
             count: 0,
             cas_id_counter: 0,
-            pending_add: PendingAdd::new(0),
+            pending_delta: 0,
+            pending_cas: Pending::new(PENDING_ADD_WAIT_MS),
+            expired_cas: HashMap::new(),
+            kv_backoff: Backoff::new(KV_BACKOFF_BASE_MS, KV_BACKOFF_MAX_MS),
             pending_read_ok: VecDeque::new(),
-            other_nodes: system_nodes,
+            read_ok_wait_ms: READ_OK_WAIT_MS,
+            pending_read_ok_peak: 0,
+            pending_kv_reads: Pending::new(READ_BARRIER_WAIT_MS),
+            linearizable_reads: std::env::var(LINEARIZABLE_READS_ENV)
+                .is_ok_and(|enabled| enabled == "1" || enabled == "true"),
+            membership: Membership::default(),
+            sync_peers_on_read: false,
+            kv: Box::new(RealKvService::from_env().expect("Invalid KV service.")),
+            readiness: Readiness::from_env(),
+            initial_sync: None,
+            contributions: HashMap::new(),
+            #[cfg(feature = "metrics")]
+            add_latency: Histogram::default(),
+            #[cfg(feature = "metrics")]
+            read_latency: Histogram::default(),
         }
     }
 
-    fn handle_message(
+    /// A read_ok from seq-kv, or from a peer along with its own contribution to the count.
+    fn handle_read_ok_message(
         &mut self,
-        request: NodeMessage<RequestType>,
+        src: String,
+        read_ok: ReadOkBody,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        match request.body {
-            RequestType::Add(body) => self.handle_add(request.src, body),
-            RequestType::Read(body) => self.handle_read(request.src, body),
-            RequestType::SeqKVError(err) => self.handle_seq_kv_error(err),
-            RequestType::CasOk(cas_ok) => self.handle_cas_ok(cas_ok),
-            RequestType::ReadOk(read_ok) => self.handle_read_ok(read_ok),
+        if let Some(contributed) = read_ok.contributed {
+            self.contributions.insert(src, contributed);
         }
+        self.handle_read_ok(read_ok.into_kv_read())
     }
 
     fn handle_read_ok(
         &mut self,
         read_ok: SeqKVReadResponse,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        eprintln!(
-            "{} [{}] Received seq_kv_read_ok({})",
-            get_ts(),
-            self.node_id,
-            self.count
-        );
+        node_log!(self.node_id, "Received seq_kv_read_ok({})", self.count);
         if read_ok.value > self.count {
             self.count = read_ok.value;
-            eprintln!(
-                "{} [{}] replaced count with read_ok value: {}",
-                get_ts(),
+            node_log!(
                 self.node_id,
+                "replaced count with read_ok value: {}",
                 self.count
             )
         }
+
+        if std::mem::take(&mut self.sync_peers_on_read) {
+            let peers: Vec<String> = self.membership.peers().map(str::to_string).collect();
+            for n_id in peers.iter() {
+                self.send_read_ok(n_id, None);
+            }
+        }
+
+        let client = read_ok
+            .in_reply_to
+            .and_then(|id| self.pending_kv_reads.take(id));
+        if let Some((src, msg_id)) = client {
+            self.send_read_ok(&src, msg_id);
+        }
+
+        if read_ok.in_reply_to.is_some() && read_ok.in_reply_to == self.initial_sync {
+            self.finish_initial_sync()?;
+        }
         Ok(())
     }
 
@@ -135,113 +287,195 @@ impl MaelstromHandler {
         &mut self,
         cas_ok: SeqKVNoDataResponse,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        if cas_ok.in_reply_to == self.pending_add.msg_id {
-            self.count += self.pending_add.value;
-            self.pending_add.value = 0;
-            self.pending_add.msg_id = None;
-        } else {
-            panic!("We should not received CAS message from other nodes.");
-        }
+        let delta = match cas_ok.in_reply_to.and_then(|id| self.pending_cas.take(id)) {
+            Some(delta) => delta,
+            None => {
+                // The CAS timed out before seq-kv answered: its delta is stored after all, so
+                // it must not be sent again. The count may have moved further, read it again.
+                let late = cas_ok
+                    .in_reply_to
+                    .and_then(|id| self.expired_cas.remove(&id));
+                if let Some(delta) = late {
+                    self.pending_delta = checked_sub(self.pending_delta, delta)?;
+                    let contributed = self.contributions.entry(self.node_id.clone()).or_default();
+                    *contributed = checked_add(*contributed, delta)?;
+                }
+                node_log!(
+                    self.node_id,
+                    "Late seq_kv_cas_ok for {:?} carrying {:?}, reading the count again",
+                    cas_ok.in_reply_to,
+                    late
+                );
+                return self.send_seq_kv_read(None);
+            }
+        };
+        self.count = checked_add(self.count, delta)?;
+        self.pending_delta = checked_sub(self.pending_delta, delta)?;
+        let contributed = self.contributions.entry(self.node_id.clone()).or_default();
+        *contributed = checked_add(*contributed, delta)?;
+        self.kv_backoff.reset();
 
-        eprintln!(
-            "{} [{}] Received seq_kv_cas_ok, new count: {}",
-            get_ts(),
+        node_log!(
             self.node_id,
+            "Received seq_kv_cas_ok, new count: {}",
             self.count
         );
 
-        for n_id in self.other_nodes.iter() {
+        for n_id in self.membership.peers() {
             self.send_read_ok(n_id, None);
         }
 
         Ok(())
     }
 
-    fn handle_free_cycle(&mut self) {
-        eprintln!(
-            "{} [{}] Pending to Add: {}",
-            get_ts(),
-            self.node_id,
-            self.pending_add.value
-        );
+    fn handle_free_cycle(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        node_log!(self.node_id, "Pending to Add: {}", self.pending_delta);
+
+        if !self.readiness.is_ready() {
+            self.send_initial_sync()?;
+        }
 
         let has_pending_send_ok = self
             .pending_read_ok
             .front()
-            .map_or(false, |p_rok| p_rok.timer.is_done());
+            .is_some_and(|p_rok| p_rok.timer.is_done());
         if has_pending_send_ok {
             if let Some(pending_read_ok) = self.pending_read_ok.pop_front() {
                 let (source, msg_id) = pending_read_ok.message_data;
                 self.send_read_ok(&source, msg_id);
-                return;
+                #[cfg(feature = "metrics")]
+                self.read_latency.record(pending_read_ok.received.elapsed());
+                return Ok(());
             }
         }
 
-        let new_id = self.get_id();
-        if self.pending_add.value > 0 && self.pending_add.timer.is_done() {
-            self.send_seq_kv_compare_and_swap(
-                Some(self.count),
-                Some(self.count + self.pending_add.value),
-                new_id,
+        for (_, client) in self.pending_kv_reads.expired() {
+            self.send_read_barrier(client)?;
+        }
+
+        for (msg_id, delta) in self.pending_cas.expired() {
+            node_log!(
+                self.node_id,
+                "seq_kv_cas {} carrying {} timed out",
+                msg_id,
+                delta
             );
-            self.pending_add.msg_id = Some(new_id);
-            self.pending_add.timer.reset();
+            self.expired_cas.insert(msg_id, delta);
+        }
+
+        if !self.kv_backoff.is_waiting() {
+            self.retry_pending_cas()?;
         }
+
+        Ok(())
+    }
+
+    /// Send the deltas not stored yet, unless a CAS carrying them is already in flight.
+    fn retry_pending_cas(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.pending_delta > 0 && self.pending_cas.is_empty() {
+            let new_id = self.get_id();
+            let to = Some(checked_add(self.count, self.pending_delta)?);
+            self.pending_cas.insert(new_id, self.pending_delta);
+            self.send_seq_kv_compare_and_swap(Some(self.count), to, new_id)?;
+        }
+
+        Ok(())
     }
 
     fn handle_seq_kv_error(
         &mut self,
         err: SeqKVErrorResponse,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        if err.in_reply_to == self.pending_add.msg_id && err.code == 22 {
-            self.pending_add.msg_id = None;
-            self.send_seq_kv_read();
-        } else {
-            eprintln!("{} [{}] seq-kv error: {:?}", get_ts(), self.node_id, err);
+        if err.in_reply_to.is_some() && err.in_reply_to == self.initial_sync {
+            // Nothing was ever added, there is nothing to sync. Otherwise the free cycle
+            // reads again.
+            if matches!(err.node_error(), NodeError::KeyDoesNotExist) {
+                self.finish_initial_sync()?;
+            }
+            return Ok(());
+        }
+        let pending_cas = err.in_reply_to.and_then(|id| self.pending_cas.take(id));
+        // A CAS that failed after timing out was never applied, its delta is still pending.
+        if let Some(cas_id) = err.in_reply_to {
+            self.expired_cas.remove(&cas_id);
+        }
+        if let Some(read_id) = err.in_reply_to {
+            if let Some((src, msg_id)) = self.pending_kv_reads.take(read_id) {
+                match err.node_error() {
+                    // Nothing was ever added.
+                    NodeError::KeyDoesNotExist => self.send_read_ok(&src, msg_id),
+                    // Keep waiting, the free cycle reads again once the barrier expires.
+                    _ => self.pending_kv_reads.insert(read_id, (src, msg_id)),
+                }
+                return Ok(());
+            }
+        }
+
+        match err.node_error() {
+            // Someone else moved or created the counter first: sync and let the free cycle
+            // try again.
+            NodeError::PreconditionFailed | NodeError::KeyAlreadyExists
+                if pending_cas.is_some() =>
+            {
+                self.send_seq_kv_read(None)?;
+            }
+            // seq-kv is down for now, nothing to sync: retry the same CAS after a backoff.
+            NodeError::TemporarilyUnavailable if pending_cas.is_some() => {
+                let delay_ms = self.kv_backoff.next_delay_ms();
+                self.kv_backoff.schedule();
+                node_log!(
+                    self.node_id,
+                    "seq-kv unavailable, retry {} in {}ms",
+                    self.kv_backoff.attempts(),
+                    delay_ms
+                );
+            }
+            node_error => {
+                node_log!(self.node_id, "seq-kv error {:?}: {:?}", node_error, err);
+            }
         }
 
         Ok(())
     }
 
     fn handle_add(&mut self, src: String, body: AddBody) -> Result<(), Box<dyn std::error::Error>> {
+        #[cfg(feature = "metrics")]
+        let received = Instant::now();
         let msg_id = self.get_id();
 
-        eprintln!(
-            "{} [{}] Received add({}) from {}",
-            get_ts(),
-            self.node_id,
-            body.delta,
-            src
-        );
-
-        let add_ok = NodeMessage {
-            src: self.node_id.clone(),
-            dest: src.clone(),
-            body: AddResponse {
+        // Check for overflow before acknowledging, so the client doesn't get an add_ok for
+        // a delta we cannot apply.
+        let delta = body.total_delta()?;
+        node_log!(self.node_id, "Received add({}) from {}", delta, src);
+        let pending_delta = checked_add(self.pending_delta, delta)?;
+        let to = Some(checked_add(self.count, pending_delta)?);
+
+        let add_ok = NodeMessage::new(
+            self.node_id.clone(),
+            src.clone(),
+            AddResponse {
                 _type: "add_ok".into(),
                 in_reply_to: body.msg_id,
                 msg_id: None,
             },
-        };
+        );
         self.send_add_ok(&src, add_ok);
+        #[cfg(feature = "metrics")]
+        self.add_latency.record(received.elapsed());
 
-        if body.delta == 0 {
+        if delta == 0 {
             return Ok(());
         }
 
-        self.pending_add.value += body.delta;
+        self.pending_delta = pending_delta;
 
         let from = if self.count == 0 {
             None
         } else {
             Some(self.count)
         };
-        let to = Some(self.count + self.pending_add.value);
-        self.send_seq_kv_compare_and_swap(from, to, msg_id);
-
-        self.pending_add.msg_id = Some(msg_id);
-
-        Ok(())
+        self.pending_cas.insert(msg_id, pending_delta);
+        self.send_seq_kv_compare_and_swap(from, to, msg_id)
     }
 
     fn handle_read(
@@ -249,75 +483,288 @@ impl MaelstromHandler {
         src: String,
         body: ReadBody,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        eprintln!(
-            "{} [{}] Received read from {}, replying soon.",
-            get_ts(),
+        node_log!(
             self.node_id,
+            "Received read from {}, replying soon.",
             src.clone()
         );
+        // A debugging aid, answered right away with whatever this node knows.
+        if body.breakdown {
+            self.send_read_breakdown(&src, body.msg_id);
+            return Ok(());
+        }
+        if self.linearizable_reads {
+            return self.send_read_barrier((src, body.msg_id));
+        }
+        if self.pending_read_ok.len() >= MAX_DEFERRED_READS {
+            node_log!(
+                self.node_id,
+                "{} reads already deferred, answering {} now",
+                self.pending_read_ok.len(),
+                src
+            );
+            self.send_read_ok(&src, body.msg_id);
+            return Ok(());
+        }
         self.pending_read_ok.push_back(PendingReadOk {
-            timer: Timer::from_millis(READ_OK_WAIT_MS),
+            timer: Timer::from_millis(self.read_ok_wait_ms),
+            #[cfg(feature = "metrics")]
+            received: Instant::now(),
             message_data: (src, body.msg_id),
         });
+        self.pending_read_ok_peak = self.pending_read_ok_peak.max(self.pending_read_ok.len());
         // self.send_seq_kv_read(); // Send a read to sync data before sending read_ok.
         Ok(())
     }
 
-    fn send_seq_kv_read(&self) {
-        let seq_kv_read = NodeMessage {
-            src: self.node_id.clone(),
-            dest: "seq-kv".to_string(),
-            body: SeqKVRequest::Read(SeqKVReadRequest {
-                in_reply_to: None,
-                msg_id: None,
-                key: "sum".to_string(),
-            }),
+    /// Read the counter from seq-kv before answering any client. A retry reuses the msg_id of
+    /// the read already in flight, so whichever is answered first ends the sync.
+    fn send_initial_sync(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let msg_id = match self.initial_sync {
+            Some(msg_id) => msg_id,
+            None => self.get_id(),
         };
-        write_node_message(&seq_kv_read).expect("Cannot write resend message.");
-        eprintln!("{} [{}] Sent seq_kv_read", get_ts(), self.node_id);
+        self.initial_sync = Some(msg_id);
+        node_log!(
+            self.node_id,
+            "Waiting for the initial sync, {} requests held",
+            self.readiness.held()
+        );
+        self.send_seq_kv_read(Some(msg_id))
+    }
+
+    /// The initial read was answered: handle the requests held until now.
+    fn finish_initial_sync(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.initial_sync = None;
+        let held = self.readiness.mark_ready();
+        node_log!(
+            self.node_id,
+            "Initial sync done, count: {}, handling {} held requests",
+            self.count,
+            held.len()
+        );
+        for request in held {
+            self.handle_message(request)?;
+        }
+        Ok(())
+    }
+
+    /// Read the counter from seq-kv and answer `client` once the value arrives.
+    fn send_read_barrier(
+        &mut self,
+        client: (String, Option<u64>),
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let msg_id = self.get_id();
+        self.pending_kv_reads.insert(msg_id, client);
+        self.send_seq_kv_read(Some(msg_id))
+    }
+
+    /// What the node is still waiting on, to debug a node that looks stuck.
+    fn pending_summary(&self) -> PendingSummary {
+        PendingSummary {
+            pending_delta: self.pending_delta,
+            pending_cas: self
+                .pending_cas
+                .iter()
+                .map(|(msg_id, delta)| (msg_id, *delta))
+                .collect(),
+            pending_reads: self
+                .pending_read_ok
+                .iter()
+                .map(|pending_read_ok| pending_read_ok.message_data.0.clone())
+                .chain(
+                    self.pending_kv_reads
+                        .iter()
+                        .map(|(_, (src, _))| src.clone()),
+                )
+                .collect(),
+            pending_reads_peak: self.pending_read_ok_peak,
+            kv_backoff_attempts: self.kv_backoff.attempts(),
+        }
+    }
+
+    /// Replace the peer set with the `node_ids` of a membership change.
+    fn handle_membership(
+        &mut self,
+        src: String,
+        body: MembershipBody,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut node_ids = body.node_ids;
+        if !node_ids.contains(&self.node_id) {
+            node_ids.push(self.node_id.clone());
+        }
+        let added = node_ids
+            .iter()
+            .any(|node_id| !self.membership.contains(node_id));
+        self.membership = Membership::new(self.node_id.clone(), node_ids)?;
+        node_log!(
+            self.node_id,
+            "Membership changed, peers: {:?}",
+            self.membership.peers().collect::<Vec<_>>()
+        );
+
+        let response = NodeMessage::new(
+            self.node_id.clone(),
+            src,
+            MembershipResponse {
+                _type: "membership_ok".into(),
+                in_reply_to: body.msg_id,
+            },
+        );
+        write_node_message(&response)?;
+
+        if SYNC_ON_MEMBERSHIP_CHANGE && added {
+            self.sync_peers_on_read = true;
+            self.send_seq_kv_read(None)?;
+        }
+        Ok(())
+    }
+
+    fn handle_set_param(
+        &mut self,
+        src: String,
+        body: SetParamBody,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if !CONTROL_ENABLED {
+            node_log!(
+                self.node_id,
+                "Ignoring set_param({}), built without the control feature",
+                body.name
+            );
+            return Ok(());
+        }
+        match body.name.as_str() {
+            "read_ok_wait_ms" => {
+                self.read_ok_wait_ms = body.value;
+                for pending_read_ok in self.pending_read_ok.iter_mut() {
+                    pending_read_ok
+                        .timer
+                        .set_duration(Duration::from_millis(body.value));
+                }
+            }
+            _ => return Err(body.unknown_param(&PARAMS)),
+        }
+        node_log!(self.node_id, "Set {} to {}ms", body.name, body.value);
+
+        write_node_message(&NodeMessage::new(self.node_id.clone(), src, body.ok()))
+    }
+
+    fn handle_pending_summary(
+        &mut self,
+        src: String,
+        body: ReadBody,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let response = NodeMessage::new(
+            self.node_id.clone(),
+            src,
+            PendingSummaryResponse {
+                _type: "pending_summary_ok".into(),
+                in_reply_to: body.msg_id,
+                pending: self.pending_summary(),
+            },
+        );
+        write_node_message(&response)
+    }
+
+    fn send_seq_kv_read(&mut self, msg_id: Option<u64>) -> Result<(), Box<dyn std::error::Error>> {
+        let reply = self.kv.read(&self.node_id, COUNTER_KEY, msg_id)?;
+        node_log!(self.node_id, "Sent seq_kv_read");
+        self.handle_kv_reply(reply)
     }
 
-    fn send_seq_kv_compare_and_swap(&self, from: Option<u64>, to: Option<u64>, msg_id: u64) {
-        let seq_kv_cas = NodeMessage {
-            src: self.node_id.clone(),
-            dest: "seq-kv".to_string(),
-            body: SeqKVRequest::CompareAndSwap(SeqKVCompareAndSwapRequest {
+    fn send_seq_kv_compare_and_swap(
+        &mut self,
+        from: Option<u64>,
+        to: Option<u64>,
+        msg_id: u64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let reply = self.kv.cas(
+            &self.node_id,
+            SeqKVCompareAndSwapRequest {
                 in_reply_to: None,
                 msg_id: Some(msg_id),
-                key: "sum".to_string(),
+                key: COUNTER_KEY.to_string(),
                 from,
                 to,
-                create_if_not_exists: true,
-            }),
-        };
-        write_node_message(&seq_kv_cas).expect("Cannot write resend message.");
-        eprintln!(
-            "{} [{}] Sent seq_kv_cas({:?},{:?})",
-            get_ts(),
-            self.node_id,
-            from,
-            to,
-        );
+                create_if_not_exists: CAS_CREATE_IF_NOT_EXISTS,
+            },
+        )?;
+        node_log!(self.node_id, "Sent seq_kv_cas({:?},{:?})", from, to);
+        self.handle_kv_reply(reply)
+    }
+
+    /// Handle a reply the KV service answered with right away, see `KvService`.
+    fn handle_kv_reply(
+        &mut self,
+        reply: Option<SeqKvReply>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match reply {
+            None => Ok(()),
+            Some(SeqKvReply::ReadOk(read_ok)) => self.handle_read_ok(read_ok),
+            Some(SeqKvReply::CasOk(cas_ok) | SeqKvReply::WriteOk(cas_ok)) => {
+                self.handle_cas_ok(cas_ok)
+            }
+            Some(SeqKvReply::Error(err)) => self.handle_seq_kv_error(err),
+        }
     }
 
     fn send_add_ok(&self, dst: &str, add_ok: NodeMessage<AddResponse>) {
         write_node_message(&add_ok).expect("Cannot write resend message.");
-        eprintln!("{} [{}] Sent add_ok to {}", get_ts(), self.node_id, dst);
+        node_log!(self.node_id, "Sent add_ok to {}", dst);
     }
 
     fn send_read_ok(&self, dst: &str, in_reply_to: Option<u64>) {
-        let response = NodeMessage {
-            src: self.node_id.clone(),
-            dest: dst.to_string(),
-            body: ReadResponse {
+        // Peers learn our contribution along with the count, for their breakdown reads.
+        let contributed = if self.membership.contains(dst) {
+            Some(self.own_contribution())
+        } else {
+            None
+        };
+        let response = NodeMessage::new(
+            self.node_id.clone(),
+            dst.to_string(),
+            ReadResponse {
                 _type: "read_ok".into(),
                 in_reply_to,
                 msg_id: None,
                 value: self.count,
+                contributed,
+                by_node: None,
+                unattributed: None,
             },
-        };
+        );
+        write_node_message(&response).expect("Cannot write read_ok message.");
+        node_log!(self.node_id, "Sent read_ok to {}", dst);
+    }
+
+    fn own_contribution(&self) -> u64 {
+        self.contributions.get(&self.node_id).copied().unwrap_or(0)
+    }
+
+    /// Answer a read with the count and the share of it each node added, see
+    /// `ReadBody::breakdown`.
+    fn send_read_breakdown(&self, dst: &str, in_reply_to: Option<u64>) {
+        let by_node: BTreeMap<String, u64> = self
+            .contributions
+            .iter()
+            .map(|(node_id, contributed)| (node_id.clone(), *contributed))
+            .collect();
+        let attributed = by_node.values().sum::<u64>();
+        let response = NodeMessage::new(
+            self.node_id.clone(),
+            dst.to_string(),
+            ReadResponse {
+                _type: "read_ok".into(),
+                in_reply_to,
+                msg_id: None,
+                value: self.count,
+                contributed: None,
+                by_node: Some(by_node),
+                unattributed: Some(self.count.saturating_sub(attributed)),
+            },
+        );
         write_node_message(&response).expect("Cannot write read_ok message.");
-        eprintln!("{} [{}] Sent read_ok to {}", get_ts(), self.node_id, dst);
+        node_log!(self.node_id, "Sent read_ok breakdown to {}", dst);
     }
 
     fn get_id(&mut self) -> u64 {
@@ -326,13 +773,6 @@ impl MaelstromHandler {
     }
 }
 
-fn get_ts() -> String {
-    let ts = std::time::SystemTime::now()
-        .duration_since(std::time::SystemTime::UNIX_EPOCH)
-        .unwrap();
-    format!("{}.{}", ts.as_secs(), ts.subsec_millis())
-}
-
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(tag = "type")]
 enum RequestType {
@@ -345,7 +785,32 @@ enum RequestType {
     #[serde(rename = "cas_ok")]
     CasOk(SeqKVNoDataResponse),
     #[serde(rename = "read_ok")]
-    ReadOk(SeqKVReadResponse),
+    ReadOk(ReadOkBody),
+    #[serde(rename = "pending_summary")]
+    PendingSummary(ReadBody),
+    /// Not sent by Maelstrom, used to experiment with a changing cluster.
+    #[serde(rename = "membership")]
+    Membership(MembershipBody),
+    /// Not sent by Maelstrom, see `PARAMS`.
+    #[serde(rename = "set_param")]
+    SetParam(SetParamBody),
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct MembershipBody {
+    node_ids: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    in_reply_to: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    msg_id: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct MembershipResponse {
+    #[serde(rename = "type")]
+    _type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    in_reply_to: Option<u64>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -354,7 +819,24 @@ struct AddBody {
     in_reply_to: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     msg_id: Option<u64>,
-    delta: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    delta: Option<u64>,
+    /// Several deltas coalesced by the client, applied with a single CAS and acknowledged
+    /// with a single add_ok. Used instead of `delta` when present.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    deltas: Option<Vec<u64>>,
+}
+
+impl AddBody {
+    fn total_delta(&self) -> Result<u64, NodeError> {
+        match (&self.deltas, self.delta) {
+            (Some(deltas), _) => deltas
+                .iter()
+                .try_fold(0, |total, delta| checked_add(total, *delta)),
+            (None, Some(delta)) => Ok(delta),
+            (None, None) => Err(NodeError::MalformedRequest),
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -363,6 +845,33 @@ struct ReadBody {
     in_reply_to: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     msg_id: Option<u64>,
+    /// Also return how much each node added, to see which one lags. Answered right away,
+    /// without waiting for the peers to sync like a plain read.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    breakdown: bool,
+}
+
+/// A read_ok from seq-kv, or from a peer syncing its count.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct ReadOkBody {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    in_reply_to: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    msg_id: Option<u64>,
+    value: u64,
+    /// The peer's own contribution to the count, absent from seq-kv replies.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    contributed: Option<u64>,
+}
+
+impl ReadOkBody {
+    fn into_kv_read(self) -> SeqKVReadResponse {
+        SeqKVReadResponse {
+            in_reply_to: self.in_reply_to,
+            msg_id: self.msg_id,
+            value: self.value,
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -374,6 +883,15 @@ struct ReadResponse {
     in_reply_to: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     msg_id: Option<u64>,
+    /// Our own contribution to `value`, sent to peers only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    contributed: Option<u64>,
+    /// How much of `value` each node added, for breakdown reads. Peers whose read_ok did not
+    /// reach us yet are missing, their share is counted in `unattributed`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    by_node: Option<BTreeMap<String, u64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    unattributed: Option<u64>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -385,3 +903,24 @@ struct AddResponse {
     #[serde(skip_serializing_if = "Option::is_none")]
     msg_id: Option<u64>,
 }
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct PendingSummary {
+    pending_delta: u64,
+    /// CAS requests in flight, as `(msg_id, delta)`.
+    pending_cas: Vec<(u64, u64)>,
+    /// Clients waiting for a read_ok.
+    pending_reads: Vec<String>,
+    /// Most reads deferred at once, see `MAX_DEFERRED_READS`.
+    pending_reads_peak: usize,
+    kv_backoff_attempts: u32,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct PendingSummaryResponse {
+    #[serde(rename = "type")]
+    _type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    in_reply_to: Option<u64>,
+    pending: PendingSummary,
+}
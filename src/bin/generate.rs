@@ -1,12 +1,12 @@
+use distributed_systems::maelstrom::error::NodeRuntimeError;
 use distributed_systems::maelstrom::*;
 use serde::{Deserialize, Serialize};
 
-fn main() {
-    let mut id_count = 0;
-    let node_id = get_node_id().unwrap();
-    loop {
-        node_loop(&node_id, &mut id_count).unwrap();
-    }
+fn main() -> Result<(), NodeRuntimeError> {
+    run_node_event_loop(GenerateNode {
+        node_id: String::new(),
+        id_count: 0,
+    })
 }
 
 fn generate_id(node_id: &str, current_count: u32) -> u64 {
@@ -19,22 +19,35 @@ fn generate_id(node_id: &str, current_count: u32) -> u64 {
     ((acc as u64) << 32) + current_count as u64
 }
 
-fn node_loop(node_id: &str, current_count: &mut u32) -> Result<(), Box<dyn std::error::Error>> {
-    let msg: NodeMessage<GenerateRequest> = read_node_message()?;
-    let new_id = generate_id(node_id, *current_count);
-    let new_msg: NodeMessage<GenerateResponse> = NodeMessage {
-        dest: msg.src,
-        src: node_id.to_string(),
-        body: GenerateResponse {
+struct GenerateNode {
+    node_id: String,
+    id_count: u32,
+}
+
+impl MaelstromNode for GenerateNode {
+    type MessageBody = GenerateRequest;
+
+    fn initialize(&mut self, node_id: String, _node_ids: Vec<String>) {
+        self.node_id = node_id;
+    }
+
+    fn respond(
+        &mut self,
+        msg: NodeMessage<GenerateRequest>,
+    ) -> Result<Vec<OutgoingMessage>, Box<dyn std::error::Error>> {
+        let new_id = generate_id(&self.node_id, self.id_count);
+        let response = GenerateResponse {
             _type: "generate_ok".into(),
             id: new_id,
+            msg_id: self.id_count as u64,
             in_reply_to: msg.body.msg_id,
-        },
-    };
-    write_node_message(&new_msg)?;
-    *current_count += 1;
+        };
+        self.id_count += 1;
 
-    Ok(())
+        Ok(vec![response
+            .into_reply(&self.node_id, &msg)
+            .into_outgoing()?])
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -49,5 +62,9 @@ pub struct GenerateResponse {
     #[serde(rename = "type")]
     pub _type: String,
     pub id: u64,
+    /// Our own message id, unique per node like `id`.
+    pub msg_id: u64,
     pub in_reply_to: u64,
 }
+
+impl IntoReply for GenerateResponse {}
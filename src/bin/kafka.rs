@@ -1,196 +1,454 @@
-use std::collections::HashMap;
-use std::sync::mpsc::{channel, TryRecvError};
-use std::thread;
+use std::collections::{HashMap, VecDeque};
+use std::sync::mpsc::TryRecvError;
+use std::time::{Duration, Instant};
 
+use distributed_systems::maelstrom::error::NodeError;
+use distributed_systems::maelstrom::membership::{strict_mode_from_env, Membership, SourceKind};
 use distributed_systems::{kafka::*, maelstrom::*, *};
 
 const POLL_SIZE: usize = 50;
+/// How many `(msg_id, offset)` pairs we remember per client to detect retried sends.
+const RECENT_SENDS_PER_SOURCE: usize = 64;
+/// Consumer group used by commit/list requests that don't name one.
+const DEFAULT_GROUP: &str = "default";
+/// How the entries of a multi-key poll are shared between its keys, unless the request asks
+/// for something else. `PerKey` returns up to `POLL_SIZE` entries for every key.
+const POLL_BUDGET: PollBudget = PollBudget::PerKey;
+/// Flush a client's coalesced send_ok as soon as this many sends are waiting, see
+/// `SEND_OK_BATCH_MS_ENV`.
+const SEND_OK_BATCH_MAX_SIZE: usize = 32;
 
 fn main() {
-    let node_id = get_node_id().unwrap();
+    let membership = get_membership().unwrap();
     let mut state = GlobalState {
-        node_id,
+        node_id: membership.node_id().to_string(),
+        membership,
         log_entries: HashMap::new(),
+        recent_sends: HashMap::new(),
+        committed_offsets: HashMap::new(),
+        send_ok_delay: std::env::var(SEND_OK_BATCH_MS_ENV)
+            .ok()
+            .map(|millis| Duration::from_millis(millis.parse().expect("Invalid send_ok delay."))),
+        send_ok_batches: HashMap::new(),
+        compact_keep_entries: std::env::var(COMPACT_KEEP_ENTRIES_ENV)
+            .ok()
+            .map(|entries| entries.parse().expect("Invalid number of entries to keep.")),
+        max_entries_per_key: std::env::var(MAX_ENTRIES_PER_KEY_ENV)
+            .ok()
+            .map(|entries| entries.parse().expect("Invalid maximum number of entries.")),
     };
-    let (tx, rx) = channel();
-
-    thread::spawn(move || loop {
-        let request: NodeMessage<RequestType> =
-            read_node_message().expect("Could not read request");
-        tx.send(request).unwrap();
-    });
+    let strict = strict_mode_from_env();
+    let rx = spawn_request_reader::<RequestType>();
     loop {
         match rx.try_recv() {
-            Ok(msg) => {
-                state.handle_message(msg).expect("Could not parse message");
+            Ok((msg, _)) if strict && !state.membership.accepts(&msg.src) => {}
+            Ok((msg, _)) => {
+                if let Err(err) = state.handle_message(msg) {
+                    node_log!(state.node_id, "Error handling message: {}", err);
+                }
+            }
+            Err(TryRecvError::Empty) => state.flush_ready_send_oks(),
+            Err(TryRecvError::Disconnected) => {
+                // Stdin is closed, the send_ok still held back go out once their delay is over.
+                let node_id = state.node_id.clone();
+                drain_with_grace(&node_id, || {
+                    state.flush_ready_send_oks();
+                    !state.send_ok_batches.is_empty()
+                });
+                return;
             }
-            Err(TryRecvError::Empty) => {}
-            Err(TryRecvError::Disconnected) => panic!("Internal error"),
         }
     }
 }
 
 struct GlobalState {
     node_id: String,
-    log_entries: HashMap<String, Vec<SparseLogEntry>>,
+    membership: Membership,
+    log_entries: HashMap<String, KeyLog>,
+    /// Offsets handed out to each client, keyed by the `msg_id` of the send.
+    recent_sends: HashMap<String, VecDeque<(u64, Offset)>>,
+    /// Last committed offset of each `(group, key)`.
+    committed_offsets: HashMap<(String, String), Offset>,
+    /// How long send_ok replies are held back to be coalesced, `None` answers right away.
+    send_ok_delay: Option<Duration>,
+    /// send_ok replies held back for each client.
+    send_ok_batches: HashMap<String, SendOkBatch>,
+    /// Keep at most this many entries per key, dropping the oldest ones. `None` keeps
+    /// everything.
+    compact_keep_entries: Option<usize>,
+    /// Refuse sends to a key holding this many entries, replying `TemporarilyUnavailable`
+    /// instead of an offset. `None` accepts everything.
+    max_entries_per_key: Option<usize>,
+}
+
+/// send_ok replies to a client waiting to go out as one `send_ok_batch`.
+struct SendOkBatch {
+    started: Instant,
+    sends: Vec<(u64, Offset)>,
 }
 
 struct SparseLogEntry {
-    offset: u64,
-    data: u64,
-    commited: bool,
+    offset: Offset,
+    data: LogValue,
+}
+
+#[derive(Default)]
+struct KeyLog {
+    /// First offset still stored, everything below it was compacted away.
+    base_offset: Offset,
+    entries: Vec<SparseLogEntry>,
+}
+
+impl KeyLog {
+    fn next_offset(&self) -> Result<Offset, NodeError> {
+        match self.entries.last() {
+            Some(last_entry) => last_entry.offset.next(),
+            None => Ok(self.base_offset),
+        }
+    }
+
+    /// Drop the oldest entries so at most `keep` remain.
+    fn compact(&mut self, keep: usize) -> Result<(), Box<dyn std::error::Error>> {
+        if self.entries.len() <= keep {
+            return Ok(());
+        }
+        let next_offset = self.next_offset()?;
+        self.entries.drain(..self.entries.len() - keep);
+        self.base_offset = self
+            .entries
+            .first()
+            .map_or(next_offset, |first_entry| first_entry.offset);
+        Ok(())
+    }
 }
 
 impl GlobalState {
+    /// Offset previously assigned to the send `msg_id` from `src`, if we still remember it.
+    fn recent_send_offset(&self, src: &str, msg_id: Option<u64>) -> Option<Offset> {
+        let msg_id = msg_id?;
+        self.recent_sends
+            .get(src)?
+            .iter()
+            .find(|(sent_id, _)| *sent_id == msg_id)
+            .map(|(_, offset)| *offset)
+    }
+
+    fn remember_send(&mut self, src: &str, msg_id: u64, offset: Offset) {
+        let sends = self.recent_sends.entry(src.to_string()).or_default();
+        if sends.len() == RECENT_SENDS_PER_SOURCE {
+            sends.pop_front();
+        }
+        sends.push_back((msg_id, offset));
+    }
+
+    /// Answer the send `in_reply_to` of `dest` with `offset`, or hold the reply back to be
+    /// coalesced with the next ones when `send_ok_delay` is set.
+    fn reply_send_ok(
+        &mut self,
+        dest: String,
+        in_reply_to: Option<u64>,
+        offset: Offset,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // A send without a msg_id cannot be told apart in a batch, it gets its own send_ok.
+        let (Some(_), Some(msg_id)) = (self.send_ok_delay, in_reply_to) else {
+            let res = NodeMessage::new(
+                self.node_id.clone(),
+                dest,
+                ResponseType::SendResponse(SendResponse {
+                    offset,
+                    owner: None,
+                    in_reply_to,
+                    msg_id: None,
+                }),
+            );
+            return write_node_message(&res);
+        };
+
+        let batch = self
+            .send_ok_batches
+            .entry(dest.clone())
+            .or_insert_with(|| SendOkBatch {
+                started: Instant::now(),
+                sends: vec![],
+            });
+        batch.sends.push((msg_id, offset));
+        if batch.sends.len() >= SEND_OK_BATCH_MAX_SIZE {
+            self.flush_send_oks(&dest)?;
+        }
+        Ok(())
+    }
+
+    fn flush_send_oks(&mut self, dest: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(batch) = self.send_ok_batches.remove(dest) else {
+            return Ok(());
+        };
+        node_log!(
+            self.node_id,
+            "Sending {} coalesced send_ok to {}",
+            batch.sends.len(),
+            dest
+        );
+        let res = NodeMessage::new(
+            self.node_id.clone(),
+            dest.to_string(),
+            ResponseType::SendBatchResponse(SendBatchResponse { sends: batch.sends }),
+        );
+        write_node_message(&res)
+    }
+
+    /// Send the coalesced send_ok whose oldest reply waited `send_ok_delay`.
+    fn flush_ready_send_oks(&mut self) {
+        let Some(delay) = self.send_ok_delay else {
+            return;
+        };
+        let ready: Vec<String> = self
+            .send_ok_batches
+            .iter()
+            .filter(|(_, batch)| batch.started.elapsed() >= delay)
+            .map(|(dest, _)| dest.clone())
+            .collect();
+        for dest in ready {
+            if let Err(err) = self.flush_send_oks(&dest) {
+                node_log!(self.node_id, "Cannot send coalesced send_ok: {}", err);
+            }
+        }
+    }
+
     pub fn handle_message(
         &mut self,
         msg: NodeMessage<RequestType>,
     ) -> Result<(), Box<dyn std::error::Error>> {
         match msg.body {
             RequestType::SendRequest(send) => {
-                eprintln!(
-                    "{} [{}] Received send({}): {}-{}",
-                    get_ts(),
+                node_log!(
                     self.node_id,
+                    "Received send({}): {}-{}",
                     msg.dest,
                     send.msg,
                     send.key,
                 );
-                let mut new_offset = 0;
-
-                self.log_entries
-                    .entry(send.key)
-                    .and_modify(|val| {
-                        let last_element_offset = val
-                            .last_mut()
-                            .expect("Append only log should always have an element.")
-                            .offset;
-                        new_offset = last_element_offset + 1;
-                        val.push(SparseLogEntry {
-                            offset: last_element_offset + 1,
-                            data: send.msg,
-                            commited: false,
-                        });
-                    })
-                    .or_insert(vec![SparseLogEntry {
-                        offset: 0,
-                        data: send.msg,
-                        commited: false,
-                    }]);
-
-                let res = NodeMessage {
-                    src: self.node_id.clone(),
-                    dest: msg.src,
-                    body: ResponseType::SendResponse(SendResponse {
-                        offset: new_offset,
-                        in_reply_to: send.msg_id,
-                        msg_id: None,
-                    }),
+
+                // A client retrying a send whose send_ok got lost must get the same offset back.
+                if let Some(offset) = self.recent_send_offset(&msg.src, send.msg_id) {
+                    node_log!(
+                        self.node_id,
+                        "Duplicated send({:?}) from {}, replying offset {}",
+                        send.msg_id,
+                        msg.src,
+                        offset,
+                    );
+                    return self.reply_send_ok(msg.src, send.msg_id, offset);
+                }
+
+                let log = self.log_entries.entry(send.key.clone()).or_default();
+                if self
+                    .max_entries_per_key
+                    .is_some_and(|max_entries| log.entries.len() >= max_entries)
+                {
+                    let text = format!("log {} is full", send.key);
+                    node_log!(self.node_id, "Refusing send from {}: {}", msg.src, text);
+                    if let Some(msg_id) = send.msg_id {
+                        let error = NodeError::TemporarilyUnavailable;
+                        reply_error(&self.node_id, &msg.src, msg_id, error, Some(text))?;
+                    }
+                    return Ok(());
+                }
+                let new_offset = match log.next_offset() {
+                    Ok(new_offset) => new_offset,
+                    Err(err) => {
+                        node_log!(self.node_id, "Refusing send from {}: {}", msg.src, err);
+                        if let Some(msg_id) = send.msg_id {
+                            let text = Some(err.to_string());
+                            reply_error(&self.node_id, &msg.src, msg_id, NodeError::Crash, text)?;
+                        }
+                        return Ok(());
+                    }
                 };
+                log.entries.push(SparseLogEntry {
+                    offset: new_offset,
+                    data: send.msg,
+                });
+                if let Some(keep) = self.compact_keep_entries {
+                    log.compact(keep)?;
+                }
 
-                write_node_message(&res).expect("Cannot write resend message.");
-                Ok(())
+                if let Some(msg_id) = send.msg_id {
+                    self.remember_send(&msg.src, msg_id, new_offset);
+                }
+
+                self.reply_send_ok(msg.src, send.msg_id, new_offset)
             }
             RequestType::PollRequest(poll) => {
-                eprintln!(
-                    "{} [{}] Received poll({}): {:?}",
-                    get_ts(),
+                node_log!(
                     self.node_id,
+                    "Received poll({}): {:?}",
                     msg.dest,
                     poll.offsets,
                 );
                 let mut msgs = HashMap::new();
-                for (log_key, offset) in poll.offsets.iter() {
-                    let data_points: Option<Vec<[u64; 2]>> = self.log_entries.get(log_key).map(|keys| {
-                        keys.iter()
-                            .filter(|k| k.offset >= *offset)
-                            .take(POLL_SIZE)
-                            .map(|k| [k.offset, k.data])
-                            .collect()
-                    });
-                    msgs.insert(log_key.clone(), data_points.unwrap_or(vec![]));
+                let mut trimmed = HashMap::new();
+                let mut log_length = HashMap::new();
+                let group = poll.group.as_deref().unwrap_or(DEFAULT_GROUP);
+                let budget = poll.budget.unwrap_or(POLL_BUDGET);
+                let key_limit = match budget {
+                    PollBudget::PerKey => POLL_SIZE,
+                    PollBudget::GlobalBudget(total) => total,
+                };
+                // Sorted, so a global budget is shared the same way whatever the request order.
+                let mut poll_offsets: Vec<_> = poll.offsets.iter().collect();
+                poll_offsets.sort();
+                let mut candidates = vec![];
+                for (log_key, offset) in poll_offsets {
+                    let end_offset = if poll.isolation == Isolation::ReadCommitted {
+                        // Nothing is visible until the group committed something.
+                        match self
+                            .committed_offsets
+                            .get(&(group.to_string(), log_key.clone()))
+                        {
+                            Some(committed) => committed.next()?,
+                            None => Offset(0),
+                        }
+                    } else {
+                        Offset(u64::MAX)
+                    };
+                    let Some(log) = self.log_entries.get(log_key) else {
+                        msgs.insert(log_key.clone(), vec![]);
+                        if poll.include_log_length {
+                            log_length.insert(log_key.clone(), 0);
+                        }
+                        continue;
+                    };
+                    if poll.include_log_length {
+                        // Compacted entries still count, offsets keep growing past them.
+                        log_length.insert(log_key.clone(), log.next_offset()?.0);
+                    }
+                    // Consumers restarting from an old offset get whatever survived compaction.
+                    if *offset < log.base_offset {
+                        trimmed.insert(log_key.clone(), log.base_offset);
+                    }
+                    let data_points: Vec<_> = log
+                        .entries
+                        .iter()
+                        .filter(|k| k.offset >= *offset)
+                        .take_while(|k| k.offset < end_offset)
+                        .take(key_limit)
+                        .map(|k| (k.offset, k.data))
+                        .collect();
+                    candidates.push((log_key.clone(), data_points));
+                }
+                let available: Vec<usize> = candidates
+                    .iter()
+                    .map(|(_, entries)| entries.len())
+                    .collect();
+                let allocated = budget.allocate(POLL_SIZE, &available);
+                // Clients only understand the JSON pairs.
+                let pack = poll.packed && self.membership.classify(&msg.src) == SourceKind::Node;
+                let mut packed = HashMap::new();
+                for ((log_key, mut data_points), count) in candidates.into_iter().zip(allocated) {
+                    data_points.truncate(count);
+                    if pack {
+                        packed.insert(log_key, PackedEntries::pack(&data_points)?);
+                    } else {
+                        msgs.insert(log_key, data_points);
+                    }
                 }
 
-                let res = NodeMessage {
-                    src: self.node_id.clone(),
-                    dest: msg.src,
-                    body: ResponseType::PollResponse(PollResponse {
+                let res = NodeMessage::new(
+                    self.node_id.clone(),
+                    msg.src,
+                    ResponseType::PollResponse(PollResponse {
                         msgs,
+                        trimmed,
+                        log_length,
+                        packed,
                         in_reply_to: poll.msg_id,
                         msg_id: None,
                     }),
-                };
+                );
 
                 write_node_message(&res).expect("Cannot write resend message.");
 
                 Ok(())
             }
             RequestType::CommitOffsetsRequest(commit_offset) => {
-                eprintln!(
-                    "{} [{}] Received commit_offset({}): {:?}",
-                    get_ts(),
+                node_log!(
                     self.node_id,
+                    "Received commit_offset({}): {:?}",
                     msg.dest,
                     commit_offset.offsets,
                 );
-                for (log_key, offset) in commit_offset.offsets.iter() {
-                    if let Some(sparse_log) = self.log_entries.get_mut(log_key) {
-                        for sparse_key in sparse_log.iter_mut() {
-                            if sparse_key.offset <= *offset {
-                                sparse_key.commited = true;
-                            }
+                let group = commit_offset.group.as_deref().unwrap_or(DEFAULT_GROUP);
+                if commit_offset.atomic {
+                    let mut conflicts: Vec<&String> = commit_offset
+                        .offsets
+                        .iter()
+                        .filter(|(log_key, offset)| {
+                            self.committed_offsets
+                                .get(&(group.to_string(), log_key.to_string()))
+                                .is_some_and(|committed| committed > offset)
+                        })
+                        .map(|(log_key, _)| log_key)
+                        .collect();
+                    if !conflicts.is_empty() {
+                        conflicts.sort();
+                        let text = format!("commit would move {:?} backwards", conflicts);
+                        node_log!(self.node_id, "Refusing commit from {}: {}", msg.src, text);
+                        if let Some(msg_id) = commit_offset.msg_id {
+                            let error = NodeError::TxnConflict;
+                            reply_error(&self.node_id, &msg.src, msg_id, error, Some(text))?;
                         }
+                        return Ok(());
                     }
                 }
+                for (log_key, offset) in commit_offset.offsets.iter() {
+                    // Commits never move a group backwards.
+                    self.committed_offsets
+                        .entry((group.to_string(), log_key.clone()))
+                        .and_modify(|committed| *committed = (*committed).max(*offset))
+                        .or_insert(*offset);
+                }
 
-                let res = NodeMessage {
-                    src: self.node_id.clone(),
-                    dest: msg.src,
-                    body: ResponseType::CommitOffsetsResponse(SimpleMessage {
+                let res = NodeMessage::new(
+                    self.node_id.clone(),
+                    msg.src,
+                    ResponseType::CommitOffsetsResponse(SimpleMessage {
                         in_reply_to: commit_offset.msg_id,
                         msg_id: None,
                     }),
-                };
+                );
 
                 write_node_message(&res).expect("Cannot write resend message.");
                 Ok(())
-            },
+            }
             RequestType::ListCommitedOffsetsRequest(list_commit) => {
-                eprintln!(
-                    "{} [{}] Received list_commit({}): {:?}",
-                    get_ts(),
+                node_log!(
                     self.node_id,
+                    "Received list_commit({}): {:?}",
                     msg.dest,
                     list_commit.keys,
                 );
+                let group = list_commit.group.as_deref().unwrap_or(DEFAULT_GROUP);
                 let mut offsets = HashMap::new();
                 for log_key in list_commit.keys.iter() {
-                    if let Some(sparse_log) = self.log_entries.get_mut(log_key) {
-                        let mut last_commited = None;
-                        for sparse_key in sparse_log.iter_mut() {
-                            if sparse_key.commited {
-                                last_commited = Some(sparse_key.offset);
-                            } else {
-                                break;
-                            }
-                        }
-                        offsets.insert(log_key.clone(), last_commited.unwrap_or(0));
+                    let group_key = (group.to_string(), log_key.clone());
+                    if let Some(committed) = self.committed_offsets.get(&group_key) {
+                        offsets.insert(log_key.clone(), *committed);
                     }
                 }
 
-                let res = NodeMessage {
-                    src: self.node_id.clone(),
-                    dest: msg.src,
-                    body: ResponseType::ListCommitedOffsetsResponse(ListCommitedOffsetsResponse {
+                let res = NodeMessage::new(
+                    self.node_id.clone(),
+                    msg.src,
+                    ResponseType::ListCommitedOffsetsResponse(ListCommitedOffsetsResponse {
                         offsets,
                         in_reply_to: list_commit.msg_id,
                         msg_id: None,
                     }),
-                };
+                );
 
                 write_node_message(&res).expect("Cannot write resend message.");
                 Ok(())
-            },
+            }
         }
     }
-}
\ No newline at end of file
+}
@@ -1,196 +1,948 @@
 use std::collections::HashMap;
-use std::sync::mpsc::{channel, TryRecvError};
-use std::thread;
+use std::sync::mpsc::TryRecvError;
 
+use distributed_systems::logging::enter_message;
+use distributed_systems::maelstrom::backoff::Backoff;
+use distributed_systems::maelstrom::error::NodeError;
+use distributed_systems::maelstrom::membership::strict_mode_from_env;
+use distributed_systems::maelstrom::pending::Pending;
+use distributed_systems::maelstrom::seq_kv::*;
+use distributed_systems::maelstrom::seq_kv_client::{KvCompletion, KvOp, PendingOp, SeqKvClient};
 use distributed_systems::{kafka::*, maelstrom::*, *};
+use serde::Deserialize;
 
 const POLL_SIZE: usize = 50;
+/// Keep committed offsets in seq-kv, so every node of the cluster agrees on them, instead of in
+/// the memory of the node that received the commit.
+const SEQ_KV_COMMITS: bool = true;
+/// Consumer group used by commit/list requests that don't name one.
+const DEFAULT_GROUP: &str = "default";
+/// How long a seq-kv request may go unanswered before it is sent again.
+const KV_RPC_WAIT_MS: u64 = 500;
+const KV_BACKOFF_BASE_MS: u64 = 50;
+const KV_BACKOFF_MAX_MS: u64 = 2000;
 
 fn main() {
-    let node_id = get_node_id().unwrap();
+    let membership = get_membership().unwrap();
     let mut state = GlobalState {
-        node_id,
+        node_id: membership.node_id().to_string(),
+        node_ids: membership.node_ids().to_vec(),
         log_entries: HashMap::new(),
+        committed_watermarks: HashMap::new(),
+        id_counter: 0,
+        kv_rpcs: Pending::new(KV_RPC_WAIT_MS),
+        kv_backoff: Backoff::new(KV_BACKOFF_BASE_MS, KV_BACKOFF_MAX_MS),
+        kv_retries: vec![],
+        pending_requests: HashMap::new(),
+        atomic_commits: HashMap::new(),
+        lin_kv_offsets: std::env::var(LIN_KV_OFFSETS_ENV)
+            .is_ok_and(|enabled| enabled == "1" || enabled == "true"),
+        offset_counters: SeqKvClient::lin_kv(membership.node_id(), KV_RPC_WAIT_MS),
+        offset_backoff: Backoff::new(KV_BACKOFF_BASE_MS, KV_BACKOFF_MAX_MS),
+        offset_retries: vec![],
+        owner_hints: std::env::var(OWNER_HINTS_ENV)
+            .is_ok_and(|enabled| enabled == "1" || enabled == "true"),
     };
-    let (tx, rx) = channel();
-
-    thread::spawn(move || loop {
-        let request: NodeMessage<RequestType> =
-            read_node_message().expect("Could not read request");
-        tx.send(request).unwrap();
-    });
+    let strict = strict_mode_from_env();
+    let rx = spawn_request_reader::<Incoming>();
     loop {
         match rx.try_recv() {
-            Ok(msg) => {
-                state.handle_message(msg).expect("Could not parse message");
+            Ok((msg, _)) if strict && !membership.accepts(&msg.src) => {}
+            Ok((msg, context)) => {
+                let src = msg.src.clone();
+                let _scope = enter_message(context);
+                if let Err(err) = state.handle_message(msg) {
+                    report_handler_error(&state.node_id, &src, context.msg_id, err.as_ref());
+                }
+            }
+            Err(TryRecvError::Empty) => state.retry_kv_rpcs(),
+            Err(TryRecvError::Disconnected) => {
+                // Stdin is closed, keep sending the KV requests still waiting for a while: they
+                // may have been lost, and the requests waiting on them are answered on success.
+                let node_id = state.node_id.clone();
+                drain_with_grace(&node_id, || {
+                    state.retry_kv_rpcs();
+                    state.has_pending_work()
+                });
+                return;
             }
-            Err(TryRecvError::Empty) => {}
-            Err(TryRecvError::Disconnected) => panic!("Internal error"),
         }
     }
 }
 
 struct GlobalState {
     node_id: String,
+    node_ids: Vec<String>,
     log_entries: HashMap<String, Vec<SparseLogEntry>>,
+    /// Highest offset of each key such that it and every offset before it are committed.
+    committed_watermarks: HashMap<String, Offset>,
+    id_counter: u32,
+    /// seq-kv requests waiting on a reply, by msg_id.
+    kv_rpcs: Pending<KvRpc>,
+    kv_backoff: Backoff,
+    /// seq-kv requests to send again once `kv_backoff` is over.
+    kv_retries: Vec<KvRpc>,
+    /// Client requests waiting on seq-kv, by the id shared by their `KvRpc`s.
+    pending_requests: HashMap<u64, PendingRequest>,
+    /// Atomic `commit_offsets` waiting on seq-kv, by the id shared by their `KvRpc`s.
+    atomic_commits: HashMap<u64, AtomicCommit>,
+    /// Allocate offsets from the lin-kv counter of each key, see `LIN_KV_OFFSETS_ENV`.
+    lin_kv_offsets: bool,
+    /// Increments of the lin-kv offset counters, with the send waiting on each.
+    offset_counters: SeqKvClient<PendingSend>,
+    offset_backoff: Backoff,
+    /// Sends whose offset counter increment failed, restarted once `offset_backoff` is over.
+    offset_retries: Vec<PendingSend>,
+    /// Tell clients which node owns a key in the send_ok of a send they made to another node,
+    /// see `OWNER_HINTS_ENV`. Nodes do not forward sends to the owner yet, so this is only a
+    /// hint for clients that want to keep all the sends of a key on a single node.
+    owner_hints: bool,
+}
+
+/// Messages a node receives: kafka requests from clients and replies from seq-kv or lin-kv.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Incoming {
+    Kafka(RequestType),
+    SeqKv(SeqKvReply),
+    /// A read of a key an atomic commit created then rolled back, see `KvRpc::Rollback`.
+    ClearedKey(SeqKVReadResponse<()>),
+}
+
+/// One step of a client request that needs seq-kv.
+#[derive(Debug, Clone)]
+enum KvRpc {
+    /// Read the committed offset of a key, before moving it forward.
+    CommitRead(OffsetCommit),
+    /// Move the committed offset of a key from the value read to the commit.
+    CommitCas(OffsetCommit, Option<Offset>),
+    /// Read the committed offset of a key for `list_committed_offsets`.
+    ListRead {
+        request_id: u64,
+        group: String,
+        log_key: String,
+    },
+    /// Read the committed offset of a key of an atomic commit, before moving any key.
+    AtomicRead(OffsetCommit),
+    /// Move the committed offset of a key of an atomic commit from the value read.
+    AtomicCas(OffsetCommit, Option<Offset>),
+    /// Put back the committed offset a failed atomic commit moved. seq-kv cannot delete keys,
+    /// a key the commit created is set to null instead, which reads as never committed.
+    Rollback(OffsetCommit, Option<Offset>),
+}
+
+/// A send waiting on the lin-kv increment allocating its offset.
+#[derive(Debug, Clone)]
+struct PendingSend {
+    client: String,
+    key: String,
+    msg: LogValue,
+    owner: Option<String>,
+    in_reply_to: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+struct OffsetCommit {
+    request_id: u64,
+    group: String,
+    log_key: String,
+    offset: Offset,
+}
+
+/// A `commit_offsets` or `list_committed_offsets` answered once every key is done.
+#[derive(Debug)]
+struct PendingRequest {
+    client: String,
+    in_reply_to: Option<u64>,
+    remaining: usize,
+    /// Committed offsets read so far, only used by `list_committed_offsets`.
+    offsets: HashMap<String, Offset>,
+    kind: PendingKind,
+}
+
+/// An atomic `commit_offsets`: every key is read first, then moved one after the other. When a
+/// key moved since it was read, the keys already moved are put back and the whole batch fails
+/// with `TxnConflict`.
+#[derive(Debug)]
+struct AtomicCommit {
+    client: String,
+    in_reply_to: Option<u64>,
+    group: String,
+    /// Offsets to commit, sorted by key.
+    offsets: Vec<(String, Offset)>,
+    /// Committed offset read for each key, `None` if nothing was committed.
+    read: HashMap<String, Option<Offset>>,
+    /// Index in `offsets` of the next key to move.
+    next: usize,
+    /// Keys moved so far, with the committed offset each had before.
+    applied: Vec<(OffsetCommit, Option<Offset>)>,
+    /// Why the commit failed, once a key conflicted.
+    failure: Option<String>,
+    /// Rollbacks still waiting on seq-kv.
+    rolling_back: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum PendingKind {
+    CommitOffsets,
+    ListCommittedOffsets,
+}
+
+/// lin-kv key holding the next offset to allocate in the log of `log_key`.
+fn offset_counter_key(log_key: &str) -> String {
+    format!("next_offset/{}", log_key)
+}
+
+/// seq-kv key holding the committed offset of `log_key` for `group`.
+fn committed_offset_key(group: &str, log_key: &str) -> String {
+    format!("offset/{}/{}", group, log_key)
 }
 
 struct SparseLogEntry {
-    offset: u64,
-    data: u64,
-    commited: bool,
+    offset: Offset,
+    data: LogValue,
 }
 
 impl GlobalState {
     pub fn handle_message(
+        &mut self,
+        msg: NodeMessage<Incoming>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match msg.body {
+            Incoming::Kafka(body) => self.handle_kafka(NodeMessage::new(msg.src, msg.dest, body)),
+            Incoming::SeqKv(reply) if msg.src == LIN_KV => {
+                let completion = self.offset_counters.handle_response(reply);
+                if completion.is_none() {
+                    node_log!(self.node_id, "Ignoring late lin-kv reply");
+                }
+                self.advance_offset(completion);
+                Ok(())
+            }
+            Incoming::SeqKv(reply) => {
+                self.handle_seq_kv_reply(reply);
+                Ok(())
+            }
+            Incoming::ClearedKey(read_ok) => {
+                self.handle_seq_kv_result(read_ok.in_reply_to, Err(NodeError::KeyDoesNotExist));
+                Ok(())
+            }
+        }
+    }
+
+    fn handle_kafka(
         &mut self,
         msg: NodeMessage<RequestType>,
     ) -> Result<(), Box<dyn std::error::Error>> {
         match msg.body {
             RequestType::SendRequest(send) => {
-                eprintln!(
-                    "{} [{}] Received send({}): {}-{}",
-                    get_ts(),
+                node_log!(
                     self.node_id,
+                    "Received send({}): {}-{}",
                     msg.dest,
                     send.msg,
-                    send.key,
+                    send.key
                 );
-                let mut new_offset = 0;
-
-                self.log_entries
-                    .entry(send.key)
-                    .and_modify(|val| {
-                        let last_element_offset = val
-                            .last_mut()
-                            .expect("Append only log should always have an element.")
-                            .offset;
-                        new_offset = last_element_offset + 1;
-                        val.push(SparseLogEntry {
-                            offset: last_element_offset + 1,
-                            data: send.msg,
-                            commited: false,
-                        });
-                    })
-                    .or_insert(vec![SparseLogEntry {
-                        offset: 0,
-                        data: send.msg,
-                        commited: false,
-                    }]);
-
-                let res = NodeMessage {
-                    src: self.node_id.clone(),
-                    dest: msg.src,
-                    body: ResponseType::SendResponse(SendResponse {
-                        offset: new_offset,
-                        in_reply_to: send.msg_id,
-                        msg_id: None,
-                    }),
+                let owner = key_owner(&send.key, &self.node_ids)
+                    .filter(|owner| self.owner_hints && *owner != self.node_id)
+                    .map(str::to_string);
+                let send = PendingSend {
+                    client: msg.src,
+                    key: send.key,
+                    msg: send.msg,
+                    owner,
+                    in_reply_to: send.msg_id,
                 };
+                if self.lin_kv_offsets {
+                    self.allocate_offset(send);
+                    return Ok(());
+                }
 
-                write_node_message(&res).expect("Cannot write resend message.");
+                let offset = self.log_entries.get(&send.key).and_then(|log| log.last());
+                let offset = match offset {
+                    Some(last_entry) => last_entry.offset.next()?,
+                    None => Offset(0),
+                };
+                self.append(send, offset);
                 Ok(())
             }
             RequestType::PollRequest(poll) => {
-                eprintln!(
-                    "{} [{}] Received poll({}): {:?}",
-                    get_ts(),
+                node_log!(
                     self.node_id,
+                    "Received poll({}): {:?}",
                     msg.dest,
-                    poll.offsets,
+                    poll.offsets
                 );
                 let mut msgs = HashMap::new();
+                let mut log_length = HashMap::new();
                 for (log_key, offset) in poll.offsets.iter() {
-                    let data_points: Option<Vec<[u64; 2]>> = self.log_entries.get(log_key).map(|keys| {
-                        keys.iter()
-                            .filter(|k| k.offset >= *offset)
-                            .take(POLL_SIZE)
-                            .map(|k| [k.offset, k.data])
-                            .collect()
-                    });
+                    let watermark = self.committed_watermarks.get(log_key).copied();
+                    let read_committed = poll.isolation == Isolation::ReadCommitted;
+                    let data_points: Option<Vec<(Offset, LogValue)>> =
+                        self.log_entries.get(log_key).map(|keys| {
+                            keys.iter()
+                                .filter(|k| k.offset >= *offset)
+                                .take_while(|k| {
+                                    !read_committed
+                                        || watermark.is_some_and(|watermark| k.offset <= watermark)
+                                })
+                                .take(POLL_SIZE)
+                                .map(|k| (k.offset, k.data))
+                                .collect()
+                        });
                     msgs.insert(log_key.clone(), data_points.unwrap_or(vec![]));
+                    if poll.include_log_length {
+                        let length = match self.log_entries.get(log_key).and_then(|log| log.last())
+                        {
+                            Some(last_entry) => last_entry.offset.next()?.0,
+                            None => 0,
+                        };
+                        log_length.insert(log_key.clone(), length);
+                    }
                 }
 
-                let res = NodeMessage {
-                    src: self.node_id.clone(),
-                    dest: msg.src,
-                    body: ResponseType::PollResponse(PollResponse {
+                let res = NodeMessage::new(
+                    self.node_id.clone(),
+                    msg.src,
+                    ResponseType::PollResponse(PollResponse {
                         msgs,
+                        trimmed: HashMap::new(),
+                        log_length,
+                        packed: HashMap::new(),
                         in_reply_to: poll.msg_id,
                         msg_id: None,
                     }),
-                };
+                );
 
                 write_node_message(&res).expect("Cannot write resend message.");
 
                 Ok(())
             }
             RequestType::CommitOffsetsRequest(commit_offset) => {
-                eprintln!(
-                    "{} [{}] Received commit_offset({}): {:?}",
-                    get_ts(),
+                node_log!(
                     self.node_id,
+                    "Received commit_offset({}): {:?}",
                     msg.dest,
-                    commit_offset.offsets,
+                    commit_offset.offsets
                 );
-                for (log_key, offset) in commit_offset.offsets.iter() {
-                    if let Some(sparse_log) = self.log_entries.get_mut(log_key) {
-                        for sparse_key in sparse_log.iter_mut() {
-                            if sparse_key.offset <= *offset {
-                                sparse_key.commited = true;
-                            }
-                        }
+                if commit_offset.atomic && !commit_offset.offsets.is_empty() {
+                    let request_id = self.next_id();
+                    let group = commit_offset.group.as_deref().unwrap_or(DEFAULT_GROUP);
+                    let mut offsets: Vec<(String, Offset)> =
+                        commit_offset.offsets.into_iter().collect();
+                    offsets.sort_by(|(left, _), (right, _)| left.cmp(right));
+                    for (log_key, offset) in offsets.iter() {
+                        self.send_kv_rpc(KvRpc::AtomicRead(OffsetCommit {
+                            request_id,
+                            group: group.to_string(),
+                            log_key: log_key.clone(),
+                            offset: *offset,
+                        }));
+                    }
+                    self.atomic_commits.insert(
+                        request_id,
+                        AtomicCommit {
+                            client: msg.src,
+                            in_reply_to: commit_offset.msg_id,
+                            group: group.to_string(),
+                            offsets,
+                            read: HashMap::new(),
+                            next: 0,
+                            applied: vec![],
+                            failure: None,
+                            rolling_back: 0,
+                        },
+                    );
+                    return Ok(());
+                }
+                if SEQ_KV_COMMITS && !commit_offset.offsets.is_empty() {
+                    let request_id = self.next_id();
+                    let group = commit_offset.group.as_deref().unwrap_or(DEFAULT_GROUP);
+                    for (log_key, offset) in commit_offset.offsets.iter() {
+                        self.send_kv_rpc(KvRpc::CommitRead(OffsetCommit {
+                            request_id,
+                            group: group.to_string(),
+                            log_key: log_key.clone(),
+                            offset: *offset,
+                        }));
                     }
+                    self.pending_requests.insert(
+                        request_id,
+                        PendingRequest {
+                            client: msg.src,
+                            in_reply_to: commit_offset.msg_id,
+                            remaining: commit_offset.offsets.len(),
+                            offsets: HashMap::new(),
+                            kind: PendingKind::CommitOffsets,
+                        },
+                    );
+                    return Ok(());
+                }
+                for (log_key, offset) in commit_offset.offsets.iter() {
+                    self.advance_watermark(log_key, *offset);
                 }
 
-                let res = NodeMessage {
-                    src: self.node_id.clone(),
-                    dest: msg.src,
-                    body: ResponseType::CommitOffsetsResponse(SimpleMessage {
+                let res = NodeMessage::new(
+                    self.node_id.clone(),
+                    msg.src,
+                    ResponseType::CommitOffsetsResponse(SimpleMessage {
                         in_reply_to: commit_offset.msg_id,
                         msg_id: None,
                     }),
-                };
+                );
 
                 write_node_message(&res).expect("Cannot write resend message.");
                 Ok(())
-            },
+            }
             RequestType::ListCommitedOffsetsRequest(list_commit) => {
-                eprintln!(
-                    "{} [{}] Received list_commit({}): {:?}",
-                    get_ts(),
+                node_log!(
                     self.node_id,
+                    "Received list_commit({}): {:?}",
                     msg.dest,
-                    list_commit.keys,
+                    list_commit.keys
                 );
+                if SEQ_KV_COMMITS && !list_commit.keys.is_empty() {
+                    let request_id = self.next_id();
+                    let group = list_commit.group.as_deref().unwrap_or(DEFAULT_GROUP);
+                    for log_key in list_commit.keys.iter() {
+                        self.send_kv_rpc(KvRpc::ListRead {
+                            request_id,
+                            group: group.to_string(),
+                            log_key: log_key.clone(),
+                        });
+                    }
+                    self.pending_requests.insert(
+                        request_id,
+                        PendingRequest {
+                            client: msg.src,
+                            in_reply_to: list_commit.msg_id,
+                            remaining: list_commit.keys.len(),
+                            offsets: HashMap::new(),
+                            kind: PendingKind::ListCommittedOffsets,
+                        },
+                    );
+                    return Ok(());
+                }
                 let mut offsets = HashMap::new();
                 for log_key in list_commit.keys.iter() {
-                    if let Some(sparse_log) = self.log_entries.get_mut(log_key) {
-                        let mut last_commited = None;
-                        for sparse_key in sparse_log.iter_mut() {
-                            if sparse_key.commited {
-                                last_commited = Some(sparse_key.offset);
-                            } else {
-                                break;
-                            }
-                        }
-                        offsets.insert(log_key.clone(), last_commited.unwrap_or(0));
+                    if self.log_entries.contains_key(log_key) {
+                        let watermark = self.committed_watermarks.get(log_key).copied();
+                        offsets.insert(log_key.clone(), watermark.unwrap_or(Offset(0)));
                     }
                 }
 
-                let res = NodeMessage {
-                    src: self.node_id.clone(),
-                    dest: msg.src,
-                    body: ResponseType::ListCommitedOffsetsResponse(ListCommitedOffsetsResponse {
+                let res = NodeMessage::new(
+                    self.node_id.clone(),
+                    msg.src,
+                    ResponseType::ListCommitedOffsetsResponse(ListCommitedOffsetsResponse {
                         offsets,
                         in_reply_to: list_commit.msg_id,
                         msg_id: None,
                     }),
-                };
+                );
 
                 write_node_message(&res).expect("Cannot write resend message.");
                 Ok(())
+            }
+        }
+    }
+
+    /// Add the message of `send` to its log at `offset` and answer the client.
+    fn append(&mut self, send: PendingSend, offset: Offset) {
+        let log = self.log_entries.entry(send.key).or_default();
+        // Offsets from lin-kv may commit out of order, keep the log sorted anyway.
+        let index = log.partition_point(|entry| entry.offset < offset);
+        log.insert(
+            index,
+            SparseLogEntry {
+                offset,
+                data: send.msg,
             },
+        );
+
+        let res = NodeMessage::new(
+            self.node_id.clone(),
+            send.client,
+            ResponseType::SendResponse(SendResponse {
+                offset,
+                owner: send.owner,
+                in_reply_to: send.in_reply_to,
+                msg_id: None,
+            }),
+        );
+        write_node_message(&res).expect("Cannot write resend message.");
+    }
+
+    /// Start the read-CAS loop incrementing the lin-kv offset counter of the key of `send`.
+    fn allocate_offset(&mut self, send: PendingSend) {
+        let key = offset_counter_key(&send.key);
+        let completion = self
+            .offset_counters
+            .read(&key, send)
+            .expect("Cannot write lin-kv message.");
+        self.advance_offset(completion);
+    }
+
+    /// Move the offset counter increments forward: swap the value read for the next one, and
+    /// append once a swap commits. The offset is the value swapped out, so two nodes never get
+    /// the same one. A swap losing to another node restarts from a fresh read at once, any other
+    /// error once `offset_backoff` is over.
+    fn advance_offset(&mut self, mut completion: Option<KvCompletion<PendingSend>>) {
+        while let Some(KvCompletion {
+            op,
+            context: send,
+            result,
+            ..
+        }) = completion.take()
+        {
+            let key = offset_counter_key(&send.key);
+            completion = match (op, result) {
+                (KvOp::Read { .. }, Ok(current)) => {
+                    let current = Offset(current.unwrap_or(0));
+                    let next = match current.next() {
+                        Ok(next) => next,
+                        Err(err) => {
+                            self.reject_send(send, err);
+                            return;
+                        }
+                    };
+                    self.offset_counters.compare_and_swap(
+                        &key,
+                        Some(current.0),
+                        next.0,
+                        false,
+                        send,
+                    )
+                }
+                (KvOp::Read { .. }, Err(NodeError::KeyDoesNotExist)) => self
+                    .offset_counters
+                    .compare_and_swap(&key, None, 1, true, send),
+                (KvOp::CompareAndSwap { from, .. }, Ok(_)) => {
+                    self.append(send, Offset(from.unwrap_or(0)));
+                    // lin-kv answers again, the sends waiting on the backoff can go now.
+                    self.offset_backoff.reset();
+                    for send in std::mem::take(&mut self.offset_retries) {
+                        self.allocate_offset(send);
+                    }
+                    return;
+                }
+                (
+                    op @ KvOp::CompareAndSwap { .. },
+                    Err(NodeError::PreconditionFailed | NodeError::KeyAlreadyExists),
+                ) => {
+                    node_log!(
+                        self.node_id,
+                        "lin-kv {:?} lost to another node, reading the offset counter again",
+                        op
+                    );
+                    self.offset_counters.read(&key, send)
+                }
+                (op, result) => {
+                    let delay_ms = self.offset_backoff.next_delay_ms();
+                    self.offset_backoff.schedule();
+                    node_log!(
+                        self.node_id,
+                        "lin-kv {:?} on {:?}, retry {} in {}ms",
+                        result,
+                        op,
+                        self.offset_backoff.attempts(),
+                        delay_ms
+                    );
+                    self.offset_retries.push(send);
+                    return;
+                }
+            }
+            .expect("Cannot write lin-kv message.");
+        }
+    }
+
+    /// Answer a send that cannot get an offset with `err`.
+    fn reject_send(&self, send: PendingSend, err: NodeError) {
+        node_log!(
+            self.node_id,
+            "Refusing send to {} from {}: {}",
+            send.key,
+            send.client,
+            err
+        );
+        if let Some(msg_id) = send.in_reply_to {
+            reply_error(&self.node_id, &send.client, msg_id, err, None)
+                .expect("Cannot write message.");
+        }
+    }
+
+    /// Move the committed watermark of `log_key` for a commit of `offset`, see
+    /// `kafka::advance_watermark`.
+    fn advance_watermark(&mut self, log_key: &str, offset: Offset) {
+        let last_entry = self.log_entries.get(log_key).and_then(|log| log.last());
+        if let Some(last_entry) = last_entry {
+            let watermark = self.committed_watermarks.get(log_key).copied();
+            let watermark = kafka::advance_watermark(watermark, offset, last_entry.offset);
+            self.committed_watermarks.insert(log_key.to_string(), watermark);
+        }
+    }
+
+    fn handle_seq_kv_reply(&mut self, reply: SeqKvReply) {
+        let (in_reply_to, result) = match reply {
+            SeqKvReply::ReadOk(read_ok) => (read_ok.in_reply_to, Ok(Some(Offset(read_ok.value)))),
+            SeqKvReply::CasOk(cas_ok) | SeqKvReply::WriteOk(cas_ok) => {
+                (cas_ok.in_reply_to, Ok(None))
+            }
+            SeqKvReply::Error(err) => (err.in_reply_to, Err(err.node_error())),
+        };
+        self.handle_seq_kv_result(in_reply_to, result);
+    }
+
+    fn handle_seq_kv_result(
+        &mut self,
+        in_reply_to: Option<u64>,
+        result: Result<Option<Offset>, NodeError>,
+    ) {
+        let Some(rpc) = in_reply_to.and_then(|msg_id| self.kv_rpcs.take(msg_id)) else {
+            node_log!(
+                self.node_id,
+                "Ignoring late seq-kv reply to {:?}",
+                in_reply_to
+            );
+            return;
+        };
+        if result.is_ok() {
+            self.kv_backoff.reset();
+        }
+
+        match (rpc, result) {
+            (KvRpc::CommitRead(commit), Ok(current)) => self.advance_commit(commit, current),
+            (KvRpc::CommitRead(commit), Err(NodeError::KeyDoesNotExist)) => {
+                self.advance_commit(commit, None)
+            }
+            (KvRpc::CommitCas(commit, _), Ok(_)) => {
+                self.advance_watermark(&commit.log_key, commit.offset);
+                self.complete_key(commit.request_id, &commit.log_key, None);
+            }
+            // Another node moved the offset first: read it again and retry from there, unless
+            // it is already past ours.
+            (
+                KvRpc::CommitCas(commit, _),
+                Err(NodeError::PreconditionFailed | NodeError::KeyAlreadyExists),
+            ) => self.send_kv_rpc(KvRpc::CommitRead(commit)),
+            (
+                KvRpc::ListRead {
+                    request_id,
+                    log_key,
+                    ..
+                },
+                Ok(offset),
+            ) => {
+                if let Some(offset) = offset {
+                    self.advance_watermark(&log_key, offset);
+                }
+                self.complete_key(request_id, &log_key, offset);
+            }
+            // Nothing was ever committed for this key.
+            (
+                KvRpc::ListRead {
+                    request_id,
+                    log_key,
+                    ..
+                },
+                Err(NodeError::KeyDoesNotExist),
+            ) => self.complete_key(request_id, &log_key, None),
+            (KvRpc::AtomicRead(commit), Ok(current)) => self.read_atomic_key(commit, current),
+            (KvRpc::AtomicRead(commit), Err(NodeError::KeyDoesNotExist)) => {
+                self.read_atomic_key(commit, None)
+            }
+            (KvRpc::AtomicCas(commit, from), Ok(_)) => {
+                let request_id = commit.request_id;
+                if let Some(atomic) = self.atomic_commits.get_mut(&request_id) {
+                    atomic.applied.push((commit, from));
+                    atomic.next += 1;
+                }
+                self.advance_atomic_commit(request_id);
+            }
+            (
+                KvRpc::AtomicCas(commit, from),
+                Err(NodeError::PreconditionFailed | NodeError::KeyAlreadyExists),
+            ) => self.roll_back_atomic_commit(commit, from),
+            // A commit moved the key again since, there is nothing left to put back.
+            (KvRpc::Rollback(commit, _), Ok(_) | Err(NodeError::PreconditionFailed)) => {
+                self.complete_rollback(commit.request_id)
+            }
+            (rpc, Err(node_error)) => {
+                let delay_ms = self.kv_backoff.next_delay_ms();
+                self.kv_backoff.schedule();
+                node_log!(
+                    self.node_id,
+                    "seq-kv error {:?} on {:?}, retry {} in {}ms",
+                    node_error,
+                    rpc,
+                    self.kv_backoff.attempts(),
+                    delay_ms
+                );
+                self.kv_retries.push(rpc);
+            }
         }
     }
-}
\ No newline at end of file
+
+    /// CAS the committed offset of a key from `current` to the commit, unless that would move
+    /// it backwards.
+    fn advance_commit(&mut self, commit: OffsetCommit, current: Option<Offset>) {
+        if current.is_some_and(|current| current >= commit.offset) {
+            node_log!(
+                self.node_id,
+                "Not moving {} of {} back from {:?} to {:?}",
+                commit.log_key,
+                commit.group,
+                current,
+                commit.offset
+            );
+            self.complete_key(commit.request_id, &commit.log_key, None);
+            return;
+        }
+        self.send_kv_rpc(KvRpc::CommitCas(commit, current));
+    }
+
+    /// Record the committed offset read for a key of an atomic commit. Once every key is read,
+    /// reject the commit if it would move any of them backwards, or start moving them.
+    fn read_atomic_key(&mut self, commit: OffsetCommit, current: Option<Offset>) {
+        let Some(atomic) = self.atomic_commits.get_mut(&commit.request_id) else {
+            return;
+        };
+        atomic.read.insert(commit.log_key, current);
+        if atomic.read.len() < atomic.offsets.len() {
+            return;
+        }
+
+        let conflicts: Vec<&String> = atomic
+            .offsets
+            .iter()
+            .filter(|(log_key, offset)| atomic.read[log_key].is_some_and(|read| read > *offset))
+            .map(|(log_key, _)| log_key)
+            .collect();
+        if !conflicts.is_empty() {
+            let text = format!("commit would move {:?} backwards", conflicts);
+            let atomic = self
+                .atomic_commits
+                .remove(&commit.request_id)
+                .expect("Atomic commit was just found.");
+            self.reject_atomic_commit(atomic, text);
+            return;
+        }
+        self.advance_atomic_commit(commit.request_id);
+    }
+
+    /// CAS the next key of an atomic commit that is not at its offset yet, answering the
+    /// client once every key is.
+    fn advance_atomic_commit(&mut self, request_id: u64) {
+        let Some(atomic) = self.atomic_commits.get_mut(&request_id) else {
+            return;
+        };
+        while let Some((log_key, offset)) = atomic.offsets.get(atomic.next) {
+            let current = atomic.read[log_key];
+            if current == Some(*offset) {
+                atomic.next += 1;
+                continue;
+            }
+            let commit = OffsetCommit {
+                request_id,
+                group: atomic.group.clone(),
+                log_key: log_key.clone(),
+                offset: *offset,
+            };
+            self.send_kv_rpc(KvRpc::AtomicCas(commit, current));
+            return;
+        }
+
+        let atomic = self
+            .atomic_commits
+            .remove(&request_id)
+            .expect("Atomic commit was just found.");
+        for (log_key, offset) in atomic.offsets.iter() {
+            self.advance_watermark(log_key, *offset);
+        }
+        let res = NodeMessage::new(
+            self.node_id.clone(),
+            atomic.client,
+            ResponseType::CommitOffsetsResponse(SimpleMessage {
+                in_reply_to: atomic.in_reply_to,
+                msg_id: None,
+            }),
+        );
+        write_node_message(&res).expect("Cannot write resend message.");
+    }
+
+    /// A key of an atomic commit moved since it was read: put back every key moved so far,
+    /// then reject the commit. The CAS that failed may have been applied by an earlier attempt
+    /// that timed out, so it is put back too, which fails harmlessly if it was not.
+    fn roll_back_atomic_commit(&mut self, commit: OffsetCommit, from: Option<Offset>) {
+        let Some(atomic) = self.atomic_commits.get_mut(&commit.request_id) else {
+            return;
+        };
+        node_log!(
+            self.node_id,
+            "{} of {} moved from {:?}, rolling back {} keys",
+            commit.log_key,
+            commit.group,
+            from,
+            atomic.applied.len()
+        );
+        atomic.failure = Some(format!("{} moved while committing", commit.log_key));
+        atomic.applied.push((commit, from));
+        let rollbacks: Vec<KvRpc> = atomic
+            .applied
+            .drain(..)
+            .map(|(commit, previous)| KvRpc::Rollback(commit, previous))
+            .collect();
+        atomic.rolling_back = rollbacks.len();
+        for rpc in rollbacks {
+            self.send_kv_rpc(rpc);
+        }
+    }
+
+    /// Mark a rollback of an atomic commit as done, rejecting the commit after the last one.
+    fn complete_rollback(&mut self, request_id: u64) {
+        let Some(atomic) = self.atomic_commits.get_mut(&request_id) else {
+            return;
+        };
+        atomic.rolling_back -= 1;
+        if atomic.rolling_back > 0 {
+            return;
+        }
+
+        let mut atomic = self
+            .atomic_commits
+            .remove(&request_id)
+            .expect("Atomic commit was just found.");
+        let text = atomic.failure.take().unwrap_or_default();
+        self.reject_atomic_commit(atomic, text);
+    }
+
+    /// Answer an atomic commit that left every key as it was with `TxnConflict`.
+    fn reject_atomic_commit(&self, atomic: AtomicCommit, text: String) {
+        node_log!(
+            self.node_id,
+            "Refusing commit from {}: {}",
+            atomic.client,
+            text
+        );
+        if let Some(msg_id) = atomic.in_reply_to {
+            let error = NodeError::TxnConflict;
+            reply_error(&self.node_id, &atomic.client, msg_id, error, Some(text))
+                .expect("Cannot write message.");
+        }
+    }
+
+    /// Mark a key of a pending request as done, replying to the client after the last one.
+    fn complete_key(&mut self, request_id: u64, log_key: &str, offset: Option<Offset>) {
+        let Some(request) = self.pending_requests.get_mut(&request_id) else {
+            return;
+        };
+        if let Some(offset) = offset {
+            request.offsets.insert(log_key.to_string(), offset);
+        }
+        request.remaining -= 1;
+        if request.remaining > 0 {
+            return;
+        }
+
+        let request = self
+            .pending_requests
+            .remove(&request_id)
+            .expect("Pending request was just found.");
+        let body = match request.kind {
+            PendingKind::CommitOffsets => ResponseType::CommitOffsetsResponse(SimpleMessage {
+                in_reply_to: request.in_reply_to,
+                msg_id: None,
+            }),
+            PendingKind::ListCommittedOffsets => {
+                ResponseType::ListCommitedOffsetsResponse(ListCommitedOffsetsResponse {
+                    offsets: request.offsets,
+                    in_reply_to: request.in_reply_to,
+                    msg_id: None,
+                })
+            }
+        };
+        let res = NodeMessage::new(self.node_id.clone(), request.client, body);
+        write_node_message(&res).expect("Cannot write resend message.");
+    }
+
+    /// Whether requests are still waiting on seq-kv or lin-kv.
+    fn has_pending_work(&self) -> bool {
+        !self.kv_rpcs.is_empty()
+            || !self.kv_retries.is_empty()
+            || !self.offset_counters.is_empty()
+            || !self.offset_retries.is_empty()
+    }
+
+    /// Send again the seq-kv requests that went unanswered or whose backoff is over, and restart
+    /// the lin-kv offset increments that went unanswered or whose backoff is over.
+    fn retry_kv_rpcs(&mut self) {
+        for (_, PendingOp { context, .. }) in self.offset_counters.expired() {
+            // The swap may have committed without us hearing of it, which only leaves a gap.
+            node_log!(self.node_id, "lin-kv request timed out, reading again");
+            self.allocate_offset(context);
+        }
+        for (_, rpc) in self.kv_rpcs.expired() {
+            node_log!(
+                self.node_id,
+                "seq-kv request timed out, resending {:?}",
+                rpc
+            );
+            self.send_kv_rpc(rpc);
+        }
+        if self.kv_backoff.take_ready() {
+            for rpc in std::mem::take(&mut self.kv_retries) {
+                self.send_kv_rpc(rpc);
+            }
+        }
+        if self.offset_backoff.take_ready() {
+            for send in std::mem::take(&mut self.offset_retries) {
+                self.allocate_offset(send);
+            }
+        }
+    }
+
+    fn send_kv_rpc(&mut self, rpc: KvRpc) {
+        let msg_id = self.next_id();
+        let body = match &rpc {
+            KvRpc::CommitRead(commit) | KvRpc::AtomicRead(commit) => {
+                SeqKVRequest::Read(SeqKVReadRequest {
+                    in_reply_to: None,
+                    msg_id: Some(msg_id),
+                    key: committed_offset_key(&commit.group, &commit.log_key),
+                })
+            }
+            KvRpc::CommitCas(commit, from) | KvRpc::AtomicCas(commit, from) => {
+                SeqKVRequest::CompareAndSwap(SeqKVCompareAndSwapRequest {
+                    in_reply_to: None,
+                    msg_id: Some(msg_id),
+                    key: committed_offset_key(&commit.group, &commit.log_key),
+                    from: from.map(|offset| offset.0),
+                    to: Some(commit.offset.0),
+                    create_if_not_exists: from.is_none(),
+                })
+            }
+            KvRpc::Rollback(commit, previous) => {
+                SeqKVRequest::CompareAndSwap(SeqKVCompareAndSwapRequest {
+                    in_reply_to: None,
+                    msg_id: Some(msg_id),
+                    key: committed_offset_key(&commit.group, &commit.log_key),
+                    from: Some(commit.offset.0),
+                    to: previous.map(|offset| offset.0),
+                    create_if_not_exists: false,
+                })
+            }
+            KvRpc::ListRead { group, log_key, .. } => SeqKVRequest::Read(SeqKVReadRequest {
+                in_reply_to: None,
+                msg_id: Some(msg_id),
+                key: committed_offset_key(group, log_key),
+            }),
+        };
+        let req = NodeMessage::new(self.node_id.clone(), SEQ_KV.to_string(), body);
+        write_node_message(&req).expect("Cannot write seq-kv message.");
+        self.kv_rpcs.insert(msg_id, rpc);
+    }
+
+    fn next_id(&mut self) -> u64 {
+        self.id_counter += 1;
+        generate_id(&self.node_id, self.id_counter)
+    }
+}
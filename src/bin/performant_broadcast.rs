@@ -1,17 +1,20 @@
 use std::collections::{HashMap, HashSet};
-use std::sync::mpsc::{channel, TryRecvError};
-use std::thread;
+use std::sync::mpsc::TryRecvError;
 use std::time::{Duration, Instant};
 
+use distributed_systems::logging::enter_message;
+use distributed_systems::maelstrom::membership::strict_mode_from_env;
 use distributed_systems::maelstrom::*;
+use distributed_systems::node_log;
 use serde::{Deserialize, Serialize};
 
 const WAIT_TIME: Duration = Duration::from_millis(200);
 
 fn main() {
-    let node_id = get_node_id().unwrap();
+    let membership = get_membership().unwrap();
+    let strict = strict_mode_from_env();
     let mut state = GlobalState {
-        node_id,
+        node_id: membership.node_id().to_string(),
         neighborhood: vec![],
         topology: HashMap::new(),
         values: HashSet::new(),
@@ -20,24 +23,24 @@ fn main() {
             neighborhoods: HashMap::new(),
         },
     };
-    let (tx, rx) = channel();
-
-    thread::spawn(move || loop {
-        let request: NodeMessage<RequestType> =
-            read_node_message().expect("Could not read request");
-        tx.send(request).unwrap();
-    });
+    let rx = spawn_request_reader::<RequestType>();
     loop {
         match rx.try_recv() {
-            Ok(node_message) => {
-                handle_message(node_message, &mut state).expect("Could not parse message");
+            Ok((node_message, _)) if strict && !membership.accepts(&node_message.src) => {}
+            Ok((node_message, context)) => {
+                let src = node_message.src.clone();
+                let _scope = enter_message(context);
+                if let Err(err) = handle_message(node_message, &mut state) {
+                    report_handler_error(&state.node_id, &src, context.msg_id, err.as_ref());
+                }
             }
             Err(TryRecvError::Empty) => {
                 if let Some(response) = state.message_bus.pick_message() {
                     write_node_message(response).expect("Cannot write resend message.");
                 };
             }
-            Err(TryRecvError::Disconnected) => panic!("Internal error"),
+            // Stdin is closed, no broadcast_ok can arrive anymore to stop the resends.
+            Err(TryRecvError::Disconnected) => return,
         }
     }
 }
@@ -48,64 +51,54 @@ fn handle_message(
 ) -> Result<(), Box<dyn std::error::Error>> {
     match request.body {
         RequestType::BroadcastOk(broadcast_ok) => {
-            let msg = broadcast_ok.msg_id.unwrap();
-            eprintln!(
-                "{} [{}] Received broadcast_ok({}) from {}",
-                get_ts(),
+            let msg = broadcast_ok
+                .acked_value
+                .ok_or("broadcast_ok without an acked_value")?;
+            node_log!(
                 state.node_id,
+                "Received broadcast_ok({}) from {}",
                 msg,
                 request.src
             );
             state.message_bus.delete_message(&request.src, msg);
         }
         RequestType::Read(read_body) => {
-            eprintln!(
-                "{} [{}] Received read from {}",
-                get_ts(),
-                state.node_id,
-                request.src
-            );
-            let n = NodeMessage {
-                src: state.node_id.clone(),
-                dest: request.src.clone(),
-                body: ResponseBody::Read(ReadResponse {
+            node_log!(state.node_id, "Received read from {}", request.src);
+            let n = NodeMessage::new(
+                state.node_id.clone(),
+                request.src.clone(),
+                ResponseBody::Read(ReadResponse {
                     _type: "read_ok".into(),
                     messages: state.values.iter().copied().collect(),
                     in_reply_to: read_body.msg_id,
                     msg_id: None,
                 }),
-            };
-            write_node_message(&n).expect("Cannot write message.");
-            eprintln!(
-                "{} [{}] Sent read_ok to {}",
-                get_ts(),
-                state.node_id,
-                request.src
             );
+            write_node_message(&n).expect("Cannot write message.");
+            node_log!(state.node_id, "Sent read_ok to {}", request.src);
         }
         RequestType::Broadcast(broadcast_request) => {
-            eprintln!(
-                "{} [{}] Received broadcast({}) from {}",
-                get_ts(),
+            node_log!(
                 state.node_id,
+                "Received broadcast({}) from {}",
                 broadcast_request.message,
                 request.src
             );
             state.values.insert(broadcast_request.message);
-            let n = NodeMessage {
-                src: state.node_id.clone(),
-                dest: request.src.clone(),
-                body: ResponseBody::Basic(BasicResponse {
+            let n = NodeMessage::new(
+                state.node_id.clone(),
+                request.src.clone(),
+                ResponseBody::Basic(BasicResponse {
                     _type: "broadcast_ok".into(),
                     in_reply_to: broadcast_request.msg_id,
-                    msg_id: Some(broadcast_request.message),
+                    msg_id: None,
+                    acked_value: Some(broadcast_request.message),
                 }),
-            };
+            );
             write_node_message(&n).expect("Cannot write message.");
-            eprintln!(
-                "{} [{}] Sent broadcast_ok({}) to {}",
-                get_ts(),
+            node_log!(
                 state.node_id,
+                "Sent broadcast_ok({}) to {}",
                 broadcast_request.message,
                 request.src
             );
@@ -123,16 +116,16 @@ fn handle_message(
                 if neighborhood_node_id == &request.src {
                     continue;
                 }
-                let node = NodeMessage {
-                    src: state.node_id.clone(),
-                    dest: neighborhood_node_id.clone(),
-                    body: BroadcastResponse {
+                let node = NodeMessage::new(
+                    state.node_id.clone(),
+                    neighborhood_node_id.clone(),
+                    BroadcastResponse {
                         _type: "broadcast".into(),
                         in_reply_to: None,
                         msg_id: None,
                         message: broadcast_request.message,
                     },
-                };
+                );
 
                 let new_message_opt = state.message_bus.add_message(
                     neighborhood_node_id,
@@ -141,10 +134,9 @@ fn handle_message(
                 );
                 if let Some(new_message) = new_message_opt {
                     write_node_message(&new_message).unwrap();
-                    eprintln!(
-                        "{} [{}] Sent broadcast({}) to {}",
-                        get_ts(),
+                    node_log!(
                         state.node_id,
+                        "Sent broadcast({}) to {}",
                         broadcast_request.message,
                         neighborhood_node_id
                     );
@@ -154,10 +146,9 @@ fn handle_message(
             state.past_broadcast.insert(broadcast_request.message);
         }
         RequestType::Topology(topology) => {
-            eprintln!(
-                "{} [{}] Received topology from {}: {:?}",
-                get_ts(),
+            node_log!(
                 state.node_id,
+                "Received topology from {}: {:?}",
                 request.src,
                 topology.topology
             );
@@ -190,42 +181,30 @@ fn handle_message(
             .map(|v| v.to_string())
             .collect();
             state.message_bus.update_neighborhood(&state.neighborhood);
-            eprintln!(
-                "{} [{}] Ignoring Maelstrom topology, setting neighborhood: {:?}",
-                get_ts(),
+            node_log!(
                 state.node_id,
+                "Ignoring Maelstrom topology, setting neighborhood: {:?}",
                 state.neighborhood
             );
 
-            let n = NodeMessage {
-                src: state.node_id.clone(),
-                dest: request.src.clone(),
-                body: ResponseBody::Basic(BasicResponse {
+            let n = NodeMessage::new(
+                state.node_id.clone(),
+                request.src.clone(),
+                ResponseBody::Basic(BasicResponse {
                     _type: "topology_ok".into(),
                     in_reply_to: topology.msg_id,
                     msg_id: None,
+                    acked_value: None,
                 }),
-            };
-            write_node_message(&n).expect("Cannot write message.");
-            eprintln!(
-                "{} [{}] Sent topology_ok to {}",
-                get_ts(),
-                state.node_id,
-                request.src
             );
+            write_node_message(&n).expect("Cannot write message.");
+            node_log!(state.node_id, "Sent topology_ok to {}", request.src);
         }
     };
 
     Ok(())
 }
 
-fn get_ts() -> String {
-    let ts = std::time::SystemTime::now()
-        .duration_since(std::time::SystemTime::UNIX_EPOCH)
-        .unwrap();
-    format!("{}.{}", ts.as_secs(), ts.subsec_millis())
-}
-
 struct GlobalState {
     node_id: String,
     neighborhood: Vec<String>,
@@ -319,18 +298,6 @@ impl Timer {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
-struct PendingBroadcast {
-    src_node: String,
-    message: u64,
-}
-
-#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
-struct BroadcastSent {
-    destination_node: String,
-    message: u64,
-}
-
 #[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(untagged)]
 enum ResponseBody {
@@ -349,7 +316,7 @@ enum RequestType {
     #[serde(rename = "topology")]
     Topology(TopologyBody),
     #[serde(rename = "broadcast_ok")]
-    BroadcastOk(ReadBody),
+    BroadcastOk(BroadcastOkBody),
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -369,6 +336,17 @@ struct ReadBody {
     msg_id: Option<u64>,
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct BroadcastOkBody {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    in_reply_to: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    msg_id: Option<u64>,
+    /// Value being acknowledged, `msg_id` stays free for actual message ids.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    acked_value: Option<u64>,
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 struct TopologyBody {
     topology: HashMap<String, Vec<String>>,
@@ -386,6 +364,9 @@ struct BasicResponse {
     in_reply_to: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     msg_id: Option<u64>,
+    /// Value a broadcast_ok acknowledges.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    acked_value: Option<u64>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -1,78 +1,276 @@
-use std::collections::{HashMap, HashSet, VecDeque};
-use std::sync::mpsc::{channel, TryRecvError};
-use std::thread;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::mpsc::TryRecvError;
 use std::time::{Duration, Instant};
 
+use distributed_systems::broadcast::{
+    deliver, highest_priority, DeliveryLog, OverflowPolicy, PickPolicy, Role, SnapshotSet,
+    StarOfStars, ValueLog, BATCH_MAX_DELAY_MS_ENV, BATCH_MAX_SIZE_ENV, READ_REPAIR_ENV,
+    TREE_READ_ENV,
+};
+use distributed_systems::logging::enter_message;
+use distributed_systems::maelstrom::control::{SetParamBody, CONTROL_ENABLED};
+use distributed_systems::maelstrom::error::{ErrorReply, NodeError};
+use distributed_systems::maelstrom::gather::Gather;
+use distributed_systems::maelstrom::membership::strict_mode_from_env;
+use distributed_systems::maelstrom::readiness::Readiness;
 use distributed_systems::maelstrom::*;
+use distributed_systems::node_log;
 use serde::{Deserialize, Serialize};
 
 const WAIT_TIME: Duration = Duration::from_millis(120);
 const READ_WAIT_TIME: Duration = Duration::from_millis(1850);
+/// A client read arriving within this long of a replicate read to a peer rides on that one
+/// instead of sending another, its read_ok is only sent `READ_WAIT_TIME` later anyway.
+const REPLICATE_READ_COALESCE: Duration = Duration::from_millis(300);
+/// Distance between two hubs of the star-of-stars overlay.
+const HUB_SPAN: usize = 5;
+/// How long a node waits for its subtree before answering a tree read with what it has.
+const TREE_READ_TIMEOUT_MS: u64 = 1000;
+/// With `WAIT_FOR_SYNC_ENV` set, how long the client requests received after init wait for
+/// every neighbor to answer the initial read, so a neighbor that is down cannot hold them
+/// forever.
+const INITIAL_SYNC_TIMEOUT_MS: u64 = 1000;
+/// Hops a value may travel before nodes stop forwarding it, a backstop against cycles in
+/// topologies other than the star-of-stars overlay.
+const MAX_HOPS: u32 = 16;
+/// Values a `broadcast_batch` holds at most, unless `BATCH_MAX_SIZE_ENV` says otherwise.
+const BATCH_MAX_SIZE: usize = 32;
+/// Answer reads from a sorted snapshot of the values kept between reads, rebuilt only after a
+/// new value arrived, instead of collecting the whole set on every read.
+const SNAPSHOT_READS: bool = true;
+/// Which of the broadcasts a neighbor did not acknowledge yet is retried first.
+const PICK_POLICY: PickPolicy = PickPolicy::Oldest;
+/// What to do once many broadcasts are pending for a neighbor, unless
+/// `MAX_PENDING_PER_NEIGHBOR_ENV` sets a limit.
+const OVERFLOW_POLICY: OverflowPolicy = OverflowPolicy::Unbounded;
+/// Parameters a `set_param` control message can change: `wait_ms` replaces `WAIT_TIME` and
+/// `read_wait_ms` replaces `READ_WAIT_TIME`, including for the timers already running.
+const PARAMS: [&str; 2] = ["wait_ms", "read_wait_ms"];
+
+/// When the values waiting for a neighbor are flushed as a single batch.
+#[derive(Debug, Clone, Copy)]
+struct BatchConfig {
+    /// Flush as soon as this many values are waiting.
+    max_size: usize,
+    /// Flush once the oldest waiting value waited this long.
+    max_delay: Duration,
+}
+
+impl BatchConfig {
+    /// Batching configured through `BATCH_MAX_DELAY_MS_ENV` and `BATCH_MAX_SIZE_ENV`, if any.
+    fn from_env() -> Option<BatchConfig> {
+        let max_delay = std::env::var(BATCH_MAX_DELAY_MS_ENV).ok()?;
+        let max_delay = Duration::from_millis(max_delay.parse().expect("Invalid batch delay."));
+        let max_size = std::env::var(BATCH_MAX_SIZE_ENV).map_or(BATCH_MAX_SIZE, |max_size| {
+            max_size.parse().expect("Invalid batch size.")
+        });
+        Some(BatchConfig {
+            max_size,
+            max_delay,
+        })
+    }
+}
 
 fn main() {
-    let node_id = get_node_id().unwrap();
+    let membership = get_membership().unwrap();
+    let strict = strict_mode_from_env();
+    let value_log = ValueLog::from_env().expect("Cannot open value log.");
+    let overflow = OverflowPolicy::from_env().expect("Invalid pending limit.");
+    let values = match &value_log {
+        Some(value_log) => value_log.load().expect("Cannot load value log."),
+        None => HashSet::new(),
+    };
+    // Versions count the values learned, so they keep growing across restarts.
+    let version = values.len() as u64;
     let mut state = GlobalState {
-        node_id,
+        node_id: membership.node_id().to_string(),
         neighborhood: vec![],
+        suspected_down: HashSet::new(),
+        overlay: StarOfStars::new(0, HUB_SPAN),
+        role: Role::Leaf,
         topology: HashMap::new(),
-        values: HashSet::new(),
+        values: SnapshotSet::new(values),
+        deliveries: DeliveryLog::new(),
+        value_log,
+        version,
+        peer_versions: HashMap::new(),
         past_broadcast: HashSet::new(),
+        known_by: HashMap::new(),
         message_bus: MessageBus {
             neighborhoods: HashMap::new(),
+            batches: HashMap::new(),
+            priorities: HashMap::new(),
+            wait_time: WAIT_TIME,
+            overflow: overflow.unwrap_or(OVERFLOW_POLICY),
         },
         customer_read_bus: CustomerBus {
             messages: VecDeque::new(),
+            read_wait_time: READ_WAIT_TIME,
         },
+        batch: BatchConfig::from_env(),
+        read_repair: std::env::var(READ_REPAIR_ENV)
+            .is_ok_and(|enabled| enabled == "1" || enabled == "true"),
+        tree_read: std::env::var(TREE_READ_ENV)
+            .is_ok_and(|enabled| enabled == "1" || enabled == "true"),
+        tree_reads: HashMap::new(),
+        read_id_counter: 0,
+        last_replicate_reads: HashMap::new(),
+        readiness: Readiness::from_env(),
+        initial_sync: None,
     };
-    let (tx, rx) = channel();
-
-    thread::spawn(move || loop {
-        let request: NodeMessage<RequestType> =
-            read_node_message().expect("Could not read request");
-        tx.send(request).unwrap();
-    });
+    let rx = spawn_request_reader::<RequestType>();
     loop {
-        if let Some(mut message) = state.customer_read_bus.pop() {
-            message.body.messages = state.values.iter().cloned().collect();
+        finish_initial_sync(&mut state, false);
+        state.finish_expired_tree_reads();
+        state.flush_ready_batches();
+        if let Some(read) = state.customer_read_bus.pop() {
+            let values = state.read_values();
+            let message = read.read_ok(&state.node_id, values);
             write_node_message(&message).expect("Cannot write resend message.");
-            eprintln!(
-                "{} [{}] Sent read_ok to {}: {:?}",
-                get_ts(),
+            node_log!(
                 state.node_id,
+                "Sent read_ok to {}: {:?}",
                 message.dest,
                 message.body.messages
             );
         }
 
         match rx.try_recv() {
-            Ok(node_message) => {
-                handle_message(node_message, &mut state).expect("Could not parse message");
+            Ok((node_message, _)) if strict && !membership.accepts(&node_message.src) => {}
+            Ok(request) => {
+                if let Some(request) = state.hold_until_ready(request) {
+                    handle_request(&mut state, request);
+                }
             }
             Err(TryRecvError::Empty) => {
                 if let Some(response) = state.message_bus.pick_message() {
                     write_node_message(response).expect("Cannot write resend message.");
                 };
             }
-            Err(TryRecvError::Disconnected) => panic!("Internal error"),
+            Err(TryRecvError::Disconnected) => {
+                // Stdin is closed, answer the reads still waiting on their timer before exiting.
+                finish_initial_sync(&mut state, true);
+                for read in state.customer_read_bus.drain_all() {
+                    let values = state.read_values();
+                    let message = read.read_ok(&state.node_id, values);
+                    write_node_message(&message).expect("Cannot write message.");
+                }
+                return;
+            }
         }
     }
 }
 
+fn handle_request(state: &mut GlobalState, (node_message, context): Request<RequestType>) {
+    let src = node_message.src.clone();
+    let _scope = enter_message(context);
+    if let Err(err) = handle_message(node_message, state) {
+        report_handler_error(&state.node_id, &src, context.msg_id, err.as_ref());
+    }
+}
+
+/// Handle the client requests held since init once every neighbor answered the initial read
+/// or `INITIAL_SYNC_TIMEOUT_MS` is over, or right away with `force`.
+fn finish_initial_sync(state: &mut GlobalState, force: bool) {
+    if state.readiness.is_ready() {
+        return;
+    }
+    let synced = state
+        .initial_sync
+        .as_ref()
+        .is_some_and(|initial_sync| initial_sync.is_done() || initial_sync.is_expired());
+    if !synced && !force {
+        return;
+    }
+
+    if let Some(initial_sync) = state.initial_sync.take() {
+        if !initial_sync.is_done() {
+            node_log!(
+                state.node_id,
+                "Initial sync timed out waiting on {:?}",
+                initial_sync.missing().collect::<Vec<_>>()
+            );
+        }
+    }
+    let held = state.readiness.mark_ready();
+    node_log!(
+        state.node_id,
+        "Initial sync done, handling {} held requests",
+        held.len()
+    );
+    for request in held {
+        handle_request(state, request);
+    }
+}
+
 fn handle_message(
     request: NodeMessage<RequestType>,
     state: &mut GlobalState,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    let src_role = state.overlay.role(&request.src);
     match request.body {
         RequestType::ReadOk(read_ok) => {
+            if let Some(initial_sync) = state.initial_sync.as_mut() {
+                initial_sync.add(&request.src, ());
+            }
+            if let Some(version) = read_ok.version {
+                let last_version = state.peer_versions.get(&request.src).copied();
+                if last_version.is_some_and(|last_version| last_version >= version) {
+                    node_log!(
+                        state.node_id,
+                        "Skipping read_ok version {} from {}, already merged {:?}",
+                        version,
+                        request.src,
+                        last_version
+                    );
+                    return Ok(());
+                }
+                state.peer_versions.insert(request.src.clone(), version);
+            }
+
             let ok_msgs: HashSet<u64> = read_ok.messages.into_iter().collect();
-            let new_msgs: HashSet<u64> = ok_msgs.difference(&state.values).copied().collect();
-            state.values = state.values.union(&new_msgs).copied().collect();
+            for msg in ok_msgs.iter() {
+                state.mark_known(&request.src, *msg);
+            }
+            if state.read_repair && src_role.kind().is_internal() {
+                let mut missing: Vec<u64> = state
+                    .values
+                    .as_set()
+                    .difference(&ok_msgs)
+                    .copied()
+                    .collect();
+                missing.sort_unstable();
+                for msg in missing {
+                    let repair = NodeMessage::new(
+                        state.node_id.clone(),
+                        request.src.clone(),
+                        BroadcastResponse {
+                            _type: "broadcast".into(),
+                            in_reply_to: None,
+                            msg_id: None,
+                            message: msg,
+                            ttl: Some(MAX_HOPS),
+                        },
+                    );
+                    write_node_message(&repair).expect("Cannot write message.");
+                    node_log!(
+                        state.node_id,
+                        "Sent broadcast({}) to {} [read-repair]",
+                        msg,
+                        request.src
+                    );
+                }
+            }
+            let new_msgs: HashSet<u64> =
+                ok_msgs.difference(state.values.as_set()).copied().collect();
+            for msg in new_msgs.iter() {
+                state.apply_value(*msg);
+            }
 
-            eprintln!(
-                "{} [{}] Received read_ok({:?}) from {}",
-                get_ts(),
+            node_log!(
                 state.node_id,
-                state.values,
+                "Received read_ok({:?}) from {}",
+                state.values.as_set(),
                 request.src
             );
 
@@ -80,8 +278,9 @@ fn handle_message(
                 return Ok(());
             }
 
+            let neighborhood = state.neighborhood.clone();
             for msg in new_msgs {
-                for dst_node_id in state.neighborhood.iter() {
+                for dst_node_id in neighborhood.iter() {
                     // Node is sending us broadcast, we don't need to broadcast to it.
                     state.message_bus.delete_message_checked(&request.src, msg);
 
@@ -89,44 +288,35 @@ fn handle_message(
                         continue;
                     }
 
-                    if dst_node_id == &state.node_id {
+                    if dst_node_id == &state.node_id || state.is_known_by(dst_node_id, msg) {
                         continue;
                     }
-                    let broadcast_msg = NodeMessage {
-                        src: state.node_id.clone(),
-                        dest: dst_node_id.clone(),
-                        body: BroadcastResponse {
+                    let broadcast_msg = NodeMessage::new(
+                        state.node_id.clone(),
+                        dst_node_id.clone(),
+                        BroadcastResponse {
                             _type: "broadcast".into(),
                             in_reply_to: None,
                             msg_id: None,
                             message: msg,
+                            ttl: Some(MAX_HOPS),
                         },
-                    };
-
-                    let is_master_to_master =
-                        is_main_node(&dst_node_id) && is_main_node(&state.node_id);
-                    // Only master-master messages are tracked and retried.
-                    if is_master_to_master {
-                        let new_message_opt =
-                            state
-                                .message_bus
-                                .add_message(dst_node_id, msg, broadcast_msg.clone());
-                        if let Some(new_message) = new_message_opt {
-                            write_node_message(&new_message).unwrap();
-                            eprintln!(
-                                "{} [{}] Sent broadcast({}) to {} [read-sync]",
-                                get_ts(),
+                    );
+
+                    if Role::should_track(state.role, state.overlay.role(dst_node_id)) {
+                        if state.send_tracked(dst_node_id, broadcast_msg) {
+                            node_log!(
                                 state.node_id,
+                                "Sent broadcast({}) to {} [read-sync]",
                                 msg,
                                 dst_node_id
                             );
                         }
                     } else {
-                        write_node_message(&broadcast_msg).unwrap();
-                        eprintln!(
-                            "{} [{}] Sent broadcast({}) to {} [read-sync][no-tracking]",
-                            get_ts(),
+                        write_node_message(&broadcast_msg).expect("Cannot write message.");
+                        node_log!(
                             state.node_id,
+                            "Sent broadcast({}) to {} [read-sync][no-tracking]",
                             msg,
                             dst_node_id
                         );
@@ -137,38 +327,35 @@ fn handle_message(
             }
         }
         RequestType::BroadcastOk(broadcast_ok) => {
-            let msg = broadcast_ok.msg_id.unwrap();
-            eprintln!(
-                "{} [{}] Received broadcast_ok({}) from {}",
-                get_ts(),
+            let msg = broadcast_ok
+                .acked_value
+                .ok_or("broadcast_ok without an acked_value")?;
+            node_log!(
                 state.node_id,
+                "Received broadcast_ok({}) from {}",
                 msg,
                 request.src
             );
-            state.message_bus.delete_message(&request.src, msg);
+            // The neighbor may have been removed since we sent the broadcast.
+            state.message_bus.delete_message_checked(&request.src, msg);
+            state.mark_known(&request.src, msg);
         }
         RequestType::Read(read_body) => {
-            eprintln!(
-                "{} [{}] Received read from {}",
-                get_ts(),
-                state.node_id,
-                request.src
-            );
-            let read_ok = NodeMessage {
-                src: state.node_id.clone(),
-                dest: request.src.clone(),
-                body: ReadResponse {
-                    _type: "read_ok".into(),
-                    messages: state.values.iter().copied().collect(),
-                    in_reply_to: read_body.msg_id,
-                    msg_id: None,
-                },
-            };
-
-            if is_customer_node(&request.src) {
+            node_log!(state.node_id, "Received read from {}", request.src);
+            if state.tree_read && src_role == Role::Client {
+                state.start_tree_read(
+                    ReadRequester::Client {
+                        src: request.src,
+                        msg_id: read_body.msg_id,
+                    },
+                    None,
+                );
+                return Ok(());
+            }
+            if src_role == Role::Client {
                 let mut read_replicate_nodes = HashSet::new();
 
-                if is_main_node(&state.node_id) {
+                if state.role == Role::Hub {
                     for replicate_node in state.neighborhood.iter() {
                         if replicate_node == &state.node_id {
                             continue;
@@ -191,217 +378,765 @@ fn handle_message(
                     if neighborhood_node_id == state.node_id {
                         continue;
                     }
+                    let in_flight = state
+                        .last_replicate_reads
+                        .get(&neighborhood_node_id)
+                        .is_some_and(|sent_at| sent_at.elapsed() < REPLICATE_READ_COALESCE);
+                    if in_flight {
+                        node_log!(
+                            state.node_id,
+                            "Coalescing replicate read to {}",
+                            neighborhood_node_id
+                        );
+                        continue;
+                    }
 
-                    let new_read = NodeMessage {
-                        src: state.node_id.clone(),
-                        dest: neighborhood_node_id.clone(),
-                        body: RequestType::Read(ReadBody {
+                    let new_read = NodeMessage::new(
+                        state.node_id.clone(),
+                        neighborhood_node_id.clone(),
+                        RequestType::Read(ReadBody {
                             in_reply_to: None,
                             msg_id: None,
                         }),
-                    };
+                    );
                     write_node_message(&new_read).expect("Cannot write message.");
-                    eprintln!(
-                        "{} [{}] Sent replicate read to {}",
-                        get_ts(),
+                    state
+                        .last_replicate_reads
+                        .insert(neighborhood_node_id.clone(), Instant::now());
+                    node_log!(
                         state.node_id,
+                        "Sent replicate read to {}",
                         neighborhood_node_id
                     );
                 }
-                state.customer_read_bus.add(read_ok);
+                state
+                    .customer_read_bus
+                    .add(request.src.clone(), read_body.msg_id);
             } else {
+                let read_ok = NodeMessage::new(
+                    state.node_id.clone(),
+                    request.src.clone(),
+                    ReadResponse {
+                        _type: "read_ok".into(),
+                        messages: state.read_values(),
+                        // Only peers use the version, deferred client reads get none.
+                        version: Some(state.version),
+                        in_reply_to: read_body.msg_id,
+                        msg_id: None,
+                    },
+                );
                 write_node_message(&read_ok).expect("Cannot write message.");
-                eprintln!(
-                    "{} [{}] Sent read_ok to {}: {:?}",
-                    get_ts(),
+                node_log!(
                     state.node_id,
+                    "Sent read_ok to {}: {:?}",
                     request.src,
                     read_ok.body.messages
                 );
             }
         }
         RequestType::Broadcast(broadcast_request) => {
-            eprintln!(
-                "{} [{}] Received broadcast({}) from {}",
-                get_ts(),
+            node_log!(
                 state.node_id,
+                "Received broadcast({}) from {}",
                 broadcast_request.message,
                 request.src
             );
-            state.values.insert(broadcast_request.message);
-
-            let is_customer = is_customer_node(&request.src);
-            let is_master_broadcast = is_main_node(&request.src) && is_main_node(&state.node_id);
+            state.apply_value(broadcast_request.message);
 
-            if is_customer || is_master_broadcast {
-                let n = NodeMessage {
-                    src: state.node_id.clone(),
-                    dest: request.src.clone(),
-                    body: ResponseBody::Basic(BasicResponse {
+            if Role::should_ack(src_role, state.role) {
+                let n = NodeMessage::new(
+                    state.node_id.clone(),
+                    request.src.clone(),
+                    ResponseBody::Basic(BasicResponse {
                         _type: "broadcast_ok".into(),
                         in_reply_to: broadcast_request.msg_id,
-                        msg_id: Some(broadcast_request.message),
+                        msg_id: None,
+                        acked_value: Some(broadcast_request.message),
                     }),
-                };
+                );
                 write_node_message(&n).expect("Cannot write message.");
-                eprintln!(
-                    "{} [{}] Sent broadcast_ok({}) to {}",
-                    get_ts(),
+                node_log!(
                     state.node_id,
+                    "Sent broadcast_ok({}) to {}",
                     broadcast_request.message,
                     request.src
                 );
             }
 
-            // Node is sending us broadcast, we don't need to broadcast to it.
-            state
-                .message_bus
-                .delete_message_checked(&request.src, broadcast_request.message);
+            forward_value(
+                state,
+                &request.src,
+                broadcast_request.message,
+                broadcast_request.ttl,
+            );
+        }
+        RequestType::BroadcastBatch(batch) => {
+            node_log!(
+                state.node_id,
+                "Received broadcast_batch({:?}) from {}",
+                batch.messages,
+                request.src
+            );
+            for value in batch.messages.iter() {
+                state.apply_value(*value);
+                forward_value(state, &request.src, *value, batch.ttl);
+            }
 
-            if state.past_broadcast.contains(&broadcast_request.message) {
+            let batch_ok = NodeMessage::new(
+                state.node_id.clone(),
+                request.src.clone(),
+                RequestType::BroadcastBatchOk(BroadcastBatchBody {
+                    messages: batch.messages,
+                    ttl: None,
+                    in_reply_to: batch.msg_id,
+                    msg_id: None,
+                }),
+            );
+            write_node_message(&batch_ok).expect("Cannot write message.");
+        }
+        RequestType::BroadcastBatchOk(batch_ok) => {
+            for value in batch_ok.messages {
+                state
+                    .message_bus
+                    .delete_message_checked(&request.src, value);
+                state.mark_known(&request.src, value);
+            }
+        }
+        RequestType::TreeRead(tree_read) => {
+            node_log!(state.node_id, "Received tree_read from {}", request.src);
+            let parent = request.src.clone();
+            state.start_tree_read(
+                ReadRequester::Peer {
+                    src: request.src,
+                    msg_id: tree_read.msg_id,
+                },
+                Some(&parent),
+            );
+        }
+        RequestType::TreeReadOk(tree_read_ok) => {
+            let Some(read_id) = tree_read_ok.in_reply_to else {
+                return Ok(());
+            };
+            let is_done = match state.tree_reads.get_mut(&read_id) {
+                Some(tree_read) => tree_read.gather.add(&request.src, tree_read_ok.messages),
+                // Late reply to a read we already answered.
+                None => false,
+            };
+            if is_done {
+                state.finish_tree_read(read_id);
+            }
+        }
+        RequestType::PendingSummary(summary_request) => {
+            let response = NodeMessage::new(
+                state.node_id.clone(),
+                request.src.clone(),
+                PendingSummaryResponse {
+                    _type: "pending_summary_ok".into(),
+                    in_reply_to: summary_request.msg_id,
+                    pending: state.pending_summary(),
+                },
+            );
+            write_node_message(&response).expect("Cannot write message.");
+        }
+        RequestType::DeliveryCheck(check) => {
+            let response = NodeMessage::new(
+                state.node_id.clone(),
+                request.src.clone(),
+                DeliveryCheckResponse {
+                    _type: "delivery_check_ok".into(),
+                    in_reply_to: check.msg_id,
+                    applied: state.deliveries.applied(),
+                    duplicates: state.deliveries.duplicates(),
+                },
+            );
+            write_node_message(&response).expect("Cannot write message.");
+        }
+        RequestType::SetParam(set_param) => {
+            if !CONTROL_ENABLED {
+                node_log!(
+                    state.node_id,
+                    "Ignoring set_param({}), built without the control feature",
+                    set_param.name
+                );
                 return Ok(());
             }
+            let duration = Duration::from_millis(set_param.value);
+            match set_param.name.as_str() {
+                "wait_ms" => state.message_bus.set_wait_time(duration),
+                "read_wait_ms" => state.customer_read_bus.set_read_wait_time(duration),
+                _ => return Err(set_param.unknown_param(&PARAMS)),
+            }
+            node_log!(
+                state.node_id,
+                "Set {} to {}ms",
+                set_param.name,
+                set_param.value
+            );
+            let response = NodeMessage::new(state.node_id.clone(), request.src, set_param.ok());
+            write_node_message(&response).expect("Cannot write message.");
+        }
+        RequestType::AddNeighbor(neighbor) => {
+            if !state.neighborhood.contains(&neighbor.node_id) {
+                state.neighborhood.push(neighbor.node_id.clone());
+            }
+            state.message_bus.add_neighbor(&neighbor.node_id);
+            state.message_bus.set_priority(
+                &neighbor.node_id,
+                Role::link_priority(state.role, state.overlay.role(&neighbor.node_id)),
+            );
+            node_log!(
+                state.node_id,
+                "Added neighbor {}, neighborhood: {:?}",
+                neighbor.node_id,
+                state.neighborhood
+            );
 
-            for neighborhood_node_id in state.neighborhood.iter() {
-                if neighborhood_node_id == &request.src {
-                    continue;
-                }
-                let node = NodeMessage {
-                    src: state.node_id.clone(),
-                    dest: neighborhood_node_id.clone(),
-                    body: BroadcastResponse {
-                        _type: "broadcast".into(),
-                        in_reply_to: None,
-                        msg_id: None,
-                        message: broadcast_request.message,
-                    },
-                };
-                let is_master_to_master =
-                    is_main_node(&neighborhood_node_id) && is_main_node(&state.node_id);
-                // Only master-master messages are tracked and retried.
-                if is_master_to_master {
-                    let new_message_opt = state.message_bus.add_message(
-                        neighborhood_node_id,
-                        broadcast_request.message,
-                        node.clone(),
-                    );
-                    if let Some(new_message) = new_message_opt {
-                        write_node_message(&new_message).unwrap();
-                        eprintln!(
-                            "{} [{}] Sent broadcast({}) to {}",
-                            get_ts(),
-                            state.node_id,
-                            broadcast_request.message,
-                            neighborhood_node_id
-                        );
-                    }
-                } else {
-                    write_node_message(&node).unwrap();
-                    eprintln!(
-                        "{} [{}] Sent broadcast({}) to {} [no-tracking]",
-                        get_ts(),
-                        state.node_id,
-                        broadcast_request.message,
-                        neighborhood_node_id
-                    );
-                }
+            let n = NodeMessage::new(
+                state.node_id.clone(),
+                request.src.clone(),
+                ResponseBody::Basic(BasicResponse {
+                    _type: "add_neighbor_ok".into(),
+                    in_reply_to: neighbor.msg_id,
+                    msg_id: None,
+                    acked_value: None,
+                }),
+            );
+            write_node_message(&n).expect("Cannot write message.");
+        }
+        RequestType::Error(error) => {
+            if error.code == NodeError::NodeNotFound && state.neighborhood.contains(&request.src) {
+                // Same as a remove_neighbor: stop retrying to a node that does not exist.
+                state.suspected_down.insert(request.src.clone());
+                state.neighborhood.retain(|node_id| node_id != &request.src);
+                state.message_bus.remove_neighbor(&request.src);
+                node_log!(
+                    state.node_id,
+                    "Neighbor {} not found, dropped from neighborhood: {:?}",
+                    request.src,
+                    state.neighborhood
+                );
+            } else {
+                node_log!(
+                    state.node_id,
+                    "Received error from {}: {:?}",
+                    request.src,
+                    error
+                );
             }
+        }
+        RequestType::RemoveNeighbor(neighbor) => {
+            state
+                .neighborhood
+                .retain(|node_id| node_id != &neighbor.node_id);
+            state.message_bus.remove_neighbor(&neighbor.node_id);
+            node_log!(
+                state.node_id,
+                "Removed neighbor {}, neighborhood: {:?}",
+                neighbor.node_id,
+                state.neighborhood
+            );
 
-            state.past_broadcast.insert(broadcast_request.message);
+            let n = NodeMessage::new(
+                state.node_id.clone(),
+                request.src.clone(),
+                ResponseBody::Basic(BasicResponse {
+                    _type: "remove_neighbor_ok".into(),
+                    in_reply_to: neighbor.msg_id,
+                    msg_id: None,
+                    acked_value: None,
+                }),
+            );
+            write_node_message(&n).expect("Cannot write message.");
         }
         RequestType::Topology(topology) => {
-            eprintln!(
-                "{} [{}] Received topology from {}: {:?}",
-                get_ts(),
+            node_log!(
                 state.node_id,
+                "Received topology from {}: {:?}",
                 request.src,
                 topology.topology
             );
+            state.overlay = StarOfStars::new(topology.topology.len(), HUB_SPAN);
             state.topology = topology.topology;
-            let node_number: String = state.node_id.chars().skip(1).collect();
-            state.neighborhood = match node_number.parse::<u64>().unwrap() {
-                0 => vec!["n1", "n2", "n3", "n4", "n5"],
-                1..=4 => vec!["n0"],
-                5 => vec!["n0", "n6", "n7", "n8", "n9", "n10"],
-                6..=9 => vec!["n5"],
-                10 => vec!["n5", "n11", "n12", "n13", "n14", "n15"],
-                11..=14 => vec!["n10"],
-                15 => vec!["n10", "n16", "n17", "n18", "n19", "n20"],
-                16..=19 => vec!["n15"],
-                20 => vec!["n15", "n21", "n22", "n23", "n24"],
-                21..=24 => vec!["n20"],
-                _ => vec![],
-            }
-            .into_iter()
-            .map(|v| v.to_string())
-            .collect();
+            state.role = state.overlay.role(&state.node_id);
+            state.neighborhood = state.overlay.neighborhood(&state.node_id);
+            state
+                .neighborhood
+                .retain(|node_id| !state.suspected_down.contains(node_id));
             state.message_bus.update_neighborhood(&state.neighborhood);
-            eprintln!(
-                "{} [{}] Ignoring Maelstrom topology, setting neighborhood: {:?}",
-                get_ts(),
+            for node_id in state.neighborhood.iter() {
+                state.message_bus.set_priority(
+                    node_id,
+                    Role::link_priority(state.role, state.overlay.role(node_id)),
+                );
+            }
+            node_log!(
                 state.node_id,
+                "Ignoring Maelstrom topology, setting neighborhood: {:?}",
                 state.neighborhood
             );
 
-            let n = NodeMessage {
-                src: state.node_id.clone(),
-                dest: request.src.clone(),
-                body: ResponseBody::Basic(BasicResponse {
+            let n = NodeMessage::new(
+                state.node_id.clone(),
+                request.src.clone(),
+                ResponseBody::Basic(BasicResponse {
                     _type: "topology_ok".into(),
                     in_reply_to: topology.msg_id,
                     msg_id: None,
+                    acked_value: None,
                 }),
-            };
-            write_node_message(&n).expect("Cannot write message.");
-            eprintln!(
-                "{} [{}] Sent topology_ok to {}",
-                get_ts(),
-                state.node_id,
-                request.src
             );
+            write_node_message(&n).expect("Cannot write message.");
+            node_log!(state.node_id, "Sent topology_ok to {}", request.src);
+            state.start_initial_sync();
         }
     };
 
     Ok(())
 }
 
-fn get_ts() -> String {
-    let ts = std::time::SystemTime::now()
-        .duration_since(std::time::SystemTime::UNIX_EPOCH)
-        .unwrap();
-    format!("{}.{}", ts.as_secs(), ts.subsec_millis())
+/// Forward a value received from `src` to the neighbors that may not have it yet, once.
+fn forward_value(state: &mut GlobalState, src: &str, value: u64, ttl: Option<u32>) {
+    // Node is sending us broadcast, we don't need to broadcast to it.
+    state.message_bus.delete_message_checked(src, value);
+    state.mark_known(src, value);
+
+    if state.past_broadcast.contains(&value) {
+        return;
+    }
+
+    // Client broadcasts carry no ttl, they start with the full hop budget.
+    let ttl = ttl.unwrap_or(MAX_HOPS);
+    if ttl == 0 {
+        node_log!(
+            state.node_id,
+            "Not forwarding broadcast({}) from {}, out of hops",
+            value,
+            src
+        );
+        state.past_broadcast.insert(value);
+        return;
+    }
+
+    for neighborhood_node_id in state.neighborhood.clone().iter() {
+        if neighborhood_node_id == src || state.is_known_by(neighborhood_node_id, value) {
+            continue;
+        }
+        let node = NodeMessage::new(
+            state.node_id.clone(),
+            neighborhood_node_id.clone(),
+            BroadcastResponse {
+                _type: "broadcast".into(),
+                in_reply_to: None,
+                msg_id: None,
+                message: value,
+                ttl: Some(ttl - 1),
+            },
+        );
+        if Role::should_track(state.role, state.overlay.role(neighborhood_node_id)) {
+            if state.send_tracked(neighborhood_node_id, node) {
+                node_log!(
+                    state.node_id,
+                    "Sent broadcast({}) to {}",
+                    value,
+                    neighborhood_node_id
+                );
+            }
+        } else {
+            write_node_message(&node).expect("Cannot write message.");
+            node_log!(
+                state.node_id,
+                "Sent broadcast({}) to {} [no-tracking]",
+                value,
+                neighborhood_node_id
+            );
+        }
+    }
+
+    state.past_broadcast.insert(value);
 }
 
 struct GlobalState {
     node_id: String,
     neighborhood: Vec<String>,
+    /// Neighbors Maelstrom reported as unknown, left out of the neighborhood from then on.
+    suspected_down: HashSet<String>,
+    overlay: StarOfStars,
+    /// Our own role in `overlay`, updated with the topology.
+    role: Role,
     topology: HashMap<String, Vec<String>>,
-    values: HashSet<u64>,
+    values: SnapshotSet,
+    /// Every value applied to `values`, to check none is applied twice.
+    deliveries: DeliveryLog,
+    /// Where newly learned values are persisted, if enabled.
+    value_log: Option<ValueLog>,
+    /// Bumped for every value added to `values`, sent along internal read_ok.
+    version: u64,
+    /// Last version merged from each peer's read_ok.
+    peer_versions: HashMap<String, u64>,
     past_broadcast: HashSet<u64>,
+    /// Values each peer is known to have, because it sent or acknowledged them.
+    known_by: HashMap<String, HashSet<u64>>,
     message_bus: MessageBus,
     customer_read_bus: CustomerBus,
+    /// Coalesce the broadcasts to other nodes into `broadcast_batch` messages, `None` sends
+    /// every value on its own as soon as it is learned.
+    batch: Option<BatchConfig>,
+    /// Push the values a peer is missing, as seen in its read_ok, back to it, see
+    /// `READ_REPAIR_ENV`.
+    read_repair: bool,
+    /// Answer client reads by walking the overlay as a tree, instead of syncing with the
+    /// neighborhood and waiting `READ_WAIT_TIME`, see `TREE_READ_ENV`.
+    tree_read: bool,
+    /// Tree reads waiting on our subtree, by the msg_id of the tree_read we sent.
+    tree_reads: HashMap<u64, TreeRead>,
+    read_id_counter: u32,
+    /// When the last replicate read was sent to each peer, see `REPLICATE_READ_COALESCE`.
+    last_replicate_reads: HashMap<String, Instant>,
+    /// Client reads and broadcasts held until the initial sync, see `WAIT_FOR_SYNC_ENV`.
+    readiness: Readiness<Request<RequestType>>,
+    /// Neighbors whose read_ok to the initial read we are still waiting on.
+    initial_sync: Option<Gather<()>>,
+}
+
+/// Who a tree read must be answered to.
+#[derive(Debug, Clone)]
+enum ReadRequester {
+    Client { src: String, msg_id: Option<u64> },
+    Peer { src: String, msg_id: Option<u64> },
+}
+
+#[derive(Debug, Clone)]
+struct TreeRead {
+    gather: Gather<Vec<u64>>,
+    requester: ReadRequester,
+}
+
+impl GlobalState {
+    /// Hand `request` back to be handled now, unless it is a client request arriving before
+    /// the initial sync is done.
+    fn hold_until_ready(&mut self, request: Request<RequestType>) -> Option<Request<RequestType>> {
+        let (node_message, _) = &request;
+        let is_client_request = self.overlay.role(&node_message.src) == Role::Client
+            && matches!(
+                node_message.body,
+                RequestType::Read(_) | RequestType::Broadcast(_)
+            );
+        if !is_client_request {
+            return Some(request);
+        }
+        self.readiness.hold(request)
+    }
+
+    /// Read every neighbor once before answering clients, see `WAIT_FOR_SYNC_ENV`.
+    fn start_initial_sync(&mut self) {
+        if self.readiness.is_ready() || self.initial_sync.is_some() {
+            return;
+        }
+        let neighbors: Vec<String> = self
+            .neighborhood
+            .iter()
+            .filter(|node_id| **node_id != self.node_id)
+            .cloned()
+            .collect();
+        for neighbor in neighbors.iter() {
+            let read = NodeMessage::new(
+                self.node_id.clone(),
+                neighbor.clone(),
+                RequestType::Read(ReadBody {
+                    in_reply_to: None,
+                    msg_id: None,
+                }),
+            );
+            write_node_message(&read).expect("Cannot write message.");
+        }
+        node_log!(self.node_id, "Sent initial sync read to {:?}", neighbors);
+        self.initial_sync = Some(Gather::new(neighbors, INITIAL_SYNC_TIMEOUT_MS));
+    }
+
+    /// The values to answer a read with, see `SNAPSHOT_READS`.
+    fn read_values(&mut self) -> Vec<u64> {
+        if SNAPSHOT_READS {
+            self.values.snapshot().to_vec()
+        } else {
+            self.values.as_set().iter().copied().collect()
+        }
+    }
+
+    /// Apply a value a client or a peer delivered, returning whether it was new. Retransmitted
+    /// values are not applied again, see `deliveries`.
+    fn apply_value(&mut self, value: u64) -> bool {
+        let applied = deliver(&mut self.values, &mut self.deliveries, value, |_| {});
+        if applied {
+            self.version += 1;
+            self.persist_value(value);
+        }
+        applied
+    }
+
+    /// Forward a read to every neighbor but `parent`, our subtree in the overlay, and answer
+    /// `requester` once they all replied.
+    fn start_tree_read(&mut self, requester: ReadRequester, parent: Option<&str>) {
+        self.read_id_counter += 1;
+        let read_id = generate_id(&self.node_id, self.read_id_counter);
+        let children: Vec<String> = self
+            .neighborhood
+            .iter()
+            .filter(|node_id| Some(node_id.as_str()) != parent && **node_id != self.node_id)
+            .cloned()
+            .collect();
+
+        for child in children.iter() {
+            let tree_read = NodeMessage::new(
+                self.node_id.clone(),
+                child.clone(),
+                RequestType::TreeRead(TreeReadBody {
+                    in_reply_to: None,
+                    msg_id: Some(read_id),
+                }),
+            );
+            write_node_message(&tree_read).expect("Cannot write message.");
+        }
+        node_log!(self.node_id, "Sent tree_read {} to {:?}", read_id, children);
+
+        let gather = Gather::new(children, TREE_READ_TIMEOUT_MS);
+        let is_done = gather.is_done();
+        self.tree_reads
+            .insert(read_id, TreeRead { gather, requester });
+        if is_done {
+            self.finish_tree_read(read_id);
+        }
+    }
+
+    /// Answer a tree read with our values merged with whatever the subtree sent back.
+    fn finish_tree_read(&mut self, read_id: u64) {
+        let Some(tree_read) = self.tree_reads.remove(&read_id) else {
+            return;
+        };
+        if !tree_read.gather.is_done() {
+            node_log!(
+                self.node_id,
+                "tree_read {} timed out waiting on {:?}",
+                read_id,
+                tree_read.gather.missing().collect::<Vec<_>>()
+            );
+        }
+
+        let mut messages = self.values.as_set().clone();
+        for (_, subtree_values) in tree_read.gather.into_replies() {
+            messages.extend(subtree_values);
+        }
+        let messages: Vec<u64> = messages.into_iter().collect();
+
+        match tree_read.requester {
+            ReadRequester::Client { src, msg_id } => {
+                let read_ok = NodeMessage::new(
+                    self.node_id.clone(),
+                    src,
+                    ReadResponse {
+                        _type: "read_ok".into(),
+                        messages,
+                        version: None,
+                        in_reply_to: msg_id,
+                        msg_id: None,
+                    },
+                );
+                write_node_message(&read_ok).expect("Cannot write message.");
+            }
+            ReadRequester::Peer { src, msg_id } => {
+                let tree_read_ok = NodeMessage::new(
+                    self.node_id.clone(),
+                    src,
+                    RequestType::TreeReadOk(TreeReadOkBody {
+                        messages,
+                        in_reply_to: msg_id,
+                        msg_id: None,
+                    }),
+                );
+                write_node_message(&tree_read_ok).expect("Cannot write message.");
+            }
+        }
+    }
+
+    /// Answer the tree reads whose subtree took too long with the values we have.
+    fn finish_expired_tree_reads(&mut self) {
+        let expired: Vec<u64> = self
+            .tree_reads
+            .iter()
+            .filter(|(_, tree_read)| tree_read.gather.is_expired())
+            .map(|(read_id, _)| *read_id)
+            .collect();
+        for read_id in expired {
+            self.finish_tree_read(read_id);
+        }
+    }
+
+    /// Send a broadcast to another hub, tracked by the message bus until acknowledged and
+    /// batched with other values when `batch` is set. Returns whether anything was sent now.
+    fn send_tracked(&mut self, dst: &str, broadcast: NodeMessage<BroadcastResponse>) -> bool {
+        let value = broadcast.body.message;
+        let ttl = broadcast.body.ttl;
+        let Some(new_message) = self.message_bus.add_message(dst, value, broadcast) else {
+            return false;
+        };
+        for dropped in self.message_bus.drop_overflow(dst) {
+            node_log!(
+                self.node_id,
+                "Dropped pending broadcast({}) to {}, left to the read sync",
+                dropped,
+                dst
+            );
+        }
+
+        // Batches are an internal message type, only other nodes understand them.
+        let batch = self
+            .batch
+            .filter(|_| self.overlay.role(dst).kind().is_internal());
+        match batch {
+            None => {
+                write_node_message(&new_message).expect("Cannot write message.");
+                true
+            }
+            Some(config) => match self.message_bus.push_batch(dst, value, ttl, &config) {
+                Some(batch) => {
+                    self.send_batch(dst, batch);
+                    true
+                }
+                None => false,
+            },
+        }
+    }
+
+    fn send_batch(&self, dst: &str, batch: Batch) {
+        node_log!(
+            self.node_id,
+            "Sending broadcast_batch({:?}) to {}",
+            batch.values,
+            dst
+        );
+        let batch_msg = NodeMessage::new(
+            self.node_id.clone(),
+            dst.to_string(),
+            RequestType::BroadcastBatch(BroadcastBatchBody {
+                messages: batch.values,
+                ttl: batch.ttl,
+                in_reply_to: None,
+                msg_id: None,
+            }),
+        );
+        write_node_message(&batch_msg).expect("Cannot write message.");
+    }
+
+    /// Send the batches whose oldest value waited long enough.
+    fn flush_ready_batches(&mut self) {
+        let Some(config) = self.batch else {
+            return;
+        };
+        for (dst, batch) in self.message_bus.ready_batches(&config) {
+            self.send_batch(&dst, batch);
+        }
+    }
+
+    /// Record that `node_id` has `value`, so we do not forward it there again.
+    fn mark_known(&mut self, node_id: &str, value: u64) {
+        if self.overlay.role(node_id) == Role::Client {
+            return;
+        }
+        self.known_by
+            .entry(node_id.to_string())
+            .or_default()
+            .insert(value);
+    }
+
+    fn is_known_by(&self, node_id: &str, value: u64) -> bool {
+        self.known_by
+            .get(node_id)
+            .is_some_and(|values| values.contains(&value))
+    }
+
+    /// Append a newly learned value to the value log, if enabled.
+    fn persist_value(&mut self, value: u64) {
+        if let Some(value_log) = self.value_log.as_mut() {
+            if let Err(err) = value_log.append(value) {
+                node_log!(self.node_id, "Cannot persist value {}: {}", value, err);
+            }
+        }
+    }
+
+    /// What the node is still waiting on, to debug a node that looks stuck.
+    fn pending_summary(&self) -> PendingSummary {
+        PendingSummary {
+            unacked_broadcasts: self.message_bus.unacked(),
+            pending_reads: self
+                .customer_read_bus
+                .messages
+                .iter()
+                .map(|(_, read)| read.client.clone())
+                .collect(),
+        }
+    }
+}
+
+/// A client read waiting for replicate reads before being answered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct DeferredRead {
+    client: String,
+    /// msg_id of the client's read, the read_ok answers to it.
+    msg_id: Option<u64>,
+}
+
+impl DeferredRead {
+    /// The read_ok answering this read with `messages`. Clients get no version.
+    fn read_ok(&self, node_id: &str, messages: Vec<u64>) -> NodeMessage<ReadResponse> {
+        NodeMessage::new(
+            node_id.to_string(),
+            self.client.clone(),
+            ReadResponse {
+                _type: "read_ok".into(),
+                messages,
+                version: None,
+                in_reply_to: self.msg_id,
+                msg_id: None,
+            },
+        )
+    }
 }
 
 #[derive(Debug, Clone)]
 struct CustomerBus {
-    messages: VecDeque<(Timer, NodeMessage<ReadResponse>)>,
+    messages: VecDeque<(Timer, DeferredRead)>,
+    /// How long a read is deferred, `READ_WAIT_TIME` unless changed by a `set_param`.
+    read_wait_time: Duration,
 }
 
 impl CustomerBus {
-    /// Add an element to the customer bus with a newly created timer.
-    pub fn add(&mut self, message: NodeMessage<ReadResponse>) {
+    /// Defer the read `msg_id` of `client` with a newly created timer. A read already waiting
+    /// (a client retry) is not deferred twice, so every read gets exactly one read_ok.
+    pub fn add(&mut self, client: String, msg_id: Option<u64>) {
+        let read = DeferredRead { client, msg_id };
+        let waiting = read.msg_id.is_some() && self.messages.iter().any(|(_, r)| r == &read);
+        if waiting {
+            return;
+        }
         self.messages.push_back((
             Timer {
                 instant: Instant::now(),
-                duration: READ_WAIT_TIME,
+                duration: self.read_wait_time,
             },
-            message,
+            read,
         ));
     }
 
+    /// Defer the reads for `read_wait_time` from now on, the ones already waiting included.
+    pub fn set_read_wait_time(&mut self, read_wait_time: Duration) {
+        self.read_wait_time = read_wait_time;
+        for (timer, _) in self.messages.iter_mut() {
+            timer.duration = read_wait_time;
+        }
+    }
+
     /// Pop an element from the customer bus, this will happend if there is an element
     /// and if the timer is done.
-    pub fn pop(&mut self) -> Option<NodeMessage<ReadResponse>> {
+    pub fn pop(&mut self) -> Option<DeferredRead> {
         if let Some((timer, _)) = self.messages.front() {
             if timer.is_done() {
                 return self.messages.pop_front().map(|(_, m)| m);
@@ -410,11 +1145,52 @@ impl CustomerBus {
 
         None
     }
+
+    /// Take every deferred read, whether its timer is done or not.
+    pub fn drain_all(&mut self) -> Vec<DeferredRead> {
+        self.messages.drain(..).map(|(_, m)| m).collect()
+    }
 }
 
 #[derive(Debug, Clone)]
 struct MessageBus {
-    neighborhoods: HashMap<String, (Timer, HashMap<u64, NodeMessage<BroadcastResponse>>)>,
+    neighborhoods: HashMap<String, (Timer, PendingBroadcasts)>,
+    /// Values waiting to be sent to each neighbor in the next batch.
+    batches: HashMap<String, Batch>,
+    /// Neighbors with a higher priority are serviced first by `pick_message`, 0 by default.
+    priorities: HashMap<String, u8>,
+    /// How long a neighbor is left alone between two sends, `WAIT_TIME` unless changed by a
+    /// `set_param`.
+    wait_time: Duration,
+    /// Bounds the broadcasts pending for each neighbor, see `OVERFLOW_POLICY`.
+    overflow: OverflowPolicy,
+}
+
+/// Values accumulated for a neighbor, see `BatchConfig`.
+#[derive(Debug, Clone)]
+struct Batch {
+    started: Instant,
+    values: Vec<u64>,
+    /// Lowest hop budget of the batched values.
+    ttl: Option<u32>,
+}
+
+impl Batch {
+    fn new() -> Batch {
+        Batch {
+            started: Instant::now(),
+            values: vec![],
+            ttl: None,
+        }
+    }
+
+    fn push(&mut self, value: u64, ttl: Option<u32>) {
+        self.values.push(value);
+        self.ttl = match (self.ttl, ttl) {
+            (Some(current), Some(ttl)) => Some(current.min(ttl)),
+            (current, ttl) => current.or(ttl),
+        };
+    }
 }
 
 impl MessageBus {
@@ -425,27 +1201,102 @@ impl MessageBus {
                 (
                     Timer {
                         instant: Instant::now(),
-                        duration: WAIT_TIME,
+                        duration: self.wait_time,
                     },
-                    HashMap::new(),
+                    PendingBroadcasts::default(),
                 ),
             );
         }
     }
 
-    /// Pick a message from the Bus. We should reset the timer every time we send
-    /// a message from the Bus.
-    pub fn pick_message(&mut self) -> Option<&NodeMessage<BroadcastResponse>> {
-        for (timer, responses) in self.neighborhoods.values_mut() {
-            if timer.is_done() {
-                timer.reset();
-                return responses.values().next();
-            }
+    /// Start tracking a new neighbor, keeping the pending messages of the existing ones.
+    pub fn add_neighbor(&mut self, node_id: &str) {
+        self.neighborhoods
+            .entry(node_id.to_string())
+            .or_insert_with(|| {
+                (
+                    Timer {
+                        instant: Instant::now(),
+                        duration: self.wait_time,
+                    },
+                    PendingBroadcasts::default(),
+                )
+            });
+    }
+
+    /// Retry the neighbors every `wait_time` from now on, including the timers running.
+    pub fn set_wait_time(&mut self, wait_time: Duration) {
+        self.wait_time = wait_time;
+        for (timer, _) in self.neighborhoods.values_mut() {
+            timer.duration = wait_time;
+        }
+    }
+
+    /// Stop tracking a neighbor, dropping the messages still pending for it.
+    pub fn remove_neighbor(&mut self, node_id: &str) {
+        self.neighborhoods.remove(node_id);
+        self.batches.remove(node_id);
+        self.priorities.remove(node_id);
+    }
+
+    pub fn set_priority(&mut self, node_id: &str, priority: u8) {
+        self.priorities.insert(node_id.to_string(), priority);
+    }
+
+    /// Queue a value for the next batch to `node_id`, returning the batch once it is full.
+    pub fn push_batch(
+        &mut self,
+        node_id: &str,
+        value: u64,
+        ttl: Option<u32>,
+        config: &BatchConfig,
+    ) -> Option<Batch> {
+        let batch = self
+            .batches
+            .entry(node_id.to_string())
+            .or_insert_with(Batch::new);
+        batch.push(value, ttl);
+        if batch.values.len() >= config.max_size {
+            return self.batches.remove(node_id);
         }
 
         None
     }
 
+    /// Take the batches whose oldest value waited `max_delay`.
+    pub fn ready_batches(&mut self, config: &BatchConfig) -> Vec<(String, Batch)> {
+        let ready: Vec<String> = self
+            .batches
+            .iter()
+            .filter(|(_, batch)| batch.started.elapsed() >= config.max_delay)
+            .map(|(node_id, _)| node_id.clone())
+            .collect();
+        ready
+            .into_iter()
+            .filter_map(|node_id| self.batches.remove_entry(&node_id))
+            .collect()
+    }
+
+    /// Pick a message from the Bus. We should reset the timer every time we send
+    /// a message from the Bus.
+    ///
+    /// Among the neighbors due for a message, the one with the highest priority goes first.
+    /// Which of its messages is sent depends on `PICK_POLICY`.
+    pub fn pick_message(&mut self) -> Option<&NodeMessage<BroadcastResponse>> {
+        let due = self
+            .neighborhoods
+            .iter()
+            .filter(|(_, (timer, responses))| timer.is_done() && !responses.is_empty())
+            .map(|(node_id, _)| {
+                let priority = self.priorities.get(node_id).copied().unwrap_or(0);
+                (node_id.as_str(), priority)
+            });
+        let node_id = highest_priority(due)?.to_string();
+        let (timer, responses) = self.neighborhoods.get_mut(&node_id)?;
+        timer.reset();
+        responses.pick(PICK_POLICY)
+    }
+
     /// If we add a message, we are sending a message to a node. For politeness, we add a timer to send another
     /// message to this node. Unless we receive something from it.
     ///
@@ -459,16 +1310,33 @@ impl MessageBus {
         let (timer, nodes) = self.neighborhoods.get_mut(node_id).unwrap();
         timer.reset();
 
-        match nodes.insert(message_value, message.clone()) {
-            Some(_) => None,
-            None => Some(message),
+        if nodes.insert(message_value, message.clone()) {
+            Some(message)
+        } else {
+            None
         }
     }
 
-    /// Remove message from a node specific slot.
-    pub fn delete_message(&mut self, node_id: &str, message: u64) {
-        let (_timer, nodes) = self.neighborhoods.get_mut(node_id).unwrap();
-        nodes.remove(&message);
+    /// Drop the oldest broadcasts pending for `node_id` beyond what the overflow policy allows,
+    /// returning their values.
+    pub fn drop_overflow(&mut self, node_id: &str) -> Vec<u64> {
+        let Some((_timer, nodes)) = self.neighborhoods.get_mut(node_id) else {
+            return vec![];
+        };
+        let excess = self.overflow.excess(nodes.len());
+        (0..excess).filter_map(|_| nodes.pop_oldest()).collect()
+    }
+
+    /// Values each neighbor did not acknowledge yet, sorted.
+    pub fn unacked(&self) -> HashMap<String, Vec<u64>> {
+        self.neighborhoods
+            .iter()
+            .map(|(node_id, (_timer, nodes))| {
+                let mut values: Vec<u64> = nodes.values().collect();
+                values.sort_unstable();
+                (node_id.clone(), values)
+            })
+            .collect()
     }
 
     /// Remove message from a node specific slot.
@@ -479,6 +1347,66 @@ impl MessageBus {
     }
 }
 
+/// Broadcasts a neighbor did not acknowledge yet, in the order they were queued.
+#[derive(Debug, Clone, Default)]
+struct PendingBroadcasts {
+    /// Incremented for every value queued, so `order` sorts by insertion.
+    next_seq: u64,
+    /// Value of each queue position.
+    order: BTreeMap<u64, u64>,
+    /// Queue position and message of each value.
+    messages: HashMap<u64, (u64, NodeMessage<BroadcastResponse>)>,
+}
+
+impl PendingBroadcasts {
+    /// Queue the message carrying `value`, returning whether the value was not pending yet. A
+    /// value already pending keeps its place in the queue.
+    fn insert(&mut self, value: u64, message: NodeMessage<BroadcastResponse>) -> bool {
+        if let Some((_, pending)) = self.messages.get_mut(&value) {
+            *pending = message;
+            return false;
+        }
+        self.next_seq += 1;
+        self.order.insert(self.next_seq, value);
+        self.messages.insert(value, (self.next_seq, message));
+        true
+    }
+
+    fn remove(&mut self, value: &u64) {
+        if let Some((seq, _)) = self.messages.remove(value) {
+            self.order.remove(&seq);
+        }
+    }
+
+    /// The message to retry next, see `PickPolicy`.
+    fn pick(&self, policy: PickPolicy) -> Option<&NodeMessage<BroadcastResponse>> {
+        let (_, value) = match policy {
+            PickPolicy::Oldest => self.order.first_key_value()?,
+            PickPolicy::Newest => self.order.last_key_value()?,
+        };
+        self.messages.get(value).map(|(_, message)| message)
+    }
+
+    /// Stop tracking the value queued first, returning it.
+    fn pop_oldest(&mut self) -> Option<u64> {
+        let (_, value) = self.order.pop_first()?;
+        self.messages.remove(&value);
+        Some(value)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    fn values(&self) -> impl Iterator<Item = u64> + '_ {
+        self.messages.keys().copied()
+    }
+}
+
 #[derive(Debug, Clone)]
 struct Timer {
     instant: Instant,
@@ -495,26 +1423,6 @@ impl Timer {
     }
 }
 
-fn is_customer_node(node_id: &str) -> bool {
-    node_id.chars().next() == Some('c')
-}
-
-fn is_main_node(node_id: &str) -> bool {
-    node_id == "n0" || node_id == "n5" || node_id == "n10" || node_id == "n15" || node_id == "n20"
-}
-
-#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
-struct PendingBroadcast {
-    src_node: String,
-    message: u64,
-}
-
-#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
-struct BroadcastSent {
-    destination_node: String,
-    message: u64,
-}
-
 #[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(untagged)]
 enum ResponseBody {
@@ -535,12 +1443,52 @@ enum RequestType {
     #[serde(rename = "topology")]
     Topology(TopologyBody),
     #[serde(rename = "broadcast_ok")]
-    BroadcastOk(ReadBody),
+    BroadcastOk(BroadcastOkBody),
+    #[serde(rename = "pending_summary")]
+    PendingSummary(ReadBody),
+    /// Not sent by Maelstrom, reports whether any value was applied more than once.
+    #[serde(rename = "delivery_check")]
+    DeliveryCheck(ReadBody),
+    #[serde(rename = "broadcast_batch")]
+    BroadcastBatch(BroadcastBatchBody),
+    #[serde(rename = "broadcast_batch_ok")]
+    BroadcastBatchOk(BroadcastBatchBody),
+    #[serde(rename = "tree_read")]
+    TreeRead(TreeReadBody),
+    #[serde(rename = "tree_read_ok")]
+    TreeReadOk(TreeReadOkBody),
+    #[serde(rename = "add_neighbor")]
+    AddNeighbor(NeighborBody),
+    #[serde(rename = "remove_neighbor")]
+    RemoveNeighbor(NeighborBody),
+    /// Answers something we sent, e.g. `NodeNotFound` for a broadcast to a node Maelstrom
+    /// does not know.
+    #[serde(rename = "error")]
+    Error(ErrorReply),
+    /// Not sent by Maelstrom, see `PARAMS`.
+    #[serde(rename = "set_param")]
+    SetParam(SetParamBody),
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 struct BroadcastBody {
     message: u64,
+    /// Hops left before the value stops being forwarded, unset for client broadcasts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ttl: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    in_reply_to: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    msg_id: Option<u64>,
+}
+
+/// Several broadcast values in one message, acknowledged together by a `broadcast_batch_ok`
+/// listing them.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct BroadcastBatchBody {
+    messages: Vec<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ttl: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     in_reply_to: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -555,6 +1503,44 @@ struct ReadBody {
     msg_id: Option<u64>,
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct BroadcastOkBody {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    in_reply_to: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    msg_id: Option<u64>,
+    /// Value being acknowledged, `msg_id` stays free for actual message ids.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    acked_value: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct TreeReadBody {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    in_reply_to: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    msg_id: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct TreeReadOkBody {
+    /// Values of the whole subtree rooted at the sender.
+    messages: Vec<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    in_reply_to: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    msg_id: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct NeighborBody {
+    node_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    in_reply_to: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    msg_id: Option<u64>,
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 struct TopologyBody {
     topology: HashMap<String, Vec<String>>,
@@ -572,6 +1558,9 @@ struct BasicResponse {
     in_reply_to: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     msg_id: Option<u64>,
+    /// Value a broadcast_ok acknowledges.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    acked_value: Option<u64>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -579,6 +1568,8 @@ struct ReadResponse {
     #[serde(rename = "type")]
     _type: String,
     messages: Vec<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    version: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     in_reply_to: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -588,6 +1579,9 @@ struct ReadResponse {
 #[derive(Deserialize, Serialize, Debug, Clone)]
 struct ReadOkBody {
     messages: Vec<u64>,
+    /// Version of the sender's values, see `GlobalState::version`.
+    #[serde(default)]
+    version: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     in_reply_to: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -599,8 +1593,40 @@ struct BroadcastResponse {
     #[serde(rename = "type")]
     _type: String,
     message: u64,
+    /// Hops left before the value stops being forwarded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ttl: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     in_reply_to: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     msg_id: Option<u64>,
 }
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct PendingSummary {
+    /// Broadcast values not acknowledged yet, per neighbor.
+    unacked_broadcasts: HashMap<String, Vec<u64>>,
+    /// Clients waiting for a read_ok.
+    pending_reads: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct DeliveryCheckResponse {
+    #[serde(rename = "type")]
+    _type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    in_reply_to: Option<u64>,
+    /// Distinct values applied since the node started.
+    applied: usize,
+    /// Values applied more than once, empty unless something is wrong.
+    duplicates: Vec<u64>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct PendingSummaryResponse {
+    #[serde(rename = "type")]
+    _type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    in_reply_to: Option<u64>,
+    pending: PendingSummary,
+}
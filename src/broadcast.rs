@@ -0,0 +1,564 @@
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::maelstrom::membership::SourceKind;
+
+/// Environment variable with the path of the file broadcast values are persisted to. Unset
+/// keeps the values in memory only.
+pub const VALUE_LOG_PATH_ENV: &str = "BROADCAST_VALUE_LOG";
+/// Environment variable with how many appends are batched before an fsync, 1 (the default)
+/// syncs every write.
+pub const VALUE_LOG_SYNC_EVERY_ENV: &str = "BROADCAST_VALUE_LOG_SYNC_EVERY";
+/// Environment variable holding how many broadcasts may be pending for a neighbor before the
+/// oldest ones are dropped, see `OverflowPolicy::DropOldest`.
+pub const MAX_PENDING_PER_NEIGHBOR_ENV: &str = "BROADCAST_MAX_PENDING_PER_NEIGHBOR";
+/// Environment variable that, set to `1` or `true`, makes client reads walk the overlay as a
+/// tree and merge the values of every node on the way back, instead of syncing with the
+/// neighborhood and waiting for the read_ok of every neighbor.
+pub const TREE_READ_ENV: &str = "BROADCAST_TREE_READ";
+/// Environment variable with how many milliseconds the broadcasts to another node may wait to be
+/// coalesced into a single `broadcast_batch`. It should stay below the retry delay, or the
+/// retries fire before the batch is flushed. Unset sends every value on its own.
+pub const BATCH_MAX_DELAY_MS_ENV: &str = "BROADCAST_BATCH_MAX_DELAY_MS";
+/// Environment variable with how many values a `broadcast_batch` holds at most, flushed as soon
+/// as it is full. Only read when `BATCH_MAX_DELAY_MS_ENV` is set.
+pub const BATCH_MAX_SIZE_ENV: &str = "BROADCAST_BATCH_MAX_SIZE";
+/// Environment variable that, set to `1` or `true`, makes nodes push the values a peer is
+/// missing, as seen in its read_ok, straight back to it, so reads speed up convergence instead
+/// of only pulling values in.
+pub const READ_REPAIR_ENV: &str = "BROADCAST_READ_REPAIR";
+
+/// Part an endpoint plays in the broadcast overlay, see `StarOfStars::role`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Hub,
+    Leaf,
+    /// A Maelstrom client, `c1`, `c2`, ...
+    Client,
+    /// Anything else, such as a Maelstrom service.
+    Service,
+}
+
+impl Role {
+    pub fn kind(self) -> SourceKind {
+        match self {
+            Role::Hub | Role::Leaf => SourceKind::Node,
+            Role::Client => SourceKind::Client,
+            Role::Service => SourceKind::Service,
+        }
+    }
+
+    /// Only hub-to-hub broadcasts are tracked and retried until acknowledged, leaves catch up
+    /// through the read sync instead.
+    pub fn should_track(src: Role, dst: Role) -> bool {
+        src == Role::Hub && dst == Role::Hub
+    }
+
+    /// Whether a broadcast from `src` to `dst` is answered with a broadcast_ok: clients are
+    /// always answered, nodes only when the broadcast is tracked.
+    pub fn should_ack(src: Role, dst: Role) -> bool {
+        src == Role::Client || Role::should_track(src, dst)
+    }
+
+    /// How urgently a link from `src` to `dst` should be serviced when only a few messages
+    /// can go out: the hub backbone first, then the hub-to-leaf links, then everything else.
+    pub fn link_priority(src: Role, dst: Role) -> u8 {
+        match (src, dst) {
+            (Role::Hub, Role::Hub) => 2,
+            (Role::Hub, Role::Leaf) | (Role::Leaf, Role::Hub) => 1,
+            _ => 0,
+        }
+    }
+}
+
+/// The neighbor to service first among the ones due for a message, given with the priority of
+/// their link (see `Role::link_priority`): the highest priority, the first one listed on a tie.
+pub fn highest_priority<'a>(due: impl IntoIterator<Item = (&'a str, u8)>) -> Option<&'a str> {
+    due.into_iter()
+        .min_by_key(|(_, priority)| Reverse(*priority))
+        .map(|(node_id, _)| node_id)
+}
+
+/// Star-of-stars overlay used by the broadcast workloads.
+///
+/// Every `hub_span`-th node (`n0`, `n5`, `n10`, ... for a span of 5) is a hub. Hubs are chained
+/// to the previous and next hub, and the nodes in between are leaves attached only to the hub
+/// right before them. This keeps the number of hops low while most nodes talk to a single peer.
+#[derive(Debug, Clone)]
+pub struct StarOfStars {
+    node_count: usize,
+    hub_span: usize,
+}
+
+impl StarOfStars {
+    pub fn new(node_count: usize, hub_span: usize) -> StarOfStars {
+        StarOfStars {
+            node_count,
+            hub_span: hub_span.max(1),
+        }
+    }
+
+    /// Hubs are the backbone of the overlay, the broadcast code calls them masters.
+    pub fn is_hub(&self, node_id: &str) -> bool {
+        self.index_of(node_id)
+            .is_some_and(|index| index % self.hub_span == 0)
+    }
+
+    pub fn role(&self, node_id: &str) -> Role {
+        if node_id.starts_with('c') {
+            Role::Client
+        } else if self.is_hub(node_id) {
+            Role::Hub
+        } else if node_id.starts_with('n') {
+            Role::Leaf
+        } else {
+            Role::Service
+        }
+    }
+
+    /// Nodes this node should talk to. Hubs list the previous hub first, then their leaves and
+    /// then the next hub; leaves only know their hub.
+    pub fn neighborhood(&self, node_id: &str) -> Vec<String> {
+        let index = match self.index_of(node_id) {
+            Some(index) => index,
+            None => return vec![],
+        };
+
+        if index % self.hub_span != 0 {
+            return vec![node_name(index - index % self.hub_span)];
+        }
+
+        let mut neighborhood = vec![];
+        if index >= self.hub_span {
+            neighborhood.push(node_name(index - self.hub_span));
+        }
+        let next_hub = index + self.hub_span;
+        for neighbor in (index + 1)..=next_hub {
+            if neighbor < self.node_count {
+                neighborhood.push(node_name(neighbor));
+            }
+        }
+
+        neighborhood
+    }
+
+    fn index_of(&self, node_id: &str) -> Option<usize> {
+        node_id
+            .strip_prefix('n')?
+            .parse::<usize>()
+            .ok()
+            .filter(|index| *index < self.node_count)
+    }
+}
+
+fn node_name(index: usize) -> String {
+    format!("n{index}")
+}
+
+/// Order the broadcasts pending for a neighbor are retried in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PickPolicy {
+    /// The first value queued for the neighbor, so values propagate in the order they were
+    /// learned.
+    Oldest,
+    /// The last value queued for the neighbor, favoring fresh values over long-stuck ones.
+    Newest,
+}
+
+/// What happens to the broadcasts pending for a neighbor when it does not acknowledge them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Keep every value until acknowledged, however many pile up.
+    Unbounded,
+    /// Keep at most `limit` values, dropping the oldest to make room for a new one. The
+    /// neighbor still gets the dropped values through the read sync, so memory stays bounded
+    /// while recent values are delivered first.
+    DropOldest { limit: usize },
+}
+
+impl OverflowPolicy {
+    /// `DropOldest` with the limit from `MAX_PENDING_PER_NEIGHBOR_ENV`, if set. A limit that is
+    /// not a number is an error rather than silently unbounded.
+    pub fn from_env() -> Result<Option<OverflowPolicy>, Box<dyn Error>> {
+        let Ok(limit) = std::env::var(MAX_PENDING_PER_NEIGHBOR_ENV) else {
+            return Ok(None);
+        };
+        let limit = limit.parse().map_err(|err| {
+            format!(
+                "Invalid {}={}: {}",
+                MAX_PENDING_PER_NEIGHBOR_ENV, limit, err
+            )
+        })?;
+        Ok(Some(OverflowPolicy::DropOldest { limit }))
+    }
+
+    /// How many of `pending` values to drop, oldest first.
+    pub fn excess(self, pending: usize) -> usize {
+        match self {
+            OverflowPolicy::Unbounded => 0,
+            OverflowPolicy::DropOldest { limit } => pending.saturating_sub(limit),
+        }
+    }
+}
+
+/// Set of broadcast values replicated between nodes. Replicas applying the same operations,
+/// and merging each other's state, end up with the same values whatever the order.
+pub trait ValueSet {
+    /// Add `value`, returning whether it was not in the set yet.
+    fn apply_add(&mut self, value: u64) -> bool;
+    /// Remove `value`, returning whether it was in the set.
+    fn apply_remove(&mut self, value: u64) -> bool;
+    /// Fold the state of another replica into this one.
+    fn merge(&mut self, other: &Self);
+    fn contains_value(&self, value: u64) -> bool;
+    /// The values in the set, sorted.
+    fn values(&self) -> Vec<u64>;
+}
+
+/// The add-only set the broadcast workloads use: values can never be removed.
+impl ValueSet for HashSet<u64> {
+    fn apply_add(&mut self, value: u64) -> bool {
+        self.insert(value)
+    }
+
+    /// Removals are not supported, nothing is removed.
+    fn apply_remove(&mut self, _value: u64) -> bool {
+        false
+    }
+
+    fn merge(&mut self, other: &Self) {
+        self.extend(other.iter().copied());
+    }
+
+    fn contains_value(&self, value: u64) -> bool {
+        self.contains(&value)
+    }
+
+    fn values(&self) -> Vec<u64> {
+        let mut values: Vec<u64> = self.iter().copied().collect();
+        values.sort_unstable();
+        values
+    }
+}
+
+/// Add-only set that keeps a sorted snapshot of its values for reads, rebuilt only when the
+/// set changed since the last snapshot.
+#[derive(Debug, Clone, Default)]
+pub struct SnapshotSet {
+    values: HashSet<u64>,
+    snapshot: Vec<u64>,
+    dirty: bool,
+    rebuilds: u64,
+}
+
+impl SnapshotSet {
+    pub fn new(values: HashSet<u64>) -> SnapshotSet {
+        SnapshotSet {
+            values,
+            snapshot: vec![],
+            dirty: true,
+            rebuilds: 0,
+        }
+    }
+
+    /// The values, sorted, rebuilding the snapshot only if a value was added since the last call.
+    pub fn snapshot(&mut self) -> &[u64] {
+        if self.dirty {
+            self.snapshot = self.values.values();
+            self.dirty = false;
+            self.rebuilds += 1;
+        }
+        &self.snapshot
+    }
+
+    /// How many times the snapshot was rebuilt.
+    pub fn rebuilds(&self) -> u64 {
+        self.rebuilds
+    }
+
+    pub fn as_set(&self) -> &HashSet<u64> {
+        &self.values
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+impl ValueSet for SnapshotSet {
+    fn apply_add(&mut self, value: u64) -> bool {
+        let added = self.values.apply_add(value);
+        self.dirty |= added;
+        added
+    }
+
+    /// Removals are not supported, nothing is removed.
+    fn apply_remove(&mut self, _value: u64) -> bool {
+        false
+    }
+
+    fn merge(&mut self, other: &Self) {
+        let len = self.values.len();
+        self.values.merge(&other.values);
+        self.dirty |= self.values.len() != len;
+    }
+
+    fn contains_value(&self, value: u64) -> bool {
+        self.values.contains(&value)
+    }
+
+    fn values(&self) -> Vec<u64> {
+        self.values.values()
+    }
+}
+
+/// How many times every value reached a node and how many times it was applied, to check that
+/// retransmissions never apply a value twice. Filled by `deliver`.
+#[derive(Debug, Clone, Default)]
+pub struct DeliveryLog {
+    entries: HashMap<u64, DeliveryEntry>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct DeliveryEntry {
+    first_delivery: Instant,
+    deliveries: u32,
+    applications: u32,
+}
+
+impl DeliveryLog {
+    pub fn new() -> DeliveryLog {
+        DeliveryLog::default()
+    }
+
+    fn entry(&mut self, value: u64) -> &mut DeliveryEntry {
+        self.entries.entry(value).or_insert_with(|| DeliveryEntry {
+            first_delivery: Instant::now(),
+            deliveries: 0,
+            applications: 0,
+        })
+    }
+
+    /// Record that `value` reached the node, applied or not, returning whether it was the
+    /// first time.
+    pub fn record_delivery(&mut self, value: u64) -> bool {
+        let entry = self.entry(value);
+        entry.deliveries += 1;
+        entry.deliveries == 1
+    }
+
+    /// Record that `value` was applied, returning whether it was the first time.
+    pub fn record_application(&mut self, value: u64) -> bool {
+        let entry = self.entry(value);
+        entry.applications += 1;
+        entry.applications == 1
+    }
+
+    /// Whether `value` was applied exactly once.
+    pub fn applied_once(&self, value: u64) -> bool {
+        self.entries
+            .get(&value)
+            .is_some_and(|entry| entry.applications == 1)
+    }
+
+    /// How many times `value` reached the node.
+    pub fn deliveries(&self, value: u64) -> u32 {
+        self.entries.get(&value).map_or(0, |entry| entry.deliveries)
+    }
+
+    pub fn first_delivery(&self, value: u64) -> Option<Instant> {
+        self.entries.get(&value).map(|entry| entry.first_delivery)
+    }
+
+    /// Values applied more than once, sorted. Empty unless something is wrong.
+    pub fn duplicates(&self) -> Vec<u64> {
+        let mut duplicates: Vec<u64> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.applications > 1)
+            .map(|(value, _)| *value)
+            .collect();
+        duplicates.sort_unstable();
+        duplicates
+    }
+
+    /// How many distinct values were applied.
+    pub fn applied(&self) -> usize {
+        self.entries
+            .values()
+            .filter(|entry| entry.applications > 0)
+            .count()
+    }
+
+    /// How many distinct values reached the node.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Add a delivered `value` to `values`, recording the delivery in `log`. Only a value that was
+/// not there yet is applied: `on_apply` is called and the application recorded in `log`.
+/// Returns whether it was applied.
+pub fn deliver<S: ValueSet>(
+    values: &mut S,
+    log: &mut DeliveryLog,
+    value: u64,
+    on_apply: impl FnOnce(u64),
+) -> bool {
+    log.record_delivery(value);
+    if !values.apply_add(value) {
+        return false;
+    }
+    log.record_application(value);
+    on_apply(value);
+    true
+}
+
+/// Unique tag of an add, the replica that made it and a counter of that replica.
+pub type AddTag = (String, u64);
+
+/// Observed-remove set: a remove only cancels the adds its replica had seen, so an add
+/// concurrent with a remove of the same value wins once the replicas merge.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OrSet {
+    replica_id: String,
+    counter: u64,
+    adds: HashMap<u64, HashSet<AddTag>>,
+    removed: HashSet<AddTag>,
+}
+
+impl OrSet {
+    pub fn new(replica_id: impl Into<String>) -> OrSet {
+        OrSet {
+            replica_id: replica_id.into(),
+            ..OrSet::default()
+        }
+    }
+
+    fn live_tags(&self, value: u64) -> impl Iterator<Item = &AddTag> {
+        self.adds
+            .get(&value)
+            .into_iter()
+            .flatten()
+            .filter(|tag| !self.removed.contains(*tag))
+    }
+}
+
+impl ValueSet for OrSet {
+    fn apply_add(&mut self, value: u64) -> bool {
+        let was_present = self.contains_value(value);
+        self.counter += 1;
+        self.adds
+            .entry(value)
+            .or_default()
+            .insert((self.replica_id.clone(), self.counter));
+        !was_present
+    }
+
+    fn apply_remove(&mut self, value: u64) -> bool {
+        let observed: Vec<AddTag> = self.live_tags(value).cloned().collect();
+        self.removed.extend(observed.iter().cloned());
+        !observed.is_empty()
+    }
+
+    fn merge(&mut self, other: &Self) {
+        for (value, tags) in other.adds.iter() {
+            self.adds
+                .entry(*value)
+                .or_default()
+                .extend(tags.iter().cloned());
+        }
+        self.removed.extend(other.removed.iter().cloned());
+    }
+
+    fn contains_value(&self, value: u64) -> bool {
+        self.live_tags(value).next().is_some()
+    }
+
+    fn values(&self) -> Vec<u64> {
+        let mut values: Vec<u64> = self
+            .adds
+            .keys()
+            .copied()
+            .filter(|value| self.contains_value(*value))
+            .collect();
+        values.sort_unstable();
+        values
+    }
+}
+
+/// Append-only file of the broadcast values a node learned, one per line, replayed on startup
+/// so a restarted node recovers its values.
+#[derive(Debug)]
+pub struct ValueLog {
+    path: PathBuf,
+    file: File,
+    sync_every: usize,
+    unsynced: usize,
+}
+
+impl ValueLog {
+    /// Open (or create) the log at `path`, syncing to disk every `sync_every` appends.
+    pub fn open(path: impl AsRef<Path>, sync_every: usize) -> Result<ValueLog, Box<dyn Error>> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(ValueLog {
+            path,
+            file,
+            sync_every: sync_every.max(1),
+            unsynced: 0,
+        })
+    }
+
+    /// Log configured through `VALUE_LOG_PATH_ENV` and `VALUE_LOG_SYNC_EVERY_ENV`, if any.
+    pub fn from_env() -> Result<Option<ValueLog>, Box<dyn Error>> {
+        let Ok(path) = std::env::var(VALUE_LOG_PATH_ENV) else {
+            return Ok(None);
+        };
+        let sync_every = match std::env::var(VALUE_LOG_SYNC_EVERY_ENV) {
+            Ok(sync_every) => sync_every.parse()?,
+            Err(_) => 1,
+        };
+        Ok(Some(ValueLog::open(path, sync_every)?))
+    }
+
+    /// Every value appended so far. A torn last line, from a crash mid-write, is ignored.
+    pub fn load(&self) -> Result<HashSet<u64>, Box<dyn Error>> {
+        let mut values = HashSet::new();
+        for line in BufReader::new(File::open(&self.path)?).lines() {
+            if let Ok(value) = line?.trim().parse() {
+                values.insert(value);
+            }
+        }
+        Ok(values)
+    }
+
+    pub fn append(&mut self, value: u64) -> Result<(), Box<dyn Error>> {
+        writeln!(self.file, "{}", value)?;
+        self.unsynced += 1;
+        if self.unsynced >= self.sync_every {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Flush the batched appends to disk.
+    pub fn sync(&mut self) -> Result<(), Box<dyn Error>> {
+        self.file.sync_data()?;
+        self.unsynced = 0;
+        Ok(())
+    }
+}
@@ -1,6 +1,170 @@
 use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
 use serde::{Deserialize, Serialize};
 
+use crate::maelstrom::checked_add;
+use crate::maelstrom::error::NodeError;
+
+/// Environment variable with how many milliseconds the send_ok replies to a client are held
+/// back to be coalesced into a single `send_ok_batch`. Unset answers every send on its own, as
+/// standard clients expect.
+pub const SEND_OK_BATCH_MS_ENV: &str = "KAFKA_SEND_OK_BATCH_MS";
+
+/// Environment variable that, set to `1` or `true`, makes multi-node nodes allocate the offsets
+/// of a key by incrementing a counter in lin-kv, so offsets stay unique across the cluster even
+/// while two nodes both think they own the key. The send_ok waits for the increment to commit.
+pub const LIN_KV_OFFSETS_ENV: &str = "KAFKA_LIN_KV_OFFSETS";
+
+/// Environment variable that, set to `1` or `true`, makes multi-node nodes tell clients which
+/// node owns a key, see `key_owner`, in the send_ok of a send made to another node.
+pub const OWNER_HINTS_ENV: &str = "KAFKA_OWNER_HINTS";
+
+/// Environment variable with how many entries the single-node kafka keeps per key, dropping the
+/// oldest ones. Polls from an offset dropped this way are answered from the oldest entry left,
+/// see `PollResponse::trimmed`. Unset keeps everything.
+pub const COMPACT_KEEP_ENTRIES_ENV: &str = "KAFKA_COMPACT_KEEP_ENTRIES";
+
+/// Environment variable with how many entries a key of the single-node kafka may hold. Sends
+/// to a full key are answered with a `TemporarilyUnavailable` error instead of an offset.
+/// Unset accepts everything.
+pub const MAX_ENTRIES_PER_KEY_ENV: &str = "KAFKA_MAX_ENTRIES_PER_KEY";
+
+/// Position of a message in the log of a key.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Offset(pub u64);
+
+impl Offset {
+    /// The offset right after this one, a `Crash` error past `u64::MAX`.
+    pub fn next(self) -> Result<Offset, NodeError> {
+        Ok(Offset(checked_add(self.0, 1)?))
+    }
+
+    /// How many offsets `base` is behind this one, e.g. the index of this offset in a log
+    /// starting at `base`. An offset below `base` is a `MalformedRequest`, offsets come from
+    /// the clients.
+    pub fn since(self, base: Offset) -> Result<u64, NodeError> {
+        self.0
+            .checked_sub(base.0)
+            .ok_or(NodeError::MalformedRequest)
+    }
+}
+
+impl fmt::Display for Offset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Committed watermark of a log whose last entry is at `last_offset` once `offset` is committed.
+/// Committing an offset commits everything before it, so the watermark is the last entry up to
+/// that offset; older commits never move it back. Every entry at or below the watermark is
+/// committed, without scanning the log.
+pub fn advance_watermark(watermark: Option<Offset>, offset: Offset, last_offset: Offset) -> Offset {
+    let committed = offset.min(last_offset);
+    watermark.map_or(committed, |watermark| watermark.max(committed))
+}
+
+/// Message sent to the log of a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct LogValue(pub u64);
+
+impl fmt::Display for LogValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// A contiguous run of log entries packed for transfers between nodes: the offsets are implied
+/// by `base` and `count`, and the values are LEB128 varints, base64 encoded in `data`.
+/// Clients always get plain `[offset, message]` pairs.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PackedEntries {
+    pub base: Offset,
+    pub count: u64,
+    pub data: String,
+}
+
+impl PackedEntries {
+    /// Pack `entries`, failing unless every offset follows the previous one.
+    pub fn pack(entries: &[(Offset, LogValue)]) -> Result<PackedEntries, Box<dyn Error>> {
+        let base = entries.first().map_or(Offset(0), |(offset, _)| *offset);
+        let mut bytes = Vec::with_capacity(entries.len() * 2);
+        let mut expected = base;
+        for (offset, value) in entries {
+            if *offset != expected {
+                return Err(format!(
+                    "offset {} does not follow {} in a packed run",
+                    offset, expected
+                )
+                .into());
+            }
+            expected = offset.next()?;
+            let mut value = value.0;
+            while value >= 0x80 {
+                bytes.push((value as u8 & 0x7f) | 0x80);
+                value >>= 7;
+            }
+            bytes.push(value as u8);
+        }
+
+        Ok(PackedEntries {
+            base,
+            count: entries.len() as u64,
+            data: BASE64.encode(bytes),
+        })
+    }
+
+    /// The `[offset, message]` pairs packed by `pack`.
+    pub fn unpack(&self) -> Result<Vec<(Offset, LogValue)>, Box<dyn Error>> {
+        let bytes = BASE64.decode(&self.data)?;
+        // Every entry takes at least a byte, a count larger than that is rejected below.
+        let mut entries = Vec::with_capacity(bytes.len().min(self.count as usize));
+        let mut offset = self.base;
+        let mut value = 0u64;
+        let mut shift = 0;
+        for byte in bytes {
+            if shift >= 64 {
+                return Err("packed value overflows u64".into());
+            }
+            value |= ((byte & 0x7f) as u64) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                entries.push((offset, LogValue(value)));
+                offset = offset.next()?;
+                value = 0;
+                shift = 0;
+            }
+        }
+        if shift != 0 || entries.len() as u64 != self.count {
+            return Err(format!(
+                "packed run holds {} entries, expected {}",
+                entries.len(),
+                self.count
+            )
+            .into());
+        }
+
+        Ok(entries)
+    }
+}
+
+/// Node owning `log_key` among `node_ids`, picked by a hash of the key (FNV-1a) so every node
+/// and client computes the same owner. `None` if `node_ids` is empty.
+pub fn key_owner<'a>(log_key: &str, node_ids: &'a [String]) -> Option<&'a str> {
+    if node_ids.is_empty() {
+        return None;
+    }
+    let hash = log_key.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+    Some(&node_ids[(hash % node_ids.len() as u64) as usize])
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(tag = "type")]
 pub enum RequestType {
@@ -17,16 +181,81 @@ pub enum RequestType {
 #[derive(Debug, Deserialize)]
 pub struct SendRequest {
     pub key: String,
-    pub msg: u64,
+    pub msg: LogValue,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub in_reply_to: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub msg_id: Option<u64>,
 }
 
+/// Which entries a poll may return.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Isolation {
+    /// Every entry in the log.
+    #[default]
+    ReadUncommitted,
+    /// Only the entries up to the committed offset of the key.
+    ReadCommitted,
+}
+
+/// How the entries returned by a multi-key poll are shared between its keys.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PollBudget {
+    /// Every key gets up to the per-key limit of the node.
+    #[default]
+    PerKey,
+    /// At most this many entries in total, handed out round-robin across the keys so no single
+    /// key takes the whole budget.
+    GlobalBudget(usize),
+}
+
+impl PollBudget {
+    /// How many entries each key gets, given how many each one has `available`.
+    pub fn allocate(self, per_key: usize, available: &[usize]) -> Vec<usize> {
+        match self {
+            PollBudget::PerKey => available.iter().map(|count| (*count).min(per_key)).collect(),
+            PollBudget::GlobalBudget(total) => {
+                let mut allocated = vec![0; available.len()];
+                let mut left = total;
+                while left > 0 {
+                    let mut progressed = false;
+                    for (allocated, available) in allocated.iter_mut().zip(available) {
+                        if left > 0 && *allocated < *available {
+                            *allocated += 1;
+                            left -= 1;
+                            progressed = true;
+                        }
+                    }
+                    if !progressed {
+                        break;
+                    }
+                }
+                allocated
+            }
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct PollRequest {
-    pub offsets: HashMap<String, u64>,
+    pub offsets: HashMap<String, Offset>,
+    #[serde(default)]
+    pub isolation: Isolation,
+    /// Consumer group whose committed offsets bound a `read_committed` poll.
+    #[serde(default)]
+    pub group: Option<String>,
+    /// Also answer with the `log_length` of every polled key.
+    #[serde(default)]
+    pub include_log_length: bool,
+    /// Overrides the poll budget of the node for this request.
+    #[serde(default)]
+    pub budget: Option<PollBudget>,
+    /// Answer with the entries in `packed` instead of `msgs`. Only honored for polls from
+    /// another node of the cluster, clients always get `msgs`.
+    #[serde(default)]
+    pub packed: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub in_reply_to: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -35,7 +264,15 @@ pub struct PollRequest {
 
 #[derive(Debug, Deserialize)]
 pub struct CommitOffsetsRequest {
-    pub offsets: HashMap<String, u64>,
+    pub offsets: HashMap<String, Offset>,
+    /// Consumer group the offsets belong to, groups track their progress independently.
+    #[serde(default)]
+    pub group: Option<String>,
+    /// Commit every key or none: a key whose committed offset is already past the requested
+    /// one fails the whole request with `TxnConflict`. Otherwise such keys are left as they
+    /// are and the others are still committed.
+    #[serde(default)]
+    pub atomic: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub in_reply_to: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -45,6 +282,9 @@ pub struct CommitOffsetsRequest {
 #[derive(Debug, Deserialize)]
 pub struct ListCommitedOffsetsRequest {
     pub keys: Vec<String>,
+    /// Consumer group the offsets belong to, groups track their progress independently.
+    #[serde(default)]
+    pub group: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub in_reply_to: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -56,6 +296,8 @@ pub struct ListCommitedOffsetsRequest {
 pub enum ResponseType {
     #[serde(rename = "send_ok")]
     SendResponse(SendResponse),
+    #[serde(rename = "send_ok_batch")]
+    SendBatchResponse(SendBatchResponse),
     #[serde(rename = "poll_ok")]
     PollResponse(PollResponse),
     #[serde(rename = "commit_offsets_ok")]
@@ -66,22 +308,59 @@ pub enum ResponseType {
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct SendResponse {
-    pub offset: u64,
+    pub offset: Offset,
+    /// Node owning the key, see `key_owner`, so a client can send the next messages for the key
+    /// there directly. Left out unless the node answering is not the owner.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub in_reply_to: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub msg_id: Option<u64>,
 }
 
+/// Several send_ok coalesced into one reply, see `SEND_OK_BATCH_MS_ENV`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SendBatchResponse {
+    /// `[in_reply_to, offset]` of every send answered, in the order they were received.
+    pub sends: Vec<(u64, Offset)>,
+}
+
+/// `[offset, message]` pairs of each polled key.
+pub type PolledEntries = HashMap<String, Vec<(Offset, LogValue)>>;
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct PollResponse {
-    pub msgs: HashMap<String, Vec<[u64; 2]>>,
+    /// `[offset, message]` pairs of each key.
+    pub msgs: HashMap<String, Vec<(Offset, LogValue)>>,
+    /// Keys polled from an offset that was already compacted away, with the first offset
+    /// still available. Their `msgs` start from there.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub trimmed: HashMap<String, Offset>,
+    /// Offset the next message of each key will get, so a consumer can compute its lag as
+    /// `log_length - committed_offset`. Only sent when the poll asked for it.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub log_length: HashMap<String, u64>,
+    /// Entries of the keys sent packed rather than in `msgs`, see `PollRequest::packed`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub packed: HashMap<String, PackedEntries>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub in_reply_to: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub msg_id: Option<u64>,
 }
 
+impl PollResponse {
+    /// Entries of every key, whether they were sent in `msgs` or `packed`.
+    pub fn entries(&self) -> Result<PolledEntries, Box<dyn Error>> {
+        let mut entries = self.msgs.clone();
+        for (log_key, packed) in self.packed.iter() {
+            entries.insert(log_key.clone(), packed.unpack()?);
+        }
+        Ok(entries)
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct SimpleMessage {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -92,7 +371,7 @@ pub struct SimpleMessage {
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ListCommitedOffsetsResponse {
-    pub offsets: HashMap<String, u64>,
+    pub offsets: HashMap<String, Offset>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub in_reply_to: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
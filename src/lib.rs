@@ -1,9 +1,13 @@
 pub mod maelstrom;
 pub mod kafka;
+pub mod broadcast;
+pub mod logging;
+
+pub use maelstrom::prelude;
 
 pub fn get_ts() -> String {
     let ts = std::time::SystemTime::now()
         .duration_since(std::time::SystemTime::UNIX_EPOCH)
         .unwrap();
-    format!("{}.{}", ts.as_secs(), ts.subsec_millis())
+    format!("{}.{:03}", ts.as_secs(), ts.subsec_millis())
 }
\ No newline at end of file
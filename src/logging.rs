@@ -0,0 +1,135 @@
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use serde_json::Value;
+
+/// How many log lines are kept around for `dump_recent_logs`.
+pub const RECENT_LOGS_CAPACITY: usize = 512;
+
+static RECENT_LOGS: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// Sequence number of the next log line of this process.
+static LOG_SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    static CURRENT_MESSAGE: Cell<Option<MessageContext>> = const { Cell::new(None) };
+}
+
+/// Log a line prefixed with its sequence number, the timestamp, the node id and the ids of the
+/// message being handled if any (see `enter_message`), e.g.
+/// `node_log!(self.node_id, "Received send({})", msg)`.
+#[macro_export]
+macro_rules! node_log {
+    ($node_id:expr, $($arg:tt)*) => {
+        $crate::logging::log_line($crate::logging::format_log_line(
+            &$crate::get_ts(),
+            &$node_id,
+            format_args!($($arg)*),
+        ))
+    };
+}
+
+/// Next log sequence number. It only ever grows, unlike the wall clock timestamps, so sorting
+/// the lines of a node on it gives the order they were logged in.
+pub fn next_log_seq() -> u64 {
+    LOG_SEQUENCE.fetch_add(1, Ordering::Relaxed)
+}
+
+/// `#<seq> <ts> [<node_id>] <message context><message>`, the line `node_log!` logs with the
+/// current timestamp.
+pub fn format_log_line(ts: &str, node_id: &dyn fmt::Display, message: fmt::Arguments) -> String {
+    format!(
+        "#{} {} [{}] {}{}",
+        next_log_seq(),
+        ts,
+        node_id,
+        message_context(),
+        message
+    )
+}
+
+/// Ids of the message a node is handling, to correlate its log lines with the Maelstrom trace.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MessageContext {
+    pub msg_id: Option<u64>,
+    pub in_reply_to: Option<u64>,
+}
+
+impl MessageContext {
+    /// Ids found in a raw message body.
+    pub fn from_body(body: &Value) -> MessageContext {
+        MessageContext {
+            msg_id: body.get("msg_id").and_then(Value::as_u64),
+            in_reply_to: body.get("in_reply_to").and_then(Value::as_u64),
+        }
+    }
+}
+
+/// Restores the previous message context when dropped, see `enter_message`.
+#[must_use]
+pub struct MessageScope {
+    previous: Option<MessageContext>,
+}
+
+impl Drop for MessageScope {
+    fn drop(&mut self) {
+        CURRENT_MESSAGE.with(|current| current.set(self.previous));
+    }
+}
+
+/// Tag the lines logged by this thread with `context` until the returned scope is dropped.
+pub fn enter_message(context: MessageContext) -> MessageScope {
+    MessageScope {
+        previous: CURRENT_MESSAGE.with(|current| current.replace(Some(context))),
+    }
+}
+
+/// `[msg_id=1 in_reply_to=2] ` for the message being handled, empty outside of a handler.
+pub fn message_context() -> String {
+    let Some(context) = CURRENT_MESSAGE.with(Cell::get) else {
+        return String::new();
+    };
+
+    let mut ids = vec![];
+    if let Some(msg_id) = context.msg_id {
+        ids.push(format!("msg_id={}", msg_id));
+    }
+    if let Some(in_reply_to) = context.in_reply_to {
+        ids.push(format!("in_reply_to={}", in_reply_to));
+    }
+    if ids.is_empty() {
+        return String::new();
+    }
+    format!("[{}] ", ids.join(" "))
+}
+
+/// Write a line to stderr and keep it in the recent logs ring buffer.
+pub fn log_line(line: String) {
+    eprintln!("{}", line);
+
+    let mut recent_logs = RECENT_LOGS.lock().unwrap_or_else(|err| err.into_inner());
+    if recent_logs.len() == RECENT_LOGS_CAPACITY {
+        recent_logs.pop_front();
+    }
+    recent_logs.push_back(line);
+}
+
+/// The last `RECENT_LOGS_CAPACITY` lines logged, oldest first.
+pub fn recent_logs() -> Vec<String> {
+    let recent_logs = RECENT_LOGS.lock().unwrap_or_else(|err| err.into_inner());
+    recent_logs.iter().cloned().collect()
+}
+
+/// Print the recent logs again, so they are easy to find at the end of the node output
+/// when something went wrong late in a run.
+pub fn dump_recent_logs() {
+    let recent_logs = recent_logs();
+    eprintln!("===== Last {} log lines =====", recent_logs.len());
+    for line in recent_logs {
+        eprintln!("{}", line);
+    }
+    eprintln!("===== End of recent logs =====");
+}
@@ -0,0 +1,58 @@
+use super::Timer;
+
+/// Exponential backoff between retries of an operation that keeps failing, e.g. while a KV
+/// service is unavailable.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    base_ms: u64,
+    max_ms: u64,
+    attempts: u32,
+    timer: Option<Timer>,
+}
+
+impl Backoff {
+    pub fn new(base_ms: u64, max_ms: u64) -> Backoff {
+        Backoff {
+            base_ms,
+            max_ms,
+            attempts: 0,
+            timer: None,
+        }
+    }
+
+    /// Delay before the next retry: `base_ms` doubled for every failed attempt, up to `max_ms`.
+    pub fn next_delay_ms(&self) -> u64 {
+        let factor = 1u64.checked_shl(self.attempts).unwrap_or(u64::MAX);
+        self.base_ms.saturating_mul(factor).min(self.max_ms)
+    }
+
+    /// Record a failed attempt and schedule the next retry.
+    pub fn schedule(&mut self) {
+        self.timer = Some(Timer::from_millis(self.next_delay_ms()));
+        self.attempts = self.attempts.saturating_add(1);
+    }
+
+    /// Whether a retry is scheduled but not due yet.
+    pub fn is_waiting(&self) -> bool {
+        self.timer.as_ref().is_some_and(|timer| !timer.is_done())
+    }
+
+    /// Returns true once when the scheduled retry is due.
+    pub fn take_ready(&mut self) -> bool {
+        if self.timer.as_ref().is_some_and(Timer::is_done) {
+            self.timer = None;
+            return true;
+        }
+        false
+    }
+
+    /// Forget the failed attempts, usually after a success.
+    pub fn reset(&mut self) {
+        self.attempts = 0;
+        self.timer = None;
+    }
+
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+}
@@ -0,0 +1,56 @@
+use std::error::Error;
+
+use serde::{Deserialize, Serialize};
+
+use super::error::NodeError;
+use super::IntoReply;
+
+/// Whether nodes apply the `set_param` control messages they receive, see `SetParamBody`.
+/// Without the `control` feature they are logged and ignored.
+pub const CONTROL_ENABLED: bool = cfg!(feature = "control");
+
+/// Body of a `set_param` control message, changing a parameter of a running node so it can be
+/// swept within a single run, e.g. `{"type": "set_param", "name": "wait_ms", "value": 150}`.
+/// Not part of the Maelstrom protocol, each workload documents the names it knows.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct SetParamBody {
+    pub name: String,
+    pub value: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub msg_id: Option<u64>,
+}
+
+impl SetParamBody {
+    /// The set_param_ok acknowledging this request.
+    pub fn ok(&self) -> SetParamResponse {
+        SetParamResponse {
+            _type: "set_param_ok".into(),
+            name: self.name.clone(),
+            value: self.value,
+            in_reply_to: self.msg_id,
+        }
+    }
+
+    /// The error to fail the request with when the node has no parameter by that name.
+    pub fn unknown_param(&self, known: &[&str]) -> Box<dyn Error> {
+        format!(
+            "{}: unknown parameter {}, expected one of {:?}",
+            NodeError::MalformedRequest,
+            self.name,
+            known
+        )
+        .into()
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct SetParamResponse {
+    #[serde(rename = "type")]
+    pub _type: String,
+    pub name: String,
+    pub value: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub in_reply_to: Option<u64>,
+}
+
+impl IntoReply for SetParamResponse {}
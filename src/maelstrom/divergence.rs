@@ -0,0 +1,101 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::Value;
+
+use super::NodeMessage;
+
+/// A client read on which two implementations disagree, `None` meaning it was not answered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub in_reply_to: Option<u64>,
+    pub left: Option<Vec<u64>>,
+    pub right: Option<Vec<u64>>,
+}
+
+/// Run the node binary `program` with `input` on stdin, one message per line starting with
+/// the init message, and return every message it wrote.
+///
+/// Stdin is kept open for `settle` after the input is written, so replies sent from timers
+/// still make it before the node sees EOF.
+pub fn run_binary(
+    program: &str,
+    input: &str,
+    settle: Duration,
+) -> Result<Vec<NodeMessage<Value>>, Box<dyn Error>> {
+    let mut child = Command::new(program)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    let mut stdout = child.stdout.take().ok_or("Node stdout was not captured")?;
+    let reader = thread::spawn(move || {
+        let mut output = String::new();
+        stdout.read_to_string(&mut output).map(|_| output)
+    });
+
+    let mut stdin = child.stdin.take().ok_or("Node stdin was not captured")?;
+    stdin.write_all(input.as_bytes())?;
+    stdin.flush()?;
+    thread::sleep(settle);
+    drop(stdin);
+
+    let status = child.wait()?;
+    let output = reader.join().map_err(|_| "Node stdout reader panicked")??;
+    if !status.success() {
+        return Err(format!("{} exited with {}", program, status).into());
+    }
+
+    Ok(output
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Sorted values of every `read_ok` sent to a client, keyed by the read they answer.
+pub fn client_reads(outputs: &[NodeMessage<Value>]) -> BTreeMap<Option<u64>, Vec<u64>> {
+    outputs
+        .iter()
+        .filter(|msg| msg.dest.starts_with('c'))
+        .filter(|msg| msg.body["type"] == "read_ok")
+        .map(|msg| {
+            let mut values: Vec<u64> = msg.body["messages"]
+                .as_array()
+                .map(|values| values.iter().filter_map(Value::as_u64).collect())
+                .unwrap_or_default();
+            values.sort_unstable();
+            (msg.body["in_reply_to"].as_u64(), values)
+        })
+        .collect()
+}
+
+/// Replay `input` through two broadcast binaries and return the client reads they answered
+/// differently. An empty result means both agree on everything clients could observe.
+pub fn compare_broadcast_reads(
+    left: &str,
+    right: &str,
+    input: &str,
+    settle: Duration,
+) -> Result<Vec<Divergence>, Box<dyn Error>> {
+    let left_reads = client_reads(&run_binary(left, input, settle)?);
+    let right_reads = client_reads(&run_binary(right, input, settle)?);
+
+    let mut read_ids: Vec<&Option<u64>> = left_reads.keys().chain(right_reads.keys()).collect();
+    read_ids.sort_unstable();
+    read_ids.dedup();
+
+    Ok(read_ids
+        .into_iter()
+        .filter(|read_id| left_reads.get(read_id) != right_reads.get(read_id))
+        .map(|read_id| Divergence {
+            in_reply_to: *read_id,
+            left: left_reads.get(read_id).cloned(),
+            right: right_reads.get(read_id).cloned(),
+        })
+        .collect())
+}
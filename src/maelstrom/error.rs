@@ -1,6 +1,8 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Serialize, Debug)]
+/// A Maelstrom error, (de)serialized as its code.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(from = "u64", into = "u64")]
 pub enum NodeError {
     /// Indicates that the requested operation could not be completed within a timeout.
     Timeout,
@@ -65,4 +67,98 @@ impl NodeError {
             NodeError::Custom(code) => *code,
         }
     }
+
+    /// The error a code stands for, `Custom` for the codes Maelstrom does not define.
+    pub fn from_code(code: u64) -> NodeError {
+        match code {
+            0 => NodeError::Timeout,
+            1 => NodeError::NodeNotFound,
+            10 => NodeError::NotSupported,
+            11 => NodeError::TemporarilyUnavailable,
+            12 => NodeError::MalformedRequest,
+            13 => NodeError::Crash,
+            14 => NodeError::Abort,
+            20 => NodeError::KeyDoesNotExist,
+            21 => NodeError::KeyAlreadyExists,
+            22 => NodeError::PreconditionFailed,
+            23 => NodeError::TxnConflict,
+            code => NodeError::Custom(code),
+        }
+    }
+}
+
+impl std::fmt::Display for NodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NodeError::Custom(code) => write!(f, "Custom (code {})", code),
+            error => write!(f, "{:?} (code {})", error, error.code()),
+        }
+    }
 }
+
+impl std::error::Error for NodeError {}
+
+impl From<u64> for NodeError {
+    fn from(code: u64) -> NodeError {
+        NodeError::from_code(code)
+    }
+}
+
+impl From<NodeError> for u64 {
+    fn from(error: NodeError) -> u64 {
+        error.code()
+    }
+}
+
+/// An `error` reply a node received, with its code read back into a `NodeError`.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ErrorReply {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub in_reply_to: Option<u64>,
+    pub code: NodeError,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+/// Body of a Maelstrom `error` reply.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ErrorBody {
+    #[serde(rename = "type")]
+    pub _type: String,
+    pub in_reply_to: u64,
+    pub code: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+impl ErrorBody {
+    pub fn new(error: NodeError, in_reply_to: u64, text: Option<String>) -> ErrorBody {
+        ErrorBody {
+            _type: "error".into(),
+            in_reply_to,
+            code: error.code(),
+            text,
+        }
+    }
+}
+
+/// Why `run_node_event_loop` failed, rather than returning once stdin was closed.
+#[derive(Debug)]
+pub enum NodeRuntimeError {
+    /// The init message could not be read, was invalid, or could not be answered, or the
+    /// environment configures the node with an invalid value.
+    Init(Box<dyn std::error::Error>),
+    /// The thread reading the inbound messages panicked.
+    ReaderPanicked,
+}
+
+impl std::fmt::Display for NodeRuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NodeRuntimeError::Init(err) => write!(f, "Could not initialize the node: {}", err),
+            NodeRuntimeError::ReaderPanicked => write!(f, "The message reader thread panicked"),
+        }
+    }
+}
+
+impl std::error::Error for NodeRuntimeError {}
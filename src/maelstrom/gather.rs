@@ -0,0 +1,49 @@
+use std::collections::HashSet;
+
+use super::Timer;
+
+/// Collects the replies to a request fanned out to several nodes, until all of them answered or
+/// the timeout is over.
+#[derive(Debug, Clone)]
+pub struct Gather<T> {
+    waiting: HashSet<String>,
+    replies: Vec<(String, T)>,
+    timer: Timer,
+}
+
+impl<T> Gather<T> {
+    pub fn new(node_ids: impl IntoIterator<Item = String>, timeout_ms: u64) -> Gather<T> {
+        Gather {
+            waiting: node_ids.into_iter().collect(),
+            replies: vec![],
+            timer: Timer::from_millis(timeout_ms),
+        }
+    }
+
+    /// Record the reply of `node_id`, ignoring nodes we are not waiting on (e.g. a duplicated
+    /// reply). Returns whether every node answered.
+    pub fn add(&mut self, node_id: &str, reply: T) -> bool {
+        if self.waiting.remove(node_id) {
+            self.replies.push((node_id.to_string(), reply));
+        }
+        self.is_done()
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.waiting.is_empty()
+    }
+
+    /// Whether the timeout is over, the replies gathered so far are all we will get.
+    pub fn is_expired(&self) -> bool {
+        self.timer.is_done()
+    }
+
+    /// Nodes that did not answer yet.
+    pub fn missing(&self) -> impl Iterator<Item = &str> {
+        self.waiting.iter().map(String::as_str)
+    }
+
+    pub fn into_replies(self) -> Vec<(String, T)> {
+        self.replies
+    }
+}
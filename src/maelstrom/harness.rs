@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use std::error::Error;
+
+use serde_json::Value;
+
+use super::error::NodeError;
+use super::{InitResponse, MaelstromNode, NodeMessage, OutgoingMessage};
+
+/// Source of the init messages, like the Maelstrom controller.
+const CONTROLLER_ID: &str = "c0";
+
+/// Runs several nodes in the same process, with messages delivered by hand instead of read
+/// from stdin.
+///
+/// Like Maelstrom, every node is initialized before any workload message is delivered, see
+/// `init_all`.
+pub struct Harness<N> {
+    nodes: Vec<(String, N)>,
+    config: HashMap<String, Value>,
+    init_oks: Vec<NodeMessage<InitResponse>>,
+    is_shut_down: bool,
+}
+
+impl<N> Harness<N>
+where
+    N: MaelstromNode,
+{
+    pub fn new(nodes: Vec<(String, N)>) -> Harness<N> {
+        Harness {
+            nodes,
+            config: HashMap::new(),
+            init_oks: vec![],
+            is_shut_down: false,
+        }
+    }
+
+    /// Extra init fields every node is configured with, see `MaelstromNode::configure`.
+    pub fn with_config(mut self, config: HashMap<String, Value>) -> Harness<N> {
+        self.config = config;
+        self
+    }
+
+    /// Initialize every node in order and return the `init_ok` each one answered with.
+    /// Workload messages can only be delivered once this returned.
+    pub fn init_all(&mut self) -> &[NodeMessage<InitResponse>] {
+        if !self.is_initialized() {
+            let node_ids: Vec<String> = self.nodes.iter().map(|(id, _)| id.clone()).collect();
+            for (msg_id, (node_id, node)) in self.nodes.iter_mut().enumerate() {
+                node.initialize(node_id.clone(), node_ids.clone());
+                node.configure(&self.config);
+                self.init_oks.push(NodeMessage::new(
+                    node_id.clone(),
+                    CONTROLLER_ID.to_string(),
+                    InitResponse {
+                        _type: "init_ok".into(),
+                        in_reply_to: msg_id as u64,
+                    },
+                ));
+            }
+        }
+
+        &self.init_oks
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        self.init_oks.len() == self.nodes.len()
+    }
+
+    /// Hand `msg` to the node it is addressed to, returning the messages it responded with, see
+    /// `MaelstromNode::respond`.
+    pub fn deliver(
+        &mut self,
+        msg: NodeMessage<N::MessageBody>,
+    ) -> Result<Vec<OutgoingMessage>, Box<dyn Error>> {
+        if !self.is_initialized() {
+            return Err(format!(
+                "Message for {} delivered before every node was initialized",
+                msg.dest
+            )
+            .into());
+        }
+
+        match self.node_mut(&msg.dest) {
+            Some(node) => node.respond(msg),
+            None => Err(format!("{}: no node {}", NodeError::NodeNotFound, msg.dest).into()),
+        }
+    }
+
+    /// Shut every node down like the event loop does once stdin is closed, calling
+    /// `MaelstromNode::on_shutdown` once per node however many times this is called.
+    pub fn shut_down_all(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.is_shut_down {
+            return Ok(());
+        }
+        self.is_shut_down = true;
+        for (_, node) in self.nodes.iter_mut() {
+            node.handle_disconnected_queue()?;
+            node.on_shutdown();
+        }
+        Ok(())
+    }
+
+    pub fn node(&self, node_id: &str) -> Option<&N> {
+        self.nodes
+            .iter()
+            .find(|(id, _)| id == node_id)
+            .map(|(_, node)| node)
+    }
+
+    pub fn node_mut(&mut self, node_id: &str) -> Option<&mut N> {
+        self.nodes
+            .iter_mut()
+            .find(|(id, _)| id == node_id)
+            .map(|(_, node)| node)
+    }
+}
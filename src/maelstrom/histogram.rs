@@ -0,0 +1,59 @@
+use std::fmt;
+use std::time::Duration;
+
+/// Upper bounds of the default buckets, in milliseconds. Anything slower lands in an extra
+/// overflow bucket.
+pub const DEFAULT_BUCKETS_MS: [u64; 8] = [1, 5, 10, 50, 100, 250, 500, 1000];
+
+/// Minimal bucketed latency histogram, cheap enough to record every request.
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    bounds_ms: Vec<u64>,
+    counts: Vec<u64>,
+}
+
+impl Histogram {
+    /// `bounds_ms` must be sorted, they are the inclusive upper bound of each bucket.
+    pub fn new(bounds_ms: &[u64]) -> Histogram {
+        Histogram {
+            bounds_ms: bounds_ms.to_vec(),
+            counts: vec![0; bounds_ms.len() + 1],
+        }
+    }
+
+    pub fn record(&mut self, latency: Duration) {
+        let millis = latency.as_millis();
+        let bucket = self
+            .bounds_ms
+            .iter()
+            .position(|bound| millis <= *bound as u128)
+            .unwrap_or(self.bounds_ms.len());
+        self.counts[bucket] += 1;
+    }
+
+    /// Number of recorded latencies.
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Histogram {
+        Histogram::new(&DEFAULT_BUCKETS_MS)
+    }
+}
+
+impl fmt::Display for Histogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (bound, count) in self.bounds_ms.iter().zip(self.counts.iter()) {
+            write!(f, "<={}ms: {}, ", bound, count)?;
+        }
+        let last_bound = self.bounds_ms.last().copied().unwrap_or(0);
+        write!(
+            f,
+            ">{}ms: {}",
+            last_bound,
+            self.counts[self.bounds_ms.len()]
+        )
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+use std::error::Error;
+
+use super::error::NodeError;
+use super::seq_kv::*;
+use super::{write_node_message, NodeMessage};
+
+/// Environment variable naming the KV store a node keeps its state in, `seq-kv` (the default)
+/// or `lin-kv`, see `RealKvService::from_env`.
+pub const KV_SERVICE_ENV: &str = "MAELSTROM_KV_SERVICE";
+/// Environment variable that, set to `1` or `true`, makes a node keeping its state in a KV store
+/// answer reads only after a fresh read of the store, so they reflect every write confirmed
+/// before the read began.
+pub const LINEARIZABLE_READS_ENV: &str = "MAELSTROM_LINEARIZABLE_READS";
+
+/// The operations a node relies on from a Maelstrom KV service, on integer values.
+///
+/// Every operation answers `msg_id` with a `SeqKvReply`: `read_ok` with the value, `write_ok`,
+/// `cas_ok`, or an `error` (`KeyDoesNotExist` for a missing key, `PreconditionFailed` when a
+/// CAS finds another value than `from`). A service either returns the reply right away, or
+/// returns `None` and the reply arrives later as a message, like from a real KV peer.
+pub trait KvService {
+    fn read(
+        &mut self,
+        src: &str,
+        key: &str,
+        msg_id: Option<u64>,
+    ) -> Result<Option<SeqKvReply>, Box<dyn Error>>;
+    fn write(
+        &mut self,
+        src: &str,
+        key: &str,
+        value: u64,
+        msg_id: Option<u64>,
+    ) -> Result<Option<SeqKvReply>, Box<dyn Error>>;
+    /// Swap the value of `key` from `from` to `to`. With `create_if_not_exists`, a missing key
+    /// is created with `to` whatever `from` is.
+    fn cas(
+        &mut self,
+        src: &str,
+        request: SeqKVCompareAndSwapRequest,
+    ) -> Result<Option<SeqKvReply>, Box<dyn Error>>;
+}
+
+/// A Maelstrom KV service, `seq-kv` or `lin-kv`, reached through messages.
+#[derive(Debug, Clone)]
+pub struct RealKvService {
+    service: String,
+}
+
+impl RealKvService {
+    /// The store reached at `service`, e.g. `SEQ_KV` or `LIN_KV`.
+    pub fn new(service: &str) -> RealKvService {
+        RealKvService {
+            service: service.to_string(),
+        }
+    }
+
+    pub fn seq_kv() -> RealKvService {
+        RealKvService::new(SEQ_KV)
+    }
+
+    pub fn lin_kv() -> RealKvService {
+        RealKvService::new(LIN_KV)
+    }
+
+    /// The store named by `KV_SERVICE_ENV`, seq-kv when it is unset. Any other store than the
+    /// two KV ones is an error.
+    pub fn from_env() -> Result<RealKvService, Box<dyn Error>> {
+        match std::env::var(KV_SERVICE_ENV) {
+            Err(_) => Ok(RealKvService::seq_kv()),
+            Ok(service) if service == SEQ_KV || service == LIN_KV => {
+                Ok(RealKvService::new(&service))
+            }
+            Ok(service) => Err(format!(
+                "{} must be {} or {}, not {}",
+                KV_SERVICE_ENV, SEQ_KV, LIN_KV, service
+            )
+            .into()),
+        }
+    }
+
+    pub fn service(&self) -> &str {
+        &self.service
+    }
+
+    fn send(&self, src: &str, request: SeqKVRequest) -> Result<Option<SeqKvReply>, Box<dyn Error>> {
+        write_node_message(&NodeMessage::new(
+            src.to_string(),
+            self.service.clone(),
+            request,
+        ))?;
+        Ok(None)
+    }
+}
+
+impl KvService for RealKvService {
+    fn read(
+        &mut self,
+        src: &str,
+        key: &str,
+        msg_id: Option<u64>,
+    ) -> Result<Option<SeqKvReply>, Box<dyn Error>> {
+        self.send(
+            src,
+            SeqKVRequest::Read(SeqKVReadRequest {
+                in_reply_to: None,
+                msg_id,
+                key: key.to_string(),
+            }),
+        )
+    }
+
+    fn write(
+        &mut self,
+        src: &str,
+        key: &str,
+        value: u64,
+        msg_id: Option<u64>,
+    ) -> Result<Option<SeqKvReply>, Box<dyn Error>> {
+        self.send(
+            src,
+            SeqKVRequest::Write(SeqKVWriteRequest {
+                in_reply_to: None,
+                msg_id,
+                key: key.to_string(),
+                value,
+            }),
+        )
+    }
+
+    fn cas(
+        &mut self,
+        src: &str,
+        request: SeqKVCompareAndSwapRequest,
+    ) -> Result<Option<SeqKvReply>, Box<dyn Error>> {
+        self.send(src, SeqKVRequest::CompareAndSwap(request))
+    }
+}
+
+/// KV service kept in memory and answering right away, to run a node without a KV peer.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryKvService {
+    values: HashMap<String, u64>,
+}
+
+impl InMemoryKvService {
+    pub fn new() -> InMemoryKvService {
+        InMemoryKvService::default()
+    }
+
+    pub fn get(&self, key: &str) -> Option<u64> {
+        self.values.get(key).copied()
+    }
+
+    fn error(error: NodeError, in_reply_to: Option<u64>, text: String) -> Option<SeqKvReply> {
+        Some(SeqKvReply::Error(SeqKVErrorResponse {
+            in_reply_to,
+            msg_id: None,
+            code: error.code(),
+            text: Some(text),
+        }))
+    }
+
+    fn no_data(in_reply_to: Option<u64>) -> SeqKVNoDataResponse {
+        SeqKVNoDataResponse {
+            in_reply_to,
+            msg_id: None,
+        }
+    }
+}
+
+impl KvService for InMemoryKvService {
+    fn read(
+        &mut self,
+        _src: &str,
+        key: &str,
+        msg_id: Option<u64>,
+    ) -> Result<Option<SeqKvReply>, Box<dyn Error>> {
+        Ok(match self.values.get(key) {
+            Some(value) => Some(SeqKvReply::ReadOk(SeqKVReadResponse {
+                in_reply_to: msg_id,
+                msg_id: None,
+                value: *value,
+            })),
+            None => Self::error(
+                NodeError::KeyDoesNotExist,
+                msg_id,
+                format!("No key {}", key),
+            ),
+        })
+    }
+
+    fn write(
+        &mut self,
+        _src: &str,
+        key: &str,
+        value: u64,
+        msg_id: Option<u64>,
+    ) -> Result<Option<SeqKvReply>, Box<dyn Error>> {
+        self.values.insert(key.to_string(), value);
+        Ok(Some(SeqKvReply::WriteOk(Self::no_data(msg_id))))
+    }
+
+    fn cas(
+        &mut self,
+        _src: &str,
+        request: SeqKVCompareAndSwapRequest,
+    ) -> Result<Option<SeqKvReply>, Box<dyn Error>> {
+        let msg_id = request.msg_id;
+        let Some(to) = request.to else {
+            return Ok(Self::error(
+                NodeError::MalformedRequest,
+                msg_id,
+                "cas without a to value".to_string(),
+            ));
+        };
+        let reply = match self.values.get(&request.key).copied() {
+            None if request.create_if_not_exists => {
+                self.values.insert(request.key, to);
+                Some(SeqKvReply::CasOk(Self::no_data(msg_id)))
+            }
+            None => Self::error(
+                NodeError::KeyDoesNotExist,
+                msg_id,
+                format!("No key {}", request.key),
+            ),
+            Some(current) if Some(current) == request.from => {
+                self.values.insert(request.key, to);
+                Some(SeqKvReply::CasOk(Self::no_data(msg_id)))
+            }
+            Some(current) => Self::error(
+                NodeError::PreconditionFailed,
+                msg_id,
+                format!("Expected {:?}, but had {}", request.from, current),
+            ),
+        };
+        Ok(reply)
+    }
+}
@@ -0,0 +1,151 @@
+use std::error::Error;
+
+use crate::node_log;
+
+/// Environment variable enabling strict mode: messages from unknown sources are dropped.
+pub const STRICT_MODE_ENV: &str = "MAELSTROM_STRICT";
+
+/// Services provided by Maelstrom that nodes may talk to.
+const SERVICES: [&str; 4] = ["seq-kv", "lin-kv", "lww-kv", "lin-tso"];
+
+/// Who a message comes from, see `Membership::classify`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceKind {
+    /// A node of the cluster, including this one.
+    Node,
+    /// A Maelstrom client, `c1`, `c2`, ...
+    Client,
+    /// A Maelstrom service such as `seq-kv`.
+    Service,
+    Unknown,
+}
+
+impl SourceKind {
+    /// Whether messages to this kind of endpoint may go through internal-only transformations
+    /// such as batching or piggybacked acks. Clients and services are checked against the
+    /// Maelstrom protocol strictly, only the nodes of the cluster understand those.
+    pub fn is_internal(self) -> bool {
+        self == SourceKind::Node
+    }
+}
+
+/// Whether strict mode was enabled through `STRICT_MODE_ENV`.
+pub fn strict_mode_from_env() -> bool {
+    std::env::var(STRICT_MODE_ENV).is_ok_and(|strict| strict == "1" || strict == "true")
+}
+
+/// The nodes taking part in the cluster, as announced by the init message.
+#[derive(Debug, Clone, Default)]
+pub struct Membership {
+    node_id: String,
+    node_ids: Vec<String>,
+}
+
+impl Membership {
+    /// Fails if `node_id` is not one of `node_ids`. Duplicated ids are dropped with a warning,
+    /// they would skew anything picking or hashing over the nodes.
+    pub fn new(node_id: String, node_ids: Vec<String>) -> Result<Membership, Box<dyn Error>> {
+        if !node_ids.contains(&node_id) {
+            return Err(
+                format!("Node {} is not part of the cluster {:?}", node_id, node_ids).into(),
+            );
+        }
+
+        let mut unique_ids: Vec<String> = Vec::with_capacity(node_ids.len());
+        for id in node_ids {
+            if unique_ids.contains(&id) {
+                node_log!(node_id, "Ignoring duplicated node id {} in membership", id);
+            } else {
+                unique_ids.push(id);
+            }
+        }
+
+        Ok(Membership {
+            node_id,
+            node_ids: unique_ids,
+        })
+    }
+
+    pub fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    pub fn node_ids(&self) -> &[String] {
+        &self.node_ids
+    }
+
+    pub fn contains(&self, node_id: &str) -> bool {
+        self.node_ids.iter().any(|known_id| known_id == node_id)
+    }
+
+    pub fn classify(&self, src: &str) -> SourceKind {
+        let is_client = src
+            .strip_prefix('c')
+            .is_some_and(|index| !index.is_empty() && index.chars().all(|c| c.is_ascii_digit()));
+
+        if self.contains(src) {
+            SourceKind::Node
+        } else if is_client {
+            SourceKind::Client
+        } else if SERVICES.contains(&src) {
+            SourceKind::Service
+        } else {
+            SourceKind::Unknown
+        }
+    }
+
+    /// Whether messages to `dest` may go through internal-only transformations, see
+    /// `SourceKind::is_internal`.
+    pub fn is_internal(&self, dest: &str) -> bool {
+        self.classify(dest).is_internal()
+    }
+
+    /// Whether a message from `src` should be handled in strict mode, logging the ones that
+    /// are dropped.
+    pub fn accepts(&self, src: &str) -> bool {
+        if self.classify(src) == SourceKind::Unknown {
+            node_log!(self.node_id, "Dropping message from unknown source {}", src);
+            return false;
+        }
+        true
+    }
+
+    /// Keep the neighbors that are part of the cluster, logging the ones that aren't: messages
+    /// sent to them would vanish silently. Fails if no neighbor is left and `allow_empty` is false.
+    pub fn known_neighborhood(
+        &self,
+        neighborhood: Vec<String>,
+        allow_empty: bool,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let (known, unknown): (Vec<String>, Vec<String>) = neighborhood
+            .into_iter()
+            .partition(|node_id| self.contains(node_id));
+
+        if !unknown.is_empty() {
+            node_log!(
+                self.node_id,
+                "Ignoring unknown neighbors from topology: {:?}",
+                unknown
+            );
+        }
+        if known.is_empty() && !allow_empty {
+            return Err(format!("Topology left {} without neighbors", self.node_id).into());
+        }
+
+        Ok(known)
+    }
+
+    /// Every node except this one, in the order given by the init message.
+    pub fn peers(&self) -> impl Iterator<Item = &str> {
+        self.peers_except(&[])
+    }
+
+    /// Every node except this one and the ones in `excludes`, e.g. the source of a message
+    /// we are forwarding.
+    pub fn peers_except<'a>(&'a self, excludes: &'a [&str]) -> impl Iterator<Item = &'a str> {
+        self.node_ids
+            .iter()
+            .map(String::as_str)
+            .filter(move |node_id| *node_id != self.node_id && !excludes.contains(node_id))
+    }
+}
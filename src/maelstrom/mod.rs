@@ -1,46 +1,381 @@
+pub mod backoff;
+pub mod control;
+pub mod divergence;
+pub mod error;
+pub mod gather;
+pub mod harness;
+pub mod histogram;
+pub mod kv_service;
+pub mod membership;
+pub mod outbox;
+pub mod pending;
+pub mod prelude;
+pub mod rate_guard;
+pub mod readiness;
+pub mod replay;
+pub mod rng;
+pub mod rpc;
 pub mod seq_kv;
+pub mod seq_kv_client;
+pub mod transport;
+pub mod watchdog;
 
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
 use std::error::Error;
-use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+use crate::logging::MessageContext;
+use error::{ErrorBody, NodeError, NodeRuntimeError};
+use membership::{strict_mode_from_env, Membership};
+use outbox::Outbox;
+use rpc::Node;
+use watchdog::Watchdog;
+
+/// How long the event loop keeps flushing pending work once stdin is closed, see
+/// `MaelstromNode::has_pending_work`.
+pub const SHUTDOWN_GRACE_MS: u64 = 1000;
+/// Environment variable selecting the single-threaded event loop, see `run_node_event_loop`.
+pub const SINGLE_THREADED_ENV: &str = "MAELSTROM_SINGLE_THREADED";
+/// Environment variable with how many milliseconds a node waits for the init message before
+/// giving up with an error, see `init`. Unset waits forever.
+pub const INIT_TIMEOUT_MS_ENV: &str = "MAELSTROM_INIT_TIMEOUT_MS";
+
+/// Route a message to the handler method of its body's variant, e.g.
+/// `dispatch!(request, self, RequestType { Add(src) => handle_add, CasOk => handle_cas_ok })`
+/// calls `self.handle_add(request.src, body)` for an `Add(body)` and
+/// `self.handle_cas_ok(body)` for a `CasOk(body)`. The fields of the message listed after a
+/// variant are passed before its body. Every variant must be listed, the match is exhaustive.
+#[macro_export]
+macro_rules! dispatch {
+    (
+        $msg:ident,
+        $handler:expr,
+        $body:ident { $($variant:ident $(($($field:ident),*))? => $method:ident),* $(,)? }
+    ) => {
+        match $msg.body {
+            $($body::$variant(body) => $handler.$method($($($msg.$field,)*)? body),)*
+        }
+    };
+}
+
 pub trait MaelstromNode {
     type MessageBody;
 
-    fn initialize(&mut self, node_id: String);
-    fn handle_message(&mut self, msg: NodeMessage<Self::MessageBody>) -> Result<(), Box<dyn std::error::Error>>;
+    /// Called once the init message arrived, with our id and the ids of every node in the
+    /// cluster, ours included. See `peers_of` for the other nodes.
+    fn initialize(&mut self, node_id: String, node_ids: Vec<String>);
+    /// Called right after `initialize` with the fields of the init body other than the
+    /// required ones, such as a `config` object supplied by the test harness.
+    fn configure(&mut self, _config: &HashMap<String, Value>) {}
+    /// Handle `msg`, writing whatever it sends along the way. Nodes implementing `respond`
+    /// leave it out.
+    fn handle_message(&mut self, _msg: NodeMessage<Self::MessageBody>) -> Result<(), Box<dyn std::error::Error>> {
+        Err("handle_message is not implemented, see MaelstromNode::respond".into())
+    }
+    /// Handle `msg` and return the messages to send, the event loop writes them in order once
+    /// it returned. Handlers written this way never touch stdout, so tests can call them
+    /// directly. By default calls `handle_message` and returns nothing.
+    fn respond(&mut self, msg: NodeMessage<Self::MessageBody>) -> Result<Vec<OutgoingMessage>, Box<dyn std::error::Error>> {
+        self.handle_message(msg)?;
+        Ok(vec![])
+    }
+    /// Register the named timers this node wants to be called back for, see `handle_timeout`.
+    fn register_timers(&mut self, _timers: &mut TimerWheel) {}
+    /// Called once for every registered timer that expired since the last loop turn.
+    fn handle_timeout(&mut self, _timer_key: TimerKey) -> Result<(), Box<dyn std::error::Error>> { Ok(()) }
     fn handle_empty_queue(&mut self) -> Result<(), Box<dyn std::error::Error>> { Ok(()) }
-    fn handle_disconnected_queue(&mut self) -> Result<(), Box<dyn std::error::Error>> { panic!("Node queue disconnected.") }
+    /// Whether outbound work is still waiting to be flushed. Once stdin is closed and every
+    /// inbound message was handled, the event loop keeps running timers and
+    /// `handle_empty_queue` while this is true, for up to `SHUTDOWN_GRACE_MS`.
+    fn has_pending_work(&self) -> bool { false }
+    /// Called once stdin is closed and every message read from it was handled, right before
+    /// the event loop returns.
+    fn handle_disconnected_queue(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        crate::logging::dump_recent_logs();
+        Ok(())
+    }
+    /// Called once after the event loop exited cleanly, e.g. to log a summary of the run.
+    fn on_shutdown(&mut self) {}
+    /// One-line summary of the work the node is waiting on, logged by the watchdog when the
+    /// event loop looks stuck, see `watchdog::WATCHDOG_MS_ENV`.
+    fn describe_pending_work(&self) -> Option<String> { None }
+    /// The `Node` this node sends its requests through, if any. Inbound messages answering
+    /// one of its requests go to the callback of the request instead of `handle_message`, and
+    /// callbacks left without a reply are expired between loop turns.
+    fn rpc(&mut self) -> Option<&mut Node> { None }
 }
 
-pub fn run_node_event_loop<N>(mut node: N)
+/// Runs `node` until stdin is closed, returning `Ok` then. Errors are the failures the node
+/// cannot run past, `main` should return them so the process exits with a non-zero code.
+///
+/// By default a reader thread feeds the messages to the loop, so timers and
+/// `handle_empty_queue` keep running while waiting for input. With `SINGLE_THREADED_ENV` set,
+/// messages are read and handled inline instead, see `run_single_threaded`.
+pub fn run_node_event_loop<N>(mut node: N) -> Result<(), NodeRuntimeError>
 where
     N: MaelstromNode,
     N::MessageBody: DeserializeOwned + Send + 'static
 {
-    let node_id = get_node_id().unwrap();
-    node.initialize(node_id);
+    let (membership, config) = init().map_err(NodeRuntimeError::Init)?;
+    let strict = strict_mode_from_env();
+    node.initialize(membership.node_id().to_string(), membership.node_ids().to_vec());
+    node.configure(&config);
+    let mut timers = TimerWheel::new();
+    node.register_timers(&mut timers);
+    if single_threaded_from_env() {
+        run_single_threaded(&mut node, &membership, strict, &mut timers);
+        node.on_shutdown();
+        return Ok(());
+    }
+
     let (tx, rx) = std::sync::mpsc::channel();
+    let shutdown = Arc::new(AtomicBool::new(false));
+    // Only the threaded loop turns while waiting for input, the single-threaded one would
+    // look stuck whenever stdin is idle.
+    let mut watchdog = Watchdog::from_env(membership.node_id()).map_err(NodeRuntimeError::Init)?;
+    let heartbeat = watchdog.as_ref().map(Watchdog::heartbeat);
 
-    std::thread::spawn(move || loop {
-        let request: NodeMessage<N::MessageBody> =
-            read_node_message().expect("Could not read request");
-        tx.send(request).unwrap();
+    let reader_shutdown = shutdown.clone();
+    let reader = std::thread::spawn(move || {
+        while !reader_shutdown.load(Ordering::Relaxed) {
+            let request = match try_read_raw_request() {
+                Ok(Some(request)) => request,
+                Ok(None) => break,
+                // Reading again would most likely fail the same way, forever.
+                Err(err) if is_io_error(err.as_ref()) => {
+                    eprintln!("Could not read stdin, stopping: {:?}", err);
+                    break;
+                }
+                Err(err) => {
+                    eprintln!("Could not read request: {:?}", err);
+                    continue;
+                }
+            };
+            if tx.send(request).is_err() {
+                break;
+            }
+        }
     });
     loop {
-        let node_res = match rx.try_recv() {
-            Ok(msg) => node.handle_message(msg),
-            Err(std::sync::mpsc::TryRecvError::Empty) => node.handle_empty_queue(),
-            Err(std::sync::mpsc::TryRecvError::Disconnected) => node.handle_disconnected_queue(),
+        if let Some(heartbeat) = &heartbeat {
+            heartbeat.beat_with_summary(|| node.describe_pending_work());
+        }
+        match rx.try_recv() {
+            Ok(request) => handle_request(&mut node, &membership, strict, request),
+            Err(std::sync::mpsc::TryRecvError::Empty) => {
+                if let Err(err) = node.handle_empty_queue() {
+                    eprintln!("Error running node event loop: {:?}", err);
+                }
+            }
+            Err(std::sync::mpsc::TryRecvError::Disconnected) => {
+                // The channel only disconnects once it is empty, so every inbound message was
+                // handled: flush the outbound work, then exit. The drain does not beat.
+                drop(watchdog.take());
+                shut_down(&mut node, &mut timers);
+                break;
+            }
         };
 
-        match node_res {
-            Ok(()) => (),
-            Err(err) => {
-                eprintln!("Error running node event loop: {:?}", err);
+        run_timers(&mut node, &mut timers);
+    }
+
+    // The reader only stops on its own when stdin is closed, the flag covers the loop
+    // exiting for any other reason.
+    shutdown.store(true, Ordering::Relaxed);
+    reader.join().map_err(|_| NodeRuntimeError::ReaderPanicked)?;
+    node.on_shutdown();
+    Ok(())
+}
+
+/// Whether the single-threaded event loop was selected through `SINGLE_THREADED_ENV`.
+pub fn single_threaded_from_env() -> bool {
+    std::env::var(SINGLE_THREADED_ENV).is_ok_and(|value| value == "1" || value == "true")
+}
+
+/// Read and handle one message at a time, running the periodic work after each of them.
+/// Messages are processed in strict stdin order with nothing interleaved, which makes a
+/// failing run easier to reproduce. Reads block, so timers and `handle_empty_queue` only run
+/// when a message arrives.
+fn run_single_threaded<N>(
+    node: &mut N,
+    membership: &Membership,
+    strict: bool,
+    timers: &mut TimerWheel,
+)
+where
+    N: MaelstromNode,
+    N::MessageBody: DeserializeOwned,
+{
+    loop {
+        match try_read_raw_request() {
+            Ok(Some(request)) => handle_request(node, membership, strict, request),
+            Ok(None) => break,
+            Err(err) if is_io_error(err.as_ref()) => {
+                eprintln!("Could not read stdin, stopping: {:?}", err);
+                break;
             }
-        };
+            Err(err) => eprintln!("Could not read request: {:?}", err),
+        }
+
+        if let Err(err) = node.handle_empty_queue() {
+            eprintln!("Error running node event loop: {:?}", err);
+        }
+        run_timers(node, timers);
+    }
+
+    shut_down(node, timers);
+}
+
+fn handle_request<N: MaelstromNode>(
+    node: &mut N,
+    membership: &Membership,
+    strict: bool,
+    (msg, context): Request<Value>,
+)
+where
+    N::MessageBody: DeserializeOwned,
+{
+    if strict && !membership.accepts(&msg.src) {
+        return;
+    }
+
+    let src = msg.src.clone();
+    let _scope = crate::logging::enter_message(context);
+    let msg = match node.rpc() {
+        Some(rpc) => match rpc.dispatch_reply(msg) {
+            Some(msg) => msg,
+            None => return,
+        },
+        None => msg,
+    };
+    let msg = match parse_body::<N::MessageBody>(msg) {
+        Ok(msg) => msg,
+        Err(err) => {
+            eprintln!("Could not read request: {:?}", err);
+            if let Some(msg_id) = context.msg_id {
+                let text = Some(err.to_string());
+                let node_id = membership.node_id();
+                if let Err(write_err) =
+                    reply_error(node_id, &src, msg_id, NodeError::MalformedRequest, text)
+                {
+                    eprintln!("Could not write error reply: {:?}", write_err);
+                }
+            }
+            return;
+        }
+    };
+    let sent = node.respond(msg).and_then(|outgoing| {
+        let mut outbox = Outbox::new();
+        outbox.extend(outgoing)?;
+        outbox.flush()
+    });
+    if let Err(err) = sent {
+        report_handler_error(membership.node_id(), &src, context.msg_id, err.as_ref());
+    }
+}
+
+fn run_timers<N: MaelstromNode>(node: &mut N, timers: &mut TimerWheel) {
+    if let Some(rpc) = node.rpc() {
+        for msg_id in rpc.expire_callbacks() {
+            crate::node_log!(rpc.node_id(), "No reply to request {}, dropping its callback", msg_id);
+        }
+    }
+    for timer_key in timers.expired() {
+        if let Err(err) = node.handle_timeout(timer_key) {
+            eprintln!("Error handling timer {}: {:?}", timer_key, err);
+        }
+    }
+}
+
+/// Every inbound message was handled: flush the pending work and let the node clean up.
+fn shut_down<N: MaelstromNode>(node: &mut N, timers: &mut TimerWheel) {
+    drain_pending_work(node, timers);
+    if let Err(err) = node.handle_disconnected_queue() {
+        eprintln!("Error running node event loop: {:?}", err);
+    }
+}
+
+/// Log an error returned by a message handler and, if the request had a `msg_id`, answer it
+/// with that error when it is a `NodeError`, a crash error otherwise, so the client is not left
+/// hanging. Event loops call this instead of panicking, so a single bad message does not take
+/// the node and its state down.
+pub fn report_handler_error(
+    node_id: &str,
+    src: &str,
+    msg_id: Option<u64>,
+    err: &(dyn Error + 'static),
+) {
+    crate::node_log!(node_id, "Error handling message from {}: {}", src, err);
+
+    let Some(msg_id) = msg_id else {
+        return;
+    };
+    let node_error = err
+        .downcast_ref::<NodeError>()
+        .copied()
+        .unwrap_or(NodeError::Crash);
+    if let Err(write_err) = reply_error(
+        node_id,
+        src,
+        msg_id,
+        node_error,
+        Some(err.to_string()),
+    ) {
+        eprintln!("Could not write error reply: {:?}", write_err);
+    }
+}
+
+/// Answer the request `in_reply_to` of `src` with a Maelstrom `error` carrying the code of
+/// `err`, so the client gets a definite answer instead of timing out.
+pub fn reply_error(
+    node_id: &str,
+    src: &str,
+    in_reply_to: u64,
+    err: NodeError,
+    text: Option<String>,
+) -> Result<(), Box<dyn Error>> {
+    let reply = NodeMessage::new(
+        node_id.to_string(),
+        src.to_string(),
+        ErrorBody::new(err, in_reply_to, text),
+    );
+    write_node_message(&reply)
+}
+
+/// `drain_pending_work` for the event loops not built on `MaelstromNode`: keep calling
+/// `work_round`, which runs the periodic work once and returns whether some is still pending,
+/// until none is or `SHUTDOWN_GRACE_MS` is over.
+pub fn drain_with_grace(node_id: &str, mut work_round: impl FnMut() -> bool) {
+    let grace = Timer::from_millis(SHUTDOWN_GRACE_MS);
+    while work_round() {
+        if grace.is_done() {
+            crate::node_log!(node_id, "Shutdown grace period over, exiting with pending work");
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+/// Keep running timers and `handle_empty_queue` until the node has no pending work left or
+/// `SHUTDOWN_GRACE_MS` is over.
+fn drain_pending_work<N: MaelstromNode>(node: &mut N, timers: &mut TimerWheel) {
+    let grace = Timer::from_millis(SHUTDOWN_GRACE_MS);
+    while node.has_pending_work() {
+        if grace.is_done() {
+            eprintln!("Shutdown grace period over, exiting with pending work");
+            break;
+        }
+
+        if let Err(err) = node.handle_empty_queue() {
+            eprintln!("Error running node event loop: {:?}", err);
+        }
+        run_timers(node, timers);
+        std::thread::sleep(Duration::from_millis(1));
     }
 }
 
@@ -49,21 +384,114 @@ where
     B: DeserializeOwned,
 {
     let mut buffer = String::new();
-    std::io::stdin().read_line(&mut buffer)?;
+    transport::transport().read_line(&mut buffer)?;
     // eprintln!("READ: {}", buffer);
     let node_input: NodeMessage<B> = serde_json::from_str(&buffer)?;
     Ok(node_input)
 }
 
+/// Like `read_node_message`, but returns `None` once stdin (or the transport, see
+/// `transport::SOCKET_PATH_ENV`) is closed.
+pub fn try_read_node_message<B>() -> Result<Option<NodeMessage<B>>, Box<dyn Error>>
+where
+    B: DeserializeOwned,
+{
+    let mut buffer = String::new();
+    if transport::transport().read_line(&mut buffer)? == 0 {
+        return Ok(None);
+    }
+    let node_input: NodeMessage<B> = serde_json::from_str(&buffer)?;
+    Ok(Some(node_input))
+}
+
+/// A message read by the event loop, with the ids of its body.
+pub type Request<B> = (NodeMessage<B>, MessageContext);
+
+/// Like `read_node_message`, also returning the ids of the request, see `try_read_request`.
+pub fn read_request<B>() -> Result<Request<B>, Box<dyn Error>>
+where
+    B: DeserializeOwned,
+{
+    try_read_request()?.ok_or_else(|| "Stdin is closed".into())
+}
+
+/// Like `try_read_node_message`, also returning the ids of the request so it can be answered
+/// even if handling it fails, and its log lines tagged with them.
+fn try_read_request<B>() -> Result<Option<Request<B>>, Box<dyn Error>>
+where
+    B: DeserializeOwned,
+{
+    let Some((msg, context)) = try_read_raw_request()? else {
+        return Ok(None);
+    };
+    Ok(Some((parse_body(msg)?, context)))
+}
+
+/// Read requests on a thread of their own, for the event loops polling for them between other
+/// work. Lines that are not valid requests are logged and skipped, the channel disconnects once
+/// stdin is closed or cannot be read anymore.
+pub fn spawn_request_reader<B>() -> Receiver<Request<B>>
+where
+    B: DeserializeOwned + Send + 'static,
+{
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || loop {
+        let request = match try_read_request() {
+            Ok(Some(request)) => request,
+            Ok(None) => break,
+            Err(err) if is_io_error(err.as_ref()) => {
+                eprintln!("Could not read stdin, stopping: {:?}", err);
+                break;
+            }
+            Err(err) => {
+                eprintln!("Could not read request: {:?}", err);
+                continue;
+            }
+        };
+        if tx.send(request).is_err() {
+            break;
+        }
+    });
+    rx
+}
+
+/// Whether reading a message failed on the transport itself, rather than on a line that is not
+/// a valid message.
+fn is_io_error(err: &(dyn Error + 'static)) -> bool {
+    err.is::<std::io::Error>()
+}
+
+/// Like `try_read_request`, leaving the body unparsed so replies can be routed to their RPC
+/// callback whatever their type, see `MaelstromNode::rpc`.
+fn try_read_raw_request() -> Result<Option<Request<Value>>, Box<dyn Error>> {
+    let Some(msg) = try_read_node_message::<Value>()? else {
+        return Ok(None);
+    };
+    let context = MessageContext::from_body(&msg.body);
+    Ok(Some((msg, context)))
+}
+
+fn parse_body<B>(msg: NodeMessage<Value>) -> Result<NodeMessage<B>, Box<dyn Error>>
+where
+    B: DeserializeOwned,
+{
+    let body: B = serde_json::from_value(msg.body)?;
+    Ok(NodeMessage {
+        src: msg.src,
+        dest: msg.dest,
+        body,
+        extra: msg.extra,
+    })
+}
+
 pub fn write_node_message<B>(response: &NodeMessage<B>) -> Result<(), Box<dyn Error>>
 where
     B: Serialize,
 {
     let text: String = serde_json::to_string(&response)?;
     // eprintln!("SENDING: {}", text);
-    std::io::stdout().write_all(text.as_bytes())?;
-    std::io::stdout().write_all(b"\n")?;
-    std::io::stdout().flush()?;
+    rate_guard::record_send();
+    transport::transport().write_line(&text, true)?;
     Ok(())
 }
 
@@ -73,25 +501,77 @@ where
 {
     let text: String = serde_json::to_string(&response)?;
     // eprintln!("SENDING: {}", text);
-    std::io::stdout().write_all(text.as_bytes())?;
-    std::io::stdout().write_all(b"\n")?;
+    rate_guard::record_send();
+    transport::transport().write_line(&text, false)?;
     Ok(())
 }
 
 pub fn get_node_id() -> Result<String, Box<dyn Error>> {
-    let msg: NodeMessage<InitRequest> = read_node_message()?;
-    let new_msg: NodeMessage<InitResponse> = NodeMessage {
-        dest: msg.src,
-        src: msg.body.node_id,
-        body: InitResponse {
+    let membership = get_membership()?;
+    Ok(membership.node_id().to_string())
+}
+
+/// The ids in `node_ids` other than `node_id`, in order.
+pub fn peers_of(node_id: &str, node_ids: &[String]) -> Vec<String> {
+    node_ids
+        .iter()
+        .filter(|peer| *peer != node_id)
+        .cloned()
+        .collect()
+}
+
+/// Answer the init message and return the membership it announced.
+pub fn get_membership() -> Result<Membership, Box<dyn Error>> {
+    let (membership, _config) = init()?;
+    Ok(membership)
+}
+
+/// Answer the init message and return the membership it announced, along with the extra
+/// fields of its body, see `InitRequest::config`.
+pub fn init() -> Result<(Membership, HashMap<String, Value>), Box<dyn Error>> {
+    let msg = read_init_message()?;
+    // Validate before answering, a misconfigured node should not look healthy.
+    let membership = Membership::new(msg.body.node_id.clone(), msg.body.node_ids)?;
+    let new_msg: NodeMessage<InitResponse> = NodeMessage::new(
+        msg.body.node_id,
+        msg.src,
+        InitResponse {
             _type: "init_ok".into(),
             in_reply_to: msg.body.msg_id,
         },
-    };
+    );
 
     write_node_message(&new_msg)?;
 
-    Ok(new_msg.src)
+    Ok((membership, msg.body.config))
+}
+
+/// Read the init message, failing once `INIT_TIMEOUT_MS_ENV` is over if it is set, so a
+/// misconfigured node exits instead of hanging.
+fn read_init_message() -> Result<NodeMessage<InitRequest>, Box<dyn Error>> {
+    let Ok(timeout_ms) = std::env::var(INIT_TIMEOUT_MS_ENV) else {
+        return read_node_message();
+    };
+    let timeout_ms: u64 = timeout_ms
+        .parse()
+        .map_err(|err| format!("Invalid {}={}: {}", INIT_TIMEOUT_MS_ENV, timeout_ms, err))?;
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let msg = read_node_message::<InitRequest>().map_err(|err| err.to_string());
+        let _ = tx.send(msg);
+    });
+    match rx.recv_timeout(Duration::from_millis(timeout_ms)) {
+        Ok(msg) => Ok(msg?),
+        Err(_) => {
+            eprintln!(
+                "{} No init message received within {}ms, is the node started by Maelstrom?",
+                crate::get_ts(),
+                timeout_ms
+            );
+            Err(format!("No init message received within {}ms", timeout_ms).into())
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -99,6 +579,84 @@ pub struct NodeMessage<B> {
     pub src: String,
     pub dest: String,
     pub body: B,
+    /// Top-level fields other than src/dest/body. They are ignored by the workloads but kept
+    /// around so they can be inspected or sent back with `with_extra`.
+    #[serde(flatten, default, skip_serializing_if = "HashMap::is_empty")]
+    pub extra: HashMap<String, Value>,
+}
+
+impl<B> NodeMessage<B> {
+    /// In debug builds, panics if `src` or `dest` is empty, which usually means the message
+    /// was built before the node was initialized.
+    pub fn new(src: String, dest: String, body: B) -> NodeMessage<B> {
+        debug_assert!(!src.is_empty(), "NodeMessage built with an empty src (dest: {})", dest);
+        debug_assert!(!dest.is_empty(), "NodeMessage built with an empty dest (src: {})", src);
+        NodeMessage {
+            src,
+            dest,
+            body,
+            extra: HashMap::new(),
+        }
+    }
+
+    /// Copy extra top-level fields into this message, e.g. to echo the ones of a request.
+    pub fn with_extra(mut self, extra: &HashMap<String, Value>) -> NodeMessage<B> {
+        self.extra
+            .extend(extra.iter().map(|(key, value)| (key.clone(), value.clone())));
+        self
+    }
+}
+
+impl<B: Serialize> NodeMessage<B> {
+    /// Compact one-line rendering for logs, e.g. `c1->n0 type=broadcast msg_id=3 message=42`.
+    /// Scalar body fields are printed as is, arrays and objects only by their size.
+    pub fn debug_line(&self) -> String {
+        let mut line = format!("{}->{}", self.src, self.dest);
+        let body = match serde_json::to_value(&self.body) {
+            Ok(Value::Object(body)) => body,
+            Ok(body) => {
+                line.push_str(&format!(" body={}", body));
+                return line;
+            }
+            Err(err) => {
+                line.push_str(&format!(" <unserializable body: {}>", err));
+                return line;
+            }
+        };
+        for (key, value) in body.iter() {
+            let value = match value {
+                Value::String(value) => value.clone(),
+                Value::Array(values) => format!("[{}]", values.len()),
+                Value::Object(values) => format!("{{{}}}", values.len()),
+                value => value.to_string(),
+            };
+            line.push_str(&format!(" {}={}", key, value));
+        }
+        line
+    }
+}
+
+/// A message returned by `MaelstromNode::respond`, with its body already serialized.
+pub type OutgoingMessage = NodeMessage<Value>;
+
+impl<B: Serialize> NodeMessage<B> {
+    /// This message with its body serialized, e.g. to return it from `MaelstromNode::respond`.
+    pub fn into_outgoing(self) -> Result<OutgoingMessage, Box<dyn Error>> {
+        Ok(NodeMessage {
+            body: serde_json::to_value(&self.body)?,
+            src: self.src,
+            dest: self.dest,
+            extra: self.extra,
+        })
+    }
+}
+
+/// Response bodies that can wrap themselves into the reply to a request.
+pub trait IntoReply: Sized {
+    /// A message from `node_id` back to the sender of `request`, carrying this body.
+    fn into_reply<B>(self, node_id: &str, request: &NodeMessage<B>) -> NodeMessage<Self> {
+        NodeMessage::new(node_id.to_string(), request.src.clone(), self)
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -108,6 +666,9 @@ pub struct InitRequest {
     pub msg_id: u64,
     pub node_id: String,
     pub node_ids: Vec<String>,
+    /// Every other field of the body, workloads may pass parameters there.
+    #[serde(flatten)]
+    pub config: HashMap<String, Value>,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -137,6 +698,58 @@ impl Timer {
     pub fn reset(&mut self) {
         self.instant = Instant::now();
     }
+
+    /// Change how long the timer runs, still counting from its last reset.
+    pub fn set_duration(&mut self, duration: Duration) {
+        self.duration = duration;
+    }
+}
+
+/// Name of a timer registered in a `TimerWheel`.
+pub type TimerKey = &'static str;
+
+/// Named periodic timers, owned by the event loop so nodes don't need to poll their own.
+#[derive(Debug, Clone, Default)]
+pub struct TimerWheel {
+    timers: Vec<(TimerKey, Timer)>,
+}
+
+impl TimerWheel {
+    pub fn new() -> TimerWheel {
+        TimerWheel { timers: vec![] }
+    }
+
+    /// Register a timer firing every `millis`, replacing any timer with the same key.
+    pub fn register(&mut self, timer_key: TimerKey, millis: u64) {
+        self.cancel(timer_key);
+        self.timers.push((timer_key, Timer::from_millis(millis)));
+    }
+
+    pub fn cancel(&mut self, timer_key: TimerKey) {
+        self.timers.retain(|(key, _)| *key != timer_key);
+    }
+
+    /// Keys of the timers that expired, each one is re-armed for its next period as it is
+    /// yielded. Called on every loop iteration, so it doesn't allocate.
+    pub fn expired(&mut self) -> impl Iterator<Item = TimerKey> + '_ {
+        self.timers.iter_mut().filter_map(|(timer_key, timer)| {
+            if !timer.is_done() {
+                return None;
+            }
+            timer.reset();
+            Some(*timer_key)
+        })
+    }
+}
+
+/// `a + b` that fails with a `Crash` error instead of panicking (debug) or wrapping (release).
+pub fn checked_add(a: u64, b: u64) -> Result<u64, NodeError> {
+    a.checked_add(b).ok_or(NodeError::Crash)
+}
+
+/// `a - b` that fails with a `Crash` error instead of panicking (debug) or wrapping (release).
+pub fn checked_sub(a: u64, b: u64) -> Result<u64, NodeError> {
+    a.checked_sub(b).ok_or(NodeError::Crash)
 }
 
 pub fn generate_id(node_id: &str, current_count: u32) -> u64 {
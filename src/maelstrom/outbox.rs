@@ -0,0 +1,81 @@
+use std::collections::HashSet;
+use std::error::Error;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use super::{write_node_message, write_node_message_no_flush, NodeMessage};
+
+/// Messages emitted while handling one request, written out by the event loop once the handler
+/// returned instead of by the handler itself.
+///
+/// Ordering guarantee: messages are written in the order they were pushed, and every message
+/// of a request is written before the next request is handled. A handler answering a request
+/// and fanning it out pushes the reply first, so the client is acknowledged before any peer is
+/// contacted.
+///
+/// A message identical, byte for byte, to one already pending is dropped, so overlapping
+/// propagation paths of a handler do not send the same value to the same neighbor twice.
+#[derive(Debug, Default)]
+pub struct Outbox {
+    messages: Vec<NodeMessage<Value>>,
+    /// Serialized form of every pending message.
+    pending: HashSet<String>,
+}
+
+impl Outbox {
+    pub fn new() -> Outbox {
+        Outbox::default()
+    }
+
+    /// Queue `msg`, returning `false` when it was dropped as a duplicate of a pending message.
+    pub fn push<B: Serialize>(&mut self, msg: NodeMessage<B>) -> Result<bool, Box<dyn Error>> {
+        let msg = msg.into_outgoing()?;
+        if !self.pending.insert(serde_json::to_string(&msg)?) {
+            return Ok(false);
+        }
+        self.messages.push(msg);
+        Ok(true)
+    }
+
+    pub fn extend<B: Serialize>(
+        &mut self,
+        msgs: impl IntoIterator<Item = NodeMessage<B>>,
+    ) -> Result<(), Box<dyn Error>> {
+        for msg in msgs {
+            self.push(msg)?;
+        }
+        Ok(())
+    }
+
+    /// Messages pushed since the last flush, in emission order.
+    pub fn messages(&self) -> &[NodeMessage<Value>] {
+        &self.messages
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    /// Take the pending messages without writing them, e.g. to inspect them in a test.
+    pub fn take(&mut self) -> Vec<NodeMessage<Value>> {
+        self.pending.clear();
+        std::mem::take(&mut self.messages)
+    }
+
+    /// Write the pending messages in emission order, flushing once after the last one.
+    pub fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        let messages = self.take();
+        let Some((last, rest)) = messages.split_last() else {
+            return Ok(());
+        };
+        for msg in rest {
+            write_node_message_no_flush(msg)?;
+        }
+        write_node_message(last)
+    }
+}
@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+
+use super::Timer;
+
+/// Operations we are waiting a reply for, keyed by the `msg_id` of the request and given up on
+/// once their timeout is over.
+#[derive(Debug, Clone)]
+pub struct Pending<T> {
+    timeout_ms: u64,
+    entries: HashMap<u64, (Timer, T)>,
+}
+
+impl<T> Pending<T> {
+    pub fn new(timeout_ms: u64) -> Pending<T> {
+        Pending {
+            timeout_ms,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Start waiting on `msg_id`, replacing (and restarting the timer of) any previous entry.
+    pub fn insert(&mut self, msg_id: u64, value: T) {
+        self.entries
+            .insert(msg_id, (Timer::from_millis(self.timeout_ms), value));
+    }
+
+    /// Stop waiting on `msg_id`, usually because its reply arrived.
+    pub fn take(&mut self, msg_id: u64) -> Option<T> {
+        self.entries.remove(&msg_id).map(|(_, value)| value)
+    }
+
+    pub fn get(&self, msg_id: u64) -> Option<&T> {
+        self.entries.get(&msg_id).map(|(_, value)| value)
+    }
+
+    pub fn contains(&self, msg_id: u64) -> bool {
+        self.entries.contains_key(&msg_id)
+    }
+
+    /// Remove and return the entries whose timeout is over.
+    pub fn expired(&mut self) -> Vec<(u64, T)> {
+        let expired_ids: Vec<u64> = self
+            .entries
+            .iter()
+            .filter(|(_, (timer, _))| timer.is_done())
+            .map(|(msg_id, _)| *msg_id)
+            .collect();
+
+        expired_ids
+            .into_iter()
+            .filter_map(|msg_id| self.take(msg_id).map(|value| (msg_id, value)))
+            .collect()
+    }
+
+    /// Entries still waited on, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &T)> {
+        self.entries
+            .iter()
+            .map(|(msg_id, (_, value))| (*msg_id, value))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
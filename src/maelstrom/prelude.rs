@@ -0,0 +1,67 @@
+//! Items most workloads need, so a node can start with `use distributed_systems::prelude::*;`.
+//!
+//! A whole node with nothing else imported:
+//!
+//! ```no_run
+//! use distributed_systems::prelude::*;
+//!
+//! #[derive(Deserialize, Serialize)]
+//! struct Ping {
+//!     #[serde(rename = "type")]
+//!     _type: String,
+//!     msg_id: u64,
+//! }
+//!
+//! #[derive(Deserialize, Serialize)]
+//! struct Pong {
+//!     #[serde(rename = "type")]
+//!     _type: String,
+//!     in_reply_to: u64,
+//! }
+//!
+//! impl IntoReply for Pong {}
+//!
+//! #[derive(Default)]
+//! struct PingNode {
+//!     node_id: String,
+//! }
+//!
+//! impl MaelstromNode for PingNode {
+//!     type MessageBody = Ping;
+//!
+//!     fn initialize(&mut self, node_id: String, _node_ids: Vec<String>) {
+//!         self.node_id = node_id;
+//!     }
+//!
+//!     fn respond(
+//!         &mut self,
+//!         msg: NodeMessage<Ping>,
+//!     ) -> Result<Vec<OutgoingMessage>, Box<dyn std::error::Error>> {
+//!         node_log!(self.node_id, "Received {}", msg.debug_line());
+//!         let pong = Pong {
+//!             _type: "pong".into(),
+//!             in_reply_to: msg.body.msg_id,
+//!         };
+//!         Ok(vec![pong.into_reply(&self.node_id, &msg).into_outgoing()?])
+//!     }
+//! }
+//!
+//! fn main() -> Result<(), NodeRuntimeError> {
+//!     run_node_event_loop(PingNode::default())
+//! }
+//! ```
+
+pub use serde::{Deserialize, Serialize};
+
+pub use super::backoff::Backoff;
+pub use super::error::{ErrorBody, NodeError, NodeRuntimeError};
+pub use super::membership::Membership;
+pub use super::pending::Pending;
+pub use super::rng::Rng;
+pub use super::rpc::Node;
+pub use super::{
+    checked_add, checked_sub, generate_id, get_membership, get_node_id, peers_of,
+    read_node_message, reply_error, run_node_event_loop, write_node_message, IntoReply,
+    MaelstromNode, NodeMessage, OutgoingMessage, Timer, TimerKey, TimerWheel,
+};
+pub use crate::{get_ts, node_log};
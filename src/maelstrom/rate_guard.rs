@@ -0,0 +1,88 @@
+use std::error::Error;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Environment variable holding the maximum number of messages a node may send per second
+/// before a warning is logged. Unset disables the check.
+pub const MAX_SENDS_PER_SEC_ENV: &str = "MAELSTROM_MAX_SENDS_PER_SEC";
+
+const WINDOW: Duration = Duration::from_secs(1);
+
+static SEND_RATE_GUARD: OnceLock<Option<Mutex<RateGuard>>> = OnceLock::new();
+
+/// Counts outbound messages per one second window, to catch retransmission storms (e.g. a
+/// resend condition that never clears) during development.
+#[derive(Debug, Clone)]
+pub struct RateGuard {
+    max_per_window: u64,
+    window_start: Option<Instant>,
+    count: u64,
+}
+
+impl RateGuard {
+    pub fn new(max_per_sec: u64) -> RateGuard {
+        RateGuard {
+            max_per_window: max_per_sec,
+            window_start: None,
+            count: 0,
+        }
+    }
+
+    /// Guard configured through `MAX_SENDS_PER_SEC_ENV`, if set. A rate that is not a number
+    /// is an error rather than silently unchecked.
+    pub fn from_env() -> Result<Option<RateGuard>, Box<dyn Error>> {
+        let Ok(max_per_sec) = std::env::var(MAX_SENDS_PER_SEC_ENV) else {
+            return Ok(None);
+        };
+        let max_per_sec = max_per_sec
+            .parse()
+            .map_err(|err| format!("Invalid {}={}: {}", MAX_SENDS_PER_SEC_ENV, max_per_sec, err))?;
+        Ok(Some(RateGuard::new(max_per_sec)))
+    }
+
+    /// Count one message sent at `now`. Returns true for the message crossing the threshold,
+    /// once per window.
+    pub fn record_at(&mut self, now: Instant) -> bool {
+        let window_is_over = self
+            .window_start
+            .is_none_or(|window_start| now.duration_since(window_start) >= WINDOW);
+        if window_is_over {
+            self.window_start = Some(now);
+            self.count = 0;
+        }
+
+        self.count += 1;
+        self.count == self.max_per_window.saturating_add(1)
+    }
+
+    pub fn record(&mut self) -> bool {
+        self.record_at(Instant::now())
+    }
+
+    /// Messages counted in the current window.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+/// Count a message sent by this process, logging a warning when the configured rate is crossed.
+pub fn record_send() {
+    let guard = SEND_RATE_GUARD.get_or_init(|| {
+        let guard = RateGuard::from_env().expect("Invalid send rate limit.");
+        guard.map(Mutex::new)
+    });
+    let Some(guard) = guard else {
+        return;
+    };
+
+    let mut guard = guard
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if guard.record() {
+        eprintln!(
+            "{} WARNING: more than {} messages sent in the last second, runaway send loop?",
+            crate::get_ts(),
+            guard.max_per_window
+        );
+    }
+}
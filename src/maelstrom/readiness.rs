@@ -0,0 +1,58 @@
+use std::collections::VecDeque;
+
+/// Environment variable that, set to `1` or `true`, makes nodes sync with their peers after
+/// init before answering clients. Off by default, Maelstrom tolerates the stale cold reads.
+pub const WAIT_FOR_SYNC_ENV: &str = "MAELSTROM_WAIT_FOR_SYNC";
+
+/// Whether waiting for the initial sync was enabled through `WAIT_FOR_SYNC_ENV`.
+pub fn wait_for_sync_from_env() -> bool {
+    std::env::var(WAIT_FOR_SYNC_ENV).is_ok_and(|wait| wait == "1" || wait == "true")
+}
+
+/// Holds the client requests a freshly started node receives until its initial sync with the
+/// peers is done, so it does not answer them from its empty state.
+#[derive(Debug, Clone)]
+pub struct Readiness<T> {
+    ready: bool,
+    held: VecDeque<T>,
+}
+
+impl<T> Readiness<T> {
+    /// A gate that is closed until `mark_ready` when `wait_for_sync` is set, open otherwise.
+    pub fn new(wait_for_sync: bool) -> Readiness<T> {
+        Readiness {
+            ready: !wait_for_sync,
+            held: VecDeque::new(),
+        }
+    }
+
+    /// See `wait_for_sync_from_env`.
+    pub fn from_env() -> Readiness<T> {
+        Readiness::new(wait_for_sync_from_env())
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready
+    }
+
+    /// Requests held so far.
+    pub fn held(&self) -> usize {
+        self.held.len()
+    }
+
+    /// Keep `request` until the node is ready, or hand it back to be handled now if it
+    /// already is.
+    pub fn hold(&mut self, request: T) -> Option<T> {
+        if self.ready {
+            return Some(request);
+        }
+        self.held.push_back(request);
+        None
+    }
+
+    /// Open the gate, returning the held requests in the order they arrived.
+    pub fn mark_ready(&mut self) -> Vec<T> {
+        self.ready = true;
+        self.held.drain(..).collect()
+    }
+}
@@ -0,0 +1,75 @@
+use std::error::Error;
+use std::io::BufRead;
+
+use serde::de::DeserializeOwned;
+
+use super::{MaelstromNode, NodeMessage};
+
+/// Feed every message of a captured log to an already initialized node, returning how many
+/// messages were handled.
+///
+/// A capture has one message per line, optionally prefixed by the `get_ts()` timestamp it was
+/// received at, e.g. `1700000000.123 {"src":"c1","dest":"n1","body":{...}}`.
+pub fn replay<N, R>(node: &mut N, reader: R) -> Result<usize, Box<dyn Error>>
+where
+    N: MaelstromNode,
+    N::MessageBody: DeserializeOwned,
+    R: BufRead,
+{
+    replay_window(node, reader, f64::MIN, f64::MAX)
+}
+
+/// Like `replay`, but only handles the messages received between `from_ts` and `to_ts`
+/// (inclusive), so the lead-up to a failure can be replayed on its own.
+///
+/// Lines without a timestamp happen at the same time as the line before them, lines before
+/// the first timestamp are considered to be at time zero.
+pub fn replay_window<N, R>(
+    node: &mut N,
+    reader: R,
+    from_ts: f64,
+    to_ts: f64,
+) -> Result<usize, Box<dyn Error>>
+where
+    N: MaelstromNode,
+    N::MessageBody: DeserializeOwned,
+    R: BufRead,
+{
+    let mut current_ts = 0.0;
+    let mut handled = 0;
+
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let (ts, json) = split_timestamp(line)?;
+        if let Some(ts) = ts {
+            current_ts = ts;
+        }
+        if current_ts < from_ts || current_ts > to_ts {
+            continue;
+        }
+
+        let msg: NodeMessage<N::MessageBody> = serde_json::from_str(json)?;
+        if let Err(err) = node.handle_message(msg) {
+            eprintln!("Error replaying message {}: {:?}", json, err);
+        }
+        handled += 1;
+    }
+
+    Ok(handled)
+}
+
+fn split_timestamp(line: &str) -> Result<(Option<f64>, &str), Box<dyn Error>> {
+    if line.starts_with('{') {
+        return Ok((None, line));
+    }
+
+    let (ts, json) = line
+        .split_once(' ')
+        .ok_or_else(|| format!("Malformed captured line: {}", line))?;
+    Ok((Some(ts.parse()?), json.trim_start()))
+}
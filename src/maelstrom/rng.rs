@@ -0,0 +1,75 @@
+use std::error::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Environment variable with the seed of `Rng::from_env`, to make a run reproducible.
+pub const SEED_ENV: &str = "MAELSTROM_SEED";
+
+/// Small deterministic random number generator (SplitMix64). Every randomized decision of a
+/// node (peer selection, timer jitter, fault injection) should go through the same seeded
+/// instance, so a run can be replayed given its seed.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    seed: u64,
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { seed, state: seed }
+    }
+
+    /// Seeded from `SEED_ENV`, or from the clock when unset. The seed is logged so the run can
+    /// be reproduced. A seed that is not a number is an error, the run could not be replayed.
+    pub fn from_env() -> Result<Rng, Box<dyn Error>> {
+        let seed = match std::env::var(SEED_ENV) {
+            Ok(seed) => seed
+                .parse()
+                .map_err(|err| format!("Invalid {}={}: {}", SEED_ENV, seed, err))?,
+            Err(_) => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |ts| ts.as_nanos() as u64),
+        };
+        eprintln!("{} Random seed: {}", crate::get_ts(), seed);
+        Ok(Rng::new(seed))
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform value in `0..bound`, `bound` must not be zero.
+    pub fn below(&mut self, bound: u64) -> u64 {
+        // Rejection sampling, to avoid the bias of a plain modulo.
+        let zone = u64::MAX - u64::MAX % bound;
+        loop {
+            let value = self.next_u64();
+            if value < zone {
+                return value % bound;
+            }
+        }
+    }
+
+    /// A random element of `items`, `None` if it is empty.
+    pub fn choose<'a, T>(&mut self, items: &'a [T]) -> Option<&'a T> {
+        if items.is_empty() {
+            return None;
+        }
+        items.get(self.below(items.len() as u64) as usize)
+    }
+
+    /// Fisher-Yates shuffle.
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.below(i as u64 + 1) as usize;
+            items.swap(i, j);
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::error::Error;
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use super::pending::Pending;
+use super::{write_node_message, NodeMessage};
+
+/// Called with the reply to a request sent through `Node::send_rpc`.
+pub type ReplyCallback = Box<dyn FnOnce(NodeMessage<Value>)>;
+
+/// Request/reply bookkeeping shared by the workloads: assigns the `msg_id` of outgoing
+/// requests and calls back whoever sent them when the message `in_reply_to` them arrives.
+///
+/// The event loop hands every inbound message to `dispatch_reply` before `handle_message`,
+/// see `MaelstromNode::rpc`. Callbacks whose reply did not arrive within the timeout are
+/// dropped by `expire_callbacks`, so a lost reply does not leak them.
+pub struct Node {
+    node_id: String,
+    next_msg_id: u64,
+    callbacks: Pending<ReplyCallback>,
+}
+
+impl Node {
+    /// Node sending requests as `node_id`, waiting up to `reply_timeout_ms` for their replies.
+    pub fn new(node_id: impl Into<String>, reply_timeout_ms: u64) -> Node {
+        Node {
+            node_id: node_id.into(),
+            next_msg_id: 0,
+            callbacks: Pending::new(reply_timeout_ms),
+        }
+    }
+
+    pub fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    /// A `msg_id` never handed out before by this node.
+    pub fn next_msg_id(&mut self) -> u64 {
+        self.next_msg_id += 1;
+        self.next_msg_id
+    }
+
+    /// Send `body` to `dest` with a fresh `msg_id`, calling `callback` with the reply.
+    /// Returns the `msg_id` of the request.
+    pub fn send_rpc<B: Serialize>(
+        &mut self,
+        dest: &str,
+        body: B,
+        callback: impl FnOnce(NodeMessage<Value>) + 'static,
+    ) -> Result<u64, Box<dyn Error>> {
+        let request = self.rpc(dest, body, callback)?;
+        write_node_message(&request)?;
+        Ok(request.body["msg_id"].as_u64().unwrap_or_default())
+    }
+
+    /// Like `send_rpc`, returning the request instead of writing it, e.g. to push it to an
+    /// `Outbox`. The callback is registered either way.
+    pub fn rpc<B: Serialize>(
+        &mut self,
+        dest: &str,
+        body: B,
+        callback: impl FnOnce(NodeMessage<Value>) + 'static,
+    ) -> Result<NodeMessage<Value>, Box<dyn Error>> {
+        let mut body = serde_json::to_value(body)?;
+        let Some(fields) = body.as_object_mut() else {
+            return Err(format!("RPC body to {} is not an object: {}", dest, body).into());
+        };
+        let msg_id = self.next_msg_id();
+        fields.insert("msg_id".into(), json!(msg_id));
+        self.callbacks.insert(msg_id, Box::new(callback));
+
+        Ok(NodeMessage::new(
+            self.node_id.clone(),
+            dest.to_string(),
+            body,
+        ))
+    }
+
+    /// Call the callback waiting on `msg`, if it is the reply to one of our requests. Returns
+    /// the message back when nobody was waiting on it, so it can be handled as usual.
+    pub fn dispatch_reply(&mut self, msg: NodeMessage<Value>) -> Option<NodeMessage<Value>> {
+        let callback = msg
+            .body
+            .get("in_reply_to")
+            .and_then(Value::as_u64)
+            .and_then(|in_reply_to| self.callbacks.take(in_reply_to));
+        match callback {
+            Some(callback) => {
+                callback(msg);
+                None
+            }
+            None => Some(msg),
+        }
+    }
+
+    /// Drop the callbacks whose reply did not arrive in time, returning the `msg_id` of their
+    /// requests. A reply arriving afterwards is handled as any other message.
+    pub fn expire_callbacks(&mut self) -> Vec<u64> {
+        self.callbacks
+            .expired()
+            .into_iter()
+            .map(|(msg_id, _)| msg_id)
+            .collect()
+    }
+
+    /// How many requests are still waiting on their reply.
+    pub fn pending_replies(&self) -> usize {
+        self.callbacks.len()
+    }
+}
@@ -1,16 +1,26 @@
 use serde::{Deserialize, Serialize};
 
+use super::error::NodeError;
+
+/// Maelstrom's sequentially consistent KV store.
+pub const SEQ_KV: &str = "seq-kv";
+/// Maelstrom's linearizable KV store. It takes the same messages as `SEQ_KV` with the same CAS
+/// semantics, so every type here works with both, only the destination differs.
+pub const LIN_KV: &str = "lin-kv";
+
+/// A request to a KV service. Values are integers unless `V` says otherwise, e.g. `Vec<u64>` to
+/// store a list.
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(tag = "type")]
-pub enum SeqKVRequest {
+pub enum SeqKVRequest<V = u64> {
     #[serde(rename = "read")]
     Read(SeqKVReadRequest),
     #[serde(rename = "read-int")]
     ReadInt(SeqKVReadIntRequest),
     #[serde(rename = "cas")]
-    CompareAndSwap(SeqKVCompareAndSwapRequest),
+    CompareAndSwap(SeqKVCompareAndSwapRequest<V>),
     #[serde(rename = "write")]
-    Write(SeqKVWriteRequest),
+    Write(SeqKVWriteRequest<V>),
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -32,25 +42,28 @@ pub struct SeqKVReadIntRequest {
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
-pub struct SeqKVCompareAndSwapRequest {
+pub struct SeqKVCompareAndSwapRequest<V = u64> {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub in_reply_to: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub msg_id: Option<u64>,
     pub key: String,
-    pub from: Option<u64>,
-    pub to: Option<u64>,
+    pub from: Option<V>,
+    pub to: Option<V>,
+    /// Create the key with `to` when it is missing, otherwise a missing key fails with
+    /// `KeyDoesNotExist`.
+    #[serde(default)]
     pub create_if_not_exists: bool,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
-pub struct SeqKVWriteRequest {
+pub struct SeqKVWriteRequest<V = u64> {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub in_reply_to: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub msg_id: Option<u64>,
     pub key: String,
-    pub value: u64,
+    pub value: V,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -63,6 +76,13 @@ pub struct SeqKVErrorResponse {
     pub text: Option<String>,
 }
 
+impl SeqKVErrorResponse {
+    /// Typed view of `code`, e.g. `KeyAlreadyExists` when a create collides with an existing key.
+    pub fn node_error(&self) -> NodeError {
+        NodeError::from_code(self.code)
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct SeqKVNoDataResponse {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -72,10 +92,24 @@ pub struct SeqKVNoDataResponse {
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
-pub struct SeqKVReadResponse {
+pub struct SeqKVReadResponse<V = u64> {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub in_reply_to: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub msg_id: Option<u64>,
-    pub value: u64,
+    pub value: V,
+}
+
+/// Any reply a KV service sends back to a node, with values of type `V`.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(tag = "type")]
+pub enum SeqKvReply<V = u64> {
+    #[serde(rename = "read_ok")]
+    ReadOk(SeqKVReadResponse<V>),
+    #[serde(rename = "write_ok")]
+    WriteOk(SeqKVNoDataResponse),
+    #[serde(rename = "cas_ok")]
+    CasOk(SeqKVNoDataResponse),
+    #[serde(rename = "error")]
+    Error(SeqKVErrorResponse),
 }
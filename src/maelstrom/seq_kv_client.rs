@@ -0,0 +1,188 @@
+use std::error::Error;
+
+use super::error::NodeError;
+use super::generate_id;
+use super::kv_service::{KvService, RealKvService};
+use super::pending::Pending;
+use super::seq_kv::{SeqKVCompareAndSwapRequest, SeqKvReply};
+
+/// A request sent to the KV service.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KvOp {
+    Read {
+        key: String,
+    },
+    Write {
+        key: String,
+        value: u64,
+    },
+    CompareAndSwap {
+        key: String,
+        from: Option<u64>,
+        to: u64,
+        create_if_not_exists: bool,
+    },
+}
+
+/// A request waiting on its reply, with whatever its sender wants back along the result.
+#[derive(Debug, Clone)]
+pub struct PendingOp<T> {
+    pub op: KvOp,
+    pub context: T,
+}
+
+/// A request the KV service answered.
+#[derive(Debug, Clone)]
+pub struct KvCompletion<T> {
+    pub msg_id: u64,
+    pub op: KvOp,
+    pub context: T,
+    /// The value for a read, `None` for a write or a swap, or the error the service answered.
+    pub result: Result<Option<u64>, NodeError>,
+}
+
+/// Sends reads, writes and swaps to a KV service and pairs each reply with its request, so a
+/// node keeps a single map of its outstanding KV calls instead of one per kind of call.
+///
+/// Every call takes a `context` that comes back in the `KvCompletion`, e.g. the client to
+/// answer once the value is read, or a callback. A call returns its completion right away when
+/// the service answers right away (see `KvService`), otherwise the event loop hands the
+/// service's replies to `handle_response`. Requests whose reply did not arrive within the
+/// timeout are given up on through `expired`.
+pub struct SeqKvClient<T> {
+    node_id: String,
+    kv: Box<dyn KvService>,
+    id_counter: u32,
+    pending: Pending<PendingOp<T>>,
+}
+
+impl<T> SeqKvClient<T> {
+    /// Client sending as `node_id` through `kv`, waiting up to `timeout_ms` for each reply.
+    pub fn new(node_id: impl Into<String>, kv: Box<dyn KvService>, timeout_ms: u64) -> Self {
+        SeqKvClient {
+            node_id: node_id.into(),
+            kv,
+            id_counter: 0,
+            pending: Pending::new(timeout_ms),
+        }
+    }
+
+    /// Client of Maelstrom's `seq-kv`.
+    pub fn seq_kv(node_id: impl Into<String>, timeout_ms: u64) -> Self {
+        SeqKvClient::new(node_id, Box::new(RealKvService::seq_kv()), timeout_ms)
+    }
+
+    /// Client of Maelstrom's `lin-kv`.
+    pub fn lin_kv(node_id: impl Into<String>, timeout_ms: u64) -> Self {
+        SeqKvClient::new(node_id, Box::new(RealKvService::lin_kv()), timeout_ms)
+    }
+
+    pub fn read(
+        &mut self,
+        key: &str,
+        context: T,
+    ) -> Result<Option<KvCompletion<T>>, Box<dyn Error>> {
+        let op = KvOp::Read {
+            key: key.to_string(),
+        };
+        self.send(op, context)
+    }
+
+    pub fn write(
+        &mut self,
+        key: &str,
+        value: u64,
+        context: T,
+    ) -> Result<Option<KvCompletion<T>>, Box<dyn Error>> {
+        let op = KvOp::Write {
+            key: key.to_string(),
+            value,
+        };
+        self.send(op, context)
+    }
+
+    /// Swap `key` from `from` to `to`, see `KvService::cas`.
+    pub fn compare_and_swap(
+        &mut self,
+        key: &str,
+        from: Option<u64>,
+        to: u64,
+        create_if_not_exists: bool,
+        context: T,
+    ) -> Result<Option<KvCompletion<T>>, Box<dyn Error>> {
+        let op = KvOp::CompareAndSwap {
+            key: key.to_string(),
+            from,
+            to,
+            create_if_not_exists,
+        };
+        self.send(op, context)
+    }
+
+    /// Complete the request `reply` answers. Returns `None` for a reply to nothing we are
+    /// waiting on, e.g. one that arrived after its request expired.
+    pub fn handle_response(&mut self, reply: SeqKvReply) -> Option<KvCompletion<T>> {
+        let (in_reply_to, result) = match reply {
+            SeqKvReply::ReadOk(read_ok) => (read_ok.in_reply_to, Ok(Some(read_ok.value))),
+            SeqKvReply::WriteOk(ok) | SeqKvReply::CasOk(ok) => (ok.in_reply_to, Ok(None)),
+            SeqKvReply::Error(err) => (err.in_reply_to, Err(err.node_error())),
+        };
+        let msg_id = in_reply_to?;
+        let PendingOp { op, context } = self.pending.take(msg_id)?;
+        Some(KvCompletion {
+            msg_id,
+            op,
+            context,
+            result,
+        })
+    }
+
+    /// Stop waiting on the requests whose timeout is over, returning them.
+    pub fn expired(&mut self) -> Vec<(u64, PendingOp<T>)> {
+        self.pending.expired()
+    }
+
+    /// Requests still waiting on their reply, in no particular order.
+    pub fn pending(&self) -> impl Iterator<Item = (u64, &PendingOp<T>)> {
+        self.pending.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    fn send(&mut self, op: KvOp, context: T) -> Result<Option<KvCompletion<T>>, Box<dyn Error>> {
+        self.id_counter += 1;
+        let msg_id = generate_id(&self.node_id, self.id_counter);
+        let msg_id_field = Some(msg_id);
+        let reply = match &op {
+            KvOp::Read { key } => self.kv.read(&self.node_id, key, msg_id_field)?,
+            KvOp::Write { key, value } => {
+                self.kv.write(&self.node_id, key, *value, msg_id_field)?
+            }
+            KvOp::CompareAndSwap {
+                key,
+                from,
+                to,
+                create_if_not_exists,
+            } => self.kv.cas(
+                &self.node_id,
+                SeqKVCompareAndSwapRequest {
+                    in_reply_to: None,
+                    msg_id: msg_id_field,
+                    key: key.clone(),
+                    from: *from,
+                    to: Some(*to),
+                    create_if_not_exists: *create_if_not_exists,
+                },
+            )?,
+        };
+        self.pending.insert(msg_id, PendingOp { op, context });
+
+        Ok(reply.and_then(|reply| self.handle_response(reply)))
+    }
+}
@@ -0,0 +1,121 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+
+use serde_json::Value;
+
+use super::NodeMessage;
+
+/// Environment variable with the path of a Unix socket to exchange messages through instead of
+/// stdin/stdout, see `examples/socket_router.rs`. Unset uses stdio, as Maelstrom expects.
+pub const SOCKET_PATH_ENV: &str = "MAELSTROM_SOCKET";
+/// Environment variable enabling the dry run: messages are read as usual but only logged
+/// instead of sent, see `DryRunTransport`.
+pub const DRY_RUN_ENV: &str = "MAELSTROM_DRY_RUN";
+
+static TRANSPORT: OnceLock<Box<dyn Transport>> = OnceLock::new();
+
+/// Where a node reads its messages from and writes them to, one JSON message per line.
+pub trait Transport: Send + Sync {
+    /// Read the next line into `buffer`, returning 0 once the other side is closed.
+    fn read_line(&self, buffer: &mut String) -> io::Result<usize>;
+    /// Write `line` followed by a newline.
+    fn write_line(&self, line: &str, flush: bool) -> io::Result<()>;
+}
+
+/// Maelstrom's transport: messages come in on stdin and go out on stdout.
+pub struct StdioTransport;
+
+impl Transport for StdioTransport {
+    fn read_line(&self, buffer: &mut String) -> io::Result<usize> {
+        io::stdin().read_line(buffer)
+    }
+
+    fn write_line(&self, line: &str, flush: bool) -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(line.as_bytes())?;
+        stdout.write_all(b"\n")?;
+        if flush {
+            stdout.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Messages exchanged over a Unix socket, to wire several node processes together locally
+/// without Maelstrom.
+pub struct UnixSocketTransport {
+    reader: Mutex<BufReader<UnixStream>>,
+    writer: Mutex<UnixStream>,
+}
+
+impl UnixSocketTransport {
+    pub fn connect(path: impl AsRef<Path>) -> io::Result<UnixSocketTransport> {
+        let stream = UnixStream::connect(path)?;
+        Ok(UnixSocketTransport {
+            reader: Mutex::new(BufReader::new(stream.try_clone()?)),
+            writer: Mutex::new(stream),
+        })
+    }
+}
+
+impl Transport for UnixSocketTransport {
+    fn read_line(&self, buffer: &mut String) -> io::Result<usize> {
+        let mut reader = self.reader.lock().unwrap_or_else(|err| err.into_inner());
+        reader.read_line(buffer)
+    }
+
+    fn write_line(&self, line: &str, _flush: bool) -> io::Result<()> {
+        // The stream is not buffered, every write goes out right away.
+        let mut writer = self.writer.lock().unwrap_or_else(|err| err.into_inner());
+        writer.write_all(format!("{}\n", line).as_bytes())
+    }
+}
+
+/// Wraps another transport, reading from it but only logging what would be written, in the
+/// `NodeMessage::debug_line` format. Used to trace the decisions of a node against a captured
+/// input without producing a protocol stream.
+pub struct DryRunTransport {
+    inner: Box<dyn Transport>,
+}
+
+impl DryRunTransport {
+    pub fn new(inner: Box<dyn Transport>) -> DryRunTransport {
+        DryRunTransport { inner }
+    }
+}
+
+impl Transport for DryRunTransport {
+    fn read_line(&self, buffer: &mut String) -> io::Result<usize> {
+        self.inner.read_line(buffer)
+    }
+
+    fn write_line(&self, line: &str, _flush: bool) -> io::Result<()> {
+        match serde_json::from_str::<NodeMessage<Value>>(line) {
+            Ok(msg) => crate::node_log!(msg.src, "Dry run, not sending {}", msg.debug_line()),
+            Err(_) => crate::node_log!("?", "Dry run, not sending {}", line),
+        }
+        Ok(())
+    }
+}
+
+/// The transport of this process, a Unix socket if `SOCKET_PATH_ENV` is set, stdio otherwise.
+/// Wrapped in a `DryRunTransport` when `DRY_RUN_ENV` is set.
+pub fn transport() -> &'static dyn Transport {
+    TRANSPORT
+        .get_or_init(|| {
+            let transport: Box<dyn Transport> = match std::env::var(SOCKET_PATH_ENV) {
+                Ok(path) => Box::new(
+                    UnixSocketTransport::connect(&path)
+                        .unwrap_or_else(|err| panic!("Cannot connect to socket {}: {}", path, err)),
+                ),
+                Err(_) => Box::new(StdioTransport),
+            };
+            if std::env::var(DRY_RUN_ENV).is_ok_and(|value| value == "1" || value == "true") {
+                return Box::new(DryRunTransport::new(transport));
+            }
+            transport
+        })
+        .as_ref()
+}
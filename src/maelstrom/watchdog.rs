@@ -0,0 +1,160 @@
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Environment variable holding how many milliseconds the event loop may go without a turn
+/// before the watchdog warns about it. Unset disables the watchdog.
+pub const WATCHDOG_MS_ENV: &str = "MAELSTROM_WATCHDOG_MS";
+
+/// What the watchdog saw when it fired.
+#[derive(Debug, Clone)]
+pub struct Stall {
+    /// Loop turns counted so far, the loop has been stuck on the one after it.
+    pub turns: u64,
+    /// How long ago the loop last made progress.
+    pub stalled_for: Duration,
+    /// Last pending work summary the loop published, see `Heartbeat::beat_with_summary`.
+    pub pending_summary: Option<String>,
+}
+
+/// Handle the event loop bumps on every turn to show it is making progress.
+#[derive(Debug, Clone)]
+pub struct Heartbeat {
+    turns: Arc<AtomicU64>,
+    summary: Arc<Mutex<(Option<Instant>, Option<String>)>>,
+    summary_every: Duration,
+}
+
+impl Heartbeat {
+    pub fn beat(&self) {
+        self.turns.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Like `beat`, also refreshing the pending work summary the watchdog logs when it fires.
+    /// `summary` is only called every half interval, it may be expensive.
+    pub fn beat_with_summary(&self, summary: impl FnOnce() -> Option<String>) {
+        self.beat();
+        let mut published = self.summary.lock().unwrap();
+        let is_due = published
+            .0
+            .is_none_or(|at| at.elapsed() >= self.summary_every);
+        if is_due {
+            *published = (Some(Instant::now()), summary());
+        }
+    }
+}
+
+/// Thread warning when the event loop stops making progress, e.g. a handler stuck in a loop
+/// or blocked waiting on a reply that is only read by the loop itself. The loop reports its
+/// progress through a `Heartbeat`.
+#[derive(Debug)]
+pub struct Watchdog {
+    heartbeat: Heartbeat,
+    fired: Arc<AtomicU64>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Watchdog {
+    /// Watchdog logging a warning, and the pending work summary if any, when the loop did not
+    /// turn for `interval`.
+    pub fn spawn(node_id: String, interval: Duration) -> Watchdog {
+        Watchdog::with_handler(interval, move |stall| {
+            crate::node_log!(
+                node_id,
+                "Watchdog: event loop stuck for {}ms after {} turns",
+                stall.stalled_for.as_millis(),
+                stall.turns
+            );
+            if let Some(summary) = stall.pending_summary {
+                crate::node_log!(node_id, "Watchdog: pending work: {}", summary);
+            }
+        })
+    }
+
+    /// Watchdog configured through `WATCHDOG_MS_ENV`, if set. An interval that is not a number
+    /// is an error rather than silently unwatched.
+    pub fn from_env(node_id: &str) -> Result<Option<Watchdog>, Box<dyn Error>> {
+        let Ok(interval_ms) = std::env::var(WATCHDOG_MS_ENV) else {
+            return Ok(None);
+        };
+        let interval_ms = interval_ms
+            .parse()
+            .map_err(|err| format!("Invalid {}={}: {}", WATCHDOG_MS_ENV, interval_ms, err))?;
+        Ok(Some(Watchdog::spawn(
+            node_id.to_string(),
+            Duration::from_millis(interval_ms),
+        )))
+    }
+
+    /// Watchdog calling `on_stall` when the loop did not turn for `interval`. It fires once
+    /// per stall, and again only after the loop made progress.
+    pub fn with_handler(interval: Duration, on_stall: impl Fn(Stall) + Send + 'static) -> Watchdog {
+        let heartbeat = Heartbeat {
+            turns: Arc::new(AtomicU64::new(0)),
+            summary: Arc::new(Mutex::new((None, None))),
+            summary_every: interval / 2,
+        };
+        let fired = Arc::new(AtomicU64::new(0));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let watched = heartbeat.clone();
+        let thread_fired = fired.clone();
+        let thread_stop = stop.clone();
+        let check_every = (interval / 4).max(Duration::from_millis(1));
+        let thread = std::thread::spawn(move || {
+            let mut last_turns = watched.turns.load(Ordering::Relaxed);
+            let mut last_progress = Instant::now();
+            let mut reported = false;
+            while !thread_stop.load(Ordering::Relaxed) {
+                std::thread::sleep(check_every);
+                let turns = watched.turns.load(Ordering::Relaxed);
+                if turns != last_turns {
+                    last_turns = turns;
+                    last_progress = Instant::now();
+                    reported = false;
+                    continue;
+                }
+
+                let stalled_for = last_progress.elapsed();
+                if stalled_for >= interval && !reported {
+                    reported = true;
+                    thread_fired.fetch_add(1, Ordering::Relaxed);
+                    let pending_summary = watched.summary.lock().unwrap().1.clone();
+                    on_stall(Stall {
+                        turns,
+                        stalled_for,
+                        pending_summary,
+                    });
+                }
+            }
+        });
+
+        Watchdog {
+            heartbeat,
+            fired,
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn heartbeat(&self) -> Heartbeat {
+        self.heartbeat.clone()
+    }
+
+    /// How many stalls were reported so far.
+    pub fn fired(&self) -> u64 {
+        self.fired.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,46 @@
+//! Checks the retry schedule of `Backoff`.
+
+use std::thread;
+use std::time::Duration;
+
+use distributed_systems::maelstrom::backoff::Backoff;
+
+#[test]
+fn delay_doubles_up_to_the_cap() {
+    let mut backoff = Backoff::new(10, 100);
+    let mut delays = vec![];
+    for _ in 0..6 {
+        delays.push(backoff.next_delay_ms());
+        backoff.schedule();
+    }
+    assert_eq!(delays, [10, 20, 40, 80, 100, 100]);
+    assert_eq!(backoff.attempts(), 6);
+
+    backoff.reset();
+    assert_eq!(backoff.attempts(), 0);
+    assert_eq!(backoff.next_delay_ms(), 10);
+}
+
+#[test]
+fn many_failures_do_not_overflow() {
+    let mut backoff = Backoff::new(10, 1_000);
+    for _ in 0..100 {
+        backoff.schedule();
+    }
+    assert_eq!(backoff.next_delay_ms(), 1_000);
+}
+
+#[test]
+fn scheduled_retry_is_ready_once() {
+    let mut backoff = Backoff::new(10, 100);
+    assert!(!backoff.take_ready());
+
+    backoff.schedule();
+    assert!(backoff.is_waiting());
+    assert!(!backoff.take_ready());
+
+    thread::sleep(Duration::from_millis(20));
+    assert!(!backoff.is_waiting());
+    assert!(backoff.take_ready());
+    assert!(!backoff.take_ready());
+}
@@ -0,0 +1,64 @@
+//! Checks that the broadcast value an ack is for travels in `acked_value`, leaving `msg_id`
+//! free to be a real message id, through `performant_broadcast_final`.
+
+mod common;
+
+use common::{start_broadcast_hub, TestNode};
+use serde_json::{json, Value};
+
+fn broadcast(node: &mut TestNode, msg_id: u64, value: u64) {
+    node.send(&json!({"src": "c1", "dest": "n0", "body": {
+        "type": "broadcast", "msg_id": msg_id, "message": value,
+    }}));
+}
+
+fn unacked_by_n5(node: &mut TestNode, msg_id: u64) -> Value {
+    node.send(&json!({"src": "c1", "dest": "n0", "body": {
+        "type": "pending_summary", "msg_id": msg_id,
+    }}));
+    let summary = node.recv_type("pending_summary_ok");
+    let mut unacked: Vec<u64> = summary["body"]["pending"]["unacked_broadcasts"]["n5"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|value| value.as_u64().unwrap())
+        .collect();
+    unacked.sort_unstable();
+    json!(unacked)
+}
+
+#[test]
+fn client_ack_carries_the_value_in_acked_value() {
+    let mut node = start_broadcast_hub(&[]);
+    broadcast(&mut node, 10, 42);
+
+    let ack = node.recv_matching(|msg| msg["dest"] == "c1");
+    assert_eq!(ack["body"]["type"], "broadcast_ok");
+    assert_eq!(ack["body"]["in_reply_to"], 10);
+    assert_eq!(ack["body"]["acked_value"], 42);
+    assert_ne!(ack["body"].get("msg_id"), Some(&json!(42)));
+}
+
+#[test]
+fn acks_clear_the_value_named_in_acked_value() {
+    let mut node = start_broadcast_hub(&[]);
+    broadcast(&mut node, 10, 1);
+    broadcast(&mut node, 11, 2);
+    assert_eq!(unacked_by_n5(&mut node, 12), json!([1, 2]));
+
+    // The ids of the ack name the other pending value, only acked_value counts.
+    node.send(&json!({"src": "n5", "dest": "n0", "body": {
+        "type": "broadcast_ok", "msg_id": 2, "in_reply_to": 2, "acked_value": 1,
+    }}));
+    assert_eq!(unacked_by_n5(&mut node, 13), json!([2]));
+}
+
+#[test]
+fn ack_without_acked_value_clears_nothing() {
+    let mut node = start_broadcast_hub(&[]);
+    broadcast(&mut node, 10, 1);
+    node.send(&json!({"src": "n5", "dest": "n0", "body": {
+        "type": "broadcast_ok", "msg_id": 1,
+    }}));
+    assert_eq!(unacked_by_n5(&mut node, 11), json!([1]));
+}
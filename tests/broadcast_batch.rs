@@ -0,0 +1,87 @@
+//! Checks the batching of `performant_broadcast_final`, enabled through
+//! `BATCH_MAX_DELAY_MS_ENV`: the values learned within the window reach another hub as one
+//! `broadcast_batch`.
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{start_broadcast_hub, TestNode};
+use distributed_systems::broadcast::{BATCH_MAX_DELAY_MS_ENV, BATCH_MAX_SIZE_ENV};
+use serde_json::{json, Value};
+
+fn broadcast(node: &mut TestNode, value: u64) {
+    node.send(&json!({"src": "c1", "dest": "n0", "body": {
+        "type": "broadcast", "msg_id": 10 + value, "message": value,
+    }}));
+}
+
+fn is_to_n5(msg: &Value) -> bool {
+    msg["dest"] == "n5"
+}
+
+#[test]
+fn values_within_the_window_are_sent_together() {
+    let mut node = start_broadcast_hub(&[(BATCH_MAX_DELAY_MS_ENV, "100")]);
+    let started = Instant::now();
+    broadcast(&mut node, 1);
+    broadcast(&mut node, 2);
+    broadcast(&mut node, 3);
+
+    let batch = node.recv_matching(is_to_n5);
+    assert_eq!(batch["body"]["type"], "broadcast_batch");
+    assert_eq!(batch["body"]["messages"], json!([1, 2, 3]));
+    assert!(started.elapsed() >= Duration::from_millis(100));
+}
+
+#[test]
+fn full_batch_is_sent_right_away() {
+    let mut node =
+        start_broadcast_hub(&[(BATCH_MAX_DELAY_MS_ENV, "60000"), (BATCH_MAX_SIZE_ENV, "2")]);
+    broadcast(&mut node, 1);
+    broadcast(&mut node, 2);
+    broadcast(&mut node, 3);
+
+    let batch = node.recv_matching(is_to_n5);
+    assert_eq!(batch["body"]["type"], "broadcast_batch");
+    assert_eq!(batch["body"]["messages"], json!([1, 2]));
+}
+
+#[test]
+fn values_are_sent_on_their_own_by_default() {
+    let mut node = start_broadcast_hub(&[]);
+    broadcast(&mut node, 1);
+
+    let broadcast = node.recv_matching(is_to_n5);
+    assert_eq!(broadcast["body"]["type"], "broadcast");
+    assert_eq!(broadcast["body"]["message"], 1);
+}
+
+#[test]
+fn clients_are_answered_without_batching() {
+    let mut node = start_broadcast_hub(&[(BATCH_MAX_DELAY_MS_ENV, "100")]);
+    broadcast(&mut node, 1);
+    broadcast(&mut node, 2);
+
+    let mut acks = vec![];
+    let batch = loop {
+        let msg = node.recv();
+        if msg["dest"] == "c1" {
+            acks.push(msg);
+        } else if is_to_n5(&msg) {
+            break msg;
+        }
+    };
+    assert_eq!(batch["body"]["type"], "broadcast_batch");
+    let acked: Vec<(&Value, &Value)> = acks
+        .iter()
+        .map(|ack| (&ack["body"]["type"], &ack["body"]["acked_value"]))
+        .collect();
+    assert_eq!(
+        acked,
+        [
+            (&json!("broadcast_ok"), &json!(1)),
+            (&json!("broadcast_ok"), &json!(2))
+        ]
+    );
+}
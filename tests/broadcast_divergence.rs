@@ -0,0 +1,54 @@
+//! Replays the same messages through two broadcast binaries with `compare_broadcast_reads`.
+
+use std::time::Duration;
+
+use distributed_systems::maelstrom::divergence::{compare_broadcast_reads, Divergence};
+
+const SETTLE: Duration = Duration::from_millis(200);
+
+const INPUT: &str = r#"{"src":"c0","dest":"n0","body":{"type":"init","msg_id":1,"node_id":"n0","node_ids":["n0"]}}
+{"src":"c0","dest":"n0","body":{"type":"topology","msg_id":2,"topology":{"n0":[]}}}
+{"src":"c1","dest":"n0","body":{"type":"broadcast","msg_id":3,"message":7}}
+{"src":"c1","dest":"n0","body":{"type":"read","msg_id":4}}
+{"src":"c1","dest":"n0","body":{"type":"broadcast","msg_id":5,"message":2}}
+{"src":"c1","dest":"n0","body":{"type":"read","msg_id":6}}
+"#;
+
+#[test]
+fn simple_and_performant_broadcast_agree() {
+    let divergences = compare_broadcast_reads(
+        env!("CARGO_BIN_EXE_broadcast"),
+        env!("CARGO_BIN_EXE_performant_broadcast"),
+        INPUT,
+        SETTLE,
+    )
+    .unwrap();
+    assert_eq!(divergences, []);
+}
+
+#[test]
+fn unanswered_reads_are_flagged() {
+    // The echo node answers none of the reads.
+    let divergences = compare_broadcast_reads(
+        env!("CARGO_BIN_EXE_broadcast"),
+        env!("CARGO_BIN_EXE_echo"),
+        INPUT,
+        SETTLE,
+    )
+    .unwrap();
+    assert_eq!(
+        divergences,
+        [
+            Divergence {
+                in_reply_to: Some(4),
+                left: Some(vec![7]),
+                right: None,
+            },
+            Divergence {
+                in_reply_to: Some(6),
+                left: Some(vec![2, 7]),
+                right: None,
+            },
+        ]
+    );
+}
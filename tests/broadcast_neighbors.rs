@@ -0,0 +1,60 @@
+//! Checks the `add_neighbor`/`remove_neighbor` messages of `performant_broadcast_final`, which
+//! change the neighborhood at runtime.
+
+mod common;
+
+use common::{start_broadcast_hub, TestNode};
+use serde_json::{json, Value};
+
+fn unacked(node: &mut TestNode, msg_id: u64) -> Value {
+    node.send(
+        &json!({"src": "c1", "dest": "n0", "body": {"type": "pending_summary", "msg_id": msg_id}}),
+    );
+    node.recv_type("pending_summary_ok")["body"]["pending"]["unacked_broadcasts"].clone()
+}
+
+fn change_neighbor(node: &mut TestNode, change: &str, node_id: &str, msg_id: u64) {
+    node.send(&json!({"src": "c0", "dest": "n0", "body": {
+        "type": change, "node_id": node_id, "msg_id": msg_id,
+    }}));
+    let reply = node.recv_type(&format!("{}_ok", change));
+    assert_eq!(reply["body"]["in_reply_to"], msg_id);
+}
+
+#[test]
+fn removing_a_neighbor_drops_only_its_pending_messages() {
+    let mut node = start_broadcast_hub(&[]);
+    node.send(&json!({"src": "c1", "dest": "n0", "body": {
+        "type": "broadcast", "msg_id": 10, "message": 42,
+    }}));
+    assert_eq!(unacked(&mut node, 11)["n5"], json!([42]));
+
+    change_neighbor(&mut node, "remove_neighbor", "n3", 12);
+    let pending = unacked(&mut node, 13);
+    assert!(pending.get("n3").is_none(), "{}", pending);
+    assert_eq!(pending["n5"], json!([42]));
+
+    change_neighbor(&mut node, "remove_neighbor", "n5", 14);
+    let pending = unacked(&mut node, 15);
+    assert!(pending.get("n5").is_none(), "{}", pending);
+    assert_eq!(pending["n1"], json!([]));
+}
+
+#[test]
+fn added_neighbor_starts_fresh_and_keeps_the_others() {
+    let mut node = start_broadcast_hub(&[]);
+    change_neighbor(&mut node, "remove_neighbor", "n5", 10);
+    node.send(&json!({"src": "c1", "dest": "n0", "body": {
+        "type": "broadcast", "msg_id": 11, "message": 42,
+    }}));
+
+    change_neighbor(&mut node, "add_neighbor", "n5", 12);
+    let pending = unacked(&mut node, 13);
+    // Nothing was pending for n5 while it was out of the neighborhood.
+    assert_eq!(pending["n5"], json!([]));
+
+    node.send(&json!({"src": "c1", "dest": "n0", "body": {
+        "type": "broadcast", "msg_id": 14, "message": 43,
+    }}));
+    assert_eq!(unacked(&mut node, 15)["n5"], json!([43]));
+}
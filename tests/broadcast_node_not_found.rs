@@ -0,0 +1,90 @@
+//! Checks that the broadcast workloads stop retrying a neighbor Maelstrom answered with a
+//! `NodeNotFound` error, and only then.
+
+mod common;
+
+use std::time::Duration;
+
+use common::{start_broadcast_hub, TestNode};
+use distributed_systems::maelstrom::error::NodeError;
+use serde_json::{json, Value};
+
+/// Long enough for both binaries to retry an unacknowledged broadcast at least once.
+const RETRY_WINDOW: Duration = Duration::from_millis(1200);
+
+fn broadcast(node: &mut TestNode, msg_id: u64, value: u64) {
+    node.send(&json!({"src": "c1", "dest": "n0", "body": {
+        "type": "broadcast", "msg_id": msg_id, "message": value,
+    }}));
+}
+
+fn error_from(node: &mut TestNode, src: &str, code: u64) {
+    node.send(&json!({"src": src, "dest": "n0", "body": {
+        "type": "error", "code": code, "text": "no such node",
+    }}));
+}
+
+fn broadcasts_to(msgs: &[Value], dest: &str) -> usize {
+    msgs.iter()
+        .filter(|msg| msg["dest"] == dest && msg["body"]["type"] == "broadcast")
+        .count()
+}
+
+fn start_fault_tolerant() -> TestNode {
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_fault_tolerant_broadcast"));
+    node.init("n0", &["n0", "n1"]);
+    node.send(&json!({"src": "c0", "dest": "n0", "body": {
+        "type": "topology", "msg_id": 2, "topology": {"n0": ["n1"], "n1": ["n0"]},
+    }}));
+    node.recv_type("topology_ok");
+    node
+}
+
+#[test]
+fn hub_stops_retrying_a_missing_neighbor() {
+    let mut node = start_broadcast_hub(&[]);
+    broadcast(&mut node, 10, 1);
+    node.recv_matching(|msg| msg["dest"] == "n5" && msg["body"]["type"] == "broadcast");
+    error_from(&mut node, "n5", NodeError::NodeNotFound.code());
+    // Whatever was already on its way when the error arrived.
+    node.recv_for(Duration::from_millis(200));
+
+    broadcast(&mut node, 11, 2);
+    let emitted = node.recv_for(RETRY_WINDOW);
+    assert_eq!(broadcasts_to(&emitted, "n5"), 0, "{:?}", emitted);
+}
+
+#[test]
+fn hub_keeps_retrying_after_other_errors() {
+    let mut node = start_broadcast_hub(&[]);
+    broadcast(&mut node, 10, 1);
+    node.recv_matching(|msg| msg["dest"] == "n5" && msg["body"]["type"] == "broadcast");
+    error_from(&mut node, "n5", NodeError::Crash.code());
+
+    let emitted = node.recv_for(RETRY_WINDOW);
+    assert!(broadcasts_to(&emitted, "n5") > 0, "{:?}", emitted);
+}
+
+#[test]
+fn fault_tolerant_broadcast_stops_retrying_a_missing_neighbor() {
+    let mut node = start_fault_tolerant();
+    broadcast(&mut node, 10, 1);
+    node.recv_matching(|msg| msg["dest"] == "n1" && msg["body"]["type"] == "broadcast");
+    error_from(&mut node, "n1", NodeError::NodeNotFound.code());
+    node.recv_for(Duration::from_millis(200));
+
+    broadcast(&mut node, 11, 2);
+    let emitted = node.recv_for(RETRY_WINDOW);
+    assert_eq!(broadcasts_to(&emitted, "n1"), 0, "{:?}", emitted);
+}
+
+#[test]
+fn fault_tolerant_broadcast_keeps_retrying_after_other_errors() {
+    let mut node = start_fault_tolerant();
+    broadcast(&mut node, 10, 1);
+    node.recv_matching(|msg| msg["dest"] == "n1" && msg["body"]["type"] == "broadcast");
+    error_from(&mut node, "n1", NodeError::Crash.code());
+
+    let emitted = node.recv_for(RETRY_WINDOW);
+    assert!(broadcasts_to(&emitted, "n1") > 0, "{:?}", emitted);
+}
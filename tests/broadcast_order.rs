@@ -0,0 +1,40 @@
+//! Checks the order the `broadcast` binary emits the messages of a single broadcast in, see
+//! `Outbox`: the broadcast_ok first, then the fan-out to the neighbors in topology order.
+
+mod common;
+
+use common::TestNode;
+use serde_json::json;
+
+#[test]
+fn broadcast_acks_before_fan_out() {
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_broadcast"));
+    node.init("n0", &["n0", "n1", "n2"]);
+    node.send_all(&[
+        json!({"src": "c1", "dest": "n0", "body": {
+            "type": "topology", "msg_id": 2, "topology": {"n0": ["n2", "n1"]},
+        }}),
+        json!({"src": "c1", "dest": "n0", "body": {"type": "broadcast", "msg_id": 3, "message": 7}}),
+    ]);
+
+    let emitted = node.recv_n(5);
+    let order: Vec<(&str, &str)> = emitted
+        .iter()
+        .map(|msg| {
+            (
+                msg["body"]["type"].as_str().unwrap(),
+                msg["dest"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        order,
+        vec![
+            ("init_ok", "c0"),
+            ("topology_ok", "c1"),
+            ("broadcast_ok", "c1"),
+            ("broadcast", "n2"),
+            ("broadcast", "n1"),
+        ]
+    );
+}
@@ -0,0 +1,61 @@
+//! Checks `OverflowPolicy::DropOldest` in `performant_broadcast_final`: past the limit set
+//! through `MAX_PENDING_PER_NEIGHBOR_ENV`, the oldest value pending for a neighbor is dropped,
+//! and the neighbor still gets it through the read sync.
+
+mod common;
+
+use common::{start_broadcast_hub, TestNode};
+use distributed_systems::broadcast::{OverflowPolicy, MAX_PENDING_PER_NEIGHBOR_ENV};
+use serde_json::json;
+
+#[test]
+fn excess_is_dropped_oldest_first() {
+    let policy = OverflowPolicy::DropOldest { limit: 2 };
+    assert_eq!(policy.excess(1), 0);
+    assert_eq!(policy.excess(2), 0);
+    assert_eq!(policy.excess(5), 3);
+    assert_eq!(OverflowPolicy::Unbounded.excess(1000), 0);
+}
+
+#[test]
+fn dropped_value_is_recovered_through_the_read_sync() {
+    let mut node = start_broadcast_hub(&[(MAX_PENDING_PER_NEIGHBOR_ENV, "2")]);
+    // n5 never acknowledges, so the third value pushes the first one out.
+    for value in 1..=3 {
+        node.send(&json!({"src": "c1", "dest": "n0", "body": {
+            "type": "broadcast", "msg_id": 10 + value, "message": value,
+        }}));
+    }
+    node.send(
+        &json!({"src": "c1", "dest": "n0", "body": {"type": "pending_summary", "msg_id": 20}}),
+    );
+    let summary = node.recv_type("pending_summary_ok");
+    assert_eq!(
+        summary["body"]["pending"]["unacked_broadcasts"]["n5"],
+        json!([2, 3])
+    );
+
+    // n5 catches up by reading from n0, as it does on every client read.
+    node.send(&json!({"src": "n5", "dest": "n0", "body": {"type": "read", "msg_id": 30}}));
+    let read_ok = node.recv_type("read_ok");
+    assert_eq!(read_ok["dest"], "n5");
+    assert_eq!(read_ok["body"]["messages"], json!([1, 2, 3]));
+}
+
+#[test]
+fn unparsable_limit_stops_the_node() {
+    let mut node = TestNode::start_with_env(
+        env!("CARGO_BIN_EXE_performant_broadcast_final"),
+        &[(MAX_PENDING_PER_NEIGHBOR_ENV, "two")],
+    );
+    node.init("n0", &["n0"]);
+    let (_, status) = node.finish();
+
+    assert!(!status.success());
+    let stderr = node.stderr();
+    assert!(
+        stderr.contains("Invalid BROADCAST_MAX_PENDING_PER_NEIGHBOR=two"),
+        "{}",
+        stderr
+    );
+}
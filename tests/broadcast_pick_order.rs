@@ -0,0 +1,54 @@
+//! Checks the order `performant_broadcast_final` retries unacknowledged broadcasts in, with
+//! its default `PickPolicy::Oldest`: the oldest value pending for a neighbor goes first.
+
+mod common;
+
+use common::{start_broadcast_hub, TestNode};
+use serde_json::{json, Value};
+
+#[test]
+fn oldest_pending_broadcast_is_retried_first() {
+    let mut node = start_broadcast_hub(&[]);
+    for value in 1..=3 {
+        send(
+            &mut node,
+            json!({"type": "broadcast", "msg_id": 10 + value, "message": value}),
+            "c1",
+        );
+    }
+
+    // The first sends go out as the values arrive.
+    let first_sends: Vec<u64> = (0..3).map(|_| next_broadcast_to_n5(&node)).collect();
+    assert_eq!(first_sends, vec![1, 2, 3]);
+
+    // Then every retry is the oldest value n5 did not acknowledge yet.
+    let mut acked = vec![];
+    for expected in 1..=3 {
+        let retried = loop {
+            let value = next_broadcast_to_n5(&node);
+            if !acked.contains(&value) {
+                break value;
+            }
+        };
+        assert_eq!(
+            retried, expected,
+            "Retried out of order after acking {:?}",
+            acked
+        );
+        send(
+            &mut node,
+            json!({"type": "broadcast_ok", "acked_value": retried}),
+            "n5",
+        );
+        acked.push(retried);
+    }
+}
+
+fn send(node: &mut TestNode, body: Value, src: &str) {
+    node.send(&json!({"src": src, "dest": "n0", "body": body}));
+}
+
+fn next_broadcast_to_n5(node: &TestNode) -> u64 {
+    let msg = node.recv_matching(|msg| msg["dest"] == "n5" && msg["body"]["type"] == "broadcast");
+    msg["body"]["message"].as_u64().unwrap()
+}
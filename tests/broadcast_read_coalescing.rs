@@ -0,0 +1,64 @@
+//! Checks that `performant_broadcast_final` sends a single replicate read per peer for client
+//! reads arriving close together.
+
+mod common;
+
+use std::collections::HashMap;
+use std::thread;
+use std::time::Duration;
+
+use common::start_broadcast_hub;
+use serde_json::{json, Value};
+
+fn client_reads(first_msg_id: u64, count: u64) -> Vec<Value> {
+    (first_msg_id..first_msg_id + count)
+        .map(
+            |msg_id| json!({"src": "c1", "dest": "n0", "body": {"type": "read", "msg_id": msg_id}}),
+        )
+        .collect()
+}
+
+/// How many replicate reads went to each peer.
+fn replicate_reads(emitted: &[Value]) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    for msg in emitted {
+        let dest = msg["dest"].as_str().unwrap();
+        if msg["body"]["type"] == "read" && dest.starts_with('n') {
+            *counts.entry(dest.to_string()).or_default() += 1;
+        }
+    }
+    counts
+}
+
+#[test]
+fn reads_in_quick_succession_share_replicate_reads() {
+    let mut node = start_broadcast_hub(&[]);
+    node.recv_type("topology_ok");
+    node.send_all(&client_reads(10, 10));
+    let (emitted, status) = node.finish();
+    assert!(status.success(), "{}", node.stderr());
+
+    let counts = replicate_reads(&emitted);
+    assert!(!counts.is_empty(), "{:?}", emitted);
+    assert!(counts.values().all(|count| *count == 1), "{:?}", counts);
+    // Every client read is still answered.
+    let read_oks = emitted
+        .iter()
+        .filter(|msg| msg["dest"] == "c1" && msg["body"]["type"] == "read_ok")
+        .count();
+    assert_eq!(read_oks, 10);
+}
+
+#[test]
+fn reads_further_apart_send_again() {
+    let mut node = start_broadcast_hub(&[]);
+    node.recv_type("topology_ok");
+    node.send_all(&client_reads(10, 1));
+    thread::sleep(Duration::from_millis(400));
+    node.send_all(&client_reads(11, 1));
+    let (emitted, _) = node.finish();
+
+    let counts = replicate_reads(&emitted);
+    assert!(!counts.is_empty(), "{:?}", emitted);
+    assert!(counts.values().all(|count| *count == 2), "{:?}", counts);
+}
@@ -0,0 +1,44 @@
+//! Checks the read repair of `performant_broadcast_final`, enabled through `READ_REPAIR_ENV`:
+//! the values missing from a peer's read_ok are pushed back to it.
+
+mod common;
+
+use std::time::Duration;
+
+use common::{start_broadcast_hub, TestNode};
+use distributed_systems::broadcast::READ_REPAIR_ENV;
+use serde_json::json;
+
+/// Let `n0` learn 1, 2 and 3 from `n5`, so it only sends values to `n5` to repair it, then
+/// answer with a read_ok of `n5` that lacks 1 and 3. Returns the broadcasts `n0` sent back.
+fn repaired_values(node: &mut TestNode) -> Vec<u64> {
+    for value in 1..=3 {
+        node.send(&json!({"src": "n5", "dest": "n0", "body": {
+            "type": "broadcast", "msg_id": 10 + value, "message": value,
+        }}));
+    }
+    node.send(&json!({"src": "n5", "dest": "n0", "body": {
+        "type": "read_ok", "messages": [2], "version": 1,
+    }}));
+
+    let mut repaired: Vec<u64> = node
+        .recv_for(Duration::from_millis(300))
+        .iter()
+        .filter(|msg| msg["dest"] == "n5" && msg["body"]["type"] == "broadcast")
+        .map(|msg| msg["body"]["message"].as_u64().unwrap())
+        .collect();
+    repaired.sort_unstable();
+    repaired
+}
+
+#[test]
+fn values_missing_from_a_read_ok_are_pushed_back() {
+    let mut node = start_broadcast_hub(&[(READ_REPAIR_ENV, "1")]);
+    assert_eq!(repaired_values(&mut node), vec![1, 3]);
+}
+
+#[test]
+fn no_read_repair_by_default() {
+    let mut node = start_broadcast_hub(&[]);
+    assert_eq!(repaired_values(&mut node), Vec::<u64>::new());
+}
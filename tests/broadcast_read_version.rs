@@ -0,0 +1,41 @@
+//! Checks the versioned node-to-node read_ok of `performant_broadcast_final`: a read_ok with a
+//! version already merged from that peer is skipped.
+
+mod common;
+
+use common::start_broadcast_hub;
+use serde_json::json;
+
+#[test]
+fn stale_version_read_ok_is_skipped() {
+    let mut node = start_broadcast_hub(&[]);
+    node.send_all(&[
+        json!({"src": "n5", "dest": "n0", "body": {"type": "read_ok", "messages": [1], "version": 3}}),
+        // Older than what was merged from n5 already, its values are not looked at.
+        json!({"src": "n5", "dest": "n0", "body": {"type": "read_ok", "messages": [2], "version": 2}}),
+        // Another peer has its own version.
+        json!({"src": "n1", "dest": "n0", "body": {"type": "read_ok", "messages": [4], "version": 1}}),
+        json!({"src": "n5", "dest": "n0", "body": {"type": "read", "msg_id": 7}}),
+    ]);
+
+    let read_ok = node.recv_type("read_ok");
+    assert_eq!(read_ok["dest"], "n5");
+    let mut values: Vec<u64> = serde_json::from_value(read_ok["body"]["messages"].clone()).unwrap();
+    values.sort_unstable();
+    assert_eq!(values, [1, 4]);
+    // Both merges grew the values.
+    assert_eq!(read_ok["body"]["version"], 2);
+    node.finish();
+    assert!(node
+        .stderr()
+        .contains("Skipping read_ok version 2 from n5, already merged Some(3)"));
+}
+
+#[test]
+fn clients_get_no_version() {
+    let mut node = start_broadcast_hub(&[]);
+    node.send(&json!({"src": "c1", "dest": "n0", "body": {"type": "read", "msg_id": 7}}));
+    let read_ok = node.recv_type("read_ok");
+    assert_eq!(read_ok["dest"], "c1");
+    assert!(read_ok["body"].get("version").is_none(), "{}", read_ok);
+}
@@ -0,0 +1,120 @@
+//! Checks that `performant_broadcast_final` doesn't flood a topology with cycles: a value is
+//! forwarded a bounded number of times, and not at all once it is out of hops.
+
+mod common;
+
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use common::{start_broadcast_hub, TestNode, REPLY_TIMEOUT};
+use serde_json::{json, Value};
+
+const RING: [&str; 4] = ["n0", "n1", "n2", "n3"];
+
+/// Rewire the star-of-stars overlay of `RING` into the ring `n0 - n1 - n2 - n3 - n0`.
+/// n1 and n3 keep their link to the hub n0.
+const CHANGES: [(&str, &str, &str); 6] = [
+    ("n0", "remove_neighbor", "n2"),
+    ("n1", "add_neighbor", "n2"),
+    ("n2", "remove_neighbor", "n0"),
+    ("n2", "add_neighbor", "n1"),
+    ("n2", "add_neighbor", "n3"),
+    ("n3", "add_neighbor", "n2"),
+];
+
+fn start_ring() -> HashMap<String, TestNode> {
+    let topology: serde_json::Map<String, Value> = RING
+        .iter()
+        .map(|node_id| (node_id.to_string(), json!([])))
+        .collect();
+    let mut nodes = HashMap::new();
+    for node_id in RING {
+        let mut node = TestNode::start(env!("CARGO_BIN_EXE_performant_broadcast_final"));
+        node.init(node_id, &RING);
+        node.send(&json!({"src": "c0", "dest": node_id, "body": {
+            "type": "topology", "msg_id": 2, "topology": topology,
+        }}));
+        nodes.insert(node_id.to_string(), node);
+    }
+    for (msg_id, (node_id, change, neighbor)) in CHANGES.iter().enumerate() {
+        let node = nodes.get_mut(*node_id).unwrap();
+        node.send(&json!({"src": "c0", "dest": node_id, "body": {
+            "type": change, "node_id": neighbor, "msg_id": 10 + msg_id,
+        }}));
+        node.recv_type(&format!("{}_ok", change));
+    }
+    nodes
+}
+
+#[test]
+fn value_reaches_the_whole_ring_with_bounded_forwards() {
+    let mut nodes = start_ring();
+    nodes
+        .get_mut("n0")
+        .unwrap()
+        .send(&json!({"src": "c1", "dest": "n0", "body": {
+            "type": "broadcast", "msg_id": 30, "message": 7,
+        }}));
+
+    let mut reached: HashSet<String> = HashSet::from(["n0".to_string()]);
+    let mut forwards = 0;
+    // Keep routing for a while after every node has the value, to catch late re-forwards.
+    let settle = Duration::from_millis(300);
+    let deadline = Instant::now() + REPLY_TIMEOUT;
+    let mut all_reached_at = None;
+    while all_reached_at.is_none_or(|at: Instant| at.elapsed() < settle) {
+        assert!(
+            Instant::now() < deadline,
+            "Only {:?} got the value",
+            reached
+        );
+        let mut msgs = vec![];
+        for node in nodes.values() {
+            while let Some(msg) = node.try_recv(Duration::ZERO) {
+                msgs.push(msg);
+            }
+        }
+        for msg in msgs {
+            let dest = msg["dest"].as_str().unwrap().to_string();
+            let msg_type = msg["body"]["type"].as_str().unwrap();
+            if !nodes.contains_key(&dest) {
+                continue;
+            }
+            if msg_type == "broadcast" {
+                forwards += 1;
+                reached.insert(dest.clone());
+            }
+            if msg_type == "broadcast" || msg_type == "broadcast_ok" {
+                nodes.get_mut(&dest).unwrap().send(&msg);
+            }
+        }
+        if all_reached_at.is_none() && reached.len() == RING.len() {
+            all_reached_at = Some(Instant::now());
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    // At most once over each direction of the 4 links of the ring.
+    assert!(forwards <= 8, "{} forwards", forwards);
+}
+
+#[test]
+fn broadcast_out_of_hops_is_not_forwarded() {
+    let mut node = start_broadcast_hub(&[]);
+    node.send(&json!({"src": "n1", "dest": "n0", "body": {
+        "type": "broadcast", "message": 9, "ttl": 0,
+    }}));
+    node.send(&json!({"src": "n5", "dest": "n0", "body": {"type": "read", "msg_id": 3}}));
+
+    let emitted = node.recv_for(Duration::from_millis(200));
+    assert!(
+        emitted.iter().all(|msg| msg["body"]["type"] != "broadcast"),
+        "{:?}",
+        emitted
+    );
+    let read_ok = emitted
+        .iter()
+        .find(|msg| msg["body"]["type"] == "read_ok")
+        .unwrap();
+    assert_eq!(read_ok["body"]["messages"], json!([9]));
+}
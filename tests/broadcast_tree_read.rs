@@ -0,0 +1,73 @@
+//! Checks the tree reads of `performant_broadcast_final`, enabled through `TREE_READ_ENV`: a
+//! client read gathers the values of the whole overlay, whatever its depth.
+
+mod common;
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use common::{TestNode, REPLY_TIMEOUT};
+use distributed_systems::broadcast::TREE_READ_ENV;
+use serde_json::{json, Value};
+
+/// Hubs `n0`, `n5` and `n10`: a read from the leaf `n1` goes `n1 -> n0 -> n5 -> n6..n10`.
+const NODE_COUNT: usize = 11;
+
+#[test]
+fn client_read_gathers_three_levels_of_the_tree() {
+    let node_ids: Vec<String> = (0..NODE_COUNT).map(|i| format!("n{}", i)).collect();
+    let node_id_refs: Vec<&str> = node_ids.iter().map(String::as_str).collect();
+    let topology: serde_json::Map<String, Value> = node_ids
+        .iter()
+        .map(|node_id| (node_id.clone(), json!([])))
+        .collect();
+    let mut nodes: HashMap<String, TestNode> = HashMap::new();
+    for (index, node_id) in node_ids.iter().enumerate() {
+        let mut node = TestNode::start_with_env(
+            env!("CARGO_BIN_EXE_performant_broadcast_final"),
+            &[(TREE_READ_ENV, "1")],
+        );
+        node.init(node_id, &node_id_refs);
+        node.send(&json!({"src": "c0", "dest": node_id, "body": {
+            "type": "topology", "msg_id": 2, "topology": topology,
+        }}));
+        // Every node only knows its own value: the broadcasts between nodes are dropped below.
+        node.send(&json!({"src": "c0", "dest": node_id, "body": {
+            "type": "broadcast", "msg_id": 3, "message": index,
+        }}));
+        nodes.insert(node_id.clone(), node);
+    }
+    nodes
+        .get_mut("n1")
+        .unwrap()
+        .send(&json!({"src": "c1", "dest": "n1", "body": {
+            "type": "read", "msg_id": 4,
+        }}));
+
+    let deadline = Instant::now() + REPLY_TIMEOUT;
+    let read_ok = 'routing: loop {
+        assert!(Instant::now() < deadline, "No read_ok for the tree read");
+        let mut msgs = vec![];
+        for node in nodes.values() {
+            while let Some(msg) = node.try_recv(Duration::ZERO) {
+                msgs.push(msg);
+            }
+        }
+        for msg in msgs {
+            let msg_type = msg["body"]["type"].as_str().unwrap();
+            if msg["dest"] == "c1" && msg_type == "read_ok" {
+                break 'routing msg;
+            }
+            if msg_type == "tree_read" || msg_type == "tree_read_ok" {
+                let dest = msg["dest"].as_str().unwrap();
+                nodes.get_mut(dest).unwrap().send(&msg);
+            }
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    };
+
+    assert_eq!(read_ok["body"]["in_reply_to"], 4);
+    let mut values: Vec<u64> = serde_json::from_value(read_ok["body"]["messages"].clone()).unwrap();
+    values.sort_unstable();
+    assert_eq!(values, (0..NODE_COUNT as u64).collect::<Vec<_>>());
+}
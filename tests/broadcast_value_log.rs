@@ -0,0 +1,68 @@
+//! Checks that broadcast values persisted to a `ValueLog` survive a restart.
+
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+
+use common::start_broadcast_hub;
+use distributed_systems::broadcast::{ValueLog, VALUE_LOG_PATH_ENV, VALUE_LOG_SYNC_EVERY_ENV};
+use serde_json::json;
+
+/// A fresh log file for `test`, removed when dropped.
+struct LogFile(PathBuf);
+
+impl LogFile {
+    fn new(test: &str) -> LogFile {
+        let path = std::env::temp_dir().join(format!("value_log_{}_{}", std::process::id(), test));
+        let _ = fs::remove_file(&path);
+        LogFile(path)
+    }
+}
+
+impl Drop for LogFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+#[test]
+fn reopened_log_recovers_every_value() {
+    let log_file = LogFile::new("reopen");
+    {
+        let mut log = ValueLog::open(&log_file.0, 2).unwrap();
+        for value in [4, 8, 15, 16, 23] {
+            log.append(value).unwrap();
+        }
+        log.sync().unwrap();
+    }
+    let restarted = ValueLog::open(&log_file.0, 1).unwrap();
+    let mut values: Vec<u64> = restarted.load().unwrap().into_iter().collect();
+    values.sort_unstable();
+    assert_eq!(values, [4, 8, 15, 16, 23]);
+}
+
+#[test]
+fn restarted_node_reloads_its_values() {
+    let log_file = LogFile::new("restart");
+    let env = [
+        (VALUE_LOG_PATH_ENV, log_file.0.to_str().unwrap()),
+        (VALUE_LOG_SYNC_EVERY_ENV, "1"),
+    ];
+
+    let mut node = start_broadcast_hub(&env);
+    for value in 1..=3 {
+        node.send(&json!({"src": "c1", "dest": "n0", "body": {
+            "type": "broadcast", "msg_id": 10 + value, "message": value,
+        }}));
+        node.recv_type("broadcast_ok");
+    }
+    drop(node);
+
+    let mut node = start_broadcast_hub(&env);
+    node.send(&json!({"src": "n5", "dest": "n0", "body": {"type": "read", "msg_id": 20}}));
+    let read_ok = node.recv_type("read_ok");
+    let mut values: Vec<u64> = serde_json::from_value(read_ok["body"]["messages"].clone()).unwrap();
+    values.sort_unstable();
+    assert_eq!(values, [1, 2, 3]);
+}
@@ -0,0 +1,32 @@
+use distributed_systems::kafka::Offset;
+use distributed_systems::maelstrom::error::NodeError;
+use distributed_systems::maelstrom::{checked_add, checked_sub};
+
+#[test]
+fn offset_since_base() {
+    assert_eq!(Offset(7).since(Offset(5)), Ok(2));
+    assert_eq!(Offset(5).since(Offset(5)), Ok(0));
+}
+
+#[test]
+fn offset_below_base_is_a_malformed_request() {
+    assert_eq!(Offset(3).since(Offset(5)), Err(NodeError::MalformedRequest));
+}
+
+#[test]
+fn checked_sub_fails_instead_of_wrapping() {
+    assert_eq!(checked_sub(5, 3), Ok(2));
+    assert_eq!(checked_sub(3, 5), Err(NodeError::Crash));
+}
+
+#[test]
+fn checked_add_near_u64_max() {
+    assert_eq!(checked_add(u64::MAX - 1, 1), Ok(u64::MAX));
+    assert_eq!(checked_add(u64::MAX, 1), Err(NodeError::Crash));
+}
+
+#[test]
+fn offset_next_near_u64_max() {
+    assert_eq!(Offset(u64::MAX - 1).next(), Ok(Offset(u64::MAX)));
+    assert_eq!(Offset(u64::MAX).next(), Err(NodeError::Crash));
+}
@@ -0,0 +1,227 @@
+//! Runs the node binaries for the integration tests: messages are written to the node's stdin
+//! and read back from its stdout with a timeout on every read, so a node that hangs fails the
+//! test instead of blocking it forever.
+
+#![allow(dead_code)]
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStdin, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+/// How long a test waits for the next message of a node before giving up on it.
+pub const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A node binary started for a test. It is killed when dropped.
+pub struct TestNode {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    /// Messages written by the node, with when they were read.
+    rx: Receiver<(Instant, Value)>,
+    stderr: Arc<Mutex<String>>,
+    /// Collects stderr until the node exits.
+    stderr_reader: Option<JoinHandle<()>>,
+}
+
+impl TestNode {
+    pub fn start(bin: &str) -> TestNode {
+        TestNode::start_with_env(bin, &[])
+    }
+
+    /// Start `bin` with the environment variables `env` set.
+    pub fn start_with_env(bin: &str, env: &[(&str, &str)]) -> TestNode {
+        let mut child = Command::new(bin)
+            .envs(env.iter().copied())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap_or_else(|err| panic!("Cannot start {}: {}", bin, err));
+        let stdin = child.stdin.take();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let (tx, rx) = channel();
+        thread::spawn(move || {
+            for line in stdout.lines().map_while(Result::ok) {
+                let msg: Value = serde_json::from_str(&line)
+                    .unwrap_or_else(|_| panic!("Node wrote invalid JSON: {}", line));
+                if tx.send((Instant::now(), msg)).is_err() {
+                    return;
+                }
+            }
+        });
+        let stderr = Arc::new(Mutex::new(String::new()));
+        let mut child_stderr = child.stderr.take().unwrap();
+        let stderr_sink = stderr.clone();
+        let stderr_reader = thread::spawn(move || {
+            let mut buf = [0; 4096];
+            while let Ok(read @ 1..) = child_stderr.read(&mut buf) {
+                let chunk = String::from_utf8_lossy(&buf[..read]);
+                stderr_sink.lock().unwrap().push_str(&chunk);
+            }
+        });
+
+        TestNode {
+            child,
+            stdin,
+            rx,
+            stderr,
+            stderr_reader: Some(stderr_reader),
+        }
+    }
+
+    /// Send the init of `node_id` in a cluster of `node_ids`, from `c0`.
+    pub fn init(&mut self, node_id: &str, node_ids: &[&str]) {
+        self.send(&json!({"src": "c0", "dest": node_id, "body": {
+            "type": "init", "msg_id": 1, "node_id": node_id, "node_ids": node_ids,
+        }}));
+    }
+
+    /// Write a whole message, `src` and `dest` included.
+    pub fn send(&mut self, msg: &Value) {
+        self.write_line(&msg.to_string());
+    }
+
+    pub fn send_all(&mut self, msgs: &[Value]) {
+        for msg in msgs {
+            self.send(msg);
+        }
+    }
+
+    /// Write a raw line, e.g. one that is not valid JSON.
+    pub fn write_line(&mut self, line: &str) {
+        let stdin = self.stdin.as_mut().expect("stdin is already closed");
+        writeln!(stdin, "{}", line).unwrap();
+    }
+
+    /// Write raw bytes, e.g. ones that are not valid UTF-8.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        let stdin = self.stdin.as_mut().expect("stdin is already closed");
+        stdin.write_all(bytes).unwrap();
+    }
+
+    /// Close stdin, like Maelstrom does at the end of a run.
+    pub fn close_stdin(&mut self) {
+        self.stdin = None;
+    }
+
+    /// The next message of the node, failing the test after `REPLY_TIMEOUT`.
+    pub fn recv(&self) -> Value {
+        self.try_recv(REPLY_TIMEOUT)
+            .expect("Timed out waiting for the node")
+    }
+
+    /// The next message of the node within `timeout`, if any.
+    pub fn try_recv(&self, timeout: Duration) -> Option<Value> {
+        self.try_recv_at(timeout).map(|(_, msg)| msg)
+    }
+
+    /// Like `try_recv`, along with when the node wrote the message.
+    pub fn try_recv_at(&self, timeout: Duration) -> Option<(Instant, Value)> {
+        self.rx.recv_timeout(timeout).ok()
+    }
+
+    /// The next message `matches` accepts, skipping the others. Fails the test when none shows
+    /// up within `REPLY_TIMEOUT`.
+    pub fn recv_matching(&self, matches: impl Fn(&Value) -> bool) -> Value {
+        let deadline = Instant::now() + REPLY_TIMEOUT;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let msg = self
+                .try_recv(left)
+                .expect("Timed out waiting for a matching message");
+            if matches(&msg) {
+                return msg;
+            }
+        }
+    }
+
+    /// The next message with a body of type `msg_type`.
+    pub fn recv_type(&self, msg_type: &str) -> Value {
+        self.recv_matching(|msg| msg["body"]["type"] == msg_type)
+    }
+
+    /// The next `count` messages, whatever they are.
+    pub fn recv_n(&self, count: usize) -> Vec<Value> {
+        (0..count).map(|_| self.recv()).collect()
+    }
+
+    /// Every message received within `window`.
+    pub fn recv_for(&self, window: Duration) -> Vec<Value> {
+        let deadline = Instant::now() + window;
+        let mut msgs = vec![];
+        while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+            match self.try_recv(left) {
+                Some(msg) => msgs.push(msg),
+                None => break,
+            }
+        }
+        msgs
+    }
+
+    /// Close stdin and wait for the node to exit, returning everything it wrote since the last
+    /// message received and its exit status. Fails the test if it is still running after
+    /// `REPLY_TIMEOUT`.
+    pub fn finish(&mut self) -> (Vec<Value>, ExitStatus) {
+        self.close_stdin();
+        let deadline = Instant::now() + REPLY_TIMEOUT;
+        let mut msgs = vec![];
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            match self.rx.recv_timeout(left) {
+                Ok((_, msg)) => msgs.push(msg),
+                Err(RecvTimeoutError::Disconnected) => break,
+                Err(RecvTimeoutError::Timeout) => panic!("Node did not exit after stdin closed"),
+            }
+        }
+        (msgs, self.wait())
+    }
+
+    /// Wait for the node to exit on its own, failing the test after `REPLY_TIMEOUT`.
+    pub fn wait(&mut self) -> ExitStatus {
+        let deadline = Instant::now() + REPLY_TIMEOUT;
+        loop {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                if let Some(stderr_reader) = self.stderr_reader.take() {
+                    let _ = stderr_reader.join();
+                }
+                return status;
+            }
+            assert!(Instant::now() < deadline, "Node did not exit");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// What the node logged so far, everything once `wait` returned.
+    pub fn stderr(&self) -> String {
+        self.stderr.lock().unwrap().clone()
+    }
+}
+
+impl Drop for TestNode {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// `n0` of `performant_broadcast_final`, started with `env`, in a cluster of six nodes where
+/// it and `n5` are the hubs, so the broadcasts between them are tracked until acknowledged.
+pub fn start_broadcast_hub(env: &[(&str, &str)]) -> TestNode {
+    let mut node = TestNode::start_with_env(env!("CARGO_BIN_EXE_performant_broadcast_final"), env);
+    let node_ids: Vec<String> = (0..6).map(|i| format!("n{}", i)).collect();
+    let node_id_refs: Vec<&str> = node_ids.iter().map(String::as_str).collect();
+    node.init("n0", &node_id_refs);
+    // The overlay is sized from the topology, its edges are ignored.
+    let topology: serde_json::Map<String, Value> = node_ids
+        .iter()
+        .map(|node_id| (node_id.clone(), json!([])))
+        .collect();
+    node.send(&json!({"src": "c0", "dest": "n0", "body": {
+        "type": "topology", "msg_id": 2, "topology": topology,
+    }}));
+    node
+}
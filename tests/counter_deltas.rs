@@ -0,0 +1,71 @@
+//! Checks that the counter applies the deltas a client coalesced into one `add` with a single
+//! CAS, played here by the test as seq-kv.
+
+mod common;
+
+use std::time::Duration;
+
+use common::TestNode;
+use distributed_systems::maelstrom::seq_kv::SEQ_KV;
+use serde_json::{json, Value};
+
+/// Answer the seq-kv request `msg` against `stored`, counting the CAS requests.
+fn answer_seq_kv(
+    node: &mut TestNode,
+    msg: &Value,
+    stored: &mut Option<u64>,
+    cas_count: &mut usize,
+) {
+    let body = &msg["body"];
+    let reply = match (body["type"].as_str().unwrap(), *stored) {
+        ("read", Some(value)) => json!({"type": "read_ok", "value": value}),
+        ("read", None) => json!({"type": "error", "code": 20}),
+        ("cas", current) => {
+            *cas_count += 1;
+            if current.is_none() || body["from"].as_u64() == current {
+                *stored = body["to"].as_u64();
+                json!({"type": "cas_ok"})
+            } else {
+                json!({"type": "error", "code": 22})
+            }
+        }
+        (other, _) => panic!("Unexpected seq-kv request {}", other),
+    };
+    let mut reply = json!({"src": SEQ_KV, "dest": "n0", "body": reply});
+    reply["body"]["in_reply_to"] = body["msg_id"].clone();
+    node.send(&reply);
+}
+
+#[test]
+fn coalesced_deltas_are_applied_with_one_cas() {
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_g_counter"));
+    node.init("n0", &["n0"]);
+    node.send(&json!({"src": "c1", "dest": "n0", "body": {
+        "type": "add", "msg_id": 2, "deltas": [1, 2, 3],
+    }}));
+
+    let mut stored = None;
+    let mut cas_count = 0;
+    let mut add_oks = vec![];
+    while stored != Some(6) {
+        let msg = node.recv();
+        if msg["dest"] == SEQ_KV {
+            answer_seq_kv(&mut node, &msg, &mut stored, &mut cas_count);
+        } else if msg["body"]["type"] == "add_ok" {
+            add_oks.push(msg);
+        }
+    }
+    // Whatever the node still had to say about the add.
+    for msg in node.recv_for(Duration::from_millis(300)) {
+        if msg["dest"] == SEQ_KV {
+            answer_seq_kv(&mut node, &msg, &mut stored, &mut cas_count);
+        } else if msg["body"]["type"] == "add_ok" {
+            add_oks.push(msg);
+        }
+    }
+
+    assert_eq!(stored, Some(6));
+    assert_eq!(cas_count, 1);
+    assert_eq!(add_oks.len(), 1, "{:?}", add_oks);
+    assert_eq!(add_oks[0]["body"]["in_reply_to"], 2);
+}
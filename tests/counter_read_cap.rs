@@ -0,0 +1,47 @@
+//! Checks that once the counter deferred as many reads as it may, later ones are answered
+//! right away instead of queued.
+
+mod common;
+
+use std::time::Instant;
+
+use common::TestNode;
+use serde_json::{json, Value};
+
+/// `MAX_DEFERRED_READS` of the binary.
+const MAX_DEFERRED_READS: u64 = 1024;
+/// `READ_OK_WAIT_MS` of the binary, how long the deferred reads wait.
+const READ_OK_WAIT_MS: u128 = 400;
+const OVERFLOW: u64 = 5;
+
+#[test]
+fn reads_past_the_cap_are_answered_immediately() {
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_g_counter"));
+    node.init("n0", &["n0"]);
+    node.recv_type("init_ok");
+    let reads: Vec<Value> = (0..MAX_DEFERRED_READS + OVERFLOW)
+        .map(
+            |msg_id| json!({"src": "c1", "dest": "n0", "body": {"type": "read", "msg_id": msg_id}}),
+        )
+        .collect();
+    let started = Instant::now();
+    node.send_all(&reads);
+
+    let immediate: Vec<Value> = (0..OVERFLOW)
+        .map(|_| node.recv_type("read_ok")["body"]["in_reply_to"].clone())
+        .collect();
+    assert!(started.elapsed().as_millis() < READ_OK_WAIT_MS);
+    let overflow: Vec<Value> = (MAX_DEFERRED_READS..MAX_DEFERRED_READS + OVERFLOW)
+        .map(|msg_id| json!(msg_id))
+        .collect();
+    assert_eq!(immediate, overflow);
+
+    node.send(&json!({"src": "c1", "dest": "n0", "body": {
+        "type": "pending_summary", "msg_id": 5000,
+    }}));
+    let summary = node.recv_type("pending_summary_ok");
+    assert_eq!(
+        summary["body"]["pending"]["pending_reads_peak"],
+        MAX_DEFERRED_READS
+    );
+}
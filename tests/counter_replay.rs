@@ -0,0 +1,173 @@
+//! Replays captured message sequences through the `g_counter` binary and checks the values it
+//! answers reads with.
+//!
+//! Every fixture line is a step:
+//! - `{"send": <message>}` writes a message to the node. An `in_reply_to` of `"$last"` is
+//!   replaced with the `msg_id` of the last awaited message.
+//! - `{"await": {<field>: <value>, ...}}` waits for the next message to seq-kv whose body has
+//!   all these fields. A `"dest"` next to `"await"` waits for a message to that node instead.
+//! - `{"sleep_ms": <n>}` waits, e.g. for a pending CAS to time out.
+//! - `{"await_none": {<field>: <value>, ...}, "for_ms": <n>}` checks that no message to seq-kv
+//!   with all these fields is sent within `n` milliseconds.
+//! - `{"final_count": <n>}` is the count the last read must be answered with, once stdin is
+//!   closed and the node flushed its pending reads.
+
+mod common;
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::{TestNode, REPLY_TIMEOUT};
+use serde_json::{json, Value};
+
+#[test]
+fn add_then_read() {
+    replay(include_str!("fixtures/counter_replay/add_then_read.jsonl"));
+}
+
+#[test]
+fn cas_conflict_then_recas() {
+    replay(include_str!(
+        "fixtures/counter_replay/cas_conflict_then_recas.jsonl"
+    ));
+}
+
+#[test]
+fn interleaved_adds_and_reads() {
+    replay(include_str!(
+        "fixtures/counter_replay/interleaved_adds_and_reads.jsonl"
+    ));
+}
+
+#[test]
+fn membership_change_syncs_new_peer() {
+    replay(include_str!(
+        "fixtures/counter_replay/membership_change_syncs_new_peer.jsonl"
+    ));
+}
+
+#[test]
+fn read_breakdown_by_node() {
+    replay(include_str!(
+        "fixtures/counter_replay/read_breakdown_by_node.jsonl"
+    ));
+}
+
+#[test]
+fn kv_unavailable_then_retry() {
+    replay(include_str!(
+        "fixtures/counter_replay/kv_unavailable_then_retry.jsonl"
+    ));
+}
+
+#[test]
+fn cas_ok_after_timeout() {
+    replay(include_str!(
+        "fixtures/counter_replay/cas_ok_after_timeout.jsonl"
+    ));
+}
+
+#[test]
+fn create_collision_then_recas() {
+    replay(include_str!(
+        "fixtures/counter_replay/create_collision_then_recas.jsonl"
+    ));
+}
+
+fn replay(fixture: &str) {
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_g_counter"));
+    let mut outputs = vec![];
+    node.init("n0", &["n0", "n1", "n2"]);
+    await_message(&node, &mut outputs, &json!({"type": "init_ok"}), None);
+
+    let mut last_msg_id = Value::Null;
+    let mut final_count = None;
+    for line in fixture.lines().filter(|line| !line.trim().is_empty()) {
+        let step: Value = serde_json::from_str(line).expect("Invalid fixture step");
+        if let Some(msg) = step.get("send") {
+            let mut msg = msg.clone();
+            if msg["body"]["in_reply_to"] == "$last" {
+                msg["body"]["in_reply_to"] = last_msg_id.clone();
+            }
+            node.send(&msg);
+        } else if let Some(expected) = step.get("await") {
+            let dest = step["dest"].as_str().unwrap_or("seq-kv");
+            let msg = await_message(&node, &mut outputs, expected, Some(dest));
+            last_msg_id = msg["body"]["msg_id"].clone();
+        } else if let Some(unexpected) = step.get("await_none") {
+            let window = Duration::from_millis(step["for_ms"].as_u64().unwrap());
+            for msg in node.recv_for(window) {
+                assert!(
+                    msg["dest"] != "seq-kv" || !has_fields(&msg, unexpected),
+                    "Unexpected {}",
+                    msg
+                );
+                outputs.push(msg);
+            }
+        } else if let Some(ms) = step.get("sleep_ms") {
+            thread::sleep(Duration::from_millis(ms.as_u64().unwrap()));
+        } else if let Some(count) = step.get("final_count") {
+            final_count = count.as_u64();
+        } else {
+            panic!("Unknown fixture step: {}", line);
+        }
+    }
+
+    let (rest, status) = node.finish();
+    assert!(status.success());
+    outputs.extend(rest);
+
+    let reads: Vec<u64> = outputs
+        .iter()
+        .filter(|msg| msg["body"]["type"] == "read_ok")
+        .filter(|msg| {
+            msg["dest"]
+                .as_str()
+                .is_some_and(|dest| dest.starts_with('c'))
+        })
+        .map(|msg| {
+            msg["body"]["value"]
+                .as_u64()
+                .expect("read_ok without a value")
+        })
+        .collect();
+    assert!(
+        reads.windows(2).all(|pair| pair[0] <= pair[1]),
+        "Reads went backwards: {:?}",
+        reads
+    );
+    if let Some(final_count) = final_count {
+        assert_eq!(reads.last(), Some(&final_count), "Reads: {:?}", reads);
+    }
+}
+
+/// Wait for the next message to `dest` (any destination when `None`) whose body has every
+/// field of `expected`, keeping everything received along the way in `outputs`.
+fn await_message(
+    node: &TestNode,
+    outputs: &mut Vec<Value>,
+    expected: &Value,
+    dest: Option<&str>,
+) -> Value {
+    let deadline = Instant::now() + REPLY_TIMEOUT;
+    loop {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        let msg = node
+            .try_recv(timeout)
+            .unwrap_or_else(|| panic!("Timed out waiting for {}", expected));
+        outputs.push(msg.clone());
+        let to_dest = dest.is_none_or(|dest| msg["dest"] == dest);
+        if to_dest && has_fields(&msg, expected) {
+            return msg;
+        }
+    }
+}
+
+/// Whether the body of `msg` has every field of `expected`.
+fn has_fields(msg: &Value, expected: &Value) -> bool {
+    expected
+        .as_object()
+        .expect("Awaited fields must be an object")
+        .iter()
+        .all(|(field, value)| &msg["body"][field] == value)
+}
@@ -0,0 +1,98 @@
+//! Checks that deferred client reads are each answered once, to the msg_id of their own read,
+//! and that shutting down answers the ones still waiting.
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::TestNode;
+use serde_json::{json, Value};
+
+#[test]
+fn concurrent_reads_get_their_own_replies() {
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_performant_broadcast_final"));
+    node.init("n0", &["n0"]);
+    node.send_all(&[
+        json!({"src": "c0", "dest": "n0", "body": {
+            "type": "topology", "msg_id": 2, "topology": {"n0": []},
+        }}),
+        json!({"src": "c1", "dest": "n0", "body": {"type": "broadcast", "msg_id": 3, "message": 7}}),
+        json!({"src": "c1", "dest": "n0", "body": {"type": "read", "msg_id": 4}}),
+        json!({"src": "c1", "dest": "n0", "body": {"type": "read", "msg_id": 5}}),
+        // A retry of the first read, still waiting, must not get a second reply.
+        json!({"src": "c1", "dest": "n0", "body": {"type": "read", "msg_id": 4}}),
+    ]);
+    // Closing stdin answers the deferred reads right away.
+    let (emitted, status) = node.finish();
+    assert!(status.success());
+
+    let read_oks: Vec<&Value> = emitted
+        .iter()
+        .filter(|msg| msg["body"]["type"] == "read_ok")
+        .collect();
+    let replies: Vec<(&Value, &Value)> = read_oks
+        .iter()
+        .map(|msg| (&msg["dest"], &msg["body"]["in_reply_to"]))
+        .collect();
+    assert_eq!(
+        replies,
+        vec![(&json!("c1"), &json!(4)), (&json!("c1"), &json!(5))]
+    );
+    for read_ok in read_oks.iter() {
+        assert_eq!(read_ok["body"]["messages"], json!([7]));
+    }
+}
+
+/// `READ_WAIT_TIME` of `performant_broadcast_final`.
+const BROADCAST_READ_WAIT: Duration = Duration::from_millis(1850);
+
+#[test]
+fn shutdown_answers_reads_still_waiting() {
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_performant_broadcast_final"));
+    node.init("n0", &["n0"]);
+    node.send_all(&[
+        json!({"src": "c0", "dest": "n0", "body": {
+            "type": "topology", "msg_id": 2, "topology": {"n0": []},
+        }}),
+        json!({"src": "c1", "dest": "n0", "body": {"type": "broadcast", "msg_id": 3, "message": 7}}),
+        json!({"src": "c1", "dest": "n0", "body": {"type": "read", "msg_id": 4}}),
+    ]);
+    node.recv_type("broadcast_ok");
+    let started = Instant::now();
+    let (emitted, status) = node.finish();
+
+    assert!(status.success(), "{}", node.stderr());
+    assert!(started.elapsed() < BROADCAST_READ_WAIT);
+    let read_ok = emitted
+        .iter()
+        .find(|msg| msg["body"]["type"] == "read_ok")
+        .unwrap_or_else(|| panic!("Deferred read dropped: {:?}", emitted));
+    assert_eq!(read_ok["body"]["in_reply_to"], 4);
+    assert_eq!(read_ok["body"]["messages"], json!([7]));
+}
+
+/// The counter answers its deferred reads during the shutdown grace period if their timer is
+/// done by then, so the wait is stretched past it through `set_param`.
+#[cfg(feature = "control")]
+#[test]
+fn counter_shutdown_answers_reads_still_waiting() {
+    let read_wait_ms = 60_000;
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_g_counter"));
+    node.init("n0", &["n0"]);
+    node.send(&json!({"src": "c0", "dest": "n0", "body": {
+        "type": "set_param", "msg_id": 2, "name": "read_ok_wait_ms", "value": read_wait_ms,
+    }}));
+    node.recv_type("set_param_ok");
+    node.send(&json!({"src": "c1", "dest": "n0", "body": {"type": "read", "msg_id": 3}}));
+    let started = Instant::now();
+    let (emitted, _) = node.finish();
+
+    assert!(started.elapsed() < Duration::from_millis(read_wait_ms));
+    let read_oks: Vec<&Value> = emitted
+        .iter()
+        .filter(|msg| msg["body"]["type"] == "read_ok")
+        .collect();
+    assert_eq!(read_oks.len(), 1, "{:?}", emitted);
+    assert_eq!(read_oks[0]["dest"], "c1");
+    assert_eq!(read_oks[0]["body"]["in_reply_to"], 3);
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use std::collections::HashSet;
+
+use common::TestNode;
+use distributed_systems::broadcast::{deliver, DeliveryLog, ValueSet};
+use serde_json::json;
+
+#[test]
+fn retransmitted_value_is_applied_once() {
+    let mut values: HashSet<u64> = HashSet::new();
+    let mut log = DeliveryLog::new();
+    let mut applied = vec![];
+    for _ in 0..5 {
+        deliver(&mut values, &mut log, 42, |value| applied.push(value));
+    }
+    deliver(&mut values, &mut log, 7, |value| applied.push(value));
+
+    assert_eq!(applied, vec![42, 7]);
+    assert!(log.applied_once(42));
+    assert!(log.applied_once(7));
+    assert!(!log.applied_once(8));
+    assert!(log.duplicates().is_empty());
+    assert_eq!(log.deliveries(42), 5);
+    assert_eq!(log.deliveries(7), 1);
+    assert_eq!(log.applied(), 2);
+    assert!(log.first_delivery(42).unwrap() <= log.first_delivery(7).unwrap());
+    assert_eq!(values.values(), vec![7, 42]);
+}
+
+#[test]
+fn applying_a_value_twice_is_reported() {
+    let mut log = DeliveryLog::new();
+    assert!(log.record_delivery(3));
+    assert!(log.record_application(3));
+    assert!(log.applied_once(3));
+    assert!(!log.record_application(3));
+    assert!(!log.applied_once(3));
+    assert_eq!(log.duplicates(), vec![3]);
+}
+
+#[test]
+fn broadcast_node_applies_retransmissions_once() {
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_performant_broadcast_final"));
+    node.init("n0", &["n0", "n1"]);
+    let mut inputs = vec![json!({"src": "c0", "dest": "n0", "body": {
+        "type": "topology", "msg_id": 2, "topology": {"n0": ["n1"], "n1": ["n0"]},
+    }})];
+    // The same value, retried by the client and relayed back by the peer, alone and batched.
+    for msg_id in 3..6 {
+        inputs.push(json!({"src": "c1", "dest": "n0", "body": {
+            "type": "broadcast", "msg_id": msg_id, "message": 42,
+        }}));
+    }
+    inputs.push(json!({"src": "n1", "dest": "n0", "body": {"type": "broadcast", "message": 42}}));
+    inputs.push(json!({"src": "n1", "dest": "n0", "body": {
+        "type": "broadcast_batch", "messages": [42, 43],
+    }}));
+    inputs
+        .push(json!({"src": "c0", "dest": "n0", "body": {"type": "delivery_check", "msg_id": 9}}));
+    node.send_all(&inputs);
+
+    let check = node.recv_type("delivery_check_ok");
+    assert_eq!(check["body"]["in_reply_to"], 9);
+    assert_eq!(check["body"]["applied"], 2);
+    assert_eq!(check["body"]["duplicates"], json!([]));
+}
@@ -0,0 +1,80 @@
+use std::error::Error;
+
+use distributed_systems::dispatch;
+use distributed_systems::maelstrom::NodeMessage;
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+enum SpyBody {
+    #[serde(rename = "add")]
+    Add(Delta),
+    #[serde(rename = "read")]
+    Read(Empty),
+    #[serde(rename = "cas_ok")]
+    CasOk(Empty),
+}
+
+#[derive(Debug, Deserialize)]
+struct Delta {
+    delta: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Empty {}
+
+/// Records which method each message reached, and with what.
+#[derive(Default)]
+struct Spy {
+    calls: Vec<String>,
+}
+
+impl Spy {
+    fn handle(&mut self, msg: NodeMessage<SpyBody>) -> Result<(), Box<dyn Error>> {
+        dispatch!(msg, self, SpyBody {
+            Add(src, dest) => handle_add,
+            Read(src) => handle_read,
+            CasOk => handle_cas_ok,
+        })
+    }
+
+    fn handle_add(&mut self, src: String, dest: String, body: Delta) -> Result<(), Box<dyn Error>> {
+        self.calls
+            .push(format!("add({}) from {} to {}", body.delta, src, dest));
+        Ok(())
+    }
+
+    fn handle_read(&mut self, src: String, _body: Empty) -> Result<(), Box<dyn Error>> {
+        self.calls.push(format!("read from {}", src));
+        Ok(())
+    }
+
+    fn handle_cas_ok(&mut self, _body: Empty) -> Result<(), Box<dyn Error>> {
+        self.calls.push("cas_ok".to_string());
+        Ok(())
+    }
+}
+
+fn message(src: &str, body: serde_json::Value) -> NodeMessage<SpyBody> {
+    NodeMessage::new(
+        src.to_string(),
+        "n0".to_string(),
+        serde_json::from_value(body).unwrap(),
+    )
+}
+
+#[test]
+fn each_variant_reaches_its_method() {
+    let mut spy = Spy::default();
+    spy.handle(message("c1", json!({"type": "read"}))).unwrap();
+    spy.handle(message("seq-kv", json!({"type": "cas_ok"})))
+        .unwrap();
+    spy.handle(message("c2", json!({"type": "add", "delta": 3})))
+        .unwrap();
+
+    assert_eq!(
+        spy.calls,
+        vec!["read from c1", "cas_ok", "add(3) from c2 to n0"]
+    );
+}
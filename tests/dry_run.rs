@@ -0,0 +1,63 @@
+mod common;
+
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use common::TestNode;
+use distributed_systems::logging::recent_logs;
+use distributed_systems::maelstrom::transport::{DryRunTransport, Transport, DRY_RUN_ENV};
+
+/// Transport keeping the lines written to it.
+struct Sink {
+    written: Arc<Mutex<Vec<String>>>,
+}
+
+impl Transport for Sink {
+    fn read_line(&self, _buffer: &mut String) -> io::Result<usize> {
+        Ok(0)
+    }
+
+    fn write_line(&self, line: &str, _flush: bool) -> io::Result<()> {
+        self.written.lock().unwrap().push(line.to_string());
+        Ok(())
+    }
+}
+
+#[test]
+fn dry_run_logs_instead_of_writing() {
+    let written = Arc::new(Mutex::new(vec![]));
+    let transport = DryRunTransport::new(Box::new(Sink {
+        written: written.clone(),
+    }));
+
+    let line = r#"{"src":"n0","dest":"c1","body":{"type":"echo_ok","in_reply_to":2,"echo":"hi"}}"#;
+    transport.write_line(line, true).unwrap();
+
+    assert!(written.lock().unwrap().is_empty());
+    assert!(recent_logs()
+        .iter()
+        .any(|log| log
+            .ends_with("[n0] Dry run, not sending n0->c1 type=echo_ok in_reply_to=2 echo=hi")));
+}
+
+#[test]
+fn dry_run_node_writes_nothing_to_stdout() {
+    let mut node = TestNode::start_with_env(env!("CARGO_BIN_EXE_echo"), &[(DRY_RUN_ENV, "1")]);
+    node.init("n0", &["n0"]);
+    node.write_line(r#"{"src":"c1","dest":"n0","body":{"type":"echo","msg_id":2,"echo":"hi"}}"#);
+    let (emitted, status) = node.finish();
+
+    assert!(status.success());
+    assert!(emitted.is_empty(), "{:?}", emitted);
+    let stderr = node.stderr();
+    assert!(
+        stderr.contains("Dry run, not sending n0->c0 type=init_ok in_reply_to=1"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("Dry run, not sending n0->c1 type=echo_ok"),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,34 @@
+//! Checks that the `echo` binary sends back any JSON payload untouched.
+
+mod common;
+
+use common::TestNode;
+
+fn echo_back(payload: &str) -> String {
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_echo"));
+    node.init("n0", &["n0"]);
+    node.write_line(&format!(
+        r#"{{"src": "c1", "dest": "n0", "body": {{"type": "echo", "msg_id": 2, "echo": {}}}}}"#,
+        payload
+    ));
+    let echo_ok = node.recv_type("echo_ok");
+    assert_eq!(echo_ok["body"]["in_reply_to"], 2);
+    echo_ok["body"]["echo"].to_string()
+}
+
+#[test]
+fn nested_object_round_trips() {
+    let payload = r#"{"z":1,"a":{"list":[1,"two",null,{"deep":true}]},"m":-0.5}"#;
+    assert_eq!(echo_back(payload), payload);
+}
+
+#[test]
+fn array_round_trips() {
+    let payload = r#"[[],[1,[2,[3]]],{"k":"v"},"x",false]"#;
+    assert_eq!(echo_back(payload), payload);
+}
+
+#[test]
+fn string_still_works() {
+    assert_eq!(echo_back(r#""Please echo 35""#), r#""Please echo 35""#);
+}
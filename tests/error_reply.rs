@@ -0,0 +1,58 @@
+//! Checks the Maelstrom `error` replies, see `ErrorBody` and `reply_error`.
+
+mod common;
+
+use common::TestNode;
+use distributed_systems::prelude::*;
+use serde_json::json;
+
+#[test]
+fn text_is_omitted_when_none() {
+    let body = ErrorBody::new(NodeError::KeyDoesNotExist, 4, None);
+    assert_eq!(
+        serde_json::to_value(&body).unwrap(),
+        json!({"type": "error", "in_reply_to": 4, "code": 20})
+    );
+
+    let body = ErrorBody::new(NodeError::TxnConflict, 5, Some("conflict".to_string()));
+    assert_eq!(
+        serde_json::to_value(&body).unwrap(),
+        json!({"type": "error", "in_reply_to": 5, "code": 23, "text": "conflict"})
+    );
+}
+
+#[test]
+fn malformed_request_is_answered_with_an_error() {
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_echo"));
+    node.init("n0", &["n0"]);
+    node.send_all(&[
+        json!({"src": "c1", "dest": "n0", "body": {"type": "no_such_type", "msg_id": 2}}),
+        json!({"src": "c1", "dest": "n0", "body": {"type": "echo", "msg_id": 3, "echo": "hi"}}),
+    ]);
+    let (replies, status) = node.finish();
+    assert!(status.success());
+
+    assert_eq!(replies.len(), 3);
+    assert_eq!(replies[1]["dest"], "c1");
+    assert_eq!(replies[1]["body"]["type"], "error");
+    assert_eq!(replies[1]["body"]["in_reply_to"], 2);
+    assert_eq!(
+        replies[1]["body"]["code"],
+        NodeError::MalformedRequest.code()
+    );
+    assert_eq!(replies[2]["body"]["type"], "echo_ok");
+}
+
+#[test]
+fn failing_handler_is_answered_with_a_crash() {
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_broadcast"));
+    node.init("n0", &["n0", "n1"]);
+    // The handler gives up on a broadcast_ok without the value it acknowledges.
+    node.send(&json!({"src": "n1", "dest": "n0", "body": {"type": "broadcast_ok", "msg_id": 9}}));
+
+    let error = node.recv_type("error");
+    assert_eq!(error["dest"], "n1");
+    assert_eq!(error["body"]["in_reply_to"], 9);
+    assert_eq!(error["body"]["code"], 13);
+    assert_eq!(error["body"]["text"], "broadcast_ok without an acked_value");
+}
@@ -0,0 +1,139 @@
+//! Checks the exit code `run_node_event_loop` leads to, through the `echo` binary and its
+//! stdio transport, and the `on_shutdown` hook it calls, through the `g_counter` binary. The
+//! broadcast binaries running their own loops on `spawn_request_reader` exit the same way.
+
+mod common;
+
+use common::TestNode;
+use distributed_systems::maelstrom::watchdog::WATCHDOG_MS_ENV;
+use distributed_systems::maelstrom::{INIT_TIMEOUT_MS_ENV, SINGLE_THREADED_ENV};
+
+#[test]
+fn clean_eof_exits_successfully() {
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_echo"));
+    node.init("n0", &["n0"]);
+    node.write_line(r#"{"src":"c1","dest":"n0","body":{"type":"echo","msg_id":2,"echo":"hi"}}"#);
+    let (emitted, status) = node.finish();
+
+    assert!(status.success(), "{}", node.stderr());
+    assert!(
+        emitted.iter().any(|msg| msg["body"]["type"] == "echo_ok"),
+        "{:?}",
+        emitted
+    );
+}
+
+#[test]
+fn failed_init_exits_with_an_error() {
+    // The node is not part of its own cluster, init is rejected.
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_echo"));
+    node.init("n0", &["n1"]);
+    let (emitted, status) = node.finish();
+
+    assert!(!status.success());
+    let stderr = node.stderr();
+    assert!(stderr.contains("Init("), "{}", stderr);
+    assert!(emitted.is_empty(), "{:?}", emitted);
+}
+
+#[test]
+fn missing_init_times_out() {
+    let mut node =
+        TestNode::start_with_env(env!("CARGO_BIN_EXE_echo"), &[(INIT_TIMEOUT_MS_ENV, "100")]);
+    // Stdin stays open but nothing is ever sent.
+    let status = node.wait();
+
+    assert!(!status.success());
+    let stderr = node.stderr();
+    assert!(
+        stderr.contains("No init message received within 100ms"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn invalid_env_values_fail_the_node() {
+    for env in [INIT_TIMEOUT_MS_ENV, WATCHDOG_MS_ENV] {
+        let mut node = TestNode::start_with_env(env!("CARGO_BIN_EXE_echo"), &[(env, "soon")]);
+        node.init("n0", &["n0"]);
+        let status = node.wait();
+
+        assert!(!status.success(), "{}", env);
+        let stderr = node.stderr();
+        assert!(
+            stderr.contains(&format!("Invalid {}=soon", env)),
+            "{}",
+            stderr
+        );
+    }
+}
+
+#[test]
+fn read_error_stops_the_reader() {
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_echo"));
+    node.init("n0", &["n0"]);
+    // Not valid UTF-8, stdin fails to read the line. Stdin stays open: the node only exits if
+    // the reader stops instead of reading again.
+    node.write_bytes(b"\xff\xfe\n");
+    let status = node.wait();
+
+    assert!(status.success(), "{}", node.stderr());
+    assert!(node.stderr().contains("Could not read stdin, stopping"));
+}
+
+#[test]
+fn invalid_line_is_skipped() {
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_echo"));
+    node.init("n0", &["n0"]);
+    node.write_line("not json");
+    node.write_line(r#"{"src":"c1","dest":"n0","body":{"type":"echo","msg_id":2,"echo":"hi"}}"#);
+
+    assert_eq!(node.recv_type("echo_ok")["body"]["in_reply_to"], 2);
+    let (_, status) = node.finish();
+    assert!(status.success(), "{}", node.stderr());
+    assert!(node.stderr().contains("Could not read request"));
+}
+
+#[test]
+fn broadcast_loops_skip_invalid_lines_and_exit_on_eof() {
+    for program in [
+        env!("CARGO_BIN_EXE_broadcast"),
+        env!("CARGO_BIN_EXE_performant_broadcast"),
+        env!("CARGO_BIN_EXE_fault_tolerant_broadcast"),
+    ] {
+        let mut node = TestNode::start(program);
+        node.init("n0", &["n0"]);
+        node.write_line("not json");
+        node.write_line(r#"{"src":"c1","dest":"n0","body":{"type":"read","msg_id":2}}"#);
+
+        assert_eq!(node.recv_type("read_ok")["body"]["in_reply_to"], 2);
+        let (_, status) = node.finish();
+        assert!(status.success(), "{}: {}", program, node.stderr());
+        assert!(
+            node.stderr().contains("Could not read request"),
+            "{}",
+            program
+        );
+    }
+}
+
+#[test]
+fn on_shutdown_runs_once_after_stdin_closes() {
+    for env in [vec![], vec![(SINGLE_THREADED_ENV, "1")]] {
+        let mut node = TestNode::start_with_env(env!("CARGO_BIN_EXE_g_counter"), &env);
+        node.init("n0", &["n0"]);
+        node.recv_type("init_ok");
+        assert!(!node.stderr().contains("Shutting down"));
+        let (_, status) = node.finish();
+
+        assert!(status.success());
+        let stderr = node.stderr();
+        assert_eq!(
+            stderr.matches("Shutting down, final count: 0").count(),
+            1,
+            "{}",
+            stderr
+        );
+    }
+}
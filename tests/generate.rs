@@ -0,0 +1,28 @@
+//! Checks the replies of the `generate` binary.
+
+mod common;
+
+use std::collections::HashSet;
+
+use common::TestNode;
+use serde_json::json;
+
+#[test]
+fn every_generate_ok_has_its_own_msg_id() {
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_generate"));
+    node.init("n0", &["n0"]);
+    for msg_id in 2..7 {
+        node.send(
+            &json!({"src": "c1", "dest": "n0", "body": {"type": "generate", "msg_id": msg_id}}),
+        );
+    }
+
+    let mut msg_ids = HashSet::new();
+    let mut ids = HashSet::new();
+    for in_reply_to in 2..7 {
+        let generate_ok = node.recv_type("generate_ok");
+        assert_eq!(generate_ok["body"]["in_reply_to"], in_reply_to);
+        assert!(msg_ids.insert(generate_ok["body"]["msg_id"].as_u64().unwrap()));
+        assert!(ids.insert(generate_ok["body"]["id"].as_u64().unwrap()));
+    }
+}
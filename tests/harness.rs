@@ -0,0 +1,119 @@
+//! Checks the in-process `Harness` that runs several nodes without Maelstrom.
+
+use std::cell::RefCell;
+use std::error::Error;
+use std::rc::Rc;
+
+use distributed_systems::maelstrom::harness::Harness;
+use distributed_systems::maelstrom::{MaelstromNode, NodeMessage, OutgoingMessage};
+use serde_json::{json, Value};
+
+/// Every node of a test logs what it is called with to the same list, in call order.
+type Events = Rc<RefCell<Vec<String>>>;
+
+struct Probe {
+    node_id: String,
+    events: Events,
+}
+
+impl MaelstromNode for Probe {
+    type MessageBody = Value;
+
+    fn initialize(&mut self, node_id: String, node_ids: Vec<String>) {
+        self.events
+            .borrow_mut()
+            .push(format!("init {} of {:?}", node_id, node_ids));
+        self.node_id = node_id;
+    }
+
+    fn respond(&mut self, msg: NodeMessage<Value>) -> Result<Vec<OutgoingMessage>, Box<dyn Error>> {
+        self.events
+            .borrow_mut()
+            .push(format!("{} handles {}", self.node_id, msg.body["type"]));
+        Ok(vec![])
+    }
+
+    fn on_shutdown(&mut self) {
+        self.events
+            .borrow_mut()
+            .push(format!("{} shuts down", self.node_id));
+    }
+}
+
+fn probes(events: &Events) -> Harness<Probe> {
+    let nodes = ["n0", "n1", "n2"]
+        .iter()
+        .map(|node_id| {
+            let probe = Probe {
+                node_id: String::new(),
+                events: events.clone(),
+            };
+            (node_id.to_string(), probe)
+        })
+        .collect();
+    Harness::new(nodes)
+}
+
+fn message(dest: &str) -> NodeMessage<Value> {
+    NodeMessage::new(
+        "c1".to_string(),
+        dest.to_string(),
+        json!({"type": "broadcast"}),
+    )
+}
+
+#[test]
+fn every_node_is_initialized_before_any_delivery() {
+    let events = Events::default();
+    let mut harness = probes(&events);
+    assert!(harness.deliver(message("n1")).is_err());
+    assert!(events.borrow().is_empty());
+
+    let init_oks = harness.init_all();
+    assert_eq!(init_oks.len(), 3);
+    assert!(init_oks
+        .iter()
+        .all(|init_ok| init_ok.body._type == "init_ok"));
+    harness.deliver(message("n1")).unwrap();
+    harness.deliver(message("n0")).unwrap();
+
+    assert_eq!(
+        *events.borrow(),
+        [
+            r#"init n0 of ["n0", "n1", "n2"]"#,
+            r#"init n1 of ["n0", "n1", "n2"]"#,
+            r#"init n2 of ["n0", "n1", "n2"]"#,
+            r#"n1 handles "broadcast""#,
+            r#"n0 handles "broadcast""#,
+        ]
+    );
+}
+
+#[test]
+fn init_all_initializes_once() {
+    let events = Events::default();
+    let mut harness = probes(&events);
+    harness.init_all();
+    harness.init_all();
+    assert_eq!(events.borrow().len(), 3);
+}
+
+#[test]
+fn every_node_is_shut_down_once() {
+    let events = Events::default();
+    let mut harness = probes(&events);
+    harness.init_all();
+    harness.deliver(message("n2")).unwrap();
+    harness.shut_down_all().unwrap();
+    harness.shut_down_all().unwrap();
+
+    assert_eq!(
+        events.borrow()[3..],
+        [
+            r#"n2 handles "broadcast""#,
+            "n0 shuts down",
+            "n1 shuts down",
+            "n2 shuts down",
+        ]
+    );
+}
@@ -0,0 +1,26 @@
+//! Checks the bucketed latency histogram the counter records its operations in.
+
+use std::time::Duration;
+
+use distributed_systems::maelstrom::histogram::Histogram;
+
+#[test]
+fn every_recorded_latency_is_counted_once() {
+    let mut histogram = Histogram::new(&[1, 10, 100]);
+    for millis in [0, 1, 2, 10, 50, 100, 101, 5000] {
+        histogram.record(Duration::from_millis(millis));
+    }
+
+    assert_eq!(histogram.count(), 8);
+    assert_eq!(
+        histogram.to_string(),
+        "<=1ms: 2, <=10ms: 2, <=100ms: 2, >100ms: 2"
+    );
+}
+
+#[test]
+fn empty_histogram_counts_nothing() {
+    let histogram = Histogram::default();
+    assert_eq!(histogram.count(), 0);
+    assert!(histogram.to_string().ends_with(">1000ms: 0"));
+}
@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+
+use distributed_systems::maelstrom::harness::Harness;
+use distributed_systems::maelstrom::InitRequest;
+use distributed_systems::prelude::*;
+use serde_json::{json, Value};
+
+/// Remembers what it was initialized with.
+#[derive(Default)]
+struct PeerNode {
+    node_id: String,
+    peers: Vec<String>,
+    replication_factor: Option<u64>,
+}
+
+impl MaelstromNode for PeerNode {
+    type MessageBody = serde_json::Value;
+
+    fn initialize(&mut self, node_id: String, node_ids: Vec<String>) {
+        self.peers = peers_of(&node_id, &node_ids);
+        self.node_id = node_id;
+    }
+
+    fn configure(&mut self, config: &HashMap<String, Value>) {
+        self.replication_factor = config.get("replication_factor").and_then(Value::as_u64);
+    }
+}
+
+#[test]
+fn every_node_learns_the_whole_cluster() {
+    let node_ids: Vec<String> = (0..5).map(|i| format!("n{}", i)).collect();
+    let nodes = node_ids
+        .iter()
+        .map(|node_id| (node_id.clone(), PeerNode::default()))
+        .collect();
+    let mut harness = Harness::new(nodes);
+    harness.init_all();
+
+    for node_id in node_ids.iter() {
+        let node = harness.node(node_id).unwrap();
+        assert_eq!(&node.node_id, node_id);
+        let expected: Vec<&String> = node_ids.iter().filter(|id| *id != node_id).collect();
+        assert_eq!(node.peers.iter().collect::<Vec<_>>(), expected);
+    }
+}
+
+#[test]
+fn peers_exclude_only_ourselves() {
+    let node_ids = vec!["n2".to_string(), "n0".to_string(), "n1".to_string()];
+    assert_eq!(peers_of("n0", &node_ids), vec!["n2", "n1"]);
+    assert_eq!(peers_of("n7", &node_ids), node_ids);
+}
+
+#[test]
+fn extra_init_fields_are_kept_as_config() {
+    let init: InitRequest = serde_json::from_value(json!({
+        "type": "init",
+        "msg_id": 1,
+        "node_id": "n0",
+        "node_ids": ["n0", "n1"],
+        "replication_factor": 3,
+        "timeout_ms": 500,
+    }))
+    .unwrap();
+
+    assert_eq!(init.node_id, "n0");
+    assert_eq!(init.node_ids, ["n0", "n1"]);
+    assert_eq!(init.config.len(), 2);
+    assert_eq!(init.config["replication_factor"], 3);
+    assert_eq!(init.config["timeout_ms"], 500);
+}
+
+#[test]
+fn config_reaches_every_node() {
+    let nodes = ["n0", "n1"]
+        .iter()
+        .map(|node_id| (node_id.to_string(), PeerNode::default()))
+        .collect();
+    let config = HashMap::from([("replication_factor".to_string(), json!(3))]);
+    let mut harness = Harness::new(nodes).with_config(config);
+    harness.init_all();
+
+    for node_id in ["n0", "n1"] {
+        assert_eq!(harness.node(node_id).unwrap().replication_factor, Some(3));
+    }
+}
+
+#[test]
+fn config_is_empty_without_extra_fields() {
+    let mut harness = Harness::new(vec![("n0".to_string(), PeerNode::default())]);
+    harness.init_all();
+    assert_eq!(harness.node("n0").unwrap().replication_factor, None);
+}
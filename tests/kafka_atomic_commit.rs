@@ -0,0 +1,153 @@
+//! Checks the atomic commit_offsets of the `kafka` binary: a batch with a key that would move
+//! backwards is rejected as a whole. `multi-node-kafka` commits through seq-kv, played here by
+//! the test, and also puts back the keys it moved when a later one conflicts.
+
+mod common;
+
+use std::collections::HashMap;
+
+use common::TestNode;
+use distributed_systems::maelstrom::seq_kv::SEQ_KV;
+use serde_json::{json, Value};
+
+#[test]
+fn regressing_key_rejects_the_whole_batch() {
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_kafka"));
+    node.init("n0", &["n0"]);
+    node.send_all(&[
+        json!({"src": "c1", "dest": "n0", "body": {
+            "type": "commit_offsets", "msg_id": 2, "offsets": {"a": 5, "b": 3},
+        }}),
+        // `a` moves forward but `b` would go back, neither is applied.
+        json!({"src": "c1", "dest": "n0", "body": {
+            "type": "commit_offsets", "msg_id": 3, "offsets": {"a": 7, "b": 1}, "atomic": true,
+        }}),
+        json!({"src": "c1", "dest": "n0", "body": {
+            "type": "list_committed_offsets", "msg_id": 4, "keys": ["a", "b"],
+        }}),
+    ]);
+
+    let emitted = node.recv_n(4);
+    assert_eq!(emitted[1]["body"]["type"], "commit_offsets_ok");
+    assert_eq!(emitted[2]["body"]["type"], "error");
+    assert_eq!(emitted[2]["body"]["code"], 23);
+    assert_eq!(emitted[2]["body"]["in_reply_to"], 3);
+    assert_eq!(emitted[3]["body"]["offsets"], json!({"a": 5, "b": 3}));
+}
+
+/// Answer a seq-kv `read` or `cas` the way Maelstrom's seq-kv does, null values included.
+fn seq_kv_reply(values: &mut HashMap<String, Value>, request: &Value) -> Value {
+    let body = &request["body"];
+    let key = body["key"].as_str().unwrap().to_string();
+    let reply = match (body["type"].as_str().unwrap(), values.get(&key)) {
+        ("read", Some(value)) => json!({"type": "read_ok", "value": value}),
+        ("read", None) => json!({"type": "error", "code": 20}),
+        ("cas", None) if body["create_if_not_exists"] == true => {
+            values.insert(key, body["to"].clone());
+            json!({"type": "cas_ok"})
+        }
+        ("cas", None) => json!({"type": "error", "code": 20}),
+        ("cas", Some(value)) if body["from"] == *value => {
+            values.insert(key, body["to"].clone());
+            json!({"type": "cas_ok"})
+        }
+        ("cas", Some(_)) => json!({"type": "error", "code": 22}),
+        (other, _) => panic!("Unexpected seq-kv request {}", other),
+    };
+    let mut reply = json!({"src": SEQ_KV, "dest": request["src"], "body": reply});
+    reply["body"]["in_reply_to"] = body["msg_id"].clone();
+    reply
+}
+
+/// Serve the seq-kv requests of `node` until it answers the client, calling `before_cas` on
+/// the values ahead of every CAS.
+fn serve_until_reply(
+    node: &mut TestNode,
+    values: &mut HashMap<String, Value>,
+    mut before_cas: impl FnMut(&mut HashMap<String, Value>),
+) -> Value {
+    loop {
+        let msg = node.recv();
+        if msg["dest"] != SEQ_KV {
+            return msg;
+        }
+        if msg["body"]["type"] == "cas" {
+            before_cas(values);
+        }
+        node.send(&seq_kv_reply(values, &msg));
+    }
+}
+
+fn start_multi_node(values: &mut HashMap<String, Value>) -> TestNode {
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_multi-node-kafka"));
+    node.init("n0", &["n0"]);
+    node.recv_type("init_ok");
+    node.send(&json!({"src": "c1", "dest": "n0", "body": {
+        "type": "commit_offsets", "msg_id": 2, "offsets": {"a": 5, "z": 3},
+    }}));
+    let commit_ok = serve_until_reply(&mut node, values, |_| {});
+    assert_eq!(commit_ok["body"]["type"], "commit_offsets_ok");
+    node
+}
+
+#[test]
+fn seq_kv_regressing_key_rejects_the_whole_batch() {
+    let mut values = HashMap::new();
+    let mut node = start_multi_node(&mut values);
+    node.send(&json!({"src": "c1", "dest": "n0", "body": {
+        "type": "commit_offsets", "msg_id": 3, "offsets": {"a": 7, "z": 1}, "atomic": true,
+    }}));
+
+    let reply = serve_until_reply(&mut node, &mut values, |_| panic!("No key may be moved"));
+    assert_eq!(reply["body"]["type"], "error");
+    assert_eq!(reply["body"]["code"], 23);
+    assert_eq!(reply["body"]["in_reply_to"], 3);
+    assert_eq!(values["offset/default/a"], 5);
+    assert_eq!(values["offset/default/z"], 3);
+}
+
+#[test]
+fn seq_kv_key_moved_while_committing_rolls_back_the_batch() {
+    let mut values = HashMap::new();
+    let mut node = start_multi_node(&mut values);
+    node.send(&json!({"src": "c1", "dest": "n0", "body": {
+        "type": "commit_offsets", "msg_id": 3, "offsets": {"a": 7, "b": 2, "z": 4},
+        "atomic": true,
+    }}));
+
+    // Another node commits `z` past the batch once every key was read, after `a` and `b`
+    // were already moved.
+    let mut cas_count = 0;
+    let reply = serve_until_reply(&mut node, &mut values, |values| {
+        cas_count += 1;
+        if cas_count == 3 {
+            values.insert("offset/default/z".to_string(), json!(6));
+        }
+    });
+    assert_eq!(reply["body"]["type"], "error");
+    assert_eq!(reply["body"]["code"], 23);
+    assert_eq!(values["offset/default/a"], 5);
+    assert_eq!(values["offset/default/b"], Value::Null);
+    assert_eq!(values["offset/default/z"], 6);
+
+    // The key created then rolled back reads as never committed.
+    node.send(&json!({"src": "c1", "dest": "n0", "body": {
+        "type": "list_committed_offsets", "msg_id": 4, "keys": ["a", "b", "z"],
+    }}));
+    let list_ok = serve_until_reply(&mut node, &mut values, |_| {});
+    assert_eq!(list_ok["body"]["offsets"], json!({"a": 5, "z": 6}));
+}
+
+#[test]
+fn seq_kv_atomic_commit_moves_every_key() {
+    let mut values = HashMap::new();
+    let mut node = start_multi_node(&mut values);
+    node.send(&json!({"src": "c1", "dest": "n0", "body": {
+        "type": "commit_offsets", "msg_id": 3, "offsets": {"a": 7, "b": 2}, "atomic": true,
+    }}));
+
+    let reply = serve_until_reply(&mut node, &mut values, |_| {});
+    assert_eq!(reply["body"]["type"], "commit_offsets_ok");
+    assert_eq!(values["offset/default/a"], 7);
+    assert_eq!(values["offset/default/b"], 2);
+}
@@ -0,0 +1,49 @@
+//! Checks the compaction of the `kafka` binary, enabled through `COMPACT_KEEP_ENTRIES_ENV`.
+
+mod common;
+
+use common::TestNode;
+use distributed_systems::kafka::COMPACT_KEEP_ENTRIES_ENV;
+use serde_json::json;
+
+#[test]
+fn polls_below_the_kept_entries_are_trimmed() {
+    let mut node = TestNode::start_with_env(
+        env!("CARGO_BIN_EXE_kafka"),
+        &[(COMPACT_KEEP_ENTRIES_ENV, "3")],
+    );
+    node.init("n0", &["n0"]);
+    for value in 0..5 {
+        node.send(&json!({"src": "c1", "dest": "n0", "body": {
+            "type": "send", "msg_id": 10 + value, "key": "k", "msg": value * 100,
+        }}));
+    }
+    node.send(&json!({"src": "c1", "dest": "n0", "body": {
+        "type": "poll", "msg_id": 2, "offsets": {"k": 0},
+    }}));
+
+    let poll_ok = node.recv_type("poll_ok");
+    assert_eq!(poll_ok["body"]["trimmed"], json!({"k": 2}));
+    assert_eq!(
+        poll_ok["body"]["msgs"],
+        json!({"k": [[2, 200], [3, 300], [4, 400]]})
+    );
+}
+
+#[test]
+fn everything_is_kept_by_default() {
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_kafka"));
+    node.init("n0", &["n0"]);
+    for value in 0..5 {
+        node.send(&json!({"src": "c1", "dest": "n0", "body": {
+            "type": "send", "msg_id": 10 + value, "key": "k", "msg": value * 100,
+        }}));
+    }
+    node.send(&json!({"src": "c1", "dest": "n0", "body": {
+        "type": "poll", "msg_id": 2, "offsets": {"k": 0},
+    }}));
+
+    let poll_ok = node.recv_type("poll_ok");
+    assert!(poll_ok["body"].get("trimmed").is_none(), "{}", poll_ok);
+    assert_eq!(poll_ok["body"]["msgs"]["k"].as_array().unwrap().len(), 5);
+}
@@ -0,0 +1,35 @@
+//! Checks that the `kafka` binary tracks committed offsets per consumer group.
+
+mod common;
+
+use common::TestNode;
+use serde_json::json;
+
+#[test]
+fn groups_commit_the_same_key_independently() {
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_kafka"));
+    node.init("n0", &["n0"]);
+    node.send_all(&[
+        json!({"src": "c1", "dest": "n0", "body": {
+            "type": "commit_offsets", "msg_id": 2, "offsets": {"k": 5}, "group": "billing",
+        }}),
+        json!({"src": "c2", "dest": "n0", "body": {
+            "type": "commit_offsets", "msg_id": 3, "offsets": {"k": 2}, "group": "audit",
+        }}),
+        json!({"src": "c1", "dest": "n0", "body": {
+            "type": "list_committed_offsets", "msg_id": 4, "keys": ["k"], "group": "billing",
+        }}),
+        json!({"src": "c2", "dest": "n0", "body": {
+            "type": "list_committed_offsets", "msg_id": 5, "keys": ["k"], "group": "audit",
+        }}),
+        // Requests without a group use the default one, which committed nothing.
+        json!({"src": "c3", "dest": "n0", "body": {
+            "type": "list_committed_offsets", "msg_id": 6, "keys": ["k"],
+        }}),
+    ]);
+
+    let emitted = node.recv_n(6);
+    assert_eq!(emitted[3]["body"]["offsets"], json!({"k": 5}));
+    assert_eq!(emitted[4]["body"]["offsets"], json!({"k": 2}));
+    assert_eq!(emitted[5]["body"]["offsets"], json!({}));
+}
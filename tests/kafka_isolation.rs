@@ -0,0 +1,76 @@
+//! Checks that a `read_committed` poll of the `kafka` binary stops at the committed offset of
+//! the key, while the default `read_uncommitted` poll returns every entry.
+
+mod common;
+
+use common::TestNode;
+use serde_json::{json, Value};
+
+/// Start a node and append `values` to `k`, returning the node and the offset of each entry.
+fn node_with_entries(values: &[u64]) -> (TestNode, Vec<u64>) {
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_kafka"));
+    node.init("n0", &["n0"]);
+    let sends: Vec<Value> = values
+        .iter()
+        .enumerate()
+        .map(|(index, value)| {
+            json!({"src": "c1", "dest": "n0", "body": {
+                "type": "send", "msg_id": 10 + index, "key": "k", "msg": value,
+            }})
+        })
+        .collect();
+    node.send_all(&sends);
+    let offsets = node
+        .recv_n(values.len() + 1)
+        .iter()
+        .skip(1)
+        .map(|send_ok| send_ok["body"]["offset"].as_u64().unwrap())
+        .collect();
+    (node, offsets)
+}
+
+fn poll(node: &mut TestNode, msg_id: u64, isolation: Option<&str>) -> Value {
+    let mut body = json!({"type": "poll", "msg_id": msg_id, "offsets": {"k": 0}});
+    if let Some(isolation) = isolation {
+        body["isolation"] = json!(isolation);
+    }
+    node.send(&json!({"src": "c1", "dest": "n0", "body": body}));
+    node.recv_type("poll_ok")["body"]["msgs"]["k"].clone()
+}
+
+#[test]
+fn uncommitted_poll_returns_every_entry() {
+    let (mut node, offsets) = node_with_entries(&[10, 20, 30, 40]);
+    node.send(&json!({"src": "c1", "dest": "n0", "body": {
+        "type": "commit_offsets", "msg_id": 20, "offsets": {"k": offsets[1]},
+    }}));
+    node.recv_type("commit_offsets_ok");
+
+    let expected: Vec<Value> = offsets
+        .iter()
+        .zip([10, 20, 30, 40])
+        .map(|(offset, value)| json!([offset, value]))
+        .collect();
+    assert_eq!(poll(&mut node, 21, None), json!(expected));
+    assert_eq!(
+        poll(&mut node, 22, Some("read_uncommitted")),
+        json!(expected)
+    );
+}
+
+#[test]
+fn committed_poll_stops_at_the_committed_offset() {
+    let (mut node, offsets) = node_with_entries(&[10, 20, 30, 40]);
+    // Nothing is visible before the first commit.
+    assert_eq!(poll(&mut node, 20, Some("read_committed")), json!([]));
+
+    node.send(&json!({"src": "c1", "dest": "n0", "body": {
+        "type": "commit_offsets", "msg_id": 21, "offsets": {"k": offsets[1]},
+    }}));
+    node.recv_type("commit_offsets_ok");
+
+    assert_eq!(
+        poll(&mut node, 22, Some("read_committed")),
+        json!([[offsets[0], 10], [offsets[1], 20]])
+    );
+}
@@ -0,0 +1,151 @@
+//! Checks that with `LIN_KV_OFFSETS_ENV` set, nodes appending to the same key get their
+//! offsets from the shared lin-kv counter, played here by the test, and that lin-kv failures
+//! are retried without hammering it.
+
+mod common;
+
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use common::{TestNode, REPLY_TIMEOUT};
+use distributed_systems::kafka::LIN_KV_OFFSETS_ENV;
+use serde_json::{json, Value};
+
+const SENDS_PER_NODE: u64 = 5;
+
+fn start(node_id: &str) -> TestNode {
+    let mut node = TestNode::start_with_env(
+        env!("CARGO_BIN_EXE_multi-node-kafka"),
+        &[(LIN_KV_OFFSETS_ENV, "1")],
+    );
+    node.init(node_id, &["n0", "n1"]);
+    node
+}
+
+/// Answer a lin-kv `read` or `cas` the way Maelstrom's lin-kv does.
+fn lin_kv_reply(values: &mut HashMap<String, u64>, request: &Value) -> Value {
+    let body = &request["body"];
+    let key = body["key"].as_str().unwrap().to_string();
+    let current = values.get(&key).copied();
+    let reply = match (body["type"].as_str().unwrap(), current) {
+        ("read", Some(value)) => json!({"type": "read_ok", "value": value}),
+        ("read", None) => json!({"type": "error", "code": 20}),
+        ("cas", None) if body["create_if_not_exists"] == true => {
+            values.insert(key, body["to"].as_u64().unwrap());
+            json!({"type": "cas_ok"})
+        }
+        ("cas", None) => json!({"type": "error", "code": 20}),
+        ("cas", Some(value)) if body["from"].as_u64() == Some(value) => {
+            values.insert(key, body["to"].as_u64().unwrap());
+            json!({"type": "cas_ok"})
+        }
+        ("cas", Some(_)) => json!({"type": "error", "code": 22}),
+        (other, _) => panic!("Unexpected lin-kv request {}", other),
+    };
+    let mut reply = json!({"src": "lin-kv", "dest": request["src"], "body": reply});
+    reply["body"]["in_reply_to"] = body["msg_id"].clone();
+    reply
+}
+
+#[test]
+fn two_owners_of_a_key_get_distinct_increasing_offsets() {
+    let mut nodes = HashMap::new();
+    for node_id in ["n0", "n1"] {
+        nodes.insert(node_id, start(node_id));
+    }
+    // Both nodes take the sends of the same key, as if each thought it owned it.
+    for i in 0..SENDS_PER_NODE {
+        for (node_id, node) in nodes.iter_mut() {
+            node.send(&json!({"src": "c1", "dest": node_id, "body": {
+                "type": "send", "msg_id": 10 + i, "key": "k", "msg": i,
+            }}));
+        }
+    }
+
+    let mut values = HashMap::new();
+    let mut offsets: HashMap<String, Vec<u64>> = HashMap::new();
+    let mut received = 0;
+    let deadline = Instant::now() + REPLY_TIMEOUT;
+    while received < 2 * SENDS_PER_NODE {
+        assert!(
+            Instant::now() < deadline,
+            "Nodes stopped before answering every send"
+        );
+        // Serve both nodes in turn, as a single lin-kv would.
+        let msgs: Vec<Value> = nodes
+            .values()
+            .filter_map(|node| node.try_recv(Duration::from_millis(5)))
+            .collect();
+        for msg in msgs {
+            let src = msg["src"].as_str().unwrap().to_string();
+            if msg["dest"] == "lin-kv" {
+                let reply = lin_kv_reply(&mut values, &msg);
+                nodes.get_mut(src.as_str()).unwrap().send(&reply);
+            } else if msg["body"]["type"] == "send_ok" {
+                received += 1;
+                let offset = msg["body"]["offset"].as_u64().unwrap();
+                offsets.entry(src).or_default().push(offset);
+            }
+        }
+    }
+
+    let mut all = HashSet::new();
+    for (node_id, offsets) in offsets.iter() {
+        assert!(
+            offsets.windows(2).all(|pair| pair[0] < pair[1]),
+            "{}: {:?}",
+            node_id,
+            offsets
+        );
+        for offset in offsets {
+            assert!(all.insert(*offset), "offset {} given twice", offset);
+        }
+    }
+    assert_eq!(all.len() as u64, 2 * SENDS_PER_NODE);
+    assert_eq!(values["next_offset/k"], 2 * SENDS_PER_NODE);
+}
+
+#[test]
+fn unavailable_lin_kv_is_retried_after_a_backoff() {
+    let mut node = start("n0");
+    node.recv_type("init_ok");
+    node.send(&json!({"src": "c1", "dest": "n0", "body": {
+        "type": "send", "msg_id": 2, "key": "k", "msg": 7,
+    }}));
+    let read = node.recv();
+    assert_eq!(read["body"]["type"], "read", "{}", read);
+    node.send(&json!({"src": "lin-kv", "dest": "n0", "body": {
+        "type": "error", "code": 11, "in_reply_to": read["body"]["msg_id"],
+    }}));
+
+    // The first retry waits for the backoff base, 50ms.
+    assert_eq!(node.try_recv(Duration::from_millis(30)), None);
+    let mut values = HashMap::new();
+    loop {
+        let msg = node.recv();
+        if msg["dest"] == "lin-kv" {
+            node.send(&lin_kv_reply(&mut values, &msg));
+        } else {
+            assert_eq!(msg["body"]["type"], "send_ok", "{}", msg);
+            assert_eq!(msg["body"]["offset"], 0);
+            break;
+        }
+    }
+}
+
+#[test]
+fn exhausted_offset_counter_rejects_the_send() {
+    let mut node = start("n0");
+    node.recv_type("init_ok");
+    node.send(&json!({"src": "c1", "dest": "n0", "body": {
+        "type": "send", "msg_id": 2, "key": "k", "msg": 7,
+    }}));
+    let mut values = HashMap::from([("next_offset/k".to_string(), u64::MAX)]);
+    let read = node.recv();
+    node.send(&lin_kv_reply(&mut values, &read));
+
+    let reply = node.recv();
+    assert_eq!(reply["dest"], "c1", "{}", reply);
+    assert_eq!(reply["body"]["type"], "error");
+    assert_eq!(reply["body"]["in_reply_to"], 2);
+}
@@ -0,0 +1,67 @@
+//! Checks the `log_length` the `kafka` binary adds to `poll_ok` when asked to.
+
+mod common;
+
+use common::TestNode;
+use distributed_systems::kafka::COMPACT_KEEP_ENTRIES_ENV;
+use serde_json::{json, Value};
+
+/// Append `count` entries to `key`. Sends are deduplicated on their msg_id, so every entry of a
+/// test needs a different `first_msg_id`.
+fn send_entries(node: &mut TestNode, key: &str, first_msg_id: u64, count: u64) {
+    for value in 0..count {
+        node.send(&json!({"src": "c1", "dest": "n0", "body": {
+            "type": "send", "msg_id": first_msg_id + value, "key": key, "msg": value,
+        }}));
+        node.recv_type("send_ok");
+    }
+}
+
+fn poll(node: &mut TestNode, include_log_length: Option<bool>) -> Value {
+    let mut body = json!({"type": "poll", "msg_id": 2, "offsets": {"a": 0, "b": 0, "empty": 0}});
+    if let Some(include_log_length) = include_log_length {
+        body["include_log_length"] = json!(include_log_length);
+    }
+    node.send(&json!({"src": "c1", "dest": "n0", "body": body}));
+    node.recv_type("poll_ok")
+}
+
+#[test]
+fn log_length_counts_the_appended_entries() {
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_kafka"));
+    node.init("n0", &["n0"]);
+    send_entries(&mut node, "a", 10, 4);
+    send_entries(&mut node, "b", 20, 1);
+
+    let poll_ok = poll(&mut node, Some(true));
+    assert_eq!(
+        poll_ok["body"]["log_length"],
+        json!({"a": 4, "b": 1, "empty": 0})
+    );
+}
+
+#[test]
+fn log_length_includes_compacted_entries() {
+    let mut node = TestNode::start_with_env(
+        env!("CARGO_BIN_EXE_kafka"),
+        &[(COMPACT_KEEP_ENTRIES_ENV, "2")],
+    );
+    node.init("n0", &["n0"]);
+    send_entries(&mut node, "a", 10, 5);
+
+    let poll_ok = poll(&mut node, Some(true));
+    assert_eq!(poll_ok["body"]["msgs"]["a"], json!([[3, 3], [4, 4]]));
+    assert_eq!(poll_ok["body"]["log_length"]["a"], 5);
+}
+
+#[test]
+fn log_length_is_left_out_unless_asked_for() {
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_kafka"));
+    node.init("n0", &["n0"]);
+    send_entries(&mut node, "a", 10, 2);
+
+    for include_log_length in [None, Some(false)] {
+        let poll_ok = poll(&mut node, include_log_length);
+        assert!(poll_ok["body"].get("log_length").is_none(), "{}", poll_ok);
+    }
+}
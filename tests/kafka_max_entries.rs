@@ -0,0 +1,41 @@
+//! Checks that with `MAX_ENTRIES_PER_KEY_ENV` set, the `kafka` binary refuses sends to a full
+//! key with an error reply.
+
+mod common;
+
+use common::TestNode;
+use distributed_systems::kafka::MAX_ENTRIES_PER_KEY_ENV;
+use distributed_systems::maelstrom::error::{ErrorBody, NodeError};
+use serde_json::json;
+
+#[test]
+fn send_to_a_full_key_is_refused() {
+    let mut node = TestNode::start_with_env(
+        env!("CARGO_BIN_EXE_kafka"),
+        &[(MAX_ENTRIES_PER_KEY_ENV, "2")],
+    );
+    node.init("n0", &["n0"]);
+    node.send_all(&[
+        json!({"src": "c1", "dest": "n0", "body": {"type": "send", "msg_id": 2, "key": "a", "msg": 10}}),
+        json!({"src": "c1", "dest": "n0", "body": {"type": "send", "msg_id": 3, "key": "a", "msg": 11}}),
+        json!({"src": "c1", "dest": "n0", "body": {"type": "send", "msg_id": 4, "key": "a", "msg": 12}}),
+        json!({"src": "c1", "dest": "n0", "body": {"type": "send", "msg_id": 5, "key": "b", "msg": 13}}),
+    ]);
+
+    let replies = node.recv_n(5);
+    assert_eq!(replies[1]["body"]["type"], "send_ok");
+    assert_eq!(replies[2]["body"]["type"], "send_ok");
+    assert_eq!(replies[3]["dest"], "c1");
+    let error: ErrorBody = serde_json::from_value(replies[3]["body"].clone()).unwrap();
+    assert_eq!(error._type, "error");
+    assert_eq!(error.in_reply_to, 4);
+    assert_eq!(error.code, 11);
+    assert_eq!(
+        NodeError::from_code(error.code),
+        NodeError::TemporarilyUnavailable
+    );
+    assert_eq!(error.text.as_deref(), Some("log a is full"));
+    // Other keys still take sends.
+    assert_eq!(replies[4]["body"]["type"], "send_ok");
+    assert_eq!(replies[4]["body"]["in_reply_to"], 5);
+}
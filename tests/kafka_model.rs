@@ -0,0 +1,135 @@
+//! Drives the `kafka` binary with random send/poll/commit/list sequences and checks every
+//! reply against a reference model of the logs.
+//!
+//! Runs are seeded, a failure names its seed. Set `KAFKA_MODEL_SEED` to replay a single seed.
+
+mod common;
+
+use std::collections::HashMap;
+
+use common::{TestNode, REPLY_TIMEOUT};
+use distributed_systems::maelstrom::rng::Rng;
+use serde_json::{json, Value};
+
+/// Most entries a poll returns per key, `POLL_SIZE` of the binary.
+const POLL_SIZE: usize = 50;
+const OPERATIONS: usize = 400;
+const KEYS: [&str; 3] = ["k1", "k2", "k3"];
+const SEEDS: [u64; 4] = [1, 7, 42, 2024];
+
+#[test]
+fn kafka_matches_reference_model() {
+    let seeds = match std::env::var("KAFKA_MODEL_SEED") {
+        Ok(seed) => vec![seed.parse().expect("Invalid KAFKA_MODEL_SEED")],
+        Err(_) => SEEDS.to_vec(),
+    };
+    for seed in seeds {
+        run(seed);
+    }
+}
+
+/// Reference model: every key is a list of values, the value at index `i` has offset `i`.
+#[derive(Default)]
+struct Model {
+    logs: HashMap<String, Vec<u64>>,
+    committed: HashMap<String, u64>,
+}
+
+struct Node {
+    node: TestNode,
+    next_msg_id: u64,
+}
+
+impl Node {
+    /// Send a request and return the body of its reply.
+    fn request(&mut self, seed: u64, mut body: Value) -> Value {
+        self.next_msg_id += 1;
+        body["msg_id"] = json!(self.next_msg_id);
+        let msg = json!({"src": "c1", "dest": "n0", "body": body});
+        self.node.send(&msg);
+        let reply = self
+            .node
+            .try_recv(REPLY_TIMEOUT)
+            .unwrap_or_else(|| panic!("seed {}: node stopped answering", seed));
+        assert_eq!(
+            reply["body"]["in_reply_to"], self.next_msg_id,
+            "seed {}: reply to the wrong request: {}",
+            seed, reply
+        );
+        reply["body"].clone()
+    }
+}
+
+fn run(seed: u64) {
+    let mut node = Node {
+        node: TestNode::start(env!("CARGO_BIN_EXE_kafka")),
+        next_msg_id: 0,
+    };
+    let init = json!({"type": "init", "node_id": "n0", "node_ids": ["n0"]});
+    assert_eq!(node.request(seed, init)["type"], "init_ok");
+
+    let mut rng = Rng::new(seed);
+    let mut model = Model::default();
+    for _ in 0..OPERATIONS {
+        let key = rng.choose(&KEYS).unwrap().to_string();
+        let log = model.logs.entry(key.clone()).or_default();
+        match rng.below(10) {
+            // Sends dominate, so the logs grow past a poll.
+            0..=4 => {
+                let value = rng.below(1000);
+                let reply = node.request(seed, json!({"type": "send", "key": key, "msg": value}));
+                assert_eq!(
+                    reply["offset"],
+                    log.len(),
+                    "seed {}: offsets of {} are not contiguous",
+                    seed,
+                    key
+                );
+                log.push(value);
+            }
+            5..=6 => {
+                let from = rng.below(log.len() as u64 + 2) as usize;
+                let reply = node.request(seed, json!({"type": "poll", "offsets": {&key: from}}));
+                let expected: Vec<Value> = log
+                    .iter()
+                    .enumerate()
+                    .skip(from)
+                    .take(POLL_SIZE)
+                    .map(|(offset, value)| json!([offset, value]))
+                    .collect();
+                assert_eq!(
+                    reply["msgs"][&key],
+                    Value::Array(expected),
+                    "seed {}: poll of {} from {}",
+                    seed,
+                    key,
+                    from
+                );
+            }
+            7..=8 => {
+                let offset = rng.below(log.len() as u64 + 1);
+                let reply = node.request(
+                    seed,
+                    json!({"type": "commit_offsets", "offsets": {&key: offset}}),
+                );
+                assert_eq!(reply["type"], "commit_offsets_ok", "seed {}", seed);
+                let committed = model.committed.entry(key).or_insert(offset);
+                *committed = (*committed).max(offset);
+            }
+            _ => {
+                let reply = node.request(
+                    seed,
+                    json!({"type": "list_committed_offsets", "keys": KEYS}),
+                );
+                for key in KEYS {
+                    let expected = model.committed.get(key).map_or(Value::Null, |c| json!(c));
+                    assert_eq!(
+                        reply["offsets"][key], expected,
+                        "seed {}: committed offset of {} went wrong",
+                        seed, key
+                    );
+                }
+            }
+        }
+    }
+}
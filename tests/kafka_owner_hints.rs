@@ -0,0 +1,53 @@
+//! Checks that with `OWNER_HINTS_ENV` set, the send_ok of a send made to a node that does not
+//! own the key names the owner, see `key_owner`.
+
+mod common;
+
+use common::TestNode;
+use distributed_systems::kafka::{key_owner, OWNER_HINTS_ENV};
+use serde_json::{json, Value};
+
+const NODE_IDS: [&str; 2] = ["n0", "n1"];
+
+/// A key owned by `owner`.
+fn key_owned_by(owner: &str) -> String {
+    let node_ids: Vec<String> = NODE_IDS.iter().map(|id| id.to_string()).collect();
+    (0..)
+        .map(|i| format!("k{}", i))
+        .find(|key| key_owner(key, &node_ids) == Some(owner))
+        .unwrap()
+}
+
+fn send_ok(node: &mut TestNode, key: &str, msg_id: u64) -> Value {
+    node.send(&json!({"src": "c1", "dest": "n0", "body": {
+        "type": "send", "msg_id": msg_id, "key": key, "msg": 7,
+    }}));
+    node.recv_matching(|msg| msg["body"]["in_reply_to"] == msg_id)
+}
+
+#[test]
+fn send_ok_names_the_owner() {
+    let mut node = TestNode::start_with_env(
+        env!("CARGO_BIN_EXE_multi-node-kafka"),
+        &[(OWNER_HINTS_ENV, "1")],
+    );
+    node.init("n0", &NODE_IDS);
+
+    let to_owner = send_ok(&mut node, &key_owned_by("n1"), 2);
+    assert_eq!(to_owner["body"]["type"], "send_ok");
+    assert_eq!(to_owner["body"]["owner"], "n1");
+
+    let owned = send_ok(&mut node, &key_owned_by("n0"), 3);
+    assert_eq!(owned["body"]["type"], "send_ok");
+    assert!(owned["body"].get("owner").is_none(), "{}", owned);
+}
+
+#[test]
+fn no_owner_hint_by_default() {
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_multi-node-kafka"));
+    node.init("n0", &NODE_IDS);
+
+    let reply = send_ok(&mut node, &key_owned_by("n1"), 2);
+    assert_eq!(reply["body"]["type"], "send_ok");
+    assert!(reply["body"].get("owner").is_none(), "{}", reply);
+}
@@ -0,0 +1,99 @@
+//! Checks the packed encoding of poll entries used between nodes, see `PackedEntries`.
+
+mod common;
+
+use common::TestNode;
+use distributed_systems::kafka::{LogValue, Offset, PackedEntries, PollResponse};
+use distributed_systems::maelstrom::rng::Rng;
+use serde_json::{json, Value};
+
+#[test]
+fn contiguous_run_round_trips() {
+    let mut rng = Rng::new(7);
+    let entries: Vec<(Offset, LogValue)> = (0..1000)
+        .map(|i| (Offset(5000 + i), LogValue(rng.below(1000))))
+        .collect();
+
+    let packed = PackedEntries::pack(&entries).unwrap();
+    assert_eq!(packed.base, Offset(5000));
+    assert_eq!(packed.count, 1000);
+    assert_eq!(packed.unpack().unwrap(), entries);
+
+    let json_size = serde_json::to_string(&entries).unwrap().len();
+    let packed_size = serde_json::to_string(&packed).unwrap().len();
+    println!(
+        "1000 entries: {} bytes as JSON pairs, {} bytes packed",
+        json_size, packed_size
+    );
+    assert!(
+        packed_size * 3 < json_size,
+        "{} vs {}",
+        packed_size,
+        json_size
+    );
+}
+
+#[test]
+fn large_values_and_empty_runs_round_trip() {
+    let entries = vec![
+        (Offset(0), LogValue(0)),
+        (Offset(1), LogValue(127)),
+        (Offset(2), LogValue(128)),
+        (Offset(3), LogValue(u64::MAX)),
+    ];
+    assert_eq!(
+        PackedEntries::pack(&entries).unwrap().unpack().unwrap(),
+        entries
+    );
+    assert!(PackedEntries::pack(&[])
+        .unwrap()
+        .unpack()
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn gaps_and_truncated_data_are_rejected() {
+    let gap = [(Offset(1), LogValue(1)), (Offset(3), LogValue(2))];
+    assert!(PackedEntries::pack(&gap).is_err());
+
+    let mut packed = PackedEntries::pack(&[(Offset(1), LogValue(1))]).unwrap();
+    packed.count = 2;
+    assert!(packed.unpack().is_err());
+}
+
+#[test]
+fn huge_count_is_rejected() {
+    let mut packed = PackedEntries::pack(&[(Offset(1), LogValue(1))]).unwrap();
+    packed.count = u64::MAX;
+    assert!(packed.unpack().is_err());
+}
+
+#[test]
+fn only_nodes_get_packed_polls() {
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_kafka"));
+    node.init("n0", &["n0", "n1"]);
+    for value in 0..10 {
+        node.send(&json!({"src": "c1", "dest": "n0", "body": {
+            "type": "send", "msg_id": 10 + value, "key": "k", "msg": value * 100,
+        }}));
+    }
+    let poll = json!({"type": "poll", "msg_id": 2, "offsets": {"k": 4}, "packed": true});
+    node.send(&json!({"src": "n1", "dest": "n0", "body": poll}));
+    node.send(&json!({"src": "c1", "dest": "n0", "body": poll}));
+
+    let polls: Vec<Value> = (0..2).map(|_| node.recv_type("poll_ok")).collect();
+
+    let expected: Vec<(Offset, LogValue)> =
+        (4..10).map(|i| (Offset(i), LogValue(i * 100))).collect();
+
+    assert_eq!(polls[0]["dest"], "n1");
+    assert!(polls[0]["body"]["msgs"].get("k").is_none());
+    let to_node: PollResponse = serde_json::from_value(polls[0]["body"].clone()).unwrap();
+    assert_eq!(to_node.entries().unwrap()["k"], expected);
+
+    assert_eq!(polls[1]["dest"], "c1");
+    assert!(polls[1]["body"].get("packed").is_none());
+    let to_client: PollResponse = serde_json::from_value(polls[1]["body"].clone()).unwrap();
+    assert_eq!(to_client.msgs["k"], expected);
+}
@@ -0,0 +1,65 @@
+//! Checks how `PollBudget` shares the entries of a multi-key poll between its keys, on its own
+//! and through the `kafka` binary.
+
+mod common;
+
+use common::TestNode;
+use distributed_systems::kafka::PollBudget;
+use serde_json::json;
+
+#[test]
+fn global_budget_is_shared_round_robin() {
+    let allocated = PollBudget::GlobalBudget(10).allocate(50, &[20, 20, 20]);
+    assert_eq!(allocated, [4, 3, 3]);
+}
+
+#[test]
+fn global_budget_goes_to_the_keys_that_still_have_entries() {
+    let allocated = PollBudget::GlobalBudget(10).allocate(50, &[1, 20, 2]);
+    assert_eq!(allocated, [1, 7, 2]);
+    // Fewer entries than the budget, everything is returned.
+    let allocated = PollBudget::GlobalBudget(10).allocate(50, &[1, 3, 2]);
+    assert_eq!(allocated, [1, 3, 2]);
+}
+
+#[test]
+fn per_key_budget_caps_every_key_on_its_own() {
+    let allocated = PollBudget::PerKey.allocate(5, &[20, 3, 8]);
+    assert_eq!(allocated, [5, 3, 5]);
+}
+
+#[test]
+fn poll_with_a_global_budget_is_capped_in_total() {
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_kafka"));
+    node.init("n0", &["n0"]);
+    let mut msg_id = 10;
+    for key in ["a", "b", "c"] {
+        for value in 0..8 {
+            node.send(&json!({"src": "c1", "dest": "n0", "body": {
+                "type": "send", "msg_id": msg_id, "key": key, "msg": value,
+            }}));
+            node.recv_type("send_ok");
+            msg_id += 1;
+        }
+    }
+    node.send(&json!({"src": "c1", "dest": "n0", "body": {
+        "type": "poll", "msg_id": 2, "offsets": {"a": 0, "b": 0, "c": 0},
+        "budget": {"global_budget": 10},
+    }}));
+
+    let poll_ok = node.recv_type("poll_ok");
+    let counts: Vec<usize> = ["a", "b", "c"]
+        .iter()
+        .map(|key| poll_ok["body"]["msgs"][key].as_array().unwrap().len())
+        .collect();
+    assert_eq!(counts.iter().sum::<usize>(), 10);
+    assert!(
+        counts.iter().all(|count| (3..=4).contains(count)),
+        "{:?}",
+        counts
+    );
+    // Every key starts from its first entry.
+    for key in ["a", "b", "c"] {
+        assert_eq!(poll_ok["body"]["msgs"][key][0][1], 0);
+    }
+}
@@ -0,0 +1,39 @@
+//! Checks that the `Offset`/`LogValue` newtypes still put `poll_ok` entries on the wire as
+//! `[offset, message]` arrays.
+
+use std::collections::HashMap;
+
+use distributed_systems::kafka::{LogValue, Offset, PollResponse};
+use serde_json::json;
+
+#[test]
+fn entries_serialize_offset_first() {
+    let response = PollResponse {
+        msgs: HashMap::from([(
+            "k".to_string(),
+            vec![(Offset(3), LogValue(300)), (Offset(4), LogValue(7))],
+        )]),
+        trimmed: HashMap::new(),
+        log_length: HashMap::new(),
+        packed: HashMap::new(),
+        in_reply_to: Some(2),
+        msg_id: None,
+    };
+    assert_eq!(
+        serde_json::to_value(&response).unwrap(),
+        json!({"msgs": {"k": [[3, 300], [4, 7]]}, "in_reply_to": 2})
+    );
+}
+
+#[test]
+fn entries_deserialize_back() {
+    let response: PollResponse = serde_json::from_value(json!({
+        "msgs": {"k": [[3, 300], [4, 7]]}, "in_reply_to": 2,
+    }))
+    .unwrap();
+    assert_eq!(
+        response.msgs["k"],
+        [(Offset(3), LogValue(300)), (Offset(4), LogValue(7))]
+    );
+    assert_eq!(response.entries().unwrap(), response.msgs);
+}
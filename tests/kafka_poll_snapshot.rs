@@ -0,0 +1,44 @@
+//! Checks that a multi-key poll of the `kafka` binary reads every key at a single state of the
+//! logs: requests are handled one at a time, a send never lands in the middle of a poll.
+
+mod common;
+
+use common::TestNode;
+use serde_json::json;
+
+fn send(node: &mut TestNode, msg_id: u64, key: &str, msg: u64) {
+    node.send(&json!({"src": "c1", "dest": "n0", "body": {
+        "type": "send", "msg_id": msg_id, "key": key, "msg": msg,
+    }}));
+}
+
+fn poll(node: &mut TestNode, msg_id: u64) -> serde_json::Value {
+    node.send(&json!({"src": "c1", "dest": "n0", "body": {
+        "type": "poll", "msg_id": msg_id, "offsets": {"a": 0, "b": 0, "c": 0},
+    }}));
+    node.recv_type("poll_ok")
+}
+
+#[test]
+fn multi_key_poll_reads_the_logs_at_the_poll() {
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_kafka"));
+    node.init("n0", &["n0"]);
+    send(&mut node, 2, "a", 10);
+    send(&mut node, 3, "b", 20);
+    send(&mut node, 4, "a", 11);
+
+    let first = poll(&mut node, 5);
+    send(&mut node, 6, "b", 21);
+    send(&mut node, 7, "c", 30);
+    let second = poll(&mut node, 8);
+
+    // Keys without a log when the poll started, like `c`, have nothing to read.
+    assert_eq!(
+        first["body"]["msgs"],
+        json!({"a": [[0, 10], [1, 11]], "b": [[0, 20]], "c": []})
+    );
+    assert_eq!(
+        second["body"]["msgs"],
+        json!({"a": [[0, 10], [1, 11]], "b": [[0, 20], [1, 21]], "c": [[0, 30]]})
+    );
+}
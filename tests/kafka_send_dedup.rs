@@ -0,0 +1,46 @@
+//! Checks that the `kafka` binary answers a retried `send` with the offset it first assigned.
+
+mod common;
+
+use common::TestNode;
+use serde_json::json;
+
+#[test]
+fn duplicate_send_returns_the_original_offset() {
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_kafka"));
+    node.init("n0", &["n0"]);
+    let send = json!({"src": "c1", "dest": "n0", "body": {
+        "type": "send", "msg_id": 7, "key": "k", "msg": 42,
+    }});
+    node.send(&send);
+    let first = node.recv_type("send_ok");
+    node.send(&send);
+    let retry = node.recv_type("send_ok");
+    assert_eq!(first["body"]["offset"], retry["body"]["offset"]);
+
+    node.send(&json!({"src": "c1", "dest": "n0", "body": {
+        "type": "poll", "msg_id": 8, "offsets": {"k": 0},
+    }}));
+    let poll_ok = node.recv_type("poll_ok");
+    assert_eq!(
+        poll_ok["body"]["msgs"]["k"],
+        json!([[first["body"]["offset"], 42]])
+    );
+}
+
+#[test]
+fn same_msg_id_from_another_client_is_appended() {
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_kafka"));
+    node.init("n0", &["n0"]);
+    for client in ["c1", "c2"] {
+        node.send(&json!({"src": client, "dest": "n0", "body": {
+            "type": "send", "msg_id": 7, "key": "k", "msg": 42,
+        }}));
+    }
+    let offsets: Vec<_> = node
+        .recv_n(2)
+        .into_iter()
+        .map(|send_ok| send_ok["body"]["offset"].clone())
+        .collect();
+    assert_ne!(offsets[0], offsets[1]);
+}
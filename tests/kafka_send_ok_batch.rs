@@ -0,0 +1,33 @@
+//! Checks the send_ok coalescing of the `kafka` binary, enabled through
+//! `SEND_OK_BATCH_MS_ENV`.
+
+mod common;
+
+use common::TestNode;
+use distributed_systems::kafka::SEND_OK_BATCH_MS_ENV;
+use serde_json::json;
+
+#[test]
+fn sends_within_the_window_get_one_batch_reply() {
+    let mut node = TestNode::start_with_env(
+        env!("CARGO_BIN_EXE_kafka"),
+        &[(SEND_OK_BATCH_MS_ENV, "200")],
+    );
+    node.init("n0", &["n0"]);
+    node.send_all(&[
+        json!({"src": "c1", "dest": "n0", "body": {"type": "send", "msg_id": 2, "key": "a", "msg": 10}}),
+        json!({"src": "c1", "dest": "n0", "body": {"type": "send", "msg_id": 3, "key": "a", "msg": 11}}),
+        json!({"src": "c1", "dest": "n0", "body": {"type": "send", "msg_id": 4, "key": "b", "msg": 12}}),
+        // A retried send is answered in the batch too, with the offset it got the first time.
+        json!({"src": "c1", "dest": "n0", "body": {"type": "send", "msg_id": 2, "key": "a", "msg": 10}}),
+    ]);
+
+    let emitted = node.recv_n(2);
+    assert_eq!(emitted[0]["body"]["type"], "init_ok");
+    assert_eq!(
+        emitted[1],
+        json!({"src": "n0", "dest": "c1", "body": {
+            "type": "send_ok_batch", "sends": [[2, 0], [3, 1], [4, 0], [2, 0]],
+        }})
+    );
+}
@@ -0,0 +1,101 @@
+//! Checks that `multi-node-kafka` nodes committing offsets of the same key through seq-kv,
+//! played here by the test, converge on the highest one whatever order their CAS land in.
+
+mod common;
+
+use std::collections::HashMap;
+
+use common::TestNode;
+use distributed_systems::maelstrom::seq_kv::SEQ_KV;
+use serde_json::{json, Value};
+
+fn start(node_id: &str) -> TestNode {
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_multi-node-kafka"));
+    node.init(node_id, &["n0", "n1"]);
+    node.recv_type("init_ok");
+    node
+}
+
+/// Answer a seq-kv `read` or `cas` the way Maelstrom's seq-kv does.
+fn seq_kv_reply(values: &mut HashMap<String, u64>, request: &Value) -> Value {
+    let body = &request["body"];
+    let key = body["key"].as_str().unwrap().to_string();
+    let current = values.get(&key).copied();
+    let reply = match (body["type"].as_str().unwrap(), current) {
+        ("read", Some(value)) => json!({"type": "read_ok", "value": value}),
+        ("read", None) => json!({"type": "error", "code": 20}),
+        ("cas", None) if body["create_if_not_exists"] == true => {
+            values.insert(key, body["to"].as_u64().unwrap());
+            json!({"type": "cas_ok"})
+        }
+        ("cas", None) => json!({"type": "error", "code": 20}),
+        ("cas", Some(value)) if body["from"].as_u64() == Some(value) => {
+            values.insert(key, body["to"].as_u64().unwrap());
+            json!({"type": "cas_ok"})
+        }
+        ("cas", Some(_)) => json!({"type": "error", "code": 22}),
+        (other, _) => panic!("Unexpected seq-kv request {}", other),
+    };
+    let mut reply = json!({"src": SEQ_KV, "dest": request["src"], "body": reply});
+    reply["body"]["in_reply_to"] = body["msg_id"].clone();
+    reply
+}
+
+/// Serve seq-kv requests of `node` until it answers the client with `reply_type`.
+fn serve_until(node: &mut TestNode, values: &mut HashMap<String, u64>, reply_type: &str) -> Value {
+    loop {
+        let msg = node.recv();
+        if msg["dest"] == SEQ_KV {
+            node.send(&seq_kv_reply(values, &msg));
+        } else if msg["body"]["type"] == reply_type {
+            return msg;
+        }
+    }
+}
+
+/// n0 commits offset 5 and n1 offset 3 of `k`. Both read the key before either CAS lands, then
+/// the CAS of `first` is served before the other one.
+fn commit_concurrently(first: &str) -> (HashMap<String, u64>, [TestNode; 2]) {
+    let mut nodes = [start("n0"), start("n1")];
+    for (node, (node_id, offset)) in nodes.iter_mut().zip([("n0", 5), ("n1", 3)]) {
+        node.send(&json!({"src": "c1", "dest": node_id, "body": {
+            "type": "commit_offsets", "msg_id": 2, "offsets": {"k": offset},
+        }}));
+    }
+
+    let mut values = HashMap::new();
+    for node in nodes.iter_mut() {
+        let read = node.recv();
+        assert_eq!(read["body"]["type"], "read", "{}", read);
+        node.send(&seq_kv_reply(&mut values, &read));
+    }
+    let cas: Vec<Value> = nodes.iter().map(|node| node.recv()).collect();
+    let order = if first == "n0" { [0, 1] } else { [1, 0] };
+    for index in order {
+        assert_eq!(cas[index]["body"]["type"], "cas", "{}", cas[index]);
+        nodes[index].send(&seq_kv_reply(&mut values, &cas[index]));
+    }
+    for node in nodes.iter_mut() {
+        let commit_ok = serve_until(node, &mut values, "commit_offsets_ok");
+        assert_eq!(commit_ok["body"]["in_reply_to"], 2);
+    }
+    (values, nodes)
+}
+
+#[test]
+fn lower_commit_landing_first_is_moved_past() {
+    let (mut values, mut nodes) = commit_concurrently("n1");
+    assert_eq!(values["offset/default/k"], 5);
+
+    nodes[1].send(&json!({"src": "c1", "dest": "n1", "body": {
+        "type": "list_committed_offsets", "msg_id": 3, "keys": ["k"],
+    }}));
+    let list_ok = serve_until(&mut nodes[1], &mut values, "list_committed_offsets_ok");
+    assert_eq!(list_ok["body"]["offsets"], json!({"k": 5}));
+}
+
+#[test]
+fn higher_commit_landing_first_is_never_moved_back() {
+    let (values, _) = commit_concurrently("n0");
+    assert_eq!(values["offset/default/k"], 5);
+}
@@ -0,0 +1,65 @@
+//! Checks that the kafka binaries keep running their pending work for `SHUTDOWN_GRACE_MS` once
+//! stdin is closed, instead of exiting with it.
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::TestNode;
+use distributed_systems::kafka::SEND_OK_BATCH_MS_ENV;
+use distributed_systems::maelstrom::SHUTDOWN_GRACE_MS;
+use serde_json::json;
+
+#[test]
+fn held_send_oks_go_out_after_stdin_closed() {
+    let mut node = TestNode::start_with_env(
+        env!("CARGO_BIN_EXE_kafka"),
+        &[(SEND_OK_BATCH_MS_ENV, "200")],
+    );
+    node.init("n0", &["n0"]);
+    node.send_all(&[
+        json!({"src": "c1", "dest": "n0", "body": {"type": "send", "msg_id": 2, "key": "a", "msg": 10}}),
+        json!({"src": "c1", "dest": "n0", "body": {"type": "send", "msg_id": 3, "key": "a", "msg": 11}}),
+    ]);
+    node.recv_type("init_ok");
+    let (emitted, status) = node.finish();
+
+    assert!(status.success(), "{}", node.stderr());
+    assert_eq!(
+        emitted,
+        vec![json!({"src": "n0", "dest": "c1", "body": {
+            "type": "send_ok_batch", "sends": [[2, 0], [3, 1]],
+        }})]
+    );
+}
+
+#[test]
+fn unanswered_kv_requests_are_retried_until_the_grace_period_is_over() {
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_multi-node-kafka"));
+    node.init("n0", &["n0"]);
+    node.send(&json!({"src": "c1", "dest": "n0", "body": {
+        "type": "commit_offsets", "msg_id": 2, "offsets": {"a": 1},
+    }}));
+    let first_read = node.recv_matching(|msg| msg["dest"] == "seq-kv");
+    assert_eq!(first_read["body"]["type"], "read");
+
+    // seq-kv never answers, the node gives up on it once the grace period is over.
+    let closed = Instant::now();
+    let (emitted, status) = node.finish();
+    let waited = closed.elapsed();
+
+    assert!(status.success(), "{}", node.stderr());
+    assert!(
+        emitted
+            .iter()
+            .any(|msg| msg["dest"] == "seq-kv" && msg["body"]["type"] == "read"),
+        "{:?}",
+        emitted
+    );
+    assert!(
+        waited >= Duration::from_millis(SHUTDOWN_GRACE_MS),
+        "{:?}",
+        waited
+    );
+    assert!(node.stderr().contains("Shutdown grace period over"));
+}
@@ -0,0 +1,76 @@
+//! Checks that the committed watermark kept by `advance_watermark` agrees with a full scan of
+//! per-entry commit flags, the way committed offsets used to be tracked.
+
+use distributed_systems::kafka::{advance_watermark, Offset};
+
+/// A sparse log with a commit flag on every entry, like the logs of multi-node-kafka.
+struct ScannedLog {
+    entries: Vec<(Offset, bool)>,
+}
+
+impl ScannedLog {
+    fn commit(&mut self, offset: Offset) {
+        for (entry_offset, committed) in self.entries.iter_mut() {
+            if *entry_offset <= offset {
+                *committed = true;
+            }
+        }
+    }
+
+    /// Offsets of the committed entries, from the start of the log up to the first one that
+    /// isn't.
+    fn scan(&self) -> Vec<Offset> {
+        self.entries
+            .iter()
+            .take_while(|(_, committed)| *committed)
+            .map(|(offset, _)| *offset)
+            .collect()
+    }
+}
+
+#[test]
+fn watermark_matches_a_full_scan() {
+    let mut log = ScannedLog {
+        entries: [0, 2, 3, 7, 8]
+            .map(|offset| (Offset(offset), false))
+            .to_vec(),
+    };
+    let mut watermark = None;
+    // Out of order, with commits below the watermark and past the end of the log.
+    for commit in [3, 1, 5, 2, 20, 4] {
+        log.commit(Offset(commit));
+        let last_offset = log.entries.last().unwrap().0;
+        watermark = Some(advance_watermark(watermark, Offset(commit), last_offset));
+
+        let below_watermark: Vec<Offset> = log
+            .entries
+            .iter()
+            .map(|(offset, _)| *offset)
+            .filter(|offset| Some(*offset) <= watermark)
+            .collect();
+        assert_eq!(below_watermark, log.scan(), "after committing {}", commit);
+    }
+    assert_eq!(watermark, Some(Offset(8)));
+}
+
+#[test]
+fn entries_appended_after_a_commit_are_not_committed() {
+    let mut log = ScannedLog {
+        entries: vec![(Offset(0), false), (Offset(1), false)],
+    };
+    log.commit(Offset(10));
+    let watermark = advance_watermark(None, Offset(10), Offset(1));
+    log.entries.push((Offset(2), false));
+
+    assert_eq!(watermark, Offset(1));
+    assert_eq!(log.scan(), [Offset(0), Offset(1)]);
+}
+
+#[test]
+fn older_commit_never_moves_the_watermark_back() {
+    let watermark = advance_watermark(None, Offset(6), Offset(9));
+    assert_eq!(
+        advance_watermark(Some(watermark), Offset(2), Offset(9)),
+        Offset(6)
+    );
+}
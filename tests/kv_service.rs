@@ -0,0 +1,71 @@
+use distributed_systems::maelstrom::error::NodeError;
+use distributed_systems::maelstrom::kv_service::{InMemoryKvService, KvService};
+use distributed_systems::maelstrom::seq_kv::{
+    SeqKVCompareAndSwapRequest, SeqKVErrorResponse, SeqKvReply,
+};
+use serde_json::json;
+
+fn cas(from: Option<u64>, to: u64, msg_id: u64) -> SeqKVCompareAndSwapRequest {
+    SeqKVCompareAndSwapRequest {
+        in_reply_to: None,
+        msg_id: Some(msg_id),
+        key: "sum".to_string(),
+        from,
+        to: Some(to),
+        create_if_not_exists: true,
+    }
+}
+
+#[test]
+fn in_memory_cas_conflict_then_recas() {
+    let mut kv = InMemoryKvService::new();
+
+    let created = kv.cas("n0", cas(None, 3, 1)).unwrap();
+    assert!(matches!(created, Some(SeqKvReply::CasOk(ok)) if ok.in_reply_to == Some(1)));
+
+    // n1 still believes the counter is missing.
+    let conflict = kv.cas("n1", cas(None, 5, 2)).unwrap();
+    match conflict {
+        Some(SeqKvReply::Error(err)) => {
+            assert_eq!(err.in_reply_to, Some(2));
+            assert!(matches!(err.node_error(), NodeError::PreconditionFailed));
+        }
+        reply => panic!("Expected a precondition failure, got {:?}", reply),
+    }
+
+    let read = kv.read("n1", "sum", Some(3)).unwrap();
+    let current = match read {
+        Some(SeqKvReply::ReadOk(read_ok)) => read_ok.value,
+        reply => panic!("Expected a read_ok, got {:?}", reply),
+    };
+    assert_eq!(current, 3);
+
+    let recas = kv.cas("n1", cas(Some(current), current + 5, 4)).unwrap();
+    assert!(matches!(recas, Some(SeqKvReply::CasOk(_))));
+    assert_eq!(kv.get("sum"), Some(8));
+}
+
+#[test]
+fn cas_without_create_fails_on_a_missing_key() {
+    let mut kv = InMemoryKvService::new();
+    let create_once = SeqKVCompareAndSwapRequest {
+        create_if_not_exists: false,
+        ..cas(None, 3, 1)
+    };
+    match kv.cas("n0", create_once).unwrap() {
+        Some(SeqKvReply::Error(err)) => {
+            assert!(matches!(err.node_error(), NodeError::KeyDoesNotExist))
+        }
+        reply => panic!("Expected a missing key, got {:?}", reply),
+    }
+    assert_eq!(kv.get("sum"), None);
+}
+
+#[test]
+fn create_collision_is_key_already_exists() {
+    let err: SeqKVErrorResponse = serde_json::from_value(json!({
+        "type": "error", "code": 21, "in_reply_to": 2, "text": "key sum already exists",
+    }))
+    .unwrap();
+    assert!(matches!(err.node_error(), NodeError::KeyAlreadyExists));
+}
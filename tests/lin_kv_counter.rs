@@ -0,0 +1,53 @@
+//! Checks that with `KV_SERVICE_ENV` set to lin-kv, the counter is kept in lin-kv, played here
+//! by the test, and reads its own writes.
+
+mod common;
+
+use common::TestNode;
+use distributed_systems::maelstrom::kv_service::KV_SERVICE_ENV;
+use distributed_systems::maelstrom::seq_kv::{LIN_KV, SEQ_KV};
+use serde_json::json;
+
+#[test]
+fn counter_reads_its_own_writes_through_lin_kv() {
+    let mut node =
+        TestNode::start_with_env(env!("CARGO_BIN_EXE_g_counter"), &[(KV_SERVICE_ENV, LIN_KV)]);
+    node.init("n0", &["n0"]);
+    node.send(
+        &json!({"src": "c1", "dest": "n0", "body": {"type": "add", "msg_id": 2, "delta": 5}}),
+    );
+
+    let mut stored: Option<u64> = None;
+    let mut read_sent = false;
+    let read_ok = loop {
+        let msg = node.recv();
+        assert_ne!(msg["dest"], SEQ_KV, "{}", msg);
+        let body = &msg["body"];
+        if msg["dest"] == LIN_KV {
+            let reply = match (body["type"].as_str().unwrap(), stored) {
+                ("read", Some(value)) => json!({"type": "read_ok", "value": value}),
+                ("read", None) => json!({"type": "error", "code": 20}),
+                ("cas", current) if current.is_none() || body["from"].as_u64() == current => {
+                    stored = body["to"].as_u64();
+                    json!({"type": "cas_ok"})
+                }
+                ("cas", _) => json!({"type": "error", "code": 22}),
+                (other, _) => panic!("Unexpected lin-kv request {}", other),
+            };
+            let mut reply = json!({"src": LIN_KV, "dest": "n0", "body": reply});
+            reply["body"]["in_reply_to"] = body["msg_id"].clone();
+            node.send(&reply);
+        } else if msg["dest"] == "c1" && body["type"] == "read_ok" {
+            break msg;
+        }
+        // Read once the add is stored, the read_ok has to include it.
+        if stored == Some(5) && !read_sent {
+            let read = json!({"src": "c1", "dest": "n0", "body": {"type": "read", "msg_id": 3}});
+            node.send(&read);
+            read_sent = true;
+        }
+    };
+
+    assert_eq!(read_ok["body"]["in_reply_to"], 3);
+    assert_eq!(read_ok["body"]["value"], 5);
+}
@@ -0,0 +1,40 @@
+//! Checks that with `LINEARIZABLE_READS_ENV` set, `g_counter` answers a read with the value of
+//! a seq-kv read sent after the read arrived.
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::TestNode;
+use distributed_systems::maelstrom::kv_service::LINEARIZABLE_READS_ENV;
+use serde_json::json;
+
+#[test]
+fn read_waits_for_a_fresh_kv_read() {
+    let mut node = TestNode::start_with_env(
+        env!("CARGO_BIN_EXE_g_counter"),
+        &[(LINEARIZABLE_READS_ENV, "1")],
+    );
+    node.init("n0", &["n0", "n1"]);
+    node.recv_type("init_ok");
+    node.send(&json!({"src": "c1", "dest": "n0", "body": {"type": "read", "msg_id": 2}}));
+
+    let barrier = node.recv_matching(|msg| msg["dest"] == "seq-kv");
+    assert_eq!(barrier["body"]["type"], "read");
+    // Nothing goes to the client until seq-kv answers.
+    assert!(node
+        .recv_for(Duration::from_millis(100))
+        .iter()
+        .all(|msg| msg["dest"] != "c1"));
+
+    let answered = Instant::now();
+    node.send(&json!({"src": "seq-kv", "dest": "n0", "body": {
+        "type": "read_ok", "value": 42, "in_reply_to": barrier["body"]["msg_id"],
+    }}));
+    let read_ok = node.recv_matching(|msg| msg["dest"] == "c1");
+    assert_eq!(read_ok["body"]["type"], "read_ok");
+    assert_eq!(read_ok["body"]["in_reply_to"], 2);
+    assert_eq!(read_ok["body"]["value"], 42);
+    // Without the barrier, reads wait for the peers to sync for much longer.
+    assert!(answered.elapsed() < Duration::from_millis(300));
+}
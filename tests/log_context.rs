@@ -0,0 +1,50 @@
+//! Checks that lines logged while handling a message carry its ids.
+
+use distributed_systems::logging::{enter_message, format_log_line, MessageContext};
+use serde_json::json;
+
+fn line(message: &str) -> String {
+    format_log_line("1700000000000", &"n1", format_args!("{}", message))
+}
+
+#[test]
+fn lines_logged_in_a_handler_carry_the_msg_id() {
+    let context = MessageContext::from_body(&json!({"type": "add", "msg_id": 7, "delta": 3}));
+    let _scope = enter_message(context);
+
+    let logged = line("Received add(3)");
+    assert!(
+        logged.ends_with(" 1700000000000 [n1] [msg_id=7] Received add(3)"),
+        "{}",
+        logged
+    );
+}
+
+#[test]
+fn replies_carry_both_ids() {
+    let context =
+        MessageContext::from_body(&json!({"type": "read_ok", "msg_id": 4, "in_reply_to": 2}));
+    let _scope = enter_message(context);
+
+    assert!(line("Received read_ok").ends_with("[n1] [msg_id=4 in_reply_to=2] Received read_ok"));
+}
+
+#[test]
+fn leaving_a_message_restores_the_outer_one() {
+    assert!(line("Idle").ends_with("[n1] Idle"));
+    {
+        let _outer = enter_message(MessageContext {
+            msg_id: Some(1),
+            in_reply_to: None,
+        });
+        {
+            let _inner = enter_message(MessageContext {
+                msg_id: Some(2),
+                in_reply_to: None,
+            });
+            assert!(line("Inner").ends_with("[n1] [msg_id=2] Inner"));
+        }
+        assert!(line("Outer").ends_with("[n1] [msg_id=1] Outer"));
+    }
+    assert!(line("Idle").ends_with("[n1] Idle"));
+}
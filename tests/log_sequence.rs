@@ -0,0 +1,50 @@
+//! Checks the sequence numbers prefixed to log lines, see `format_log_line`.
+
+use distributed_systems::logging::{format_log_line, recent_logs};
+use distributed_systems::node_log;
+
+fn seq(line: &str) -> u64 {
+    let seq = line.split_whitespace().next().unwrap();
+    seq.strip_prefix('#').unwrap().parse().unwrap()
+}
+
+#[test]
+fn sequence_grows_when_the_clock_goes_back() {
+    // Wall clock adjusted backwards between the lines.
+    let clock = [
+        "1700000000.500",
+        "1700000000.200",
+        "1699999999.900",
+        "1699999999.900",
+    ];
+    let lines: Vec<String> = clock
+        .iter()
+        .map(|ts| format_log_line(ts, &"n0", format_args!("tick")))
+        .collect();
+
+    assert!(
+        lines[1].ends_with(" 1700000000.200 [n0] tick"),
+        "{}",
+        lines[1]
+    );
+    let seqs: Vec<u64> = lines.iter().map(|line| seq(line)).collect();
+    assert!(seqs.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", seqs);
+}
+
+#[test]
+fn node_log_lines_are_numbered() {
+    let node_id = "n1".to_string();
+    node_log!(node_id, "first");
+    node_log!(node_id, "second");
+
+    let logs = recent_logs();
+    let first = logs
+        .iter()
+        .find(|line| line.ends_with("[n1] first"))
+        .unwrap();
+    let second = logs
+        .iter()
+        .find(|line| line.ends_with("[n1] second"))
+        .unwrap();
+    assert!(seq(first) < seq(second), "{} / {}", first, second);
+}
@@ -0,0 +1,91 @@
+//! Checks the cluster membership built from the init message.
+
+use distributed_systems::logging::recent_logs;
+use distributed_systems::maelstrom::membership::Membership;
+
+fn cluster(node_id: &str) -> Membership {
+    let node_ids = ["n0", "n1", "n2", "n3"].map(String::from).to_vec();
+    Membership::new(node_id.to_string(), node_ids).unwrap()
+}
+
+#[test]
+fn peers_exclude_exactly_this_node() {
+    let membership = cluster("n1");
+    assert_eq!(membership.peers().collect::<Vec<_>>(), ["n0", "n2", "n3"]);
+    // The order is the one of the init message, every time.
+    assert_eq!(
+        membership.peers().collect::<Vec<_>>(),
+        membership.peers().collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn peers_except_skips_the_excluded_nodes_too() {
+    let membership = cluster("n1");
+    assert_eq!(
+        membership.peers_except(&["n2", "c1"]).collect::<Vec<_>>(),
+        ["n0", "n3"]
+    );
+}
+
+#[test]
+fn unknown_topology_neighbors_are_dropped_with_a_warning() {
+    let membership = cluster("n0");
+    let neighborhood = ["n1", "n9", "n3"].map(String::from).to_vec();
+    assert_eq!(
+        membership.known_neighborhood(neighborhood, false).unwrap(),
+        ["n1", "n3"]
+    );
+    assert!(recent_logs()
+        .iter()
+        .any(|line| line.ends_with("Ignoring unknown neighbors from topology: [\"n9\"]")));
+}
+
+#[test]
+fn topology_without_known_neighbors_fails_unless_allowed() {
+    let membership = cluster("n0");
+    let bogus = vec!["n9".to_string()];
+    assert!(membership.known_neighborhood(bogus.clone(), false).is_err());
+    assert!(membership
+        .known_neighborhood(bogus, true)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn duplicated_node_ids_are_dropped_with_a_warning() {
+    let node_ids = ["n0", "n1", "n0", "n2", "n1"].map(String::from).to_vec();
+    let membership = Membership::new("n2".to_string(), node_ids).unwrap();
+    assert_eq!(membership.node_ids(), ["n0", "n1", "n2"]);
+    assert_eq!(membership.peers().collect::<Vec<_>>(), ["n0", "n1"]);
+    let logs = recent_logs();
+    for duplicate in ["n0", "n1"] {
+        let warning = format!("Ignoring duplicated node id {} in membership", duplicate);
+        assert!(
+            logs.iter().any(|line| line.ends_with(&warning)),
+            "{:?}",
+            logs
+        );
+    }
+}
+
+#[test]
+fn node_missing_from_the_cluster_is_an_error() {
+    let node_ids = ["n0", "n1"].map(String::from).to_vec();
+    let err = Membership::new("n2".to_string(), node_ids).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Node n2 is not part of the cluster ["n0", "n1"]"#
+    );
+}
+
+#[test]
+fn only_cluster_nodes_get_internal_transformations() {
+    let membership = cluster("n0");
+    for node_id in ["n0", "n1", "n3"] {
+        assert!(membership.is_internal(node_id), "{}", node_id);
+    }
+    for dest in ["c1", "c12", "seq-kv", "lin-kv", "n9", "x"] {
+        assert!(!membership.is_internal(dest), "{}", dest);
+    }
+}
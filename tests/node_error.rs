@@ -0,0 +1,75 @@
+use std::error::Error;
+
+use distributed_systems::maelstrom::error::{ErrorReply, NodeError};
+use distributed_systems::maelstrom::seq_kv::SeqKvReply;
+use serde_json::json;
+
+#[test]
+fn known_codes_map_back_to_their_variant() {
+    let known = [
+        NodeError::Timeout,
+        NodeError::NodeNotFound,
+        NodeError::NotSupported,
+        NodeError::TemporarilyUnavailable,
+        NodeError::MalformedRequest,
+        NodeError::Crash,
+        NodeError::Abort,
+        NodeError::KeyDoesNotExist,
+        NodeError::KeyAlreadyExists,
+        NodeError::PreconditionFailed,
+        NodeError::TxnConflict,
+    ];
+    for error in known {
+        assert_eq!(NodeError::from_code(error.code()), error);
+    }
+    assert_eq!(NodeError::from_code(22), NodeError::PreconditionFailed);
+    assert_eq!(NodeError::from_code(1000), NodeError::Custom(1000));
+}
+
+#[test]
+fn incoming_error_body_yields_a_node_error() {
+    let reply: ErrorReply = serde_json::from_value(json!({
+        "type": "error", "in_reply_to": 7, "code": 22, "text": "expected 3, had 5",
+    }))
+    .unwrap();
+    assert_eq!(reply.code, NodeError::PreconditionFailed);
+    assert_eq!(reply.in_reply_to, Some(7));
+
+    let reply: ErrorReply = serde_json::from_value(json!({"code": 30})).unwrap();
+    assert_eq!(reply.code, NodeError::Custom(30));
+    assert_eq!(serde_json::to_value(&reply).unwrap(), json!({"code": 30}));
+}
+
+#[test]
+fn seq_kv_errors_are_typed() {
+    let reply: SeqKvReply = serde_json::from_value(json!({
+        "type": "error", "in_reply_to": 7, "code": 21, "text": "key already exists",
+    }))
+    .unwrap();
+    let SeqKvReply::Error(err) = reply else {
+        panic!("Not an error: {:?}", reply);
+    };
+    assert_eq!(err.node_error(), NodeError::KeyAlreadyExists);
+}
+
+#[test]
+fn display_names_the_error_and_its_code() {
+    assert_eq!(
+        NodeError::PreconditionFailed.to_string(),
+        "PreconditionFailed (code 22)"
+    );
+    let custom = NodeError::Custom(99).to_string();
+    assert!(custom.contains("99"), "{}", custom);
+    assert_eq!(NodeError::from_code(99).to_string(), custom);
+}
+
+fn abort() -> Result<(), Box<dyn Error>> {
+    Err(NodeError::Abort.into())
+}
+
+#[test]
+fn handlers_can_return_a_node_error() {
+    let err = abort().unwrap_err();
+    assert_eq!(err.to_string(), "Abort (code 14)");
+    assert_eq!(err.downcast_ref::<NodeError>(), Some(&NodeError::Abort));
+}
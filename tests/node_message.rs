@@ -0,0 +1,110 @@
+//! Checks the `NodeMessage` envelope shared by every workload.
+
+use distributed_systems::maelstrom::{IntoReply, NodeMessage};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+#[derive(Serialize)]
+struct PongBody {
+    #[serde(rename = "type")]
+    _type: String,
+    in_reply_to: u64,
+}
+
+impl IntoReply for PongBody {}
+
+#[test]
+fn unknown_top_level_fields_round_trip_through_a_reply() {
+    let request: NodeMessage<Value> = serde_json::from_value(json!({
+        "id": 12, "src": "c1", "dest": "n0", "body": {"type": "echo", "msg_id": 1},
+    }))
+    .unwrap();
+    assert_eq!(request.extra.get("id"), Some(&json!(12)));
+
+    let reply = NodeMessage::new(
+        request.dest.clone(),
+        request.src.clone(),
+        json!({"type": "echo_ok", "in_reply_to": 1}),
+    )
+    .with_extra(&request.extra);
+    assert_eq!(
+        serde_json::to_value(&reply).unwrap(),
+        json!({
+            "id": 12, "src": "n0", "dest": "c1", "body": {"type": "echo_ok", "in_reply_to": 1},
+        })
+    );
+}
+
+#[test]
+fn replies_leave_out_the_extra_fields_by_default() {
+    let reply = NodeMessage::new(
+        "n0".to_string(),
+        "c1".to_string(),
+        json!({"type": "echo_ok"}),
+    );
+    assert_eq!(
+        serde_json::to_value(&reply).unwrap(),
+        json!({"src": "n0", "dest": "c1", "body": {"type": "echo_ok"}})
+    );
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "NodeMessage built with an empty src")]
+fn empty_src_trips_the_debug_assertion() {
+    // What a node that sends before being initialized would build.
+    NodeMessage::new(String::new(), "c1".to_string(), json!({"type": "echo_ok"}));
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "NodeMessage built with an empty dest")]
+fn empty_dest_trips_the_debug_assertion() {
+    NodeMessage::new("n0".to_string(), String::new(), json!({"type": "echo_ok"}));
+}
+
+#[test]
+fn into_reply_addresses_the_sender() {
+    let request: NodeMessage<Value> = serde_json::from_value(json!({
+        "src": "c3", "dest": "n1", "body": {"type": "ping", "msg_id": 8},
+    }))
+    .unwrap();
+    let pong = PongBody {
+        _type: "pong".into(),
+        in_reply_to: request.body["msg_id"].as_u64().unwrap(),
+    };
+
+    let reply = pong.into_reply("n1", &request).into_outgoing().unwrap();
+    assert_eq!(reply.src, "n1");
+    assert_eq!(reply.dest, "c3");
+    assert_eq!(reply.body, json!({"type": "pong", "in_reply_to": 8}));
+}
+
+#[test]
+fn debug_line_is_a_compact_one_liner() {
+    let broadcast = NodeMessage::new(
+        "c1".to_string(),
+        "n0".to_string(),
+        json!({"type": "broadcast", "msg_id": 3, "message": 42}),
+    );
+    assert_eq!(
+        broadcast.debug_line(),
+        "c1->n0 type=broadcast msg_id=3 message=42"
+    );
+}
+
+#[test]
+fn debug_line_summarizes_collections_by_their_size() {
+    let read_ok = NodeMessage::new(
+        "n0".to_string(),
+        "c1".to_string(),
+        json!({"type": "read_ok", "in_reply_to": 4, "messages": [1, 2, 3], "extra": {"a": 1}}),
+    );
+    assert_eq!(
+        read_ok.debug_line(),
+        "n0->c1 type=read_ok in_reply_to=4 messages=[3] extra={1}"
+    );
+
+    let bare = NodeMessage::new("n0".to_string(), "n1".to_string(), json!(7));
+    assert_eq!(bare.debug_line(), "n0->n1 body=7");
+}
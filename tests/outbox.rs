@@ -0,0 +1,57 @@
+use distributed_systems::maelstrom::outbox::Outbox;
+use distributed_systems::maelstrom::NodeMessage;
+use serde_json::{json, Value};
+
+fn broadcast(dest: &str, value: u64) -> NodeMessage<Value> {
+    NodeMessage::new(
+        "n0".to_string(),
+        dest.to_string(),
+        json!({"type": "broadcast", "message": value}),
+    )
+}
+
+/// A handler reaching the same neighbor with the same value through two code paths, e.g. the
+/// broadcast fan-out and a read sync.
+fn handle_broadcast(outbox: &mut Outbox, value: u64) {
+    for neighbor in ["n1", "n2"] {
+        outbox.push(broadcast(neighbor, value)).unwrap();
+    }
+    outbox.push(broadcast("n1", value)).unwrap();
+}
+
+#[test]
+fn identical_messages_of_one_iteration_are_sent_once() {
+    let mut outbox = Outbox::new();
+    handle_broadcast(&mut outbox, 7);
+
+    let written: Vec<(String, Value)> = outbox
+        .take()
+        .into_iter()
+        .map(|msg| (msg.dest, msg.body["message"].clone()))
+        .collect();
+    assert_eq!(
+        written,
+        vec![("n1".to_string(), json!(7)), ("n2".to_string(), json!(7))]
+    );
+}
+
+#[test]
+fn messages_differing_in_any_field_are_all_sent() {
+    let mut outbox = Outbox::new();
+    assert!(outbox.push(broadcast("n1", 7)).unwrap());
+    assert!(outbox.push(broadcast("n1", 8)).unwrap());
+    assert!(outbox.push(broadcast("n2", 7)).unwrap());
+    assert!(!outbox.push(broadcast("n1", 8)).unwrap());
+    assert_eq!(outbox.len(), 3);
+}
+
+#[test]
+fn duplicates_are_only_dropped_within_an_iteration() {
+    let mut outbox = Outbox::new();
+    handle_broadcast(&mut outbox, 7);
+    assert_eq!(outbox.take().len(), 2);
+
+    // The next iteration may legitimately send the same message again, e.g. a retry.
+    handle_broadcast(&mut outbox, 7);
+    assert_eq!(outbox.take().len(), 2);
+}
@@ -0,0 +1,55 @@
+//! Checks the `pending_summary` query, which lists what a node is still waiting on.
+
+mod common;
+
+use common::{start_broadcast_hub, TestNode};
+use serde_json::json;
+
+#[test]
+fn counter_lists_the_unanswered_cas() {
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_g_counter"));
+    node.init("n0", &["n0", "n1", "n2"]);
+    node.send(
+        &json!({"src": "c1", "dest": "n0", "body": {"type": "add", "msg_id": 2, "delta": 7}}),
+    );
+    // seq-kv never answers the CAS.
+    let cas = node.recv_matching(|msg| msg["body"]["type"] == "cas");
+    node.send(
+        &json!({"src": "c1", "dest": "n0", "body": {"type": "pending_summary", "msg_id": 3}}),
+    );
+
+    let summary = node.recv_type("pending_summary_ok");
+    assert_eq!(summary["body"]["in_reply_to"], 3);
+    assert_eq!(
+        summary["body"]["pending"]["pending_cas"],
+        json!([[cas["body"]["msg_id"], 7]])
+    );
+}
+
+#[test]
+fn broadcast_lists_unacked_values_until_acked() {
+    let mut node = start_broadcast_hub(&[]);
+    node.send(&json!({"src": "c1", "dest": "n0", "body": {
+        "type": "broadcast", "msg_id": 10, "message": 42,
+    }}));
+    node.send(
+        &json!({"src": "c1", "dest": "n0", "body": {"type": "pending_summary", "msg_id": 11}}),
+    );
+    let summary = node.recv_type("pending_summary_ok");
+    assert_eq!(
+        summary["body"]["pending"]["unacked_broadcasts"]["n5"],
+        json!([42])
+    );
+
+    node.send(&json!({"src": "n5", "dest": "n0", "body": {
+        "type": "broadcast_ok", "acked_value": 42,
+    }}));
+    node.send(
+        &json!({"src": "c1", "dest": "n0", "body": {"type": "pending_summary", "msg_id": 12}}),
+    );
+    let summary = node.recv_type("pending_summary_ok");
+    assert_eq!(
+        summary["body"]["pending"]["unacked_broadcasts"]["n5"],
+        json!([])
+    );
+}
@@ -0,0 +1,65 @@
+//! Checks the outbound message rate guard enabled through `MAX_SENDS_PER_SEC_ENV`.
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::TestNode;
+use distributed_systems::maelstrom::rate_guard::{RateGuard, MAX_SENDS_PER_SEC_ENV};
+use serde_json::json;
+
+#[test]
+fn guard_fires_once_when_the_threshold_is_crossed() {
+    let mut guard = RateGuard::new(3);
+    let start = Instant::now();
+    // A runaway loop sending every millisecond.
+    let fired: Vec<bool> = (0..6)
+        .map(|tick| guard.record_at(start + Duration::from_millis(tick)))
+        .collect();
+    assert_eq!(fired, [false, false, false, true, false, false]);
+    assert_eq!(guard.count(), 6);
+
+    // A new window starts counting from scratch.
+    assert!(!guard.record_at(start + Duration::from_millis(1_000)));
+    assert_eq!(guard.count(), 1);
+}
+
+#[test]
+fn runaway_sends_are_logged() {
+    let mut node =
+        TestNode::start_with_env(env!("CARGO_BIN_EXE_echo"), &[(MAX_SENDS_PER_SEC_ENV, "2")]);
+    node.init("n0", &["n0"]);
+    for msg_id in 2..6 {
+        node.send(&json!({"src": "c1", "dest": "n0", "body": {
+            "type": "echo", "msg_id": msg_id, "echo": "again",
+        }}));
+    }
+    let (_, status) = node.finish();
+    assert!(status.success());
+
+    let warnings: Vec<String> = node
+        .stderr()
+        .lines()
+        .filter(|line| line.contains("WARNING: more than 2 messages sent"))
+        .map(String::from)
+        .collect();
+    assert_eq!(warnings.len(), 1, "{:?}", warnings);
+}
+
+#[test]
+fn invalid_rate_fails_the_node() {
+    let mut node = TestNode::start_with_env(
+        env!("CARGO_BIN_EXE_echo"),
+        &[(MAX_SENDS_PER_SEC_ENV, "many")],
+    );
+    node.init("n0", &["n0"]);
+    let status = node.wait();
+
+    assert!(!status.success());
+    let stderr = node.stderr();
+    assert!(
+        stderr.contains(&format!("Invalid {}=many", MAX_SENDS_PER_SEC_ENV)),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,52 @@
+//! Checks that the binaries reading their requests through `spawn_request_reader` skip the
+//! lines that are not valid requests and exit cleanly once stdin is closed.
+
+mod common;
+
+use common::TestNode;
+use serde_json::json;
+
+/// Write a bad line and then `request`, which must be answered with `reply_type`.
+fn bad_line_then(bin: &str, request: serde_json::Value, reply_type: &str) {
+    let mut node = TestNode::start(bin);
+    node.init("n0", &["n0"]);
+    node.write_line("not json");
+    node.write_line(r#"{"src": "c1", "dest": "n0", "body": {"type": "no_such_type"}}"#);
+    node.send(&request);
+
+    let reply = node.recv_matching(|msg| msg["dest"] == "c1");
+    assert_eq!(reply["body"]["type"], reply_type);
+    assert_eq!(reply["body"]["in_reply_to"], request["body"]["msg_id"]);
+    let (_, status) = node.finish();
+    assert!(status.success(), "{}", node.stderr());
+    assert!(!node.stderr().contains("panicked"), "{}", node.stderr());
+}
+
+#[test]
+fn kafka_processes_the_next_message() {
+    bad_line_then(
+        env!("CARGO_BIN_EXE_kafka"),
+        json!({"src": "c1", "dest": "n0", "body": {"type": "send", "msg_id": 2, "key": "k", "msg": 1}}),
+        "send_ok",
+    );
+}
+
+#[test]
+fn multi_node_kafka_processes_the_next_message() {
+    bad_line_then(
+        env!("CARGO_BIN_EXE_multi-node-kafka"),
+        json!({"src": "c1", "dest": "n0", "body": {"type": "send", "msg_id": 2, "key": "k", "msg": 1}}),
+        "send_ok",
+    );
+}
+
+#[test]
+fn broadcast_processes_the_next_message() {
+    bad_line_then(
+        env!("CARGO_BIN_EXE_performant_broadcast_final"),
+        json!({"src": "c1", "dest": "n0", "body": {
+            "type": "topology", "msg_id": 2, "topology": {"n0": []},
+        }}),
+        "topology_ok",
+    );
+}
@@ -0,0 +1,100 @@
+//! Checks that with `WAIT_FOR_SYNC_ENV` set, client requests reaching a fresh node are only
+//! answered after its initial sync, see `Readiness`.
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::TestNode;
+use distributed_systems::maelstrom::readiness::{Readiness, WAIT_FOR_SYNC_ENV};
+use serde_json::json;
+
+/// Longer than `READ_OK_WAIT_MS`, a read not held back would be answered by then.
+const HELD_FOR: Duration = Duration::from_millis(600);
+
+fn start(bin: &str) -> TestNode {
+    TestNode::start_with_env(bin, &[(WAIT_FOR_SYNC_ENV, "1")])
+}
+
+#[test]
+fn held_requests_are_released_in_order() {
+    let mut readiness = Readiness::new(true);
+    assert!(!readiness.is_ready());
+    assert_eq!(readiness.hold("read"), None);
+    assert_eq!(readiness.hold("add"), None);
+    assert_eq!(readiness.held(), 2);
+
+    assert_eq!(readiness.mark_ready(), vec!["read", "add"]);
+    assert!(readiness.is_ready());
+    assert_eq!(readiness.hold("read"), Some("read"));
+    assert_eq!(readiness.held(), 0);
+
+    assert!(Readiness::<()>::new(false).is_ready());
+}
+
+#[test]
+fn counter_read_waits_for_the_initial_kv_read() {
+    let mut node = start(env!("CARGO_BIN_EXE_g_counter"));
+    node.send(&json!({"src": "c0", "dest": "n0", "body": {
+        "type": "init", "msg_id": 1, "node_id": "n0", "node_ids": ["n0"],
+    }}));
+    node.send(&json!({"src": "c1", "dest": "n0", "body": {"type": "read", "msg_id": 2}}));
+    assert_eq!(node.recv()["body"]["type"], "init_ok");
+
+    let kv_read = node.recv();
+    assert_eq!(kv_read["dest"], "seq-kv");
+    assert_eq!(kv_read["body"]["type"], "read");
+    // Whatever the node sends until seq-kv answers, it is not our read_ok.
+    let held_until = Instant::now() + HELD_FOR;
+    while let Some(left) = held_until.checked_duration_since(Instant::now()) {
+        if let Some(msg) = node.try_recv(left) {
+            assert_eq!(msg["dest"], "seq-kv", "{}", msg);
+        }
+    }
+
+    node.send(&json!({"src": "seq-kv", "dest": "n0", "body": {
+        "type": "read_ok", "value": 42, "in_reply_to": kv_read["body"]["msg_id"],
+    }}));
+    let read_ok = loop {
+        let msg = node.recv();
+        if msg["dest"] == "c1" {
+            break msg;
+        }
+    };
+
+    assert_eq!(read_ok["body"]["type"], "read_ok");
+    assert_eq!(read_ok["body"]["in_reply_to"], 2);
+    assert_eq!(read_ok["body"]["value"], 42);
+}
+
+#[test]
+fn broadcast_read_waits_for_the_neighbors() {
+    let mut node = start(env!("CARGO_BIN_EXE_performant_broadcast_final"));
+    node.send(&json!({"src": "c0", "dest": "n0", "body": {
+        "type": "init", "msg_id": 1, "node_id": "n0", "node_ids": ["n0", "n1"],
+    }}));
+    node.send(&json!({"src": "c0", "dest": "n0", "body": {
+        "type": "topology", "msg_id": 2, "topology": {"n0": ["n1"], "n1": ["n0"]},
+    }}));
+    node.send(&json!({"src": "c1", "dest": "n0", "body": {"type": "read", "msg_id": 3}}));
+    assert_eq!(node.recv()["body"]["type"], "init_ok");
+    assert_eq!(node.recv()["body"]["type"], "topology_ok");
+    let sync_read = node.recv();
+    assert_eq!(sync_read["dest"], "n1");
+    assert_eq!(sync_read["body"]["type"], "read");
+
+    node.send(&json!({"src": "n1", "dest": "n0", "body": {
+        "type": "read_ok", "messages": [5, 6], "version": 2,
+    }}));
+    // Closing stdin answers the deferred reads right away.
+    node.close_stdin();
+    let read_ok = loop {
+        let msg = node.recv();
+        if msg["dest"] == "c1" {
+            break msg;
+        }
+    };
+
+    assert_eq!(read_ok["body"]["in_reply_to"], 3);
+    assert_eq!(read_ok["body"]["messages"], json!([5, 6]));
+}
@@ -0,0 +1,25 @@
+//! Checks the recent logs ring buffer. The buffer is global to the process, so this file holds
+//! a single test.
+
+use distributed_systems::logging::{log_line, recent_logs, RECENT_LOGS_CAPACITY};
+
+#[test]
+fn oldest_lines_are_evicted_at_capacity() {
+    for line in 0..RECENT_LOGS_CAPACITY {
+        log_line(format!("line {}", line));
+    }
+    let logs = recent_logs();
+    assert_eq!(logs.len(), RECENT_LOGS_CAPACITY);
+    assert_eq!(logs[0], "line 0");
+
+    for line in RECENT_LOGS_CAPACITY..RECENT_LOGS_CAPACITY + 10 {
+        log_line(format!("line {}", line));
+    }
+    let logs = recent_logs();
+    assert_eq!(logs.len(), RECENT_LOGS_CAPACITY);
+    assert_eq!(logs[0], "line 10");
+    assert_eq!(
+        logs.last().unwrap(),
+        &format!("line {}", RECENT_LOGS_CAPACITY + 9)
+    );
+}
@@ -0,0 +1,50 @@
+//! Checks `replay_window`, which replays only the part of a captured log in a time window.
+
+use std::error::Error;
+
+use distributed_systems::maelstrom::replay::{replay, replay_window};
+use distributed_systems::maelstrom::{MaelstromNode, NodeMessage};
+use serde_json::Value;
+
+/// Records the `msg` of every message it handles.
+#[derive(Default)]
+struct Recorder {
+    handled: Vec<u64>,
+}
+
+impl MaelstromNode for Recorder {
+    type MessageBody = Value;
+
+    fn initialize(&mut self, _node_id: String, _node_ids: Vec<String>) {}
+
+    fn handle_message(&mut self, msg: NodeMessage<Value>) -> Result<(), Box<dyn Error>> {
+        self.handled.push(msg.body["msg"].as_u64().unwrap());
+        Ok(())
+    }
+}
+
+const CAPTURE: &str = r#"
+{"src":"c1","dest":"n0","body":{"type":"send","msg":0}}
+1700000000.100 {"src":"c1","dest":"n0","body":{"type":"send","msg":1}}
+1700000000.200 {"src":"c1","dest":"n0","body":{"type":"send","msg":2}}
+{"src":"c1","dest":"n0","body":{"type":"send","msg":3}}
+1700000000.300 {"src":"c1","dest":"n0","body":{"type":"send","msg":4}}
+1700000000.400 {"src":"c1","dest":"n0","body":{"type":"send","msg":5}}
+"#;
+
+#[test]
+fn only_the_messages_in_the_window_are_handled() {
+    let mut node = Recorder::default();
+    let handled = replay_window(&mut node, CAPTURE.as_bytes(), 1700000000.2, 1700000000.3).unwrap();
+
+    // The line without a timestamp happened at the same time as the one before it.
+    assert_eq!(node.handled, [2, 3, 4]);
+    assert_eq!(handled, 3);
+}
+
+#[test]
+fn full_replay_handles_everything() {
+    let mut node = Recorder::default();
+    assert_eq!(replay(&mut node, CAPTURE.as_bytes()).unwrap(), 6);
+    assert_eq!(node.handled, [0, 1, 2, 3, 4, 5]);
+}
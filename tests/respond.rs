@@ -0,0 +1,129 @@
+mod common;
+
+use common::TestNode;
+use distributed_systems::maelstrom::harness::Harness;
+use distributed_systems::prelude::*;
+use serde_json::{json, Value};
+
+/// Answers every ping with a pong and tells `n1` about it.
+struct PingNode {
+    node_id: String,
+}
+
+impl MaelstromNode for PingNode {
+    type MessageBody = Value;
+
+    fn initialize(&mut self, node_id: String, _node_ids: Vec<String>) {
+        self.node_id = node_id;
+    }
+
+    fn respond(
+        &mut self,
+        msg: NodeMessage<Value>,
+    ) -> Result<Vec<OutgoingMessage>, Box<dyn std::error::Error>> {
+        let pong = NodeMessage::new(
+            self.node_id.clone(),
+            msg.src.clone(),
+            json!({"type": "pong", "in_reply_to": msg.body["msg_id"]}),
+        );
+        let notice = NodeMessage::new(
+            self.node_id.clone(),
+            "n1".to_string(),
+            json!({"type": "pinged", "by": msg.src}),
+        );
+        Ok(vec![pong.into_outgoing()?, notice.into_outgoing()?])
+    }
+}
+
+/// Still writes its replies itself.
+struct WritingNode {
+    handled: usize,
+}
+
+impl MaelstromNode for WritingNode {
+    type MessageBody = Value;
+
+    fn initialize(&mut self, _node_id: String, _node_ids: Vec<String>) {}
+
+    fn handle_message(
+        &mut self,
+        _msg: NodeMessage<Value>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.handled += 1;
+        Ok(())
+    }
+}
+
+fn ping() -> NodeMessage<Value> {
+    NodeMessage::new(
+        "c1".to_string(),
+        "n0".to_string(),
+        json!({"type": "ping", "msg_id": 4}),
+    )
+}
+
+#[test]
+fn respond_returns_the_messages_in_order() {
+    let mut node = PingNode {
+        node_id: String::new(),
+    };
+    node.initialize("n0".to_string(), vec!["n0".to_string(), "n1".to_string()]);
+
+    let outgoing = node.respond(ping()).unwrap();
+    let sent: Vec<(&str, &Value)> = outgoing
+        .iter()
+        .map(|msg| (msg.dest.as_str(), &msg.body))
+        .collect();
+    assert_eq!(
+        sent,
+        vec![
+            ("c1", &json!({"type": "pong", "in_reply_to": 4})),
+            ("n1", &json!({"type": "pinged", "by": "c1"})),
+        ]
+    );
+}
+
+#[test]
+fn nodes_writing_their_own_messages_return_nothing() {
+    let mut node = WritingNode { handled: 0 };
+    assert!(node.respond(ping()).unwrap().is_empty());
+    assert_eq!(node.handled, 1);
+}
+
+#[test]
+fn harness_returns_the_messages_a_node_responds_with() {
+    let nodes = ["n0", "n1"]
+        .iter()
+        .map(|node_id| {
+            let node = PingNode {
+                node_id: String::new(),
+            };
+            (node_id.to_string(), node)
+        })
+        .collect();
+    let mut harness = Harness::new(nodes);
+    harness.init_all();
+
+    let outgoing = harness.deliver(ping()).unwrap();
+    assert_eq!(outgoing.len(), 2);
+    assert_eq!(outgoing[0].src, "n0");
+    assert_eq!(outgoing[0].dest, "c1");
+    assert_eq!(outgoing[1].dest, "n1");
+}
+
+#[test]
+fn event_loop_writes_the_returned_messages() {
+    let mut node = TestNode::start(env!("CARGO_BIN_EXE_generate"));
+    node.init("n0", &["n0"]);
+    for msg_id in 2..=3 {
+        let body = json!({"type": "generate", "msg_id": msg_id});
+        node.send(&json!({"src": "c1", "dest": "n0", "body": body}));
+    }
+    let (replies, status) = node.finish();
+    assert!(status.success());
+
+    assert_eq!(replies.len(), 3);
+    assert_eq!(replies[1]["body"]["in_reply_to"], 2);
+    assert_eq!(replies[2]["body"]["in_reply_to"], 3);
+    assert_ne!(replies[1]["body"]["id"], replies[2]["body"]["id"]);
+}
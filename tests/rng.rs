@@ -0,0 +1,51 @@
+//! Checks that `Rng` makes the same choices given the same seed.
+
+use distributed_systems::maelstrom::rng::{Rng, SEED_ENV};
+
+fn peer_choices(seed: u64) -> Vec<&'static str> {
+    let peers = ["n1", "n2", "n3", "n4", "n5"];
+    let mut rng = Rng::new(seed);
+    (0..20).map(|_| *rng.choose(&peers).unwrap()).collect()
+}
+
+#[test]
+fn same_seed_gives_the_same_sequence() {
+    assert_eq!(peer_choices(42), peer_choices(42));
+
+    let mut left = Rng::new(7);
+    let mut right = Rng::new(7);
+    let mut left_items: Vec<u32> = (0..10).collect();
+    let mut right_items = left_items.clone();
+    left.shuffle(&mut left_items);
+    right.shuffle(&mut right_items);
+    assert_eq!(left_items, right_items);
+    assert_eq!(left.next_u64(), right.next_u64());
+}
+
+#[test]
+fn other_seed_gives_another_sequence() {
+    assert_ne!(peer_choices(42), peer_choices(43));
+}
+
+#[test]
+fn below_stays_in_bounds() {
+    let mut rng = Rng::new(1);
+    assert!((0..1000).all(|_| rng.below(3) < 3));
+    assert_eq!(rng.choose::<u8>(&[]), None);
+}
+
+#[test]
+fn seed_comes_from_the_env() {
+    // The only test of this binary reading `SEED_ENV`.
+    std::env::set_var(SEED_ENV, "42");
+    assert_eq!(Rng::from_env().unwrap().seed(), 42);
+
+    std::env::set_var(SEED_ENV, "forty-two");
+    let err = Rng::from_env().unwrap_err().to_string();
+    assert!(
+        err.starts_with("Invalid MAELSTROM_SEED=forty-two"),
+        "{}",
+        err
+    );
+    std::env::remove_var(SEED_ENV);
+}
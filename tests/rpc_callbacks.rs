@@ -0,0 +1,78 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use distributed_systems::maelstrom::rpc::Node;
+use distributed_systems::maelstrom::NodeMessage;
+use serde_json::{json, Value};
+
+fn reply(in_reply_to: u64) -> NodeMessage<Value> {
+    NodeMessage::new(
+        "n1".to_string(),
+        "n0".to_string(),
+        json!({"type": "broadcast_ok", "in_reply_to": in_reply_to}),
+    )
+}
+
+#[test]
+fn replies_go_to_the_callback_of_their_request() {
+    let mut node = Node::new("n0", 1000);
+    let replies = Rc::new(RefCell::new(vec![]));
+
+    let mut msg_ids = vec![];
+    for value in [10, 11] {
+        let seen = replies.clone();
+        let request = node
+            .rpc(
+                "n1",
+                json!({"type": "broadcast", "message": value}),
+                move |reply| {
+                    seen.borrow_mut()
+                        .push((value, reply.body["in_reply_to"].clone()))
+                },
+            )
+            .unwrap();
+        assert_eq!(request.src, "n0");
+        assert_eq!(request.dest, "n1");
+        msg_ids.push(request.body["msg_id"].as_u64().unwrap());
+    }
+    assert_eq!(msg_ids, vec![1, 2]);
+    assert_eq!(node.pending_replies(), 2);
+
+    assert!(node.dispatch_reply(reply(2)).is_none());
+    assert!(node.dispatch_reply(reply(1)).is_none());
+    assert_eq!(*replies.borrow(), vec![(11, json!(2)), (10, json!(1))]);
+    assert_eq!(node.pending_replies(), 0);
+
+    // Nobody waits on a second reply, nor on messages that are not replies.
+    assert!(node.dispatch_reply(reply(1)).is_some());
+    let request = NodeMessage::new("c1".to_string(), "n0".to_string(), json!({"type": "read"}));
+    assert!(node.dispatch_reply(request).is_some());
+}
+
+#[test]
+fn callbacks_without_a_reply_expire() {
+    let mut node = Node::new("n0", 10);
+    let called = Rc::new(RefCell::new(false));
+    let seen = called.clone();
+    node.rpc("n1", json!({"type": "read"}), move |_| {
+        *seen.borrow_mut() = true
+    })
+    .unwrap();
+
+    assert!(node.expire_callbacks().is_empty());
+    std::thread::sleep(Duration::from_millis(20));
+    assert_eq!(node.expire_callbacks(), vec![1]);
+    assert_eq!(node.pending_replies(), 0);
+
+    // A late reply is handled as any other message.
+    assert!(node.dispatch_reply(reply(1)).is_some());
+    assert!(!*called.borrow());
+}
+
+#[test]
+fn rpc_bodies_must_be_objects() {
+    let mut node = Node::new("n0", 1000);
+    assert!(node.rpc("n1", 42, |_| {}).is_err());
+    assert_eq!(node.pending_replies(), 0);
+}
@@ -0,0 +1,148 @@
+use std::cell::RefCell;
+use std::error::Error;
+use std::rc::Rc;
+
+use distributed_systems::maelstrom::error::NodeError;
+use distributed_systems::maelstrom::kv_service::{InMemoryKvService, KvService};
+use distributed_systems::maelstrom::seq_kv::{SeqKVCompareAndSwapRequest, SeqKvReply};
+use distributed_systems::maelstrom::seq_kv_client::{KvOp, SeqKvClient};
+use serde_json::json;
+
+/// Answers nothing right away, like a real KV peer, and records the msg_id of every request.
+#[derive(Clone, Default)]
+struct DeferredKv {
+    sent: Rc<RefCell<Vec<u64>>>,
+}
+
+impl DeferredKv {
+    fn record(&self, msg_id: Option<u64>) -> Result<Option<SeqKvReply>, Box<dyn Error>> {
+        self.sent
+            .borrow_mut()
+            .push(msg_id.ok_or("request without a msg_id")?);
+        Ok(None)
+    }
+}
+
+impl KvService for DeferredKv {
+    fn read(
+        &mut self,
+        _src: &str,
+        _key: &str,
+        msg_id: Option<u64>,
+    ) -> Result<Option<SeqKvReply>, Box<dyn Error>> {
+        self.record(msg_id)
+    }
+
+    fn write(
+        &mut self,
+        _src: &str,
+        _key: &str,
+        _value: u64,
+        msg_id: Option<u64>,
+    ) -> Result<Option<SeqKvReply>, Box<dyn Error>> {
+        self.record(msg_id)
+    }
+
+    fn cas(
+        &mut self,
+        _src: &str,
+        request: SeqKVCompareAndSwapRequest,
+    ) -> Result<Option<SeqKvReply>, Box<dyn Error>> {
+        self.record(request.msg_id)
+    }
+}
+
+fn reply(body: serde_json::Value) -> SeqKvReply {
+    serde_json::from_value(body).unwrap()
+}
+
+#[test]
+fn immediate_replies_complete_right_away() {
+    let mut kv: SeqKvClient<&str> =
+        SeqKvClient::new("n0", Box::new(InMemoryKvService::new()), 1000);
+
+    let missing = kv.read("sum", "first read").unwrap().unwrap();
+    assert_eq!(missing.context, "first read");
+    assert_eq!(missing.result, Err(NodeError::KeyDoesNotExist));
+
+    let created = kv
+        .compare_and_swap("sum", None, 3, true, "create")
+        .unwrap()
+        .unwrap();
+    assert_eq!(created.result, Ok(None));
+
+    let conflict = kv
+        .compare_and_swap("sum", Some(1), 5, true, "stale cas")
+        .unwrap()
+        .unwrap();
+    assert_eq!(conflict.context, "stale cas");
+    assert_eq!(conflict.result, Err(NodeError::PreconditionFailed));
+
+    kv.write("other", 9, "write").unwrap().unwrap();
+    let read = kv.read("sum", "read").unwrap().unwrap();
+    assert_eq!(read.result, Ok(Some(3)));
+    assert_eq!(
+        read.op,
+        KvOp::Read {
+            key: "sum".to_string()
+        }
+    );
+    assert!(kv.is_empty());
+}
+
+#[test]
+fn replies_complete_the_request_they_answer() {
+    let service = DeferredKv::default();
+    let mut kv: SeqKvClient<(String, u64)> =
+        SeqKvClient::new("n0", Box::new(service.clone()), 1000);
+
+    assert!(kv.read("sum", ("c1".to_string(), 4)).unwrap().is_none());
+    assert!(kv
+        .compare_and_swap("sum", Some(3), 8, false, ("c2".to_string(), 5))
+        .unwrap()
+        .is_none());
+    let sent = service.sent.borrow().clone();
+    assert_eq!(sent.len(), 2);
+    assert_ne!(sent[0], sent[1]);
+    assert_eq!(kv.len(), 2);
+
+    let cas_ok = kv
+        .handle_response(reply(json!({"type": "cas_ok", "in_reply_to": sent[1]})))
+        .unwrap();
+    assert_eq!(cas_ok.msg_id, sent[1]);
+    assert_eq!(cas_ok.context, ("c2".to_string(), 5));
+    assert_eq!(cas_ok.result, Ok(None));
+
+    let read_ok = kv
+        .handle_response(reply(
+            json!({"type": "read_ok", "value": 3, "in_reply_to": sent[0]}),
+        ))
+        .unwrap();
+    assert_eq!(read_ok.context, ("c1".to_string(), 4));
+    assert_eq!(read_ok.result, Ok(Some(3)));
+
+    // Already completed, or never sent by us.
+    let again = json!({"type": "read_ok", "value": 3, "in_reply_to": sent[0]});
+    assert!(kv.handle_response(reply(again)).is_none());
+    let unrelated = json!({"type": "read_ok", "value": 3});
+    assert!(kv.handle_response(reply(unrelated)).is_none());
+    assert!(kv.is_empty());
+}
+
+#[test]
+fn unanswered_requests_expire() {
+    let service = DeferredKv::default();
+    let mut kv: SeqKvClient<u64> = SeqKvClient::new("n0", Box::new(service.clone()), 0);
+    kv.write("sum", 7, 42).unwrap();
+    assert_eq!(kv.pending().count(), 1);
+
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    let expired = kv.expired();
+    assert_eq!(expired.len(), 1);
+    assert_eq!(expired[0].0, service.sent.borrow()[0]);
+    assert_eq!(expired[0].1.context, 42);
+    assert!(kv.is_empty());
+
+    let late = json!({"type": "error", "code": 0, "in_reply_to": expired[0].0});
+    assert!(kv.handle_response(reply(late)).is_none());
+}
@@ -0,0 +1,79 @@
+//! Checks the KV messages with values other than integers, here lists as list-append style
+//! workloads store them.
+
+use std::collections::HashMap;
+
+use distributed_systems::maelstrom::seq_kv::*;
+use serde_json::{json, Value};
+
+/// A KV store keeping lists, answering the JSON requests a node would send it.
+fn serve(store: &mut HashMap<String, Vec<u64>>, request: Value) -> Value {
+    let reply: SeqKvReply<Vec<u64>> = match serde_json::from_value(request).unwrap() {
+        SeqKVRequest::Write(write) => {
+            store.insert(write.key, write.value);
+            SeqKvReply::WriteOk(SeqKVNoDataResponse {
+                in_reply_to: write.msg_id,
+                msg_id: None,
+            })
+        }
+        SeqKVRequest::Read(read) => SeqKvReply::ReadOk(SeqKVReadResponse {
+            in_reply_to: read.msg_id,
+            msg_id: None,
+            value: store[&read.key].clone(),
+        }),
+        SeqKVRequest::CompareAndSwap(cas) if store.get(&cas.key) == cas.from.as_ref() => {
+            store.insert(cas.key, cas.to.unwrap());
+            SeqKvReply::CasOk(SeqKVNoDataResponse {
+                in_reply_to: cas.msg_id,
+                msg_id: None,
+            })
+        }
+        request => panic!("Unexpected request {:?}", request),
+    };
+    serde_json::to_value(reply).unwrap()
+}
+
+#[test]
+fn list_values_round_trip() {
+    let mut store = HashMap::new();
+    let write = SeqKVRequest::Write(SeqKVWriteRequest {
+        in_reply_to: None,
+        msg_id: Some(1),
+        key: "log".to_string(),
+        value: vec![1, 2],
+    });
+    let write = serde_json::to_value(write).unwrap();
+    assert_eq!(write["value"], json!([1, 2]));
+    assert_eq!(serve(&mut store, write)["type"], "write_ok");
+
+    let append = SeqKVRequest::CompareAndSwap(SeqKVCompareAndSwapRequest {
+        in_reply_to: None,
+        msg_id: Some(2),
+        key: "log".to_string(),
+        from: Some(vec![1, 2]),
+        to: Some(vec![1, 2, 3]),
+        create_if_not_exists: false,
+    });
+    let cas_ok = serve(&mut store, serde_json::to_value(append).unwrap());
+    assert_eq!(cas_ok["type"], "cas_ok");
+
+    let read = json!({"type": "read", "msg_id": 3, "key": "log"});
+    let reply: SeqKvReply<Vec<u64>> = serde_json::from_value(serve(&mut store, read)).unwrap();
+    match reply {
+        SeqKvReply::ReadOk(read_ok) => {
+            assert_eq!(read_ok.in_reply_to, Some(3));
+            assert_eq!(read_ok.value, vec![1, 2, 3]);
+        }
+        reply => panic!("Expected a read_ok, got {:?}", reply),
+    }
+}
+
+#[test]
+fn values_default_to_integers() {
+    let reply: SeqKvReply =
+        serde_json::from_value(json!({"type": "read_ok", "value": 7, "in_reply_to": 1})).unwrap();
+    assert!(matches!(reply, SeqKvReply::ReadOk(read_ok) if read_ok.value == 7));
+
+    let list = json!({"type": "read_ok", "value": [7], "in_reply_to": 1});
+    assert!(serde_json::from_value::<SeqKvReply>(list).is_err());
+}
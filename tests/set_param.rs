@@ -0,0 +1,78 @@
+//! Checks that a `set_param` control message changes how often `performant_broadcast_final`
+//! retries an unacknowledged broadcast, and that it is ignored without the `control` feature.
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{start_broadcast_hub, TestNode, REPLY_TIMEOUT};
+use serde_json::{json, Value};
+
+/// `WAIT_TIME` of the binary.
+#[cfg(not(feature = "control"))]
+const DEFAULT_WAIT: Duration = Duration::from_millis(120);
+const NEW_WAIT: Duration = Duration::from_millis(400);
+/// Slack for the time the sends take to reach the test.
+const TOLERANCE: Duration = Duration::from_millis(50);
+
+#[cfg(feature = "control")]
+#[test]
+fn set_param_slows_down_the_retries() {
+    let (gaps, replies) = retry_gaps_after_set_param();
+    assert_eq!(replies.len(), 1, "Expected one set_param_ok: {:?}", replies);
+    assert_eq!(replies[0]["body"]["in_reply_to"], 3);
+    for gap in gaps {
+        assert!(gap >= NEW_WAIT - TOLERANCE, "Retried after {:?}", gap);
+    }
+}
+
+#[cfg(not(feature = "control"))]
+#[test]
+fn set_param_is_ignored_without_the_control_feature() {
+    let (gaps, replies) = retry_gaps_after_set_param();
+    assert!(replies.is_empty(), "Unexpected replies: {:?}", replies);
+    for gap in gaps {
+        assert!(gap >= DEFAULT_WAIT - TOLERANCE, "Retried after {:?}", gap);
+        assert!(gap < NEW_WAIT - TOLERANCE, "Retried after {:?}", gap);
+    }
+}
+
+/// Set `wait_ms` to `NEW_WAIT`, broadcast a value n5 never acknowledges and measure the time
+/// between the first sends to n5. Also returns the replies to the set_param.
+fn retry_gaps_after_set_param() -> (Vec<Duration>, Vec<Value>) {
+    let mut node = start_broadcast_hub(&[]);
+    send(
+        &mut node,
+        json!({"type": "set_param", "msg_id": 3, "name": "wait_ms", "value": NEW_WAIT.as_millis() as u64}),
+    );
+    send(
+        &mut node,
+        json!({"type": "broadcast", "msg_id": 4, "message": 42}),
+    );
+
+    let mut replies = vec![];
+    let sends: Vec<Instant> = (0..3)
+        .map(|_| next_broadcast_to_n5(&node, &mut replies))
+        .collect();
+
+    let gaps = sends.windows(2).map(|pair| pair[1] - pair[0]).collect();
+    (gaps, replies)
+}
+
+fn send(node: &mut TestNode, body: Value) {
+    node.send(&json!({"src": "c0", "dest": "n0", "body": body}));
+}
+
+/// When the next broadcast to n5 was written, collecting the set_param replies seen meanwhile.
+fn next_broadcast_to_n5(node: &TestNode, replies: &mut Vec<Value>) -> Instant {
+    loop {
+        let (at, msg) = node
+            .try_recv_at(REPLY_TIMEOUT)
+            .expect("Timed out waiting for a broadcast to n5");
+        if msg["body"]["type"] == "set_param_ok" || msg["body"]["in_reply_to"] == 3 {
+            replies.push(msg);
+        } else if msg["dest"] == "n5" && msg["body"]["type"] == "broadcast" {
+            return at;
+        }
+    }
+}
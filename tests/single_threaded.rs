@@ -0,0 +1,41 @@
+//! Checks the single-threaded event loop selected through `SINGLE_THREADED_ENV`, through the
+//! `echo` binary.
+
+mod common;
+
+use common::TestNode;
+use distributed_systems::maelstrom::SINGLE_THREADED_ENV;
+use serde_json::{json, Value};
+
+#[test]
+fn messages_are_handled_in_stdin_order() {
+    let mut node =
+        TestNode::start_with_env(env!("CARGO_BIN_EXE_echo"), &[(SINGLE_THREADED_ENV, "1")]);
+    node.init("n0", &["n0"]);
+    let echoes: Vec<Value> = (0..100)
+        .map(|index| {
+            // Alternate the clients, so ordering per source would not be enough.
+            json!({"src": format!("c{}", index % 3), "dest": "n0", "body": {
+                "type": "echo", "msg_id": index, "echo": format!("echo {}", index),
+            }})
+        })
+        .collect();
+    node.send_all(&echoes);
+    let (emitted, status) = node.finish();
+
+    assert!(status.success(), "{}", node.stderr());
+    assert_eq!(emitted[0]["body"]["type"], "init_ok");
+    let replies: Vec<(Value, Value)> = emitted[1..]
+        .iter()
+        .map(|msg| {
+            (
+                msg["body"]["in_reply_to"].clone(),
+                msg["body"]["echo"].clone(),
+            )
+        })
+        .collect();
+    let expected: Vec<(Value, Value)> = (0..100)
+        .map(|index| (json!(index), json!(format!("echo {}", index))))
+        .collect();
+    assert_eq!(replies, expected);
+}
@@ -0,0 +1,34 @@
+use std::collections::HashSet;
+
+use distributed_systems::broadcast::{SnapshotSet, ValueSet};
+
+#[test]
+fn snapshot_is_reused_until_a_value_is_added() {
+    let mut values = SnapshotSet::new(HashSet::from([3, 1]));
+    assert_eq!(values.snapshot(), &[1, 3]);
+    assert_eq!(values.snapshot(), &[1, 3]);
+    assert_eq!(values.rebuilds(), 1);
+
+    // A value already in the set leaves the snapshot as it is.
+    assert!(!values.apply_add(3));
+    assert_eq!(values.snapshot(), &[1, 3]);
+    assert_eq!(values.rebuilds(), 1);
+
+    assert!(values.apply_add(2));
+    assert_eq!(values.snapshot(), &[1, 2, 3]);
+    assert_eq!(values.rebuilds(), 2);
+}
+
+#[test]
+fn snapshot_reflects_merged_values() {
+    let mut values = SnapshotSet::new(HashSet::from([1]));
+    assert_eq!(values.snapshot(), &[1]);
+
+    values.merge(&SnapshotSet::new(HashSet::from([1])));
+    assert_eq!(values.snapshot(), &[1]);
+    assert_eq!(values.rebuilds(), 1);
+
+    values.merge(&SnapshotSet::new(HashSet::from([5, 4])));
+    assert_eq!(values.snapshot(), &[1, 4, 5]);
+    assert_eq!(values.rebuilds(), 2);
+}
@@ -0,0 +1,130 @@
+//! Checks `UnixSocketTransport` with two `broadcast` processes wired together through Unix
+//! sockets, the test routing their messages like `examples/socket_router.rs` does.
+
+mod common;
+
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::REPLY_TIMEOUT;
+use distributed_systems::maelstrom::transport::SOCKET_PATH_ENV;
+use serde_json::{json, Value};
+
+/// A node process connected to the test through its socket. It is killed when dropped.
+struct SocketNode {
+    child: Child,
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+}
+
+impl SocketNode {
+    fn send(&mut self, msg: &Value) {
+        self.writer
+            .write_all(format!("{}\n", msg).as_bytes())
+            .unwrap();
+    }
+
+    fn recv(&mut self) -> Value {
+        let mut line = String::new();
+        let read = self.reader.read_line(&mut line).unwrap();
+        assert!(read > 0, "Node closed its socket");
+        serde_json::from_str(&line).unwrap()
+    }
+
+    /// Read until a message of type `_type` comes, returning it with everything read before it.
+    fn recv_until(&mut self, _type: &str) -> (Value, Vec<Value>) {
+        let mut before = vec![];
+        loop {
+            let msg = self.recv();
+            if msg["body"]["type"] == _type {
+                return (msg, before);
+            }
+            before.push(msg);
+        }
+    }
+}
+
+impl Drop for SocketNode {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Start `count` nodes of `bin` connecting to a socket of the test, in the order they connect.
+fn start_nodes(bin: &str, count: usize) -> Vec<SocketNode> {
+    let socket_path =
+        std::env::temp_dir().join(format!("socket_transport_{}.sock", std::process::id()));
+    let listener = UnixListener::bind(&socket_path).unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let mut children: Vec<Child> = (0..count)
+        .map(|_| {
+            Command::new(bin)
+                .env(SOCKET_PATH_ENV, &socket_path)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+                .unwrap()
+        })
+        .collect();
+
+    let deadline = Instant::now() + REPLY_TIMEOUT;
+    let mut streams = vec![];
+    while streams.len() < count {
+        match listener.accept() {
+            Ok((stream, _)) => streams.push(stream),
+            Err(err) if err.kind() == ErrorKind::WouldBlock && Instant::now() < deadline => {
+                thread::sleep(Duration::from_millis(10));
+            }
+            Err(err) => panic!("Nodes did not connect: {}", err),
+        }
+    }
+    std::fs::remove_file(&socket_path).unwrap();
+
+    streams
+        .into_iter()
+        .map(|stream| {
+            stream.set_nonblocking(false).unwrap();
+            stream.set_read_timeout(Some(REPLY_TIMEOUT)).unwrap();
+            SocketNode {
+                child: children.remove(0),
+                reader: BufReader::new(stream.try_clone().unwrap()),
+                writer: stream,
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn broadcast_crosses_between_two_processes() {
+    let mut nodes = start_nodes(env!("CARGO_BIN_EXE_broadcast"), 2);
+    let topology = json!({"n0": ["n1"], "n1": ["n0"]});
+    for (index, node) in nodes.iter_mut().enumerate() {
+        let node_id = format!("n{index}");
+        node.send(&json!({"src": "c0", "dest": node_id, "body": {
+            "type": "init", "msg_id": 1, "node_id": node_id, "node_ids": ["n0", "n1"],
+        }}));
+        assert_eq!(node.recv()["body"]["type"], "init_ok");
+        node.send(&json!({"src": "c0", "dest": node_id, "body": {
+            "type": "topology", "msg_id": 2, "topology": topology,
+        }}));
+        node.recv_until("topology_ok");
+    }
+
+    nodes[0].send(&json!({"src": "c1", "dest": "n0", "body": {
+        "type": "broadcast", "msg_id": 3, "message": 42,
+    }}));
+    let (forward, before) = nodes[0].recv_until("broadcast");
+    assert_eq!(before[0]["body"]["type"], "broadcast_ok");
+    assert_eq!(before[0]["dest"], "c1");
+    assert_eq!(forward["dest"], "n1");
+    nodes[1].send(&forward);
+
+    nodes[1].send(&json!({"src": "c1", "dest": "n1", "body": {"type": "read", "msg_id": 4}}));
+    let (read_ok, _) = nodes[1].recv_until("read_ok");
+    assert_eq!(read_ok["body"]["messages"], json!([42]));
+}
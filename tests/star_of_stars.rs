@@ -0,0 +1,103 @@
+//! Checks the hubs and neighborhoods `StarOfStars` derives from the cluster size, and the
+//! routing rules and link priorities of each `Role`.
+
+use distributed_systems::broadcast::{highest_priority, Role, StarOfStars};
+
+#[test]
+fn nine_nodes_have_two_hubs() {
+    let overlay = StarOfStars::new(9, 5);
+    let hubs: Vec<String> = (0..9)
+        .map(|index| format!("n{index}"))
+        .filter(|node_id| overlay.is_hub(node_id))
+        .collect();
+    assert_eq!(hubs, ["n0", "n5"]);
+
+    assert_eq!(overlay.neighborhood("n0"), ["n1", "n2", "n3", "n4", "n5"]);
+    // The last hub has no next hub, only the leaves that are left.
+    assert_eq!(overlay.neighborhood("n5"), ["n0", "n6", "n7", "n8"]);
+    assert_eq!(overlay.neighborhood("n3"), ["n0"]);
+    assert_eq!(overlay.neighborhood("n8"), ["n5"]);
+}
+
+#[test]
+fn nodes_outside_the_cluster_have_no_role_in_it() {
+    let overlay = StarOfStars::new(9, 5);
+    assert!(!overlay.is_hub("n10"));
+    assert!(overlay.neighborhood("n9").is_empty());
+    assert_eq!(overlay.role("n5"), Role::Hub);
+    assert_eq!(overlay.role("n6"), Role::Leaf);
+    assert_eq!(overlay.role("c1"), Role::Client);
+    assert_eq!(overlay.role("seq-kv"), Role::Service);
+}
+
+const ROLES: [Role; 4] = [Role::Hub, Role::Leaf, Role::Client, Role::Service];
+
+#[test]
+fn only_hub_to_hub_broadcasts_are_tracked() {
+    for src in ROLES {
+        for dst in ROLES {
+            let expected = src == Role::Hub && dst == Role::Hub;
+            assert_eq!(
+                Role::should_track(src, dst),
+                expected,
+                "{:?} -> {:?}",
+                src,
+                dst
+            );
+        }
+    }
+}
+
+#[test]
+fn clients_and_tracked_broadcasts_are_acked() {
+    for src in ROLES {
+        for dst in ROLES {
+            let expected = src == Role::Client || (src == Role::Hub && dst == Role::Hub);
+            assert_eq!(
+                Role::should_ack(src, dst),
+                expected,
+                "{:?} -> {:?}",
+                src,
+                dst
+            );
+        }
+    }
+}
+
+#[test]
+fn backbone_links_come_first() {
+    assert_eq!(Role::link_priority(Role::Hub, Role::Hub), 2);
+    assert_eq!(Role::link_priority(Role::Hub, Role::Leaf), 1);
+    assert_eq!(Role::link_priority(Role::Leaf, Role::Hub), 1);
+    assert_eq!(Role::link_priority(Role::Leaf, Role::Leaf), 0);
+    assert_eq!(Role::link_priority(Role::Client, Role::Hub), 0);
+}
+
+#[test]
+fn only_node_roles_are_internal() {
+    for role in ROLES {
+        let expected = role == Role::Hub || role == Role::Leaf;
+        assert_eq!(role.kind().is_internal(), expected, "{:?}", role);
+    }
+}
+
+#[test]
+fn hub_neighbor_is_served_before_the_leaves() {
+    let node = Role::Hub;
+    let due = [
+        ("n1", Role::link_priority(node, Role::Leaf)),
+        ("n2", Role::link_priority(node, Role::Leaf)),
+        ("n5", Role::link_priority(node, Role::Hub)),
+        ("n3", Role::link_priority(node, Role::Leaf)),
+    ];
+    assert_eq!(highest_priority(due), Some("n5"));
+}
+
+#[test]
+fn ties_go_to_the_first_neighbor_listed() {
+    assert_eq!(
+        highest_priority([("n1", 1), ("n2", 1), ("n3", 0)]),
+        Some("n1")
+    );
+    assert_eq!(highest_priority([]), None);
+}
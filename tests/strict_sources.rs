@@ -0,0 +1,73 @@
+//! Checks that with `STRICT_MODE_ENV` set, the binaries with their own event loop drop the
+//! messages from unknown sources and still handle the ones from the other nodes.
+
+mod common;
+
+use std::time::Duration;
+
+use common::TestNode;
+use distributed_systems::maelstrom::membership::STRICT_MODE_ENV;
+use serde_json::{json, Value};
+
+/// Send `body` from an unknown source, then from the peer `n1`: only the second is answered,
+/// with `reply_type`.
+fn only_known_source_is_answered(bin: &str, body: Value, reply_type: &str) {
+    let mut node = TestNode::start_with_env(bin, &[(STRICT_MODE_ENV, "1")]);
+    node.init("n0", &["n0", "n1"]);
+    node.recv_type("init_ok");
+
+    let mut unknown = json!({"src": "x9", "dest": "n0", "body": body});
+    unknown["body"]["msg_id"] = json!(2);
+    node.send(&unknown);
+    let replies = node.recv_for(Duration::from_millis(200));
+    assert!(
+        replies.iter().all(|msg| msg["dest"] != "x9"),
+        "{:?}",
+        replies
+    );
+
+    let mut known = json!({"src": "n1", "dest": "n0", "body": body});
+    known["body"]["msg_id"] = json!(3);
+    node.send(&known);
+    let reply = node.recv_matching(|msg| msg["dest"] == "n1" && msg["body"]["type"] == reply_type);
+    assert_eq!(reply["body"]["in_reply_to"], 3);
+    assert!(node
+        .stderr()
+        .contains("Dropping message from unknown source x9"));
+}
+
+#[test]
+fn kafka() {
+    only_known_source_is_answered(
+        env!("CARGO_BIN_EXE_kafka"),
+        json!({"type": "send", "key": "k", "msg": 1}),
+        "send_ok",
+    );
+}
+
+#[test]
+fn multi_node_kafka() {
+    only_known_source_is_answered(
+        env!("CARGO_BIN_EXE_multi-node-kafka"),
+        json!({"type": "send", "key": "k", "msg": 1}),
+        "send_ok",
+    );
+}
+
+#[test]
+fn performant_broadcast() {
+    only_known_source_is_answered(
+        env!("CARGO_BIN_EXE_performant_broadcast"),
+        json!({"type": "topology", "topology": {"n0": ["n1"], "n1": ["n0"]}}),
+        "topology_ok",
+    );
+}
+
+#[test]
+fn performant_broadcast_final() {
+    only_known_source_is_answered(
+        env!("CARGO_BIN_EXE_performant_broadcast_final"),
+        json!({"type": "topology", "topology": {"n0": ["n1"], "n1": ["n0"]}}),
+        "topology_ok",
+    );
+}
@@ -0,0 +1,32 @@
+//! Checks the named timers of `TimerWheel`.
+
+use std::thread;
+use std::time::Duration;
+
+use distributed_systems::maelstrom::TimerWheel;
+
+#[test]
+fn expired_timer_fires_once_per_period() {
+    let mut timers = TimerWheel::new();
+    timers.register("flush", 20);
+    timers.register("gossip", 10_000);
+    assert_eq!(timers.expired().count(), 0);
+
+    thread::sleep(Duration::from_millis(30));
+    assert_eq!(timers.expired().collect::<Vec<_>>(), ["flush"]);
+    // Re-armed, so it doesn't fire again until its next period is over.
+    assert_eq!(timers.expired().count(), 0);
+
+    thread::sleep(Duration::from_millis(30));
+    assert_eq!(timers.expired().collect::<Vec<_>>(), ["flush"]);
+}
+
+#[test]
+fn cancelled_timer_never_fires() {
+    let mut timers = TimerWheel::new();
+    timers.register("flush", 10);
+    timers.cancel("flush");
+
+    thread::sleep(Duration::from_millis(20));
+    assert_eq!(timers.expired().count(), 0);
+}
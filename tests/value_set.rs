@@ -0,0 +1,111 @@
+//! Checks the `ValueSet` implementations: the add-only `HashSet` the broadcast workloads use
+//! and the `OrSet`, whose replicas converge whatever the order of their adds, removes and
+//! merges.
+
+use std::collections::HashSet;
+
+use distributed_systems::broadcast::{OrSet, ValueSet};
+use distributed_systems::maelstrom::rng::Rng;
+
+/// Merge every replica into every other one, as once the network has healed.
+fn merge_all(replicas: &mut [OrSet]) {
+    let snapshot = replicas.to_vec();
+    for replica in replicas.iter_mut() {
+        for other in snapshot.iter() {
+            replica.merge(other);
+        }
+    }
+}
+
+#[test]
+fn add_concurrent_with_a_remove_wins() {
+    let mut r1 = OrSet::new("r1");
+    let mut r2 = OrSet::new("r2");
+    r1.apply_add(5);
+    r2.merge(&r1);
+
+    // r1 removes the add it saw while r2 adds the value again.
+    assert!(r1.apply_remove(5));
+    r2.apply_add(5);
+
+    let mut replicas = [r1, r2];
+    merge_all(&mut replicas);
+    for replica in replicas.iter() {
+        assert!(replica.contains_value(5));
+        assert_eq!(replica.values(), [5]);
+    }
+}
+
+#[test]
+fn remove_of_every_observed_add_wins() {
+    let mut r1 = OrSet::new("r1");
+    let mut r2 = OrSet::new("r2");
+    r1.apply_add(5);
+    r2.apply_add(5);
+    r1.merge(&r2);
+    assert!(r1.apply_remove(5));
+
+    let mut replicas = [r1, r2];
+    merge_all(&mut replicas);
+    for replica in replicas.iter() {
+        assert!(!replica.contains_value(5));
+        assert!(replica.values().is_empty());
+    }
+}
+
+#[test]
+fn removing_an_unknown_value_changes_nothing() {
+    let mut replica = OrSet::new("r1");
+    replica.apply_add(1);
+    assert!(!replica.apply_remove(2));
+    assert_eq!(replica.values(), [1]);
+}
+
+#[test]
+fn add_only_set_ignores_removes() {
+    let mut values: HashSet<u64> = HashSet::new();
+    assert!(values.apply_add(3));
+    assert!(!values.apply_add(3));
+    assert!(!values.apply_remove(3));
+    values.merge(&HashSet::from([1, 3]));
+    assert_eq!(values.values(), [1, 3]);
+}
+
+/// Replicas applying random adds and removes, merging with each other at random, agree once
+/// they all merged, and merging in any order gives the same values.
+#[test]
+fn random_histories_converge() {
+    for seed in 0..50 {
+        let mut rng = Rng::new(seed);
+        let mut replicas: Vec<OrSet> = ["r0", "r1", "r2"].map(OrSet::new).to_vec();
+        for _ in 0..100 {
+            let index = rng.below(3) as usize;
+            let value = rng.below(8);
+            match rng.below(3) {
+                0 => {
+                    replicas[index].apply_add(value);
+                }
+                1 => {
+                    replicas[index].apply_remove(value);
+                }
+                _ => {
+                    let other = replicas[rng.below(3) as usize].clone();
+                    replicas[index].merge(&other);
+                }
+            }
+        }
+
+        let mut forward = replicas[0].clone();
+        forward.merge(&replicas[1]);
+        forward.merge(&replicas[2]);
+        let mut backward = replicas[2].clone();
+        backward.merge(&replicas[1]);
+        backward.merge(&replicas[0]);
+        assert_eq!(forward.values(), backward.values(), "seed {}", seed);
+
+        merge_all(&mut replicas);
+        for replica in replicas.iter() {
+            assert_eq!(replica.values(), forward.values(), "seed {}", seed);
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use std::sync::mpsc::channel;
+use std::time::{Duration, Instant};
+
+use distributed_systems::maelstrom::watchdog::Watchdog;
+
+const INTERVAL: Duration = Duration::from_millis(50);
+
+#[test]
+fn watchdog_fires_once_the_loop_stalls() {
+    let (tx, rx) = channel();
+    let watchdog = Watchdog::with_handler(INTERVAL, move |stall| {
+        let _ = tx.send(stall);
+    });
+    let heartbeat = watchdog.heartbeat();
+
+    // A loop turning regularly is left alone.
+    let started = Instant::now();
+    while started.elapsed() < INTERVAL * 4 {
+        heartbeat.beat_with_summary(|| Some("pending_cas=1".to_string()));
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    assert!(rx.try_recv().is_err());
+    assert_eq!(watchdog.fired(), 0);
+
+    // Then the loop stops turning, as if a handler blocked.
+    let stall = rx
+        .recv_timeout(INTERVAL * 20)
+        .expect("Watchdog did not fire");
+    assert!(stall.stalled_for >= INTERVAL);
+    assert_eq!(stall.pending_summary.as_deref(), Some("pending_cas=1"));
+    assert_eq!(watchdog.fired(), 1);
+
+    // A stall is only reported once, until the loop turns again.
+    std::thread::sleep(INTERVAL * 3);
+    assert_eq!(watchdog.fired(), 1);
+    heartbeat.beat();
+    rx.recv_timeout(INTERVAL * 20)
+        .expect("Watchdog did not fire on the second stall");
+    assert_eq!(watchdog.fired(), 2);
+}